@@ -0,0 +1,258 @@
+//! The package name of an APK, read from the binary AndroidManifest.xml inside the ZIP
+//! so that no aapt has to be around
+
+use std::io;
+
+use crate::inflate::inflate;
+
+const MANIFEST: &str = "AndroidManifest.xml";
+
+/// Largest manifest read, real ones are well under a megabyte
+const MAX_MANIFEST: usize = 16 << 20;
+
+/// Signatures of the ZIP records read
+const EOCD_SIG: u32 = 0x0605_4b50;
+const CENTRAL_SIG: u32 = 0x0201_4b50;
+const LOCAL_SIG: u32 = 0x0403_4b50;
+
+/// Chunk types of the binary XML
+const XML_CHUNK: u16 = 0x0003;
+const STRING_POOL: u16 = 0x0001;
+const START_ELEMENT: u16 = 0x0102;
+const UTF8_FLAG: u32 = 1 << 8;
+/// No string, as in an attribute without a namespace or a raw value
+const NO_INDEX: u32 = u32::MAX;
+/// The data type of an attribute that holds a string pool index
+const TYPE_STRING: u8 = 0x03;
+
+fn invalid(what: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.into())
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// The uncompressed bytes of the entry called `name`, found through the central
+/// directory since the local headers may leave the sizes out
+fn zip_entry(zip: &[u8], name: &str) -> io::Result<Vec<u8>> {
+    let not_zip = || invalid("not a ZIP file");
+    // the end record is last, followed by a comment of up to 64k
+    let eocd = (0..=zip.len().saturating_sub(22))
+        .rev()
+        .take(0xffff + 22)
+        .find(|&i| u32_at(zip, i) == Some(EOCD_SIG))
+        .ok_or_else(not_zip)?;
+    let count = u16_at(zip, eocd + 10).ok_or_else(not_zip)? as usize;
+    let mut at = u32_at(zip, eocd + 16).ok_or_else(not_zip)? as usize;
+    let cut_off = || invalid("the ZIP is cut off");
+    for _ in 0..count {
+        if u32_at(zip, at) != Some(CENTRAL_SIG) {
+            return Err(invalid("the ZIP directory is corrupt"));
+        }
+        let method = u16_at(zip, at + 10).ok_or_else(cut_off)?;
+        let packed = u32_at(zip, at + 20).ok_or_else(cut_off)? as usize;
+        let size = u32_at(zip, at + 24).ok_or_else(cut_off)? as usize;
+        let name_len = u16_at(zip, at + 28).ok_or_else(cut_off)? as usize;
+        let extra_len = u16_at(zip, at + 30).ok_or_else(cut_off)? as usize;
+        let comment_len = u16_at(zip, at + 32).ok_or_else(cut_off)? as usize;
+        let local = u32_at(zip, at + 42).ok_or_else(cut_off)? as usize;
+        let entry_name = zip.get(at + 46..at + 46 + name_len).ok_or_else(cut_off)?;
+        at += 46 + name_len + extra_len + comment_len;
+        if entry_name != name.as_bytes() {
+            continue;
+        }
+        if u32_at(zip, local) != Some(LOCAL_SIG) {
+            return Err(invalid(format!("the ZIP entry of {name} is corrupt")));
+        }
+        let start = local
+            + 30
+            + u16_at(zip, local + 26).ok_or_else(cut_off)? as usize
+            + u16_at(zip, local + 28).ok_or_else(cut_off)? as usize;
+        let data = zip.get(start..start + packed).ok_or_else(cut_off)?;
+        if size > MAX_MANIFEST {
+            return Err(invalid(format!("{name} is too large")));
+        }
+        return match method {
+            0 => Ok(data.to_vec()),
+            8 => inflate(data, size),
+            m => Err(invalid(format!("{name} uses unsupported compression {m}"))),
+        };
+    }
+    Err(invalid(format!("no {name} in the APK")))
+}
+
+/// The strings of a string pool chunk at `at`
+fn string_pool(xml: &[u8], at: usize) -> Option<Vec<String>> {
+    let header_size = u16_at(xml, at + 2)? as usize;
+    let count = u32_at(xml, at + 8)? as usize;
+    let utf8 = u32_at(xml, at + 16)? & UTF8_FLAG != 0;
+    let strings = at + u32_at(xml, at + 20)? as usize;
+    (0..count)
+        .map(|i| {
+            let start = strings + u32_at(xml, at + header_size + i * 4)? as usize;
+            if utf8 {
+                // the length in UTF-16 units, then in bytes, each one byte or two
+                let skip = |at: usize| -> Option<(usize, usize)> {
+                    let b = *xml.get(at)? as usize;
+                    Some(if b & 0x80 != 0 {
+                        (((b & 0x7f) << 8) | *xml.get(at + 1)? as usize, at + 2)
+                    } else {
+                        (b, at + 1)
+                    })
+                };
+                let (_, at) = skip(start)?;
+                let (len, at) = skip(at)?;
+                Some(String::from_utf8_lossy(xml.get(at..at + len)?).into_owned())
+            } else {
+                let mut len = u16_at(xml, start)? as usize;
+                let mut at = start + 2;
+                if len & 0x8000 != 0 {
+                    len = ((len & 0x7fff) << 16) | u16_at(xml, at)? as usize;
+                    at += 2;
+                }
+                let units: Vec<u16> = (0..len)
+                    .map(|i| u16_at(xml, at + i * 2))
+                    .collect::<Option<_>>()?;
+                Some(String::from_utf16_lossy(&units))
+            }
+        })
+        .collect()
+}
+
+/// The `package` attribute of the `<manifest>` element in a binary XML manifest
+pub fn manifest_package(xml: &[u8]) -> io::Result<String> {
+    let corrupt = || invalid(format!("{MANIFEST} is corrupt"));
+    if u16_at(xml, 0) != Some(XML_CHUNK) {
+        return Err(invalid(format!("{MANIFEST} is not binary XML")));
+    }
+    let mut at = u16_at(xml, 2).ok_or_else(corrupt)? as usize;
+    let mut strings = None;
+    while at < xml.len() {
+        let kind = u16_at(xml, at).ok_or_else(corrupt)?;
+        let header_size = u16_at(xml, at + 2).ok_or_else(corrupt)? as usize;
+        let size = u32_at(xml, at + 4).ok_or_else(corrupt)? as usize;
+        // a chunk running past the end is a manifest cut off
+        if size < 8 || at + size > xml.len() {
+            return Err(corrupt());
+        }
+        match kind {
+            STRING_POOL => strings = Some(string_pool(xml, at).ok_or_else(corrupt)?),
+            START_ELEMENT => {
+                let strings = strings.as_ref().ok_or_else(corrupt)?;
+                let string = |i: u32| strings.get(i as usize).map(String::as_str);
+                let ext = at + header_size;
+                let element = string(u32_at(xml, ext + 4).ok_or_else(corrupt)?);
+                if element != Some("manifest") {
+                    return Err(invalid(format!(
+                        "{MANIFEST} does not start with <manifest>"
+                    )));
+                }
+                let attrs = ext + u16_at(xml, ext + 8).ok_or_else(corrupt)? as usize;
+                let attr_size = u16_at(xml, ext + 10).ok_or_else(corrupt)? as usize;
+                let attr_count = u16_at(xml, ext + 12).ok_or_else(corrupt)? as usize;
+                for a in (0..attr_count).map(|i| attrs + i * attr_size) {
+                    let ns = u32_at(xml, a).ok_or_else(corrupt)?;
+                    let name = u32_at(xml, a + 4).ok_or_else(corrupt)?;
+                    if ns != NO_INDEX || string(name) != Some("package") {
+                        continue;
+                    }
+                    let raw = u32_at(xml, a + 8).ok_or_else(corrupt)?;
+                    let data_type = *xml.get(a + 15).ok_or_else(corrupt)?;
+                    let data = u32_at(xml, a + 16).ok_or_else(corrupt)?;
+                    let value = match raw {
+                        NO_INDEX if data_type == TYPE_STRING => string(data),
+                        NO_INDEX => None,
+                        raw => string(raw),
+                    };
+                    return value
+                        .filter(|v| !v.is_empty())
+                        .map(str::to_string)
+                        .ok_or_else(|| invalid(format!("{MANIFEST} has no package name")));
+                }
+                return Err(invalid(format!("{MANIFEST} has no package name")));
+            }
+            _ => {}
+        }
+        at += size;
+    }
+    Err(invalid(format!("{MANIFEST} has no <manifest> element")))
+}
+
+/// The package name of the APK in `zip`
+pub fn package_name(zip: &[u8]) -> io::Result<String> {
+    manifest_package(&zip_entry(zip, MANIFEST)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Manifests laid out as aapt2 and the older aapt write them, UTF-8 and UTF-16
+    const AAPT2: &[u8] = include_bytes!("../fixtures/aapt2-manifest.xml");
+    const AAPT: &[u8] = include_bytes!("../fixtures/aapt-manifest.xml");
+    const NO_PACKAGE: &[u8] = include_bytes!("../fixtures/no-package-manifest.xml");
+    /// The aapt2 manifest deflated between a stored classes.dex and resources.arsc
+    const APK: &[u8] = include_bytes!("../fixtures/app.apk");
+
+    fn error(result: io::Result<String>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn package_of_real_manifests() {
+        assert_eq!(manifest_package(AAPT2).unwrap(), "org.fdroid.fdroid");
+        assert_eq!(manifest_package(AAPT).unwrap(), "com.termux");
+        assert_eq!(package_name(APK).unwrap(), "org.fdroid.fdroid");
+    }
+
+    #[test]
+    fn malformed_manifests() {
+        assert_eq!(
+            error(manifest_package(NO_PACKAGE)),
+            "AndroidManifest.xml has no package name"
+        );
+        assert_eq!(
+            error(manifest_package(b"")),
+            "AndroidManifest.xml is not binary XML"
+        );
+        assert_eq!(
+            error(manifest_package(b"<manifest package=\"a.b\"/>")),
+            "AndroidManifest.xml is not binary XML"
+        );
+        // in the string pool, the resource map and the <manifest> element
+        for cut in [10, 60, 546, 700] {
+            let e = error(manifest_package(&AAPT2[..cut]));
+            assert_eq!(e, "AndroidManifest.xml is corrupt", "cut at {cut}");
+        }
+        // the elements after <manifest> are not needed
+        let cut = &AAPT2[..AAPT2.len() - 30];
+        assert_eq!(manifest_package(cut).unwrap(), "org.fdroid.fdroid");
+        // only the header chunk, nothing after it
+        assert_eq!(
+            error(manifest_package(&AAPT2[..8])),
+            "AndroidManifest.xml has no <manifest> element"
+        );
+    }
+
+    #[test]
+    fn malformed_zips() {
+        assert_eq!(error(package_name(b"")), "not a ZIP file");
+        assert_eq!(error(package_name(&[b'x'; 100])), "not a ZIP file");
+        // just the end record, of an empty ZIP
+        let empty = [&EOCD_SIG.to_le_bytes()[..], &[0; 18]].concat();
+        assert_eq!(
+            error(package_name(&empty)),
+            "no AndroidManifest.xml in the APK"
+        );
+        // the directory where the end record says, but the data before it cut off
+        let eocd = APK.len() - 22;
+        let mut moved = APK[eocd..].to_vec();
+        moved[16..20].copy_from_slice(&0u32.to_le_bytes());
+        assert_eq!(error(package_name(&moved)), "the ZIP directory is corrupt");
+    }
+}
@@ -0,0 +1,100 @@
+//! Crash-safe file replacement so the module never loads a half-written detach.bin
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::log;
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut p = path.as_os_str().to_owned();
+    p.push(suffix);
+    PathBuf::from(p)
+}
+
+/// Writes `data` to `<path>.tmp`, fsyncs it and renames it over `path`.
+/// On failure `path` is left as it was
+pub fn write(path: impl AsRef<Path>, data: &[u8]) -> io::Result<()> {
+    write_renamed(path.as_ref(), data, |from, to| fs::rename(from, to))
+}
+
+/// `write` moving the temporary file into place with `rename`
+fn write_renamed(
+    path: &Path,
+    data: &[u8],
+    rename: impl FnOnce(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let tmp = with_suffix(path, ".tmp");
+    log::verbose(format_args!(
+        "write: {} bytes to {}",
+        data.len(),
+        path.display()
+    ));
+    let ret = log::timed(format_args!("write: {}", path.display()), || {
+        write_synced(&tmp, data).and_then(|()| rename(&tmp, path))
+    });
+    if ret.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return ret;
+    }
+    // persist the rename itself
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    }
+    Ok(())
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut f = File::create(path)?;
+    f.write_all(data)?;
+    f.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own for `test`
+    fn dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("detach-atomic-{}-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn replaces_the_file() {
+        let dir = dir("replaces");
+        let path = dir.join("detach.bin");
+        write(&path, b"old").unwrap();
+        write(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!with_suffix(&path, ".tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_rename_leaves_the_file_as_it_was() {
+        let dir = dir("rename");
+        let path = dir.join("detach.bin");
+        write(&path, b"old").unwrap();
+        let err = write_renamed(&path, b"new", |tmp, _| {
+            // written in full and synced, then lost with the power
+            assert_eq!(fs::read(tmp).unwrap(), b"new");
+            Err(io::Error::other("killed"))
+        });
+        assert_eq!(err.unwrap_err().to_string(), "killed");
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert!(!with_suffix(&path, ".tmp").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_write_leaves_no_file() {
+        let dir = dir("missing");
+        let path = dir.join("gone").join("detach.bin");
+        assert!(write(&path, b"new").is_err());
+        assert!(!path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,498 @@
+//! The detach.bin format the zygisk module reads: for every app one length byte,
+//! then the package name as UTF-16LE with the last null byte dropped
+
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+
+use crate::device::{Device, DeviceFile};
+
+/// Largest detach.bin the zygisk module reads, see DETACH_CAP in module.cpp
+pub const MODULE_CAP: usize = 511;
+
+/// A detached app name and the byte range of its entry in detach.bin
+pub type DetachEntry = (String, Range<usize>);
+
+/// Decodes `bin` into its entries, `None` if it is cut off, not ASCII, or has an entry
+/// of even length, which ends in the null half of a character
+pub fn parse(bin: &[u8]) -> Option<Vec<DetachEntry>> {
+    let mut i = 0;
+    let mut detached = Vec::new();
+    while i < bin.len() {
+        let len = bin[i] as usize;
+        if len.is_multiple_of(2) {
+            return None;
+        }
+        i += 1;
+        let encoded_name = bin.get(i..i + len)?;
+        let name = String::from_utf8(encoded_name.iter().step_by(2).cloned().collect()).ok()?;
+        detached.push((name, i - 1..i + len));
+        i += len;
+    }
+    Some(detached)
+}
+
+/// What is wrong with an entry [`inspect`] found
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Problem {
+    /// The file ends `missing` bytes before the declared length does
+    CutOff { missing: usize },
+    /// The name is encoded in `actual` bytes, the length byte says otherwise
+    Length { actual: usize },
+    /// A character byte at `at` is not one package names have
+    BadChar { at: usize },
+    /// The byte at `at` should be the null half of a UTF-16LE character
+    NotUtf16 { at: usize },
+    /// The entry at offset `first` has the same name. Harmless to the module, the
+    /// next write drops it
+    Duplicate { first: usize },
+}
+
+impl Problem {
+    /// Whether the module reads the entry as something else than its name
+    pub fn malformed(self) -> bool {
+        !matches!(self, Self::Duplicate { .. })
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CutOff { missing } => write!(f, "cut off, {missing} bytes short"),
+            Self::Length { actual } => write!(f, "the name takes {actual} bytes"),
+            Self::BadChar { at } => write!(f, "invalid character at offset {at}"),
+            Self::NotUtf16 { at } => write!(f, "not UTF-16LE at offset {at}"),
+            Self::Duplicate { first } => write!(f, "duplicate of the entry at offset {first}"),
+        }
+    }
+}
+
+/// One entry of detach.bin as the module walks it, by its length bytes
+pub struct Inspected {
+    /// Where its length byte is
+    pub offset: usize,
+    /// What the length byte says
+    pub declared: usize,
+    /// The name bytes it has, shorter than declared when the file is cut off
+    pub bytes: Range<usize>,
+    /// The character bytes of the name, others shown escaped
+    pub name: String,
+    pub problems: Vec<Problem>,
+}
+
+/// How many bytes the name starting `bin` takes when read as characters with null
+/// bytes between them, up to the first character not followed by one
+fn encoded_len(bin: &[u8]) -> usize {
+    let mut len = 0;
+    while len < bin.len() && bin[len] != 0 {
+        match bin.get(len + 1) {
+            Some(0) if bin.get(len + 2).is_some_and(|&b| b != 0) => len += 2,
+            _ => return len + 1,
+        }
+    }
+    len
+}
+
+/// Every entry of `bin` with whatever does not match what [`serialize`] writes, for
+/// files that do not [`parse`] or that the module reads differently than expected
+pub fn inspect(bin: &[u8]) -> Vec<Inspected> {
+    let mut entries = Vec::new();
+    let mut first: HashMap<String, usize> = HashMap::new();
+    let mut offset = 0;
+    while offset < bin.len() {
+        let declared = bin[offset] as usize;
+        let start = offset + 1;
+        let end = (start + declared).min(bin.len());
+        let encoded = &bin[start..end];
+        let mut problems = Vec::new();
+        if end < start + declared {
+            problems.push(Problem::CutOff {
+                missing: start + declared - end,
+            });
+        }
+        let actual = encoded_len(&bin[start..]);
+        if actual != declared {
+            problems.push(Problem::Length { actual });
+        }
+        let mut name = String::new();
+        for (i, &b) in encoded.iter().enumerate() {
+            let at = start + i;
+            if i % 2 == 1 {
+                if b != 0
+                    && !problems
+                        .iter()
+                        .any(|p| matches!(p, Problem::NotUtf16 { .. }))
+                {
+                    problems.push(Problem::NotUtf16 { at });
+                }
+                continue;
+            }
+            if !(b.is_ascii_alphanumeric() || b == b'.' || b == b'_')
+                && !problems
+                    .iter()
+                    .any(|p| matches!(p, Problem::BadChar { .. }))
+            {
+                problems.push(Problem::BadChar { at });
+            }
+            name.extend(std::ascii::escape_default(b).map(char::from));
+        }
+        match first.get(&name) {
+            Some(&first) => problems.push(Problem::Duplicate { first }),
+            None => {
+                first.insert(name.clone(), offset);
+            }
+        }
+        entries.push(Inspected {
+            offset,
+            declared,
+            bytes: start..end,
+            name,
+            problems,
+        });
+        offset = start + declared;
+    }
+    entries
+}
+
+/// Whether `app` fits the encoding, which only has room for ASCII names of up to 128 bytes
+pub fn encodable(app: &str) -> bool {
+    app.is_ascii() && !app.is_empty() && app.len() * 2 - 1 <= u8::MAX as usize
+}
+
+fn unencodable(app: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{app} cannot be stored in detach.bin"),
+    )
+}
+
+/// Appends the entry for `app` to `sink`. Fails with [`io::ErrorKind::InvalidInput`]
+/// when `app` is not [`encodable`], writing nothing
+pub fn serialize(app: &str, sink: impl Write) -> io::Result<()> {
+    if !encodable(app) {
+        return Err(unencodable(app));
+    }
+    let w: Vec<u8> = app.bytes().flat_map(|b| [0, b]).skip(1).collect();
+    let mut f = BufWriter::new(sink);
+    f.write_all(&[w.len() as u8])?;
+    f.write_all(&w)?;
+    f.flush()
+}
+
+/// The entries of `bin` as they are written: each name trimmed, once and in order, so
+/// the same apps always make the same file. `None` if `bin` does not [`parse`]
+pub fn normalize(bin: &[u8]) -> Option<Vec<u8>> {
+    let mut names: Vec<String> = (parse(bin)?.into_iter())
+        .map(|(name, _)| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+    let mut normalized = Vec::with_capacity(bin.len());
+    for name in &names {
+        serialize(name, &mut normalized).ok()?;
+    }
+    Some(normalized)
+}
+
+/// Whether `name` looks like an Android package name: two or more dot separated
+/// segments of letters, digits and underscores, each starting with a letter
+pub fn is_package_name(name: &str) -> bool {
+    name.contains('.')
+        && name.split('.').all(|seg| {
+            seg.starts_with(|c: char| c.is_ascii_alphabetic())
+                && seg.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+/// A decoded detach.bin
+#[derive(Default)]
+pub struct DetachList {
+    bin: Vec<u8>,
+    entries: Vec<DetachEntry>,
+}
+
+impl DetachList {
+    /// Decodes `bin`, `None` if it is corrupted
+    pub fn decode(bin: Vec<u8>) -> Option<Self> {
+        let entries = parse(&bin)?;
+        Some(Self { bin, entries })
+    }
+
+    /// Reads detach.bin from `device`. A missing file is an empty list, one that
+    /// does not decode fails with [`io::ErrorKind::InvalidData`]
+    pub fn read(device: &dyn Device) -> io::Result<Self> {
+        let bin = match device.read(DeviceFile::DetachBin) {
+            Ok(bin) => bin,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        Self::decode(bin).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))
+    }
+
+    /// Replaces detach.bin on `device` with the list [`normalize`]d. The Play Store
+    /// only picks the change up once restarted, see [`crate::restart_play_store`]
+    pub fn write(&self, device: &dyn Device) -> io::Result<()> {
+        let bin = normalize(&self.bin).unwrap_or_else(|| self.bin.clone());
+        device.write(DeviceFile::DetachBin, &bin)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bin
+    }
+
+    pub fn entries(&self) -> &[DetachEntry] {
+        &self.entries
+    }
+
+    /// The detached package names in file order
+    pub fn apps(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn contains(&self, app: &str) -> bool {
+        self.apps().any(|a| a == app)
+    }
+
+    /// Appends `app`, returns false when it is already in the list.
+    /// Fails with [`io::ErrorKind::InvalidInput`] for names that are not [`encodable`]
+    pub fn push(&mut self, app: &str) -> io::Result<bool> {
+        if !encodable(app) {
+            return Err(unencodable(app));
+        }
+        if self.contains(app) {
+            return Ok(false);
+        }
+        let start = self.bin.len();
+        serialize(app, &mut self.bin)?;
+        self.entries.push((app.to_string(), start..self.bin.len()));
+        Ok(true)
+    }
+
+    /// Drops `app`, returns false when it was not in the list
+    pub fn remove(&mut self, app: &str) -> bool {
+        let Some(i) = self.entries.iter().position(|(name, _)| name == app) else {
+            return false;
+        };
+        let (_, range) = self.entries.remove(i);
+        let len = range.len();
+        self.bin.drain(range);
+        for (_, r) in &mut self.entries[i..] {
+            *r = r.start - len..r.end - len;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bin(apps: &[&str]) -> Vec<u8> {
+        let mut bin = Vec::new();
+        for app in apps {
+            serialize(app, &mut bin).unwrap();
+        }
+        bin
+    }
+
+    #[test]
+    fn serialized_apps_parse_back() {
+        let apps = ["com.app1", "org.xxx2", "a"];
+        let parsed = parse(&bin(&apps)).unwrap();
+        let names: Vec<&str> = parsed.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, apps);
+        assert_eq!(parsed[0].1, 0..16);
+        assert_eq!(parsed[2].1, 32..34);
+    }
+
+    #[test]
+    fn names_of_up_to_255_bytes_are_encodable() {
+        let longest = "a".repeat(128);
+        assert!(encodable(&longest));
+        assert_eq!(bin(&[&longest])[0], 255);
+        assert_eq!(parse(&bin(&[&longest])).unwrap()[0].0, longest);
+        assert!(!encodable(&"a".repeat(129)));
+        assert!(!encodable(""));
+        assert!(!encodable("com.äpp"));
+    }
+
+    #[test]
+    fn names_that_are_not_encodable_are_not_serialized() {
+        let mut bin = bin(&["com.app1"]);
+        for app in ["a".repeat(129), String::new(), "com.äpp".to_string()] {
+            let err = serialize(&app, &mut bin).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{app}");
+            assert_eq!(bin.len(), 16, "{app}");
+        }
+        let mut list = DetachList::default();
+        assert!(list.push(&"a".repeat(129)).is_err());
+        assert_eq!(list.apps().count(), 0);
+    }
+
+    #[test]
+    fn cut_off_files_do_not_parse() {
+        let whole = bin(&["com.app1", "org.xxx2"]);
+        for len in [1, 15, 17, whole.len() - 1] {
+            assert!(parse(&whole[..len]).is_none(), "{len} bytes");
+        }
+        // a name ending in the null half of a character, as one cut off and its
+        // length byte fixed up
+        assert!(parse(&[2, b'a', 0]).is_none());
+        assert!(parse(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn garbage_does_not_parse() {
+        assert!(parse(&[3, 0xff, 0, b'a']).is_none());
+        assert!(parse(b"\xde\xad\xbe\xef").is_none());
+        // a length of 0, one running past the end and a stray byte after an entry
+        for bin in [&[0][..], &[255], &[1, b'a', 0]] {
+            assert!(parse(bin).is_none(), "{bin:?}");
+        }
+    }
+
+    #[test]
+    fn normalize_sorts_and_drops_duplicates() {
+        let messy = bin(&["org.xxx2", " com.app1", "org.xxx2", "com.app1 "]);
+        assert_eq!(normalize(&messy).unwrap(), bin(&["com.app1", "org.xxx2"]));
+        // case counts, the package manager tells them apart
+        let cases = bin(&["com.App", "com.app"]);
+        assert_eq!(normalize(&cases).unwrap(), cases);
+        assert!(normalize(&[3, b'a']).is_none());
+    }
+
+    #[test]
+    fn normalize_is_idempotent() {
+        let messy = bin(&["org.xxx2", "com.app1", " org.xxx2", "b.c", "com.app1"]);
+        let once = normalize(&messy).unwrap();
+        assert_eq!(normalize(&once).unwrap(), once);
+        // the same apps added in another order make the same file
+        let other = bin(&["b.c", "com.app1", "org.xxx2"]);
+        assert_eq!(normalize(&other).unwrap(), once);
+    }
+
+    #[test]
+    fn duplicates_are_read_as_they_are_and_reported() {
+        let dup = bin(&["org.xxx2", "com.app1", "org.xxx2"]);
+        assert_eq!(DetachList::decode(dup.clone()).unwrap().apps().count(), 3);
+        let entries = inspect(&dup);
+        assert!(entries[..2].iter().all(|e| e.problems.is_empty()));
+        assert_eq!(entries[2].problems, [Problem::Duplicate { first: 0 }]);
+        assert!(!entries[2].problems[0].malformed());
+    }
+
+    #[test]
+    fn two_apps_make_these_bytes() {
+        let normalized = normalize(&bin(&["b.cd", "a.b"])).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            normalized,
+            [
+                5, b'a', 0, b'.', 0, b'b',
+                7, b'b', 0, b'.', 0, b'c', 0, b'd',
+            ]
+        );
+    }
+
+    #[test]
+    fn list_edits_keep_the_bytes_in_step() {
+        let mut list = DetachList::decode(bin(&["com.app1"])).unwrap();
+        assert!(list.push("org.xxx2").unwrap());
+        assert!(!list.push("com.app1").unwrap());
+        assert_eq!(list.as_bytes(), bin(&["com.app1", "org.xxx2"]));
+        assert!(list.remove("com.app1"));
+        assert!(!list.remove("com.app1"));
+        assert_eq!(list.as_bytes(), bin(&["org.xxx2"]));
+        assert_eq!(list.entries()[0].1, 0..16);
+        assert!(list.push(&"a".repeat(129)).is_err());
+    }
+
+    /// Each entry as its offset, declared length, name and problems
+    fn inspected(bin: &[u8]) -> Vec<(usize, usize, String, Vec<Problem>)> {
+        (inspect(bin).into_iter())
+            .map(|e| (e.offset, e.declared, e.name, e.problems))
+            .collect()
+    }
+
+    #[test]
+    fn a_good_file_has_no_problems() {
+        let entries = inspect(&bin(&["com.app1", "org.xxx2"]));
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[1].offset, entries[1].declared), (16, 15));
+        assert_eq!(entries[1].bytes, 17..32);
+        assert_eq!(entries[1].name, "org.xxx2");
+        assert!(entries.iter().all(|e| e.problems.is_empty()));
+        assert!(inspect(&[]).is_empty());
+    }
+
+    #[test]
+    fn a_stale_length_byte() {
+        // com.ab cut down to com.a without its length byte
+        let long = [&[11][..], &bin(&["com.a"])[1..]].concat();
+        assert_eq!(
+            inspected(&long),
+            [(
+                0,
+                11,
+                "com.a".to_string(),
+                vec![
+                    Problem::CutOff { missing: 2 },
+                    Problem::Length { actual: 9 }
+                ]
+            )]
+        );
+        // com.a grown to com.ab, the module reads the b as an entry of its own
+        let short = [&[9][..], &bin(&["com.ab"])[1..]].concat();
+        let entries = inspected(&short);
+        assert_eq!(entries[0].2, "com.a");
+        assert_eq!(entries[0].3, [Problem::Length { actual: 11 }]);
+        assert!(entries[1..].iter().all(|e| !e.3.is_empty()));
+    }
+
+    #[test]
+    fn a_name_written_one_byte_wide() {
+        let narrow = [&[5][..], b"com.a"].concat();
+        let entries = inspected(&narrow);
+        // the module reads every other byte
+        assert_eq!(entries[0].2, "cma");
+        assert_eq!(
+            entries[0].3,
+            [Problem::Length { actual: 1 }, Problem::NotUtf16 { at: 2 }]
+        );
+    }
+
+    #[test]
+    fn bad_characters_are_escaped() {
+        let bad = [3, b'a', 0, b'-'];
+        assert_eq!(
+            inspected(&bad),
+            [(0, 3, "a-".to_string(), vec![Problem::BadChar { at: 3 }])]
+        );
+        let control = [1, 0x07];
+        assert_eq!(inspected(&control)[0].2, "\\x07");
+    }
+
+    #[test]
+    fn a_cut_off_file() {
+        let mut cut = bin(&["com.app1"]);
+        cut.truncate(10);
+        assert_eq!(
+            inspected(&cut),
+            [(
+                0,
+                15,
+                "com.a".to_string(),
+                vec![
+                    Problem::CutOff { missing: 6 },
+                    Problem::Length { actual: 9 }
+                ]
+            )]
+        );
+        assert!(Problem::CutOff { missing: 6 }.malformed());
+        assert_eq!(
+            Problem::CutOff { missing: 6 }.to_string(),
+            "cut off, 6 bytes short"
+        );
+    }
+}
@@ -0,0 +1,1155 @@
+//! Everything that touches the phone goes through `Device`, so the same menus can
+//! run on the phone itself or on a desktop over adb
+
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use crate::runner::{self, CommandRunner};
+use crate::su::{self, SuError};
+use crate::{atomic, instance, log, time};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceFile {
+    DetachBin,
+    DetachTxt,
+    SdcardDetachBin,
+    /// The original of a detach.bin that was rebuilt after corruption
+    CorruptBackup,
+    /// A snapshot of detach.bin, by the unix time it was taken at
+    Backup(u64),
+}
+
+pub trait Device: Send + Sync {
+    /// The root solution and module directory of the device
+    fn environment(&self) -> io::Result<&Environment>;
+    /// Where `file` lives on the device
+    fn path(&self, file: DeviceFile) -> io::Result<String>;
+    /// Raw `pm list packages` output
+    fn list_packages(&self, flags: &[&str]) -> io::Result<Vec<u8>>;
+    /// Raw `pm list users` output
+    fn list_users(&self) -> io::Result<Vec<u8>>;
+    /// Raw `dumpsys package packages` output, see [`crate::parse_package_times`]
+    fn dump_packages(&self) -> io::Result<Vec<u8>>;
+    fn read(&self, file: DeviceFile) -> io::Result<Vec<u8>>;
+    /// Replaces `file` with `content` without leaving it half-written
+    fn write(&self, file: DeviceFile, content: &[u8]) -> io::Result<()>;
+    fn remove(&self, file: DeviceFile) -> io::Result<()>;
+    fn metadata(&self, file: DeviceFile) -> io::Result<FileInfo>;
+    /// File names in the backup directory
+    fn list_backups(&self) -> io::Result<Vec<String>>;
+    /// Stops every process of `pkg`
+    fn force_stop(&self, pkg: &str) -> io::Result<()>;
+    /// Empties the cache directories of `pkg`
+    fn clear_cache(&self, pkg: &str) -> io::Result<()>;
+    /// Gives `file` the SELinux label of its directory, for a write the label it was
+    /// left with after an OTA kept from sticking
+    fn relabel(&self, file: DeviceFile) -> io::Result<()>;
+    /// Opens the Play Store page of `pkg` on the device
+    fn open_store_page(&self, pkg: &str) -> io::Result<()>;
+    /// `<package> application-label:'<label>'` lines for `pkgs`, the label part
+    /// left out where it could not be resolved
+    fn app_labels(&self, pkgs: &[&str]) -> io::Result<Vec<u8>>;
+    /// The version lines of `dumpsys package` and the APK sizes of `pkgs`, see
+    /// [`crate::parse_package_details`]
+    fn app_details(&self, pkgs: &[&str]) -> io::Result<Vec<u8>>;
+    /// Output of the module and zygisk probes, see [`parse_module_state`]
+    fn probe_module(&self) -> io::Result<Vec<u8>>;
+}
+
+pub struct FileInfo {
+    pub size: u64,
+    /// Unix time of the last change
+    pub modified: u64,
+}
+
+/// Reads the labels out of the base APKs with whichever aapt the device has
+fn labels_script(pkgs: &[&str]) -> String {
+    format!(
+        "a=$(command -v aapt || command -v aapt2) || a=false; \
+         for p in {}; do \
+         apk=$(pm path $p | head -n1); \
+         echo \"$p $($a dump badging ${{apk#package:}} 2>/dev/null | grep -m1 ^application-label:)\"; \
+         done",
+        pkgs.join(" ")
+    )
+}
+
+/// `Package [<pkg>]` before the fields of each package, an `apkSize=` line per APK
+fn details_script(pkgs: &[&str]) -> String {
+    format!(
+        "for p in {}; do \
+         echo \"Package [$p]\"; \
+         dumpsys package $p | grep -E 'versionName=|versionCode=|lastUpdateTime='; \
+         for apk in $(pm path $p); do echo \"apkSize=$(stat -c %s ${{apk#package:}})\"; done; \
+         done",
+        pkgs.join(" ")
+    )
+}
+
+static DEVICE: OnceLock<Box<dyn Device>> = OnceLock::new();
+
+pub fn set(device: Box<dyn Device>) {
+    let _ = DEVICE.set(device);
+}
+
+pub fn get() -> &'static dyn Device {
+    DEVICE.get_or_init(|| Box::new(Local::new(None))).as_ref()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Root {
+    Magisk,
+    KernelSU,
+    APatch,
+    /// The module directory was given by the user
+    Custom,
+    /// No root, commands run as the shell user through Shizuku or adb
+    Shizuku,
+}
+
+impl Display for Root {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Magisk => "Magisk",
+            Self::KernelSU => "KernelSU",
+            Self::APatch => "APatch",
+            Self::Custom => "custom",
+            Self::Shizuku => "Shizuku (no root)",
+        })
+    }
+}
+
+pub struct Environment {
+    pub root: Root,
+    pub module_dir: String,
+}
+
+/// Prints the root solution, then the module directory. KernelSU and APatch ship
+/// their own daemons and may have a magisk binary for compatibility, so they go first.
+/// A module installed without a reboot yet still sits in modules_update
+const DETECT_SCRIPT: &str = "\
+    if [ -f /data/adb/ksud ] || command -v ksud >/dev/null; then echo KernelSU; \
+    elif [ -f /data/adb/apd ] || command -v apd >/dev/null; then echo APatch; \
+    elif [ -d /data/adb/magisk ] || command -v magisk >/dev/null; then echo Magisk; \
+    else echo; fi; \
+    for d in /data/adb/modules/zygisk-detach /data/adb/modules_update/zygisk-detach; do \
+    [ -d $d ] && echo $d && break; \
+    done; true";
+
+/// What [`Device::environment`] fails with when there is nothing to work on, inside
+/// the `io::Error` so callers can tell it from a failed command
+#[derive(Clone, Copy, Debug)]
+pub enum NotDetected {
+    Root,
+    /// The root solution was found without the module in it
+    Module(Root),
+}
+
+impl Display for NotDetected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Root => write!(
+                f,
+                "no root solution found, zygisk-detach needs Magisk, KernelSU or APatch"
+            ),
+            Self::Module(root) => write!(
+                f,
+                "the zygisk-detach module does not appear to be installed in {root}, \
+                 install it or point --module-dir at it"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NotDetected {}
+
+impl NotDetected {
+    /// The one `err` carries, if any
+    pub fn of(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+fn parse_detect(out: &[u8]) -> io::Result<Environment> {
+    let out = String::from_utf8_lossy(out);
+    let mut lines = out.lines().map(str::trim);
+    let root = match lines.next() {
+        Some("KernelSU") => Root::KernelSU,
+        Some("APatch") => Root::APatch,
+        Some("Magisk") => Root::Magisk,
+        _ => return Err(io::Error::other(NotDetected::Root)),
+    };
+    let Some(module_dir) = lines.next().filter(|l| !l.is_empty()) else {
+        return Err(io::Error::other(NotDetected::Module(root)));
+    };
+    Ok(Environment {
+        root,
+        module_dir: module_dir.to_string(),
+    })
+}
+
+/// Lines for a missing `module_dir` and the flag files of the module in it, then what Magisk's
+/// settings say about zygisk and which zygisk implementations are installed as
+/// modules, for KernelSU and APatch or Magisk with its own zygisk off
+fn probe_script(module_dir: &str) -> String {
+    format!(
+        "d={module_dir}; \
+         [ -d $d ] || echo missing; \
+         [ -f $d/disable ] && echo disable; \
+         [ -f $d/remove ] && echo remove; \
+         case $d in */modules_update/*) echo update;; esac; \
+         command -v magisk >/dev/null && \
+         echo \"magisk_zygisk $(magisk --sqlite \"select value from settings where key='zygisk'\" 2>/dev/null)\"; \
+         for m in zygisksu rezygisk zygisk_next; do \
+         z=/data/adb/modules/$m; \
+         [ -d $z ] && {{ [ -f $z/disable ] && echo \"zygisk_module $m disabled\" || echo \"zygisk_module $m enabled\"; }}; \
+         done; true"
+    )
+}
+
+/// What the probes found out about the module
+pub struct ModuleState {
+    /// The module directory does not exist
+    pub missing: bool,
+    /// Turned off in the root manager
+    pub disabled: bool,
+    /// Marked for removal on the next reboot
+    pub removing: bool,
+    /// Installed or updated, waiting for a reboot to be loaded
+    pub pending_reboot: bool,
+    /// Whether zygisk is on, `None` when that could not be told
+    pub zygisk: Option<bool>,
+}
+
+/// Reads the output of the probe script. Magisk's own zygisk or an enabled zygisk
+/// module is enough, KernelSU and APatch have no zygisk without such a module
+pub fn parse_module_state(out: &[u8], root: Root) -> ModuleState {
+    let out = String::from_utf8_lossy(out);
+    let mut state = ModuleState {
+        missing: false,
+        disabled: false,
+        removing: false,
+        pending_reboot: false,
+        zygisk: None,
+    };
+    let mut magisk = None;
+    let mut module = None;
+    for line in out.lines().map(str::trim) {
+        match line.split_once(' ').unwrap_or((line, "")) {
+            ("missing", _) => state.missing = true,
+            ("disable", _) => state.disabled = true,
+            ("remove", _) => state.removing = true,
+            ("update", _) => state.pending_reboot = true,
+            // `value=1`, nothing when the setting could not be read
+            ("magisk_zygisk", value) => {
+                magisk = match value.trim().strip_prefix("value=") {
+                    Some("1") => Some(true),
+                    Some(_) => Some(false),
+                    None => magisk,
+                }
+            }
+            ("zygisk_module", rest) => {
+                let enabled = rest.ends_with(" enabled");
+                module = Some(module.unwrap_or(false) || enabled);
+            }
+            _ => {}
+        }
+    }
+    state.zygisk = match (magisk, module, root) {
+        (Some(true), ..) | (_, Some(true), _) => Some(true),
+        (Some(false), ..) | (_, Some(false), _) => Some(false),
+        (None, None, Root::KernelSU | Root::APatch) => Some(false),
+        (None, None, _) => None,
+    };
+    state
+}
+
+/// The module directory, given by the user or detected once on first use
+struct ModuleDir {
+    custom: Option<String>,
+    detected: OnceLock<Result<Environment, Undetected>>,
+}
+
+/// A failed detection, kept to fail the same way on every later use
+enum Undetected {
+    /// Kept apart so callers can still tell it from a failed command
+    NotDetected(NotDetected),
+    Failed(String),
+}
+
+impl Undetected {
+    fn new(err: io::Error) -> Self {
+        match NotDetected::of(&err) {
+            Some(&not_detected) => Self::NotDetected(not_detected),
+            None => Self::Failed(err.to_string()),
+        }
+    }
+
+    fn error(&self) -> io::Error {
+        match self {
+            Self::NotDetected(not_detected) => io::Error::other(*not_detected),
+            Self::Failed(e) => io::Error::other(e.clone()),
+        }
+    }
+}
+
+impl ModuleDir {
+    fn new(custom: Option<String>) -> Self {
+        Self {
+            custom,
+            detected: OnceLock::new(),
+        }
+    }
+
+    fn get(&self, detect: impl FnOnce() -> io::Result<Environment>) -> io::Result<&Environment> {
+        if self.detected.get().is_none() {
+            let detected = match &self.custom {
+                Some(dir) => Ok(Environment {
+                    root: Root::Custom,
+                    module_dir: dir.clone(),
+                }),
+                // a detection that timed out is run again next time, the phone may
+                // have finished booting by then
+                None => match detect() {
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => return Err(e),
+                    detected => detected.map_err(Undetected::new),
+                },
+            };
+            let _ = self.detected.set(detected);
+        }
+        (self.detected.get().expect("set above"))
+            .as_ref()
+            .map_err(Undetected::error)
+    }
+
+    fn path(&self, file: DeviceFile, env: &Environment) -> String {
+        match file {
+            DeviceFile::DetachBin => format!("{}/detach.bin", env.module_dir),
+            DeviceFile::DetachTxt => format!("{}/detach.txt", env.module_dir),
+            DeviceFile::CorruptBackup => format!("{}/detach.bin.corrupt", env.module_dir),
+            DeviceFile::SdcardDetachBin => SDCARD_DETACH_BIN.to_string(),
+            DeviceFile::Backup(time) => format!("{BACKUP_DIR}/{}", backup_file_name(time)),
+        }
+    }
+}
+
+/// Name of the snapshot of detach.bin taken at `time` in the backup directory
+pub fn backup_file_name(time: u64) -> String {
+    format!("detach-{}.bin", time::format_time(time))
+}
+
+/// The inverse of [`backup_file_name`], `None` for files that are no snapshot
+pub fn parse_backup_file_name(name: &str) -> Option<u64> {
+    time::parse_time(name.strip_prefix("detach-")?.strip_suffix(".bin")?)
+}
+
+pub const STORE_PKG: &str = "com.android.vending";
+/// Play Services, which caches some of the update checks
+pub const GMS_PKG: &str = "com.google.android.gms";
+
+#[cfg(target_os = "android")]
+const SDCARD_DETACH_BIN: &str = "/sdcard/detach.bin";
+#[cfg(target_os = "linux")]
+const SDCARD_DETACH_BIN: &str = "detach_sdcard.bin";
+#[cfg(target_os = "android")]
+const BACKUP_DIR: &str = "/sdcard/zygisk-detach/backups";
+#[cfg(target_os = "linux")]
+const BACKUP_DIR: &str = "backups";
+
+/// The directories of an app's data that only hold what it can fetch or build again
+#[cfg(target_os = "android")]
+fn cache_dirs(pkg: &str) -> [String; 2] {
+    [
+        format!("/data/data/{pkg}/cache"),
+        format!("/data/data/{pkg}/code_cache"),
+    ]
+}
+#[cfg(target_os = "linux")]
+fn cache_dirs(pkg: &str) -> [String; 2] {
+    [format!("{pkg}/cache"), format!("{pkg}/code_cache")]
+}
+
+/// `rm` of what is in the cache directories, for devices reached through a shell
+fn clear_cache_script(pkg: &str) -> String {
+    format!("rm -rf /data/data/{pkg}/cache/* /data/data/{pkg}/code_cache/*")
+}
+
+/// The `am start` arguments that show the Play Store page of `pkg`
+fn store_page_args(pkg: &str) -> [String; 5] {
+    [
+        "start".to_string(),
+        "-a".to_string(),
+        "android.intent.action.VIEW".to_string(),
+        "-d".to_string(),
+        format!("market://details?id={pkg}"),
+    ]
+}
+
+/// Copies the label of the directory of `path` to it, with the errors on stdout
+fn relabel_script(path: &str) -> String {
+    format!(
+        "c=$(ls -Zd $(dirname {path}) | cut -d' ' -f1) && [ -n \"$c\" ] && chcon $c {path} 2>&1"
+    )
+}
+
+/// `am start` with its errors on stdout, for devices reached through a shell
+fn store_page_script(pkg: &str) -> String {
+    format!("am {} 2>&1", store_page_args(pkg).join(" "))
+}
+
+/// Fails with the `Error:` line `am start` printed, as when no app can show the page.
+/// It exits with 0 either way
+fn started(out: &[u8]) -> io::Result<()> {
+    match (String::from_utf8_lossy(out).lines()).find(|l| l.starts_with("Error")) {
+        Some(error) => Err(io::Error::other(error.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// The device the CLI runs on
+pub struct Local {
+    module: ModuleDir,
+    runner: Box<dyn CommandRunner>,
+}
+
+impl Local {
+    #[cfg(target_os = "android")]
+    pub fn new(module_dir: Option<String>) -> Self {
+        Self::with_runner(module_dir, Box::new(runner::System))
+    }
+
+    /// The desktop build answers what would ask the phone's package manager itself,
+    /// see [`runner::Desktop`], and works on files in the current directory
+    #[cfg(target_os = "linux")]
+    pub fn new(module_dir: Option<String>) -> Self {
+        let module_dir = module_dir.unwrap_or_else(|| ".".to_string());
+        Self::with_runner(Some(module_dir), Box::new(runner::Desktop))
+    }
+
+    /// The device with the commands it shells out to run by `runner`, as
+    /// [`runner::Scripted`] to answer them without a phone
+    pub fn with_runner(module_dir: Option<String>, runner: Box<dyn CommandRunner>) -> Self {
+        Self {
+            module: ModuleDir::new(module_dir),
+            runner,
+        }
+    }
+
+    /// The stdout of `cmd`
+    fn stdout(&self, cmd: &str, args: &[&str]) -> io::Result<Vec<u8>> {
+        Ok(self.runner.run(cmd, args)?.stdout)
+    }
+}
+
+impl Device for Local {
+    fn path(&self, file: DeviceFile) -> io::Result<String> {
+        Ok(self.module.path(file, self.environment()?))
+    }
+
+    fn environment(&self) -> io::Result<&Environment> {
+        self.module
+            .get(|| parse_detect(&self.stdout("sh", &["-c", DETECT_SCRIPT])?))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn app_labels(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        for pkg in pkgs {
+            let name = pkg.rsplit('.').next().unwrap_or(pkg);
+            writeln!(out, "{pkg} application-label:'{name} App'")?;
+        }
+        Ok(out)
+    }
+
+    #[cfg(target_os = "android")]
+    fn app_labels(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.stdout("sh", &["-c", &labels_script(pkgs)])
+    }
+
+    #[cfg(target_os = "linux")]
+    fn app_details(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        for (i, pkg) in pkgs.iter().enumerate() {
+            writeln!(
+                out,
+                "Package [{pkg}]\n    versionCode={} minSdk=26 targetSdk=34\n    \
+                 versionName={}.{i}.0\n    lastUpdateTime=2024-02-11 09:30:00\n\
+                 apkSize={}\napkSize=1048576",
+                1000 + i,
+                pkg.len() % 5 + 1,
+                (pkg.len() as u64 + 1) * 3_000_000,
+            )?;
+        }
+        Ok(out)
+    }
+
+    #[cfg(target_os = "android")]
+    fn app_details(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.stdout("sh", &["-c", &details_script(pkgs)])
+    }
+
+    fn dump_packages(&self) -> io::Result<Vec<u8>> {
+        self.stdout("dumpsys", &["package", "packages"])
+    }
+
+    fn list_packages(&self, flags: &[&str]) -> io::Result<Vec<u8>> {
+        let args: Vec<&str> = ["list", "packages"]
+            .into_iter()
+            .chain(flags.iter().copied())
+            .collect();
+        self.stdout("pm", &args)
+    }
+
+    fn list_users(&self) -> io::Result<Vec<u8>> {
+        self.stdout("pm", &["list", "users"])
+    }
+
+    fn read(&self, file: DeviceFile) -> io::Result<Vec<u8>> {
+        let path = self.path(file)?;
+        let content = fs::read(&path)?;
+        log::verbose(format_args!("read: {} bytes from {path}", content.len()));
+        Ok(content)
+    }
+
+    fn write(&self, file: DeviceFile, content: &[u8]) -> io::Result<()> {
+        let path = self.path(file)?;
+        if let DeviceFile::Backup(_) = file {
+            fs::create_dir_all(BACKUP_DIR)?;
+        }
+        let _lock = instance::lock()?;
+        atomic::write(&path, content)
+    }
+
+    fn remove(&self, file: DeviceFile) -> io::Result<()> {
+        fs::remove_file(self.path(file)?)
+    }
+
+    fn metadata(&self, file: DeviceFile) -> io::Result<FileInfo> {
+        let meta = fs::metadata(self.path(file)?)?;
+        let modified = meta
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Ok(FileInfo {
+            size: meta.len(),
+            modified,
+        })
+    }
+
+    fn probe_module(&self) -> io::Result<Vec<u8>> {
+        let script = probe_script(&self.environment()?.module_dir);
+        self.stdout("sh", &["-c", &script])
+    }
+
+    fn list_backups(&self) -> io::Result<Vec<String>> {
+        match fs::read_dir(BACKUP_DIR) {
+            Ok(dir) => dir
+                .map(|e| Ok(e?.file_name().to_string_lossy().into_owned()))
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn force_stop(&self, pkg: &str) -> io::Result<()> {
+        let mut buf = vec![0u8; pkg.len()];
+        for proc in fs::read_dir("/proc")? {
+            let mut proc = proc?.path();
+            if !proc.is_dir() {
+                continue;
+            }
+            proc.push("cmdline");
+            let Ok(mut cmdline) = fs::OpenOptions::new().read(true).open(&proc) else {
+                continue;
+            };
+            match cmdline.read(&mut buf) {
+                Ok(n) if n > 0 => {}
+                _ => continue,
+            }
+            // a prefix match, so `:background` and similar processes go too
+            if buf.eq(pkg.as_bytes()) {
+                if let Some(pid) = proc.components().nth(2) {
+                    let pid = pid.as_os_str().to_string_lossy();
+                    let Ok(pid) = pid.parse::<i32>() else {
+                        continue;
+                    };
+                    if unsafe { libc::kill(pid, libc::SIGKILL) } != 0 {
+                        let err = io::Error::last_os_error();
+                        // exited in the meantime
+                        if err.raw_os_error() != Some(libc::ESRCH) {
+                            return Err(err);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn clear_cache(&self, pkg: &str) -> io::Result<()> {
+        for dir in cache_dirs(pkg) {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    fs::remove_dir_all(path)?;
+                } else {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "android")]
+    fn relabel(&self, file: DeviceFile) -> io::Result<()> {
+        self.stdout("sh", &["-c", &relabel_script(&self.path(file)?)])?;
+        Ok(())
+    }
+
+    /// There is no SELinux to relabel for on the desktop
+    #[cfg(target_os = "linux")]
+    fn relabel(&self, file: DeviceFile) -> io::Result<()> {
+        log::verbose(format_args!("would relabel {}", self.path(file)?));
+        Ok(())
+    }
+
+    #[cfg(target_os = "android")]
+    fn open_store_page(&self, pkg: &str) -> io::Result<()> {
+        let args = store_page_args(pkg);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let out = self.runner.run("am", &args)?;
+        started(&out.stdout).and(started(&out.stderr))
+    }
+
+    /// There is no Play Store on the desktop
+    #[cfg(target_os = "linux")]
+    fn open_store_page(&self, pkg: &str) -> io::Result<()> {
+        log::verbose(format_args!("would open market://details?id={pkg}"));
+        Ok(())
+    }
+}
+
+/// A phone reached through `adb shell su -c`
+pub struct Adb {
+    serial: Option<String>,
+    module: ModuleDir,
+}
+
+/// `exit` status the scripts below use for a missing file
+const ADB_NOT_FOUND: i32 = 3;
+const ADB_BACKUP_DIR: &str = "/sdcard/zygisk-detach/backups";
+
+impl Adb {
+    pub fn new(serial: Option<String>, module_dir: Option<String>) -> Self {
+        Self {
+            serial,
+            module: ModuleDir::new(module_dir),
+        }
+    }
+
+    /// Runs `script` as root on the device, feeding it `stdin`
+    fn su(&self, script: &str, stdin: &[u8]) -> io::Result<Vec<u8>> {
+        // adb joins the arguments into one string for the device shell
+        let quoted = format!("'{}'", script.replace('\'', r"'\''"));
+        let build = || {
+            let mut cmd = Command::new("adb");
+            if let Some(serial) = &self.serial {
+                cmd.args(["-s", serial]);
+            }
+            cmd.args(["shell", "-T", "su", "-c", &quoted]);
+            cmd
+        };
+        let stderr = match su::run(script, build, stdin) {
+            Ok(out) => return Ok(out),
+            Err(SuError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    "adb not found, install platform-tools",
+                ))
+            }
+            Err(SuError::Failed {
+                code: Some(ADB_NOT_FOUND),
+                ..
+            }) => return Err(io::ErrorKind::NotFound.into()),
+            Err(SuError::Failed { stderr, .. }) => stderr,
+            Err(e) => return Err(e.into()),
+        };
+        let msg = if stderr.contains("unauthorized") {
+            "device is unauthorized, accept the USB debugging prompt on the phone".to_string()
+        } else if stderr.contains("more than one device") {
+            "more than one device connected, pick one with -s <serial>".to_string()
+        } else if stderr.contains("no devices") || stderr.contains("not found") {
+            "no device connected".to_string()
+        } else {
+            format!("adb failed: {}", stderr.trim())
+        };
+        Err(io::Error::other(msg))
+    }
+}
+
+impl Device for Adb {
+    fn environment(&self) -> io::Result<&Environment> {
+        self.module
+            .get(|| parse_detect(&self.su(DETECT_SCRIPT, &[])?))
+    }
+
+    fn path(&self, file: DeviceFile) -> io::Result<String> {
+        let env = self.environment()?;
+        Ok(match file {
+            DeviceFile::SdcardDetachBin => "/sdcard/detach.bin".to_string(),
+            DeviceFile::Backup(time) => format!("{ADB_BACKUP_DIR}/{}", backup_file_name(time)),
+            _ => self.module.path(file, env),
+        })
+    }
+
+    fn list_packages(&self, flags: &[&str]) -> io::Result<Vec<u8>> {
+        self.su(&format!("pm list packages {}", flags.join(" ")), &[])
+    }
+
+    fn list_users(&self) -> io::Result<Vec<u8>> {
+        self.su("pm list users", &[])
+    }
+
+    fn dump_packages(&self) -> io::Result<Vec<u8>> {
+        self.su("dumpsys package packages", &[])
+    }
+
+    fn read(&self, file: DeviceFile) -> io::Result<Vec<u8>> {
+        let path = self.path(file)?;
+        self.su(
+            &format!("[ -f {path} ] || exit {ADB_NOT_FOUND}; cat {path}"),
+            &[],
+        )
+    }
+
+    fn write(&self, file: DeviceFile, content: &[u8]) -> io::Result<()> {
+        let path = self.path(file)?;
+        let mkdir = match file {
+            DeviceFile::Backup(_) => format!("mkdir -p {ADB_BACKUP_DIR} && "),
+            _ => String::new(),
+        };
+        log::verbose(format_args!("write: {} bytes to {path}", content.len()));
+        self.su(
+            &format!("{mkdir}cat > {path}.tmp && mv -f {path}.tmp {path}"),
+            content,
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, file: DeviceFile) -> io::Result<()> {
+        let path = self.path(file)?;
+        self.su(
+            &format!("[ -f {path} ] || exit {ADB_NOT_FOUND}; rm {path}"),
+            &[],
+        )?;
+        Ok(())
+    }
+
+    fn metadata(&self, file: DeviceFile) -> io::Result<FileInfo> {
+        let path = self.path(file)?;
+        let out = self.su(
+            &format!("[ -f {path} ] || exit {ADB_NOT_FOUND}; stat -c '%s %Y' {path}"),
+            &[],
+        )?;
+        let out = String::from_utf8_lossy(&out);
+        let mut fields = out.split_whitespace().map(str::parse::<u64>);
+        match (fields.next(), fields.next()) {
+            (Some(Ok(size)), Some(Ok(modified))) => Ok(FileInfo { size, modified }),
+            _ => Err(io::Error::other(format!(
+                "unexpected stat output: {}",
+                out.trim()
+            ))),
+        }
+    }
+
+    fn list_backups(&self) -> io::Result<Vec<String>> {
+        let out = self.su(
+            &format!("[ -d {ADB_BACKUP_DIR} ] && ls {ADB_BACKUP_DIR}; true"),
+            &[],
+        )?;
+        Ok(String::from_utf8_lossy(&out)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn force_stop(&self, pkg: &str) -> io::Result<()> {
+        self.su(&format!("am force-stop {pkg}"), &[])?;
+        Ok(())
+    }
+
+    fn clear_cache(&self, pkg: &str) -> io::Result<()> {
+        self.su(&clear_cache_script(pkg), &[])?;
+        Ok(())
+    }
+
+    fn relabel(&self, file: DeviceFile) -> io::Result<()> {
+        self.su(&relabel_script(&self.path(file)?), &[])?;
+        Ok(())
+    }
+
+    fn open_store_page(&self, pkg: &str) -> io::Result<()> {
+        started(&self.su(&store_page_script(pkg), &[])?)
+    }
+
+    fn app_labels(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.su(&labels_script(pkgs), &[])
+    }
+
+    fn app_details(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.su(&details_script(pkgs), &[])
+    }
+
+    fn probe_module(&self) -> io::Result<Vec<u8>> {
+        self.su(&probe_script(&self.environment()?.module_dir), &[])
+    }
+}
+
+/// A phone reached without root, through Shizuku's `rish` on the phone or `adb shell`
+/// from a computer. Both run as the shell user, which can list packages and stop apps
+/// but cannot see the module, so its files read as missing and cannot be written
+pub struct Shizuku {
+    shell: Vec<String>,
+    env: Environment,
+}
+
+const DEFAULT_MODULE_DIR: &str = "/data/adb/modules/zygisk-detach";
+
+impl Shizuku {
+    /// Through `rish` on the phone
+    pub fn rish(module_dir: Option<String>) -> Self {
+        Self::new(vec!["rish".to_string(), "-c".to_string()], module_dir)
+    }
+
+    /// Through `adb shell` without su, as with `adb tcpip` on a phone without root
+    pub fn adb(serial: Option<String>, module_dir: Option<String>) -> Self {
+        let mut shell = vec!["adb".to_string()];
+        if let Some(serial) = serial {
+            shell.extend(["-s".to_string(), serial]);
+        }
+        shell.push("shell".to_string());
+        Self::new(shell, module_dir)
+    }
+
+    fn new(shell: Vec<String>, module_dir: Option<String>) -> Self {
+        Self {
+            shell,
+            env: Environment {
+                root: Root::Shizuku,
+                module_dir: module_dir.unwrap_or_else(|| DEFAULT_MODULE_DIR.to_string()),
+            },
+        }
+    }
+
+    /// Whether Shizuku's `rish` is on the PATH
+    pub fn available() -> bool {
+        runner::output(
+            Command::new("sh")
+                .args(["-c", "command -v rish"])
+                .stdin(Stdio::null()),
+            "command -v rish",
+        )
+        .is_ok_and(|out| out.status.success())
+    }
+
+    fn sh(&self, script: &str) -> io::Result<Vec<u8>> {
+        log::verbose(format_args!("{}: {script}", self.shell[0]));
+        let what = format!("{}: {script}", self.shell[0]);
+        let out = log::timed(&what, || {
+            runner::output(
+                Command::new(&self.shell[0])
+                    .args(&self.shell[1..])
+                    .arg(script)
+                    .stdin(Stdio::null()),
+                &what,
+            )
+        })
+        .map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                io::Error::new(e.kind(), format!("{} not found", self.shell[0]))
+            } else {
+                e
+            }
+        })?;
+        if out.status.success() {
+            return Ok(out.stdout);
+        }
+        if out.status.code() == Some(ADB_NOT_FOUND) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Err(io::Error::other(format!(
+            "{} failed: {}",
+            self.shell[0],
+            String::from_utf8_lossy(&out.stderr).trim()
+        )))
+    }
+
+    /// The files in the module directory, out of reach of the shell user
+    fn in_module(file: DeviceFile) -> bool {
+        matches!(
+            file,
+            DeviceFile::DetachBin | DeviceFile::DetachTxt | DeviceFile::CorruptBackup
+        )
+    }
+
+    fn needs_root(what: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{what} needs root, Shizuku only runs commands as the shell user"),
+        )
+    }
+}
+
+impl Device for Shizuku {
+    fn environment(&self) -> io::Result<&Environment> {
+        Ok(&self.env)
+    }
+
+    fn path(&self, file: DeviceFile) -> io::Result<String> {
+        Ok(match file {
+            DeviceFile::DetachBin => format!("{}/detach.bin", self.env.module_dir),
+            DeviceFile::DetachTxt => format!("{}/detach.txt", self.env.module_dir),
+            DeviceFile::CorruptBackup => format!("{}/detach.bin.corrupt", self.env.module_dir),
+            DeviceFile::SdcardDetachBin => "/sdcard/detach.bin".to_string(),
+            DeviceFile::Backup(time) => format!("{ADB_BACKUP_DIR}/{}", backup_file_name(time)),
+        })
+    }
+
+    fn list_packages(&self, flags: &[&str]) -> io::Result<Vec<u8>> {
+        self.sh(&format!("pm list packages {}", flags.join(" ")))
+    }
+
+    fn list_users(&self) -> io::Result<Vec<u8>> {
+        self.sh("pm list users")
+    }
+
+    fn dump_packages(&self) -> io::Result<Vec<u8>> {
+        self.sh("dumpsys package packages")
+    }
+
+    fn read(&self, file: DeviceFile) -> io::Result<Vec<u8>> {
+        if Self::in_module(file) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        let path = self.path(file)?;
+        self.sh(&format!(
+            "[ -f {path} ] || exit {ADB_NOT_FOUND}; cat {path}"
+        ))
+    }
+
+    fn write(&self, file: DeviceFile, _: &[u8]) -> io::Result<()> {
+        Err(Self::needs_root(&format!("writing {}", self.path(file)?)))
+    }
+
+    fn remove(&self, file: DeviceFile) -> io::Result<()> {
+        Err(Self::needs_root(&format!("removing {}", self.path(file)?)))
+    }
+
+    fn metadata(&self, file: DeviceFile) -> io::Result<FileInfo> {
+        if Self::in_module(file) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        let path = self.path(file)?;
+        let out = self.sh(&format!(
+            "[ -f {path} ] || exit {ADB_NOT_FOUND}; stat -c '%s %Y' {path}"
+        ))?;
+        let out = String::from_utf8_lossy(&out);
+        let mut fields = out.split_whitespace().map(str::parse::<u64>);
+        match (fields.next(), fields.next()) {
+            (Some(Ok(size)), Some(Ok(modified))) => Ok(FileInfo { size, modified }),
+            _ => Err(io::Error::other(format!(
+                "unexpected stat output: {}",
+                out.trim()
+            ))),
+        }
+    }
+
+    fn list_backups(&self) -> io::Result<Vec<String>> {
+        let out = self.sh(&format!(
+            "[ -d {ADB_BACKUP_DIR} ] && ls {ADB_BACKUP_DIR}; true"
+        ))?;
+        Ok(String::from_utf8_lossy(&out)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn force_stop(&self, pkg: &str) -> io::Result<()> {
+        self.sh(&format!("am force-stop {pkg}"))?;
+        Ok(())
+    }
+
+    fn clear_cache(&self, _: &str) -> io::Result<()> {
+        Err(Self::needs_root("clearing the cache"))
+    }
+
+    fn relabel(&self, file: DeviceFile) -> io::Result<()> {
+        Err(Self::needs_root(&format!(
+            "relabeling {}",
+            self.path(file)?
+        )))
+    }
+
+    fn open_store_page(&self, pkg: &str) -> io::Result<()> {
+        started(&self.sh(&store_page_script(pkg))?)
+    }
+
+    fn app_labels(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.sh(&labels_script(pkgs))
+    }
+
+    fn app_details(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.sh(&details_script(pkgs))
+    }
+
+    fn probe_module(&self) -> io::Result<Vec<u8>> {
+        Err(Self::needs_root("checking the module"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backup_names_give_their_time_back() {
+        assert_eq!(
+            backup_file_name(1714566787),
+            "detach-2024-05-01T12-33-07.bin"
+        );
+        assert_eq!(
+            parse_backup_file_name(&backup_file_name(1714566787)),
+            Some(1714566787)
+        );
+        assert_eq!(
+            parse_backup_file_name("detach-2024-05-01T12-33-07.bin.tmp"),
+            None
+        );
+        assert_eq!(parse_backup_file_name("detach.bin"), None);
+    }
+
+    use std::sync::Arc;
+
+    use crate::runner::Scripted;
+    use crate::{parse_installed_apps, parse_installers, parse_package_times};
+
+    /// The phone with its commands answered by `runner`, which is kept to look at
+    fn phone(runner: Scripted) -> (Local, Arc<Scripted>) {
+        let runner = Arc::new(runner);
+        (Local::with_runner(None, Box::new(runner.clone())), runner)
+    }
+
+    #[test]
+    fn listing_packages_past_weird_lines() {
+        let out = "package:com.a\r\n\nWARNING: linker: libfoo.so has text relocations\n\
+                   package:\npackage:com.b  installer=null\npackage:com.d uid:10123\n";
+        let (phone, runner) = phone(Scripted::default().reply("pm list packages -3", out));
+        let listed = phone.list_packages(&["-3"]).unwrap();
+        assert_eq!(
+            parse_installed_apps(&listed).collect::<Vec<_>>(),
+            ["com.a", "com.b", "com.d"]
+        );
+        assert_eq!(runner.calls(), ["pm list packages -3"]);
+    }
+
+    #[test]
+    fn flags_are_passed_on_to_pm() {
+        let (phone, runner) = phone(Scripted::default().reply_with(|cmd, args| {
+            (cmd == "pm").then(|| format!("package:com.a installer={}\n", args.len()).into())
+        }));
+        let listed = phone.list_packages(&["-i", "--user", "10"]).unwrap();
+        assert_eq!(
+            parse_installers(&listed).collect::<Vec<_>>(),
+            [("com.a", Some("5"))]
+        );
+        phone.list_users().unwrap();
+        assert_eq!(
+            runner.calls(),
+            ["pm list packages -i --user 10", "pm list users"]
+        );
+    }
+
+    #[test]
+    fn package_times_from_dumpsys() {
+        let out = "Packages:\n  Package [com.a] (1a):\n    firstInstallTime=2024-01-01 10:00:00\n";
+        let (phone, runner) = phone(Scripted::default().reply("dumpsys package packages", out));
+        let times = parse_package_times(&phone.dump_packages().unwrap());
+        let installed = times["com.a"].installed.as_deref();
+        assert_eq!(installed, Some("2024-01-01 10:00:00"));
+        assert_eq!(runner.calls(), ["dumpsys package packages"]);
+    }
+
+    #[test]
+    fn module_probed_with_a_script() {
+        let (phone, runner) = phone(Scripted::default().reply_with(|cmd, args| {
+            (cmd == "sh" && args[0] == "-c").then(|| match args[1] {
+                DETECT_SCRIPT => b"Magisk\n.\n".to_vec(),
+                _ => b"disable\nzygisk_module zygisksu enabled\n".to_vec(),
+            })
+        }));
+        let state = parse_module_state(&phone.probe_module().unwrap(), Root::Magisk);
+        assert!(state.disabled && !state.missing);
+        assert_eq!(state.zygisk, Some(true));
+        assert!(runner.calls()[1].starts_with("sh -c d=.;"));
+    }
+
+    #[test]
+    fn commands_not_answered_fail() {
+        let (phone, _) = phone(Scripted::default());
+        let err = phone.list_packages(&[]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.to_string(), "pm: not scripted");
+    }
+
+    #[test]
+    fn the_desktop_answers_as_a_phone() {
+        let phone = Local::new(None);
+        let listed = phone.list_packages(&[]).unwrap();
+        assert_eq!(
+            parse_installed_apps(&listed).collect::<Vec<_>>(),
+            [
+                "com.app1",
+                "org.xxx2",
+                "com.android.vending",
+                "com.oem.gallery"
+            ]
+        );
+        let work = phone.list_packages(&["--user", "10"]).unwrap();
+        assert!(parse_installed_apps(&work).any(|app| app == "com.work3"));
+    }
+
+    /// The phone with the detection script answered by `out`
+    fn detected(out: &'static str) -> Local {
+        let (phone, _) = phone(Scripted::default().reply_with(move |cmd, args| {
+            (cmd == "sh" && args == ["-c", DETECT_SCRIPT]).then(|| out.into())
+        }));
+        phone
+    }
+
+    #[test]
+    fn detecting_the_root_solution_and_the_module() {
+        let phone = detected("KernelSU\n/data/adb/modules/zygisk-detach\n");
+        assert!(matches!(phone.environment().unwrap().root, Root::KernelSU));
+        assert_eq!(
+            phone.path(DeviceFile::DetachBin).unwrap(),
+            "/data/adb/modules/zygisk-detach/detach.bin"
+        );
+        let err = detected("Magisk\n")
+            .path(DeviceFile::DetachBin)
+            .unwrap_err();
+        assert!(matches!(
+            NotDetected::of(&err),
+            Some(NotDetected::Module(Root::Magisk))
+        ));
+        let err = detected("").path(DeviceFile::DetachBin).unwrap_err();
+        assert!(matches!(NotDetected::of(&err), Some(NotDetected::Root)));
+    }
+}
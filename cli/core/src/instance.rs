@@ -0,0 +1,91 @@
+//! Keeps two copies of the cli from changing the list at once, as the menus open in one
+//! Termux session and a Tasker script in another. A change reads detach.bin, works out
+//! the new list and writes it, and another copy writing in between would be lost
+//!
+//! The lock is an flock, so the kernel lets go of it when its holder dies and a crashed
+//! copy leaves nothing to clean up. The holder's pid is written in the file to tell
+//! whom it is waited for
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "android")]
+const LOCK: &str = "/data/adb/zygisk-detach/cli.lock";
+#[cfg(target_os = "linux")]
+const LOCK: &str = "zygisk-detach-cli.lock";
+
+/// How long a change waits for the other copy to finish its own
+const WAIT: Duration = Duration::from_secs(3);
+const POLL: Duration = Duration::from_millis(100);
+
+/// The locked file and how many guards hold it, a change made inside another takes it
+/// again without waiting for itself
+static HELD: Mutex<(usize, Option<File>)> = Mutex::new((0, None));
+
+fn held() -> std::sync::MutexGuard<'static, (usize, Option<File>)> {
+    HELD.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Holds the lock until dropped
+pub struct Guard(());
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let mut held = held();
+        held.0 -= 1;
+        if held.0 == 0 {
+            // closing it lets go of the flock
+            held.1 = None;
+        }
+    }
+}
+
+/// Takes the lock, waiting a few seconds for another copy of the cli to let go of it
+pub fn lock() -> io::Result<Guard> {
+    let mut held = held();
+    if held.0 == 0 {
+        held.1 = Some(acquire()?);
+    }
+    held.0 += 1;
+    Ok(Guard(()))
+}
+
+fn acquire() -> io::Result<File> {
+    if let Some(dir) = Path::new(LOCK).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(LOCK)?;
+    let start = Instant::now();
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            file.set_len(0)?;
+            write!(file, "{}", std::process::id())?;
+            return Ok(file);
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::WouldBlock {
+            return Err(err);
+        }
+        if start.elapsed() >= WAIT {
+            let owner = fs::read_to_string(LOCK)
+                .ok()
+                .and_then(|pid| pid.trim().parse::<i32>().ok());
+            let by = owner.map_or(String::new(), |pid| format!(" (pid {pid})"));
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("another zygisk-detach instance is modifying the list{by}"),
+            ));
+        }
+        thread::sleep(POLL);
+    }
+}
@@ -0,0 +1,277 @@
+//! Reading and writing the zygisk-detach app list without the cli around it.
+//!
+//! Everything that touches the phone goes through a [`device::Device`], either the
+//! phone the code runs on or one connected over adb. The free functions use the
+//! device picked with [`device::set`].
+
+use std::collections::HashMap;
+use std::io;
+
+pub mod apk;
+pub mod atomic;
+pub mod detach_bin;
+pub mod device;
+mod inflate;
+pub mod instance;
+pub mod log;
+pub mod runner;
+pub mod su;
+pub mod time;
+
+pub use detach_bin::DetachList;
+
+/// What follows `package:` on the lines of `pm list packages` output
+fn package_lines(out: &[u8]) -> impl Iterator<Item = &str> {
+    out.split(|&e| e == b'\n')
+        .filter_map(|e| e.strip_prefix(b"package:"))
+        .filter_map(|e| std::str::from_utf8(e).ok())
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+}
+
+/// Package names of `pm list packages` output, fields added by flags like `-i` or `-U`
+/// are left out
+pub fn parse_installed_apps(installed_apps: &[u8]) -> impl Iterator<Item = &str> {
+    package_lines(installed_apps).filter_map(|line| line.split_whitespace().next())
+}
+
+/// Package names with their installer, of `pm list packages -i` output like
+/// `package:com.foo  installer=com.android.vending`. Apps installed by nothing print
+/// `installer=null` and some Android versions leave the field out for them, both give
+/// `None`
+pub fn parse_installers(out: &[u8]) -> impl Iterator<Item = (&str, Option<&str>)> {
+    package_lines(out).filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        let name = fields.next()?;
+        let installer = fields
+            .find_map(|f| f.strip_prefix("installer="))
+            .filter(|i| !i.is_empty() && *i != "null");
+        Some((name, installer))
+    })
+}
+
+/// When a package was installed and last updated, as `2024-01-31 12:00:00` in local
+/// time, which sorts the same as the times do
+#[derive(Clone, Default)]
+pub struct PackageTimes {
+    pub installed: Option<String>,
+    pub updated: Option<String>,
+}
+
+/// The install and update times of each package in `dumpsys package packages` output,
+/// where a `Package [com.foo] (1a2b3c):` line starts the fields of each
+pub fn parse_package_times(out: &[u8]) -> HashMap<String, PackageTimes> {
+    let out = String::from_utf8_lossy(out);
+    let mut times: HashMap<String, PackageTimes> = HashMap::new();
+    let mut current = None;
+    for line in out.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("Package [") {
+            current = rest.split_once(']').map(|(name, _)| name.to_string());
+            continue;
+        }
+        let Some(name) = &current else {
+            continue;
+        };
+        let (field, value) = match line.split_once('=') {
+            Some((field, value)) => (field, value.trim().to_string()),
+            None => continue,
+        };
+        let entry = times.entry(name.clone()).or_default();
+        match field {
+            // the first one is the package's, later ones belong to a user section
+            "firstInstallTime" if entry.installed.is_none() => entry.installed = Some(value),
+            "lastUpdateTime" if entry.updated.is_none() => entry.updated = Some(value),
+            _ => {}
+        }
+    }
+    times
+}
+
+/// What `dumpsys package <pkg>` and `pm path <pkg>` tell about an installed app
+#[derive(Clone, Default)]
+pub struct PackageDetails {
+    pub version_name: Option<String>,
+    pub version_code: Option<u64>,
+    /// As `2024-01-31 12:00:00` in local time
+    pub updated: Option<String>,
+    /// Bytes of the base and split APKs together
+    pub size: Option<u64>,
+}
+
+/// The details of each package in output shaped like [`device::Device::app_details`]'s,
+/// `Package [com.foo]` lines starting the fields of each. The fields are looked for
+/// anywhere in a line, as Android 11 to 14 put different ones next to `versionCode=`,
+/// and only the first of each counts, later ones belong to a hidden system package
+pub fn parse_package_details(out: &[u8]) -> HashMap<String, PackageDetails> {
+    let out = String::from_utf8_lossy(out);
+    let mut details: HashMap<String, PackageDetails> = HashMap::new();
+    let mut current = None;
+    for line in out.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("Package [") {
+            current = rest.split_once(']').map(|(name, _)| name.to_string());
+            continue;
+        }
+        let Some(name) = &current else {
+            continue;
+        };
+        let entry = details.entry(name.clone()).or_default();
+        // the values of these run to the end of the line and may have spaces
+        if let Some(name) = line.strip_prefix("versionName=") {
+            entry.version_name.get_or_insert_with(|| name.to_string());
+        } else if let Some(time) = line.strip_prefix("lastUpdateTime=") {
+            entry.updated.get_or_insert_with(|| time.to_string());
+        } else if let Some(size) = line.strip_prefix("apkSize=") {
+            if let Ok(size) = size.trim().parse::<u64>() {
+                *entry.size.get_or_insert(0) += size;
+            }
+        } else if entry.version_code.is_none() {
+            entry.version_code = line
+                .split_whitespace()
+                .find_map(|field| field.strip_prefix("versionCode="))
+                .and_then(|code| code.parse().ok());
+        }
+    }
+    details
+}
+
+/// Package names of a detach.txt, one per line with blank lines and `#` comments skipped
+pub fn parse_txt(txt: &str) -> impl Iterator<Item = &str> {
+    txt.lines()
+        .map(|s| s.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+/// The installed packages, `flags` passed on to `pm list packages`
+pub fn list_packages(flags: &[&str]) -> io::Result<Vec<String>> {
+    let out = device::get().list_packages(flags)?;
+    Ok(parse_installed_apps(&out).map(str::to_string).collect())
+}
+
+/// Whether the module is enabled and zygisk is on to load it
+pub fn module_state() -> io::Result<device::ModuleState> {
+    let device = device::get();
+    let root = device.environment()?.root;
+    Ok(device::parse_module_state(&device.probe_module()?, root))
+}
+
+/// Where the zygisk-detach module is installed
+pub fn module_dir() -> io::Result<String> {
+    Ok(device::get().environment()?.module_dir.clone())
+}
+
+/// Stops the Play Store so it rereads detach.bin the next time it starts, and Play
+/// Services too with `gms`. Returns the packages that were stopped
+pub fn restart_play_store(gms: bool) -> io::Result<Vec<&'static str>> {
+    let mut pkgs = vec![device::STORE_PKG];
+    if gms {
+        pkgs.push(device::GMS_PKG);
+    }
+    for pkg in &pkgs {
+        device::get().force_stop(pkg)?;
+    }
+    Ok(pkgs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn installers(out: &str) -> Vec<(&str, Option<&str>)> {
+        parse_installers(out.as_bytes()).collect()
+    }
+
+    #[test]
+    fn installers_of_pm_list_packages_i() {
+        let out = "package:com.foo  installer=com.android.vending\npackage:org.bar  installer=org.fdroid.fdroid\n";
+        assert_eq!(
+            installers(out),
+            [
+                ("com.foo", Some("com.android.vending")),
+                ("org.bar", Some("org.fdroid.fdroid"))
+            ]
+        );
+    }
+
+    #[test]
+    fn null_missing_and_empty_installers() {
+        let out = "package:a installer=null\npackage:b\npackage:c  installer=\n";
+        assert_eq!(installers(out), [("a", None), ("b", None), ("c", None)]);
+    }
+
+    #[test]
+    fn installer_among_other_fields() {
+        // `-U` adds the uid, newer versions tab-separate or end lines with \r over adb
+        let out = "package:a uid:10123 installer=com.android.vending\r\npackage:b\tinstaller=com.android.shell\r\n";
+        assert_eq!(
+            installers(out),
+            [
+                ("a", Some("com.android.vending")),
+                ("b", Some("com.android.shell"))
+            ]
+        );
+        assert_eq!(
+            parse_installed_apps(out.as_bytes()).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+    }
+
+    #[test]
+    fn lines_without_a_package_skipped() {
+        let out = "WARNING: linker: something\n\npackage:\npackage:   \npackage:a installer=null\n";
+        assert_eq!(installers(out), [("a", None)]);
+        assert_eq!(
+            parse_installed_apps(b"\xff\npackage:\xff\npackage:b").collect::<Vec<_>>(),
+            ["b"]
+        );
+    }
+
+    /// `dumpsys package packages` on Android 13, with a package in two users
+    const PACKAGES_13: &[u8] = include_bytes!("../fixtures/packages-android13.txt");
+    /// The output of the details script on Android 11 and 14
+    const DETAILS_11: &[u8] = include_bytes!("../fixtures/details-android11.txt");
+    const DETAILS_14: &[u8] = include_bytes!("../fixtures/details-android14.txt");
+
+    #[test]
+    fn package_times_of_the_package_not_its_users() {
+        let times = parse_package_times(PACKAGES_13);
+        let whatsapp = &times["com.whatsapp"];
+        assert_eq!(whatsapp.installed.as_deref(), Some("2022-11-02 14:40:19"));
+        assert_eq!(whatsapp.updated.as_deref(), Some("2023-06-20 08:15:03"));
+        let termux = &times["com.termux"];
+        assert_eq!(termux.installed.as_deref(), Some("2023-03-09 22:01:44"));
+        // a hidden system package with no times at all
+        let youtube = &times["com.google.android.youtube"];
+        assert!(youtube.installed.is_none() && youtube.updated.is_none());
+    }
+
+    #[test]
+    fn details_on_android_11() {
+        let details = parse_package_details(DETAILS_11);
+        let whatsapp = &details["com.whatsapp"];
+        assert_eq!(whatsapp.version_name.as_deref(), Some("2.21.25.77"));
+        assert_eq!(whatsapp.version_code, Some(212577007));
+        assert_eq!(whatsapp.updated.as_deref(), Some("2021-12-20 19:04:11"));
+        assert_eq!(whatsapp.size, Some(48213094));
+        // the update is listed before the system package it replaces, split APKs add up
+        let youtube = &details["com.google.android.youtube"];
+        assert_eq!(youtube.version_name.as_deref(), Some("16.49.37"));
+        assert_eq!(youtube.version_code, Some(1524139456));
+        assert_eq!(youtube.updated.as_deref(), Some("2021-12-18 03:12:45"));
+        assert_eq!(youtube.size, Some(101835310 + 2218140 + 5041655));
+    }
+
+    #[test]
+    fn details_on_android_14() {
+        let details = parse_package_details(DETAILS_14);
+        let chrome = &details["com.android.chrome"];
+        assert_eq!(chrome.version_name.as_deref(), Some("120.0.6099.144"));
+        assert_eq!(chrome.version_code, Some(614509933));
+        assert_eq!(chrome.updated.as_deref(), Some("2024-01-04 21:37:52"));
+        assert_eq!(chrome.size, Some(7340032 + 153791));
+        let fdroid = &details["org.fdroid.fdroid"];
+        assert_eq!(fdroid.version_name.as_deref(), Some("1.19.0 (debug build)"));
+        // uninstalled between the listing and the script, nothing is known of it
+        assert!(!details.contains_key("com.removed.meanwhile"));
+        assert_eq!(details.len(), 2);
+    }
+}
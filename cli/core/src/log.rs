@@ -0,0 +1,96 @@
+//! What the cli does behind the scenes, at the level `-q`, `-v` and `-vv` pick. Lines go
+//! to stderr, or to a log file while the menus are up so they do not draw over them
+
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::time;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// `-q`, nothing but errors
+    Quiet,
+    Normal,
+    /// `-v`, every command run and file read or written
+    Verbose,
+    /// `-vv`, how long they took too
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+/// The log file lines go to instead of stderr, once set
+static FILE: Mutex<Option<File>> = Mutex::new(None);
+
+fn file() -> std::sync::MutexGuard<'static, Option<File>> {
+    FILE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        2 => Level::Verbose,
+        _ => Level::Debug,
+    }
+}
+
+/// Starts `path` over and sends the lines there from now on. The file gets the
+/// verbose lines even without `-v`, so there is something to attach to a bug report
+pub fn to_file(path: impl AsRef<Path>) -> std::io::Result<()> {
+    let mut f = File::create(path)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    writeln!(
+        f,
+        "zygisk-detach cli {} started {}",
+        env!("CARGO_PKG_VERSION"),
+        time::display_time(now)
+    )?;
+    *file() = Some(f);
+    Ok(())
+}
+
+/// Writes `msg` when the level is at least `level`
+pub fn log(level: Level, msg: impl Display) {
+    let mut file = file();
+    match file.as_mut() {
+        Some(f) if level <= self::level().max(Level::Verbose) => {
+            let _ = writeln!(f, "{msg}");
+        }
+        Some(_) => {}
+        None if level <= self::level() => eprintln!("{msg}"),
+        None => {}
+    }
+}
+
+pub fn verbose(msg: impl Display) {
+    log(Level::Verbose, msg);
+}
+
+pub fn debug(msg: impl Display) {
+    log(Level::Debug, msg);
+}
+
+/// Runs `f`, and with `-vv` logs how long `what` took
+pub fn timed<T>(what: impl Display, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let ret = f();
+    if level() >= Level::Debug {
+        debug(format_args!(
+            "{what} took {} ms",
+            start.elapsed().as_millis()
+        ));
+    }
+    ret
+}
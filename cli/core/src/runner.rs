@@ -0,0 +1,291 @@
+//! The commands [`crate::device::Local`] runs, behind a trait so they can be answered
+//! from a script instead of a phone. Each device holds its own runner, a runner for
+//! another phone only has to wrap the command lines it is given
+
+use std::io::{self, Read};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use crate::log;
+
+/// How long a command may run before it is killed, unless changed with [`set_timeout`]
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// In seconds, 0 for no limit
+static TIMEOUT: AtomicU64 = AtomicU64::new(DEFAULT_TIMEOUT.as_secs());
+
+/// How often a running command is looked at
+const POLL: Duration = Duration::from_millis(50);
+
+/// Run while a command is still running, see [`on_wait`]
+type Waiting = Box<dyn Fn(&str, Option<Duration>) + Send>;
+
+static WAITING: Mutex<Option<(ThreadId, Waiting)>> = Mutex::new(None);
+
+fn waiting() -> std::sync::MutexGuard<'static, Option<(ThreadId, Waiting)>> {
+    WAITING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Limits every command to `timeout`, zero for none
+pub fn set_timeout(timeout: Duration) {
+    TIMEOUT.store(timeout.as_secs(), Ordering::Relaxed);
+}
+
+pub fn timeout() -> Option<Duration> {
+    match TIMEOUT.load(Ordering::Relaxed) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Calls `f` with the command line and how long it has been running, every second
+/// a command started on this thread takes past the first, and with `None` once it
+/// is done. Commands other threads run, as the label lookups, are left out
+pub fn on_wait(f: impl Fn(&str, Option<Duration>) + Send + 'static) {
+    *waiting() = Some((thread::current().id(), Box::new(f)));
+}
+
+fn report_wait(what: &str, elapsed: Option<Duration>) {
+    if let Some((thread, f)) = waiting().as_ref() {
+        if *thread == thread::current().id() {
+            f(what, elapsed);
+        }
+    }
+}
+
+/// Waits for `child` and collects what it wrote to the pipes it was given. Past the
+/// [`timeout`] it is killed and the wait fails with [`io::ErrorKind::TimedOut`], `what`
+/// names it in the error
+pub fn wait(mut child: Child, what: &str) -> io::Result<Output> {
+    fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    }
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let start = Instant::now();
+    let mut reported = 0;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        let elapsed = start.elapsed();
+        if let Some(timeout) = timeout().filter(|t| elapsed >= *t) {
+            let _ = child.kill();
+            let _ = child.wait();
+            if reported > 0 {
+                report_wait(what, None);
+            }
+            log::verbose(format_args!("killed `{what}` after {}s", timeout.as_secs()));
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "`{}` did not finish within {}s",
+                    short(what),
+                    timeout.as_secs()
+                ),
+            ));
+        }
+        if elapsed.as_secs() > reported {
+            reported = elapsed.as_secs();
+            report_wait(what, Some(elapsed));
+        }
+        thread::sleep(POLL);
+    };
+    if reported > 0 {
+        report_wait(what, None);
+    }
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+/// `what` cut to its start, scripts run through su can take a screen of their own
+fn short(what: &str) -> String {
+    const MAX: usize = 60;
+    match what.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}…", &what[..end]),
+        None => what.to_string(),
+    }
+}
+
+/// Runs `cmd` with its stdout and stderr collected, see [`wait`]
+pub fn output(cmd: &mut Command, what: &str) -> io::Result<Output> {
+    let child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    wait(child, what)
+}
+
+pub trait CommandRunner: Send + Sync {
+    /// Runs `cmd` with `args` and waits for it, nothing is fed to its stdin
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output>;
+}
+
+/// A runner shared with whoever looks at what it was given, as a test
+impl<R: CommandRunner + ?Sized> CommandRunner for std::sync::Arc<R> {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output> {
+        (**self).run(cmd, args)
+    }
+}
+
+/// Runs the commands for real
+pub struct System;
+
+impl CommandRunner for System {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output> {
+        let line = line(cmd, args);
+        log::verbose(format_args!("run: {line}"));
+        log::timed(&line, || {
+            output(
+                Command::new(system_tool(cmd))
+                    .args(args)
+                    .stdin(Stdio::null()),
+                &line,
+            )
+        })
+    }
+}
+
+/// What the desktop build runs: `pm` and `dumpsys` answer as a phone with com.app1 and
+/// org.xxx2 installed next to the Play Store and another system app, and a work profile
+/// with com.work3, the rest runs for real
+#[cfg(target_os = "linux")]
+pub struct Desktop;
+
+#[cfg(target_os = "linux")]
+impl Desktop {
+    fn answer(cmd: &str, args: &[&str]) -> Option<&'static str> {
+        let flags = match (cmd, args) {
+            ("pm", ["list", "users"]) => {
+                return Some(
+                    "Users:\n\tUserInfo{0:Owner:c13} running\n\tUserInfo{10:Work profile:1030} running\n",
+                )
+            }
+            ("pm", ["list", "packages", flags @ ..]) => flags,
+            ("dumpsys", ["package", "packages"]) => {
+                return Some(
+                    "Packages:\n  Package [com.app1] (1a2b3c):\n    firstInstallTime=2023-04-01 10:00:00\n    \
+                     lastUpdateTime=2024-02-11 09:30:00\n  Package [org.xxx2] (4d5e6f):\n    \
+                     firstInstallTime=2024-01-05 18:20:00\n    lastUpdateTime=2024-01-05 18:20:00\n",
+                )
+            }
+            _ => return None,
+        };
+        Some(if flags.windows(2).any(|w| w == ["--user", "10"]) {
+            "package:com.app1\npackage:com.work3\n"
+        } else if flags.contains(&"-d") {
+            "package:org.xxx2\n"
+        } else if flags.contains(&"-s") {
+            "package:com.android.vending  installer=null\npackage:com.oem.gallery  installer=com.android.vending\n"
+        } else if flags.contains(&"-i") {
+            "package:com.app1  installer=com.android.vending\npackage:org.xxx2  installer=null\n"
+        } else {
+            "package:com.app1\npackage:org.xxx2\npackage:com.android.vending\npackage:com.oem.gallery\n"
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl CommandRunner for Desktop {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output> {
+        match Self::answer(cmd, args) {
+            Some(stdout) => Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            }),
+            None => System.run(cmd, args),
+        }
+    }
+}
+
+/// Where Android keeps its own tools
+pub const SYSTEM_BIN: &str = "/system/bin";
+
+/// `cmd` from /system/bin whatever PATH says, or looked up in PATH when it is not
+/// there. Termux puts its `$PREFIX/bin` first, which has an `am` of its own, and `tsu`
+/// may leave /system/bin out altogether
+#[cfg(target_os = "android")]
+pub fn system_tool(cmd: &str) -> String {
+    let path = format!("{SYSTEM_BIN}/{cmd}");
+    if !cmd.contains('/') && std::path::Path::new(&path).exists() {
+        path
+    } else {
+        cmd.to_string()
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn system_tool(cmd: &str) -> String {
+    cmd.to_string()
+}
+
+fn line(cmd: &str, args: &[&str]) -> String {
+    std::iter::once(cmd)
+        .chain(args.iter().copied())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+type Reply = Box<dyn Fn(&str, &[&str]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// Answers each command with the stdout of the first reply that takes it, and
+/// records every command line it was given. Commands no reply takes are not found
+#[derive(Default)]
+pub struct Scripted {
+    replies: Vec<Reply>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl Scripted {
+    /// Answers the command line `cmdline`, as in `pm list packages -3`, with `stdout`
+    pub fn reply(self, cmdline: &str, stdout: impl Into<Vec<u8>>) -> Self {
+        let (cmdline, stdout) = (cmdline.to_string(), stdout.into());
+        self.reply_with(move |cmd, args| (line(cmd, args) == cmdline).then(|| stdout.clone()))
+    }
+
+    /// Answers the commands `reply` gives an output for
+    pub fn reply_with(
+        mut self,
+        reply: impl Fn(&str, &[&str]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        self.replies.push(Box::new(reply));
+        self
+    }
+
+    /// The command lines run so far, in order
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl CommandRunner for Scripted {
+    fn run(&self, cmd: &str, args: &[&str]) -> io::Result<Output> {
+        self.calls
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(line(cmd, args));
+        let stdout = self
+            .replies
+            .iter()
+            .find_map(|reply| reply(cmd, args))
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("{cmd}: not scripted"))
+            })?;
+        Ok(Output {
+            status: ExitStatus::from_raw(0),
+            stdout,
+            stderr: Vec::new(),
+        })
+    }
+}
@@ -0,0 +1,139 @@
+//! Running commands through su. On some ROMs the first `su -c` races the root
+//! manager's prompt and fails, so a run refused like that is tried again a couple of
+//! times before giving up
+
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::{log, runner};
+
+/// How many times a refused run is tried again, and how long it waits before each
+const RETRIES: u32 = 2;
+const RETRY_DELAY: Duration = Duration::from_millis(700);
+
+#[derive(Debug)]
+pub enum SuError {
+    /// There is no su, or it cannot be run
+    Missing,
+    /// The root manager refused, or its prompt went unanswered
+    Denied,
+    /// su ran the command and it failed
+    Failed { code: Option<i32>, stderr: String },
+    /// The command could not be started at all, or did not finish in time
+    Io(io::Error),
+}
+
+impl Display for SuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "su is not installed or cannot be run"),
+            Self::Denied => write!(f, "root was denied, grant it in the root manager"),
+            Self::Failed { code, stderr } => {
+                write!(f, "the command failed")?;
+                if let Some(code) = code {
+                    write!(f, " with status {code}")?;
+                }
+                match stderr.trim() {
+                    "" => Ok(()),
+                    stderr => write!(f, ": {stderr}"),
+                }
+            }
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SuError {}
+
+/// Missing and denied su both read as no permission, never as a missing file, which
+/// reads of the module files would take for an empty one
+impl From<SuError> for io::Error {
+    fn from(err: SuError) -> Self {
+        match err {
+            SuError::Io(e) => e,
+            SuError::Missing | SuError::Denied => {
+                io::Error::new(io::ErrorKind::PermissionDenied, err)
+            }
+            SuError::Failed { .. } => io::Error::other(err),
+        }
+    }
+}
+
+/// Sorts a failed run by what su left on stderr
+fn classify(code: Option<i32>, stderr: &str) -> SuError {
+    let lower = stderr.to_lowercase();
+    if lower.contains("su: not found")
+        || lower.contains("su: inaccessible")
+        || lower.contains("su: no such file")
+    {
+        SuError::Missing
+    } else if lower.contains("denied") || lower.contains("timed out") || lower.contains("timeout") {
+        SuError::Denied
+    } else {
+        SuError::Failed {
+            code,
+            stderr: stderr.to_string(),
+        }
+    }
+}
+
+/// Runs the command `build` makes, which goes through su, feeding it `stdin`, and
+/// returns its stdout. A run refused like a prompt that was denied or timed out is
+/// tried again. `what` names the command in the log
+pub fn run(what: &str, build: impl Fn() -> Command, stdin: &[u8]) -> Result<Vec<u8>, SuError> {
+    let mut attempt = 0;
+    loop {
+        log::verbose(format_args!("su: {what}"));
+        let err = match log::timed(format_args!("su: {what}"), || run_once(what, &build, stdin)) {
+            Ok(out) => return Ok(out),
+            Err(err) => err,
+        };
+        log::verbose(format_args!("su: `{what}` failed: {err}"));
+        if !matches!(err, SuError::Denied) || attempt == RETRIES {
+            return Err(err);
+        }
+        attempt += 1;
+        thread::sleep(RETRY_DELAY);
+    }
+}
+
+fn run_once(what: &str, build: &impl Fn() -> Command, stdin: &[u8]) -> Result<Vec<u8>, SuError> {
+    let mut child = build()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(SuError::Io)?;
+    let mut child_stdin = child.stdin.take().expect("stdin is piped");
+    // a su that exits right away stops reading, which is not the failure to report
+    let _ = child_stdin.write_all(stdin);
+    drop(child_stdin);
+    let out = runner::wait(child, what).map_err(SuError::Io)?;
+    if out.status.success() {
+        return Ok(out.stdout);
+    }
+    Err(classify(
+        out.status.code(),
+        &String::from_utf8_lossy(&out.stderr),
+    ))
+}
+
+/// `su -c <script>` on the device the code runs on
+pub fn local(script: &str) -> Result<Vec<u8>, SuError> {
+    let out = run(
+        script,
+        || {
+            let mut cmd = Command::new("su");
+            cmd.args(["-c", script]);
+            cmd
+        },
+        &[],
+    );
+    match out {
+        Err(SuError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Err(SuError::Missing),
+        out => out,
+    }
+}
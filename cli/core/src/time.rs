@@ -0,0 +1,76 @@
+//! UTC timestamps for file names, without pulling in a date crate
+
+/// Days since 1970-01-01 to a (year, month, day) date, after Howard Hinnant's `civil_from_days`
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// The inverse of `civil_from_days`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// `time` as `2024-05-01T12-33-07` in UTC, without colons so it can be a file name
+pub fn format_time(time: u64) -> String {
+    let secs = (time % 86400) as u32;
+    let (y, m, d) = civil_from_days((time / 86400) as i64);
+    format!(
+        "{y:04}-{m:02}-{d:02}T{:02}-{:02}-{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// `time` as `2024-05-01 12:33:07` in UTC, for people to read
+pub fn display_time(time: u64) -> String {
+    let s = format_time(time);
+    let (date, clock) = s.split_once('T').unwrap_or((&s, ""));
+    format!("{date} {}", clock.replace('-', ":"))
+}
+
+/// The inverse of [`format_time`]
+pub fn parse_time(s: &str) -> Option<u64> {
+    let (date, time) = s.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<u32>);
+    let (y, m, d) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, '-').map(str::parse::<u64>);
+    let (h, min, sec) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    let days = u64::try_from(days_from_civil(y as i64, m, d)).ok()?;
+    Some(days * 86400 + h * 3600 + min * 60 + sec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_format_and_parse_back() {
+        assert_eq!(format_time(0), "1970-01-01T00-00-00");
+        assert_eq!(format_time(1714566787), "2024-05-01T12-33-07");
+        assert_eq!(display_time(1714566787), "2024-05-01 12:33:07");
+        // the day after the 29th of February
+        assert_eq!(format_time(951868800), "2000-03-01T00-00-00");
+        for time in [0, 951782400, 1714566787, 4102444799] {
+            assert_eq!(parse_time(&format_time(time)), Some(time));
+        }
+        assert_eq!(parse_time("2024-05-01"), None);
+        assert_eq!(parse_time("2024-05-01T12-33"), None);
+        assert_eq!(parse_time("1969-12-31T23-59-59"), None);
+    }
+}
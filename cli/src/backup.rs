@@ -0,0 +1,98 @@
+//! Timestamped copies of detach.bin taken before anything destructive
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::device::{self, DeviceFile};
+use crate::{detach_bin, detach_bin_changed, error, get_detached_apps};
+use crate::{read_detach_bin, write_detach_bin, CLIResult};
+
+/// How many snapshots are kept, older ones are deleted first
+const KEEP: usize = 10;
+
+/// Times of the snapshots on the device, newest first
+fn times() -> CLIResult<Vec<u64>> {
+    let names = device::get()
+        .list_backups()
+        .map_err(error::Error::DetachFile)?;
+    let mut times: Vec<u64> = names
+        .iter()
+        .filter_map(|n| device::parse_backup_file_name(n))
+        .collect();
+    times.sort_unstable_by(|a, b| b.cmp(a));
+    times.dedup();
+    Ok(times)
+}
+
+/// Which of `times`, newest first, fall outside the retention count
+fn expired(times: &[u64]) -> &[u64] {
+    times.get(KEEP..).unwrap_or_default()
+}
+
+/// Copies detach.bin into the backup directory and drops the oldest copies, returns
+/// the time of the copy. An empty or missing detach.bin has nothing worth keeping
+pub fn snapshot() -> CLIResult<Option<u64>> {
+    let content = read_detach_bin()?;
+    if content.is_empty() {
+        return Ok(None);
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let device = device::get();
+    device
+        .write(DeviceFile::Backup(now), &content)
+        .map_err(error::Error::DetachFile)?;
+    for &time in expired(&times()?) {
+        let _ = device.remove(DeviceFile::Backup(time));
+    }
+    Ok(Some(now))
+}
+
+pub struct Backup {
+    pub time: u64,
+    /// `None` when the copy does not decode
+    pub entries: Option<usize>,
+}
+
+/// The snapshots on the device, newest first
+pub fn list() -> CLIResult<Vec<Backup>> {
+    let device = device::get();
+    times()?
+        .into_iter()
+        .map(|time| {
+            let content = device
+                .read(DeviceFile::Backup(time))
+                .map_err(error::Error::DetachFile)?;
+            Ok(Backup {
+                time,
+                entries: detach_bin::parse(&content).map(|e| e.len()),
+            })
+        })
+        .collect()
+}
+
+/// Puts the snapshot taken at `time` back as detach.bin, returns its entry count.
+/// The current detach.bin is snapshotted first so a restore can be undone
+pub fn restore(time: u64) -> CLIResult<usize> {
+    let content = device::get()
+        .read(DeviceFile::Backup(time))
+        .map_err(error::Error::DetachFile)?;
+    let entries = get_detached_apps(&content)?.len();
+    snapshot()?;
+    write_detach_bin(&content)?;
+    detach_bin_changed();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_oldest_past_the_count_expire() {
+        let times: Vec<u64> = (0..13).rev().collect();
+        assert_eq!(expired(&times), [2, 1, 0]);
+        assert!(expired(&times[..KEEP]).is_empty());
+        assert!(expired(&[]).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+//! `*` in "Detach multiple": checks at once the listed apps whose package starts with a
+//! prefix or matches a glob, or that one installer installed. Only the listed apps are
+//! looked at, the ones a sort or a filter left out stay as they were
+
+use std::collections::HashMap;
+
+use crate::rules;
+
+/// What the listed apps are matched against
+pub enum Pattern<'a> {
+    /// A package prefix like `com.google.`, or a glob like `com.*.maps` once it has `*`
+    /// or `?`
+    Name(&'a str),
+    Installer(Option<&'a str>),
+}
+
+impl Pattern<'_> {
+    pub fn matches(&self, pkg: &str, installer: Option<&str>) -> bool {
+        match *self {
+            Self::Name(glob) if glob.contains(['*', '?']) => rules::glob_match(glob, pkg),
+            Self::Name(prefix) => pkg.starts_with(prefix),
+            Self::Installer(of) => installer == of,
+        }
+    }
+}
+
+/// Checks the apps of `listed` that match `pattern`, each given with its index in
+/// `checked`. Returns how many matched and how many of those were not checked yet
+pub fn check<'a>(
+    listed: impl IntoIterator<Item = (usize, &'a str, Option<&'a str>)>,
+    checked: &mut [bool],
+    pattern: &Pattern,
+) -> (usize, usize) {
+    let (mut matched, mut added) = (0, 0);
+    for (i, pkg, installer) in listed {
+        if pattern.matches(pkg, installer) {
+            matched += 1;
+            added += usize::from(!checked[i]);
+            checked[i] = true;
+        }
+    }
+    (matched, added)
+}
+
+/// The installers of the listed apps with how many apps each installed, the most first
+pub fn installers<'a>(
+    listed: impl IntoIterator<Item = Option<&'a str>>,
+) -> Vec<(Option<&'a str>, usize)> {
+    let mut counts: HashMap<Option<&str>, usize> = HashMap::new();
+    for installer in listed {
+        *counts.entry(installer).or_default() += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTED: [(usize, &str, Option<&str>); 4] = [
+        (0, "com.google.android.maps", Some("com.android.vending")),
+        (1, "com.google.photos", Some("com.android.vending")),
+        (3, "org.fdroid.app", Some("org.fdroid.fdroid")),
+        (4, "com.sideloaded", None),
+    ];
+
+    #[test]
+    fn patterns() {
+        assert!(Pattern::Name("com.google.").matches("com.google.photos", None));
+        assert!(!Pattern::Name("com.google.").matches("com.googlex", None));
+        assert!(Pattern::Name("com.*.maps").matches("com.google.maps", None));
+        assert!(!Pattern::Name("com.*.maps").matches("com.google.maps.go", None));
+        assert!(Pattern::Name("com.g??gle.maps").matches("com.google.maps", None));
+        let play = Pattern::Installer(Some("com.android.vending"));
+        assert!(play.matches("a.b", Some("com.android.vending")));
+        assert!(!play.matches("a.b", None));
+        assert!(Pattern::Installer(None).matches("a.b", None));
+    }
+
+    #[test]
+    fn checks_the_listed_apps_that_match() {
+        // 1 is checked already, 2 is not listed and stays as it was
+        let mut checked = vec![false, true, false, false, false];
+        let pattern = Pattern::Name("com.google.");
+        assert_eq!(check(LISTED, &mut checked, &pattern), (2, 1));
+        assert_eq!(checked, [true, true, false, false, false]);
+        let pattern = Pattern::Installer(None);
+        assert_eq!(check(LISTED, &mut checked, &pattern), (1, 1));
+        assert_eq!(checked, [true, true, false, false, true]);
+        assert_eq!(check([], &mut checked, &Pattern::Name("")), (0, 0));
+        let pattern = Pattern::Name("*");
+        assert_eq!(check(LISTED, &mut checked, &pattern), (4, 1));
+        assert_eq!(checked, [true, true, false, true, true]);
+    }
+
+    #[test]
+    fn installers_the_most_first() {
+        let counts = installers(LISTED.iter().map(|&(_, _, i)| i));
+        assert_eq!(
+            counts,
+            [
+                (Some("com.android.vending"), 2),
+                (None, 1),
+                (Some("org.fdroid.fdroid"), 1)
+            ]
+        );
+        assert_eq!(installers([]), []);
+    }
+}
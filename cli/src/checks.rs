@@ -0,0 +1,116 @@
+//! Whether detaching has any effect: the module has to be installed, enabled and
+//! loaded by zygisk. Edits to the list still work when it is not
+
+use std::fmt::Display;
+
+use crate::colorize::ToColored;
+use crate::device::{self, Root};
+
+pub struct Check {
+    /// `None` when the probe could not tell
+    pub pass: Option<bool>,
+    pub text: String,
+}
+
+impl Check {
+    pub fn pass(text: impl Display) -> Self {
+        Self {
+            pass: Some(true),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn fail(text: impl Display) -> Self {
+        Self {
+            pass: Some(false),
+            text: text.to_string(),
+        }
+    }
+
+    fn unknown(text: impl Display) -> Self {
+        Self {
+            pass: None,
+            text: text.to_string(),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.pass {
+            Some(true) => write!(f, "{} {}", "[ ok ]".green(), self.text),
+            Some(false) => write!(f, "{} {}", "[FAIL]".red().bold(), self.text),
+            None => write!(f, "{} {}", "[ ?? ]".yellow(), self.text),
+        }
+    }
+}
+
+/// Probes the module and zygisk, a failing probe is reported as a check
+/// instead of an error
+pub fn module() -> Vec<Check> {
+    let env = match device::get().environment() {
+        Ok(env) => env,
+        Err(e) => return vec![Check::fail(e)],
+    };
+    let mut checks = vec![Check::pass(format_args!(
+        "{} with the module in {}",
+        env.root, env.module_dir
+    ))];
+    let state = match detach_core::module_state() {
+        Ok(state) => state,
+        Err(e) => {
+            checks.push(Check::unknown(format_args!(
+                "could not check the module: {e}"
+            )));
+            return checks;
+        }
+    };
+    if state.missing {
+        checks.push(Check::fail(format_args!(
+            "{} does not exist, install the module or fix --module-dir",
+            env.module_dir
+        )));
+        return checks;
+    }
+    let manager = match env.root {
+        Root::Custom => "your root manager".to_string(),
+        root => root.to_string(),
+    };
+    checks.push(if state.disabled {
+        Check::fail(format_args!(
+            "the module is disabled, enable it in {manager} and reboot"
+        ))
+    } else {
+        Check::pass("the module is enabled")
+    });
+    if state.removing {
+        checks.push(Check::fail(
+            "the module is set to be removed on the next reboot",
+        ));
+    }
+    if state.pending_reboot {
+        checks.push(Check::fail(
+            "the module is installed but not loaded yet, reboot the phone",
+        ));
+    }
+    checks.push(match (state.zygisk, env.root) {
+        (Some(true), _) => Check::pass("zygisk is enabled"),
+        (Some(false), Root::Magisk) => {
+            Check::fail("zygisk is off, turn it on in the Magisk settings and reboot")
+        }
+        (Some(false), _) => {
+            Check::fail("no zygisk found, install Zygisk Next or ReZygisk and reboot")
+        }
+        (None, _) => Check::unknown("could not tell whether zygisk is enabled"),
+    });
+    checks
+}
+
+/// The checks that failed, worded as warnings for the menus
+pub fn warnings(checks: &[Check]) -> Vec<&str> {
+    checks
+        .iter()
+        .filter(|c| c.pass == Some(false))
+        .map(|c| c.text.as_str())
+        .collect()
+}
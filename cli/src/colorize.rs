@@ -1,93 +1,675 @@
+use std::borrow::Cow;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use termion::{color, style};
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(Self::Auto),
+            "always" => Some(Self::Always),
+            "never" => Some(Self::Never),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Always => "always",
+            Self::Never => "never",
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Decides once whether the helpers below emit escape sequences.
+/// `Auto` colors only when stdout is a terminal and `NO_COLOR` is unset or empty
+pub fn init(choice: ColorChoice) {
+    let enabled = colors(
+        choice,
+        std::env::var_os("NO_COLOR").as_deref(),
+        termion::is_tty(&std::io::stdout()),
+    );
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether `choice` colors, given `NO_COLOR` and whether stdout is a terminal
+fn colors(choice: ColorChoice, no_color: Option<&std::ffi::OsStr>, tty: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => no_color.is_none_or(|v| v.is_empty()) && tty,
+    }
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// How the menus mark the selected item, `theme` in the config or `--theme`
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    /// Black on white, the other items faint
+    Default,
+    /// A `▶` and bold on top of the default's colors, nothing faint
+    Contrast,
+    /// A `▶` and bold without the colors, nothing faint, for light themes
+    Plain,
+}
+
+impl Theme {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "default" => Some(Self::Default),
+            "contrast" => Some(Self::Contrast),
+            "plain" => Some(Self::Plain),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Contrast => "contrast",
+            Self::Plain => "plain",
+        }
+    }
+}
+
+static THEME: AtomicU8 = AtomicU8::new(Theme::Default as u8);
+
+pub fn set_theme(theme: Theme) {
+    THEME.store(theme as u8, Ordering::Relaxed);
+}
+
+pub fn theme() -> Theme {
+    match THEME.load(Ordering::Relaxed) {
+        0 => Theme::Default,
+        1 => Theme::Contrast,
+        _ => Theme::Plain,
+    }
+}
+
+/// Marks the selected item without colors
+const MARKER: &str = "▶ ";
+
+/// Whether the selected item gets [`MARKER`]. Without colors it always does, or
+/// nothing would show which one it is
+fn marked() -> bool {
+    theme() != Theme::Default || !enabled()
+}
+
+/// Columns [`highlight`] adds in front of the item
+pub fn highlight_width() -> usize {
+    if marked() {
+        str_width(MARKER)
+    } else {
+        0
+    }
+}
+
+/// The selected item of a menu, as the theme marks it. Every menu draws it with this
+pub fn highlight(item: &str) -> String {
+    let marker = if marked() { MARKER } else { "" };
+    match theme() {
+        Theme::Default => format!("{marker}{item}").black().white_bg().to_string(),
+        Theme::Contrast => format!("{marker}{item}")
+            .bold()
+            .black()
+            .white_bg()
+            .to_string(),
+        Theme::Plain => format!("{marker}{item}").bold().to_string(),
+    }
+}
+
+/// Faint, unless the theme leaves it out for being hard to read
+fn faint_code() -> String {
+    if theme() == Theme::Default {
+        style::Faint.to_string()
+    } else {
+        String::new()
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// One of the 256 colors of the 8-bit palette
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn fg(self) -> String {
+        match self {
+            Self::Rgb(r, g, b) => color::Rgb(r, g, b).fg_string(),
+            c => color::AnsiValue(c.ansi()).fg_string(),
+        }
+    }
+
+    fn bg(self) -> String {
+        match self {
+            Self::Rgb(r, g, b) => color::Rgb(r, g, b).bg_string(),
+            c => color::AnsiValue(c.ansi()).bg_string(),
+        }
+    }
+
+    fn ansi(self) -> u8 {
+        match self {
+            Self::Black => 0,
+            Self::Red => 1,
+            Self::Green => 2,
+            Self::Yellow => 3,
+            Self::Blue => 4,
+            Self::Magenta => 5,
+            Self::Cyan => 6,
+            Self::White => 7,
+            Self::Ansi(n) => n,
+            Self::Rgb(..) => unreachable!("rgb has no palette index"),
+        }
+    }
+}
+
+/// `d` with the escape sequences in `code` applied. Styling a `Colored` again adds to
+/// its `code` instead of wrapping it, so chains end in a single reset
 pub struct Colored<D> {
     d: D,
-    code: &'static str,
+    code: String,
 }
 
+const RESET: &str = "\x1b[0m";
+
 impl<D: Display> Display for Colored<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.write_str(self.code)?;
-        self.d.fmt(f)?;
-        f.write_str("\x1b[0m")?;
-        Ok(())
+        self.paint(f, enabled())
     }
 }
 
-pub trait ToColored: Display + Sized {
-    fn faint(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: style::Faint.as_ref(),
+impl<D: Display> Colored<D> {
+    /// Writes `d`, with the escape sequences only when `colored`
+    fn paint(&self, f: &mut std::fmt::Formatter, colored: bool) -> std::fmt::Result {
+        if !colored {
+            return self.d.fmt(f);
+        }
+        f.write_str(&self.code)?;
+        // styled parts inside reset everything when they end, so bring ours back after them
+        let inner = self.d.to_string();
+        let mut parts = inner.split(RESET);
+        f.write_str(parts.next().unwrap_or_default())?;
+        for part in parts {
+            f.write_str(RESET)?;
+            f.write_str(&self.code)?;
+            f.write_str(part)?;
         }
+        f.write_str(RESET)?;
+        Ok(())
     }
+}
 
-    fn red(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Red.fg_str(),
+/// Defines every style both on `ToColored`, to start a chain from anything `Display`,
+/// and on `Colored`, to extend a chain
+macro_rules! styles {
+    ($($(#[$doc:meta])* $name:ident($($arg:ident: $ty:ty),*) => $code:expr;)*) => {
+        #[allow(dead_code)]
+        pub trait ToColored: Display + Sized {
+            $(
+                $(#[$doc])*
+                fn $name(&self, $($arg: $ty),*) -> Colored<&Self> {
+                    Colored {
+                        d: self,
+                        code: String::from($code),
+                    }
+                }
+            )*
         }
-    }
 
-    fn white_bg(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::White.bg_str(),
+        #[allow(dead_code)]
+        impl<D> Colored<D> {
+            $(
+                $(#[$doc])*
+                pub fn $name(mut self, $($arg: $ty),*) -> Self {
+                    self.code.push_str(&String::from($code));
+                    self
+                }
+            )*
         }
+    };
+}
+
+styles! {
+    faint() => faint_code();
+    bold() => style::Bold.to_string();
+    underline() => style::Underline.to_string();
+    invert() => style::Invert.to_string();
+    red() => color::Red.fg_str();
+    green() => color::Green.fg_str();
+    black() => color::Black.fg_str();
+    yellow() => color::Yellow.fg_str();
+    blue() => color::Blue.fg_str();
+    magenta() => color::Magenta.fg_str();
+    cyan() => color::Cyan.fg_str();
+    white() => color::White.fg_str();
+    white_bg() => color::White.bg_str();
+    fg(c: Color) => c.fg();
+    bg(c: Color) => c.bg();
+    /// Foreground from the 8-bit palette
+    color256(n: u8) => Color::Ansi(n).fg();
+    /// 24-bit foreground, for terminals with truecolor support
+    rgb(r: u8, g: u8, b: u8) => Color::Rgb(r, g, b).fg();
+}
+
+impl<D: Display> ToColored for D {}
+
+impl<D> std::ops::Deref for Colored<D> {
+    type Target = D;
+
+    fn deref(&self) -> &Self::Target {
+        &self.d
+    }
+}
+
+/// Columns `c` takes on the screen: 2 for the characters East Asian Width calls wide or
+/// fullwidth, emoji among them, 0 for combining marks and other zero-width characters
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x1160..=0x11FF
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0xE0000..=0xE0FFF => 0,
+        c if c < 0x20 || (0x7F..0xA0).contains(&c) => 0,
+        0x1100..=0x115F
+        | 0x231A..=0x231B
+        | 0x2329..=0x232A
+        | 0x23E9..=0x23EC
+        | 0x23F0
+        | 0x23F3
+        | 0x25FD..=0x25FE
+        | 0x2614..=0x2615
+        | 0x2648..=0x2653
+        | 0x267F
+        | 0x2693
+        | 0x26A1
+        | 0x26AA..=0x26AB
+        | 0x26BD..=0x26BE
+        | 0x26C4..=0x26C5
+        | 0x26CE
+        | 0x26D4
+        | 0x26EA
+        | 0x26F2..=0x26F3
+        | 0x26F5
+        | 0x26FA
+        | 0x26FD
+        | 0x2705
+        | 0x270A..=0x270B
+        | 0x2728
+        | 0x274C
+        | 0x274E
+        | 0x2753..=0x2755
+        | 0x2757
+        | 0x2795..=0x2797
+        | 0x27B0
+        | 0x27BF
+        | 0x2B1B..=0x2B1C
+        | 0x2B50
+        | 0x2B55
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x16FE0..=0x18CFF
+        | 0x1B000..=0x1B2FF
+        | 0x1F004
+        | 0x1F0CF
+        | 0x1F18E
+        | 0x1F191..=0x1F19A
+        | 0x1F200..=0x1F265
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F7E0..=0x1F7EB
+        | 0x1F90C..=0x1F9FF
+        | 0x1FA70..=0x1FAFF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Whether `c` is drawn as part of the character before it: zero-width characters,
+/// the emoji a ZWJ joins on and skin tones
+pub fn joins(prev: char, c: char) -> bool {
+    char_width(c) == 0 || prev == '\u{200D}' || (0x1F3FB..=0x1F3FF).contains(&(c as u32))
+}
+
+/// Columns `c` adds after `prev`: none when it [`joins`] it, but the emoji presentation
+/// selector widens a narrow symbol, as in ❤️
+pub fn char_width_after(prev: Option<char>, c: char) -> usize {
+    match prev {
+        Some(p) if c == '\u{FE0F}' => usize::from(char_width(p) == 1),
+        Some(p) if joins(p, c) => 0,
+        _ => char_width(c),
     }
+}
 
-    fn green(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Green.fg_str(),
+pub fn str_width(s: &str) -> usize {
+    let mut prev = None;
+    (s.chars())
+        .map(|c| {
+            let w = char_width_after(prev, c);
+            prev = Some(c);
+            w
+        })
+        .sum()
+}
+
+/// `s` without its CSI escape sequences
+pub fn strip_styles(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            out.push(c);
         }
     }
+    out
+}
+
+/// Columns `s` takes on the screen, ignoring CSI escape sequences
+pub fn display_width(s: &str) -> usize {
+    str_width(&strip_styles(s))
+}
 
-    fn black(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Black.fg_str(),
+/// Cuts `s` down to `width` columns, marking the cut with '…'.
+/// Escape sequences are kept and do not count towards the width
+pub fn truncate(s: &str, width: usize) -> Cow<'_, str> {
+    if display_width(s) <= width {
+        return Cow::Borrowed(s);
+    }
+    let mut t = String::new();
+    let mut w = 0;
+    let mut styled = false;
+    let mut prev = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            styled = true;
+            t.push(c);
+            if let Some(c) = chars.next() {
+                t.push(c);
+                if c == '[' {
+                    for c in chars.by_ref() {
+                        t.push(c);
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+            }
+            continue;
+        }
+        w += char_width_after(prev, c);
+        if w + 1 > width {
+            break;
         }
+        prev = Some(c);
+        t.push(c);
+    }
+    if width > 0 {
+        t.push('…');
     }
-    fn yellow(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Yellow.fg_str(),
+    if styled {
+        t.push_str("\x1b[0m");
+    }
+    Cow::Owned(t)
+}
+
+/// Cuts `s` down to `width` columns by taking out the middle, marked with '…', so both
+/// ends stay readable as in `com.goo…le.android.youtube`. Escape sequences are all
+/// kept, the cut ones too, so the styles after the cut are the ones `s` has there
+pub fn truncate_middle(s: &str, width: usize) -> Cow<'_, str> {
+    if display_width(s) <= width {
+        return Cow::Borrowed(s);
+    }
+    // the escape sequences and characters of `s` with their widths, what is drawn as
+    // part of a character kept with it so that a cut takes or leaves them together
+    let mut parts: Vec<(&str, usize)> = Vec::new();
+    let mut rest = s;
+    let mut prev = None;
+    let mut on_char = false;
+    while let Some(c) = rest.chars().next() {
+        let len = if c == '\x1b' {
+            let mut chars = rest.char_indices().skip(1);
+            match chars.next() {
+                Some((_, '[')) => chars
+                    .find(|(_, c)| ('@'..='~').contains(c))
+                    .map_or(rest.len(), |(i, c)| i + c.len_utf8()),
+                Some((i, c)) => i + c.len_utf8(),
+                None => rest.len(),
+            }
+        } else {
+            c.len_utf8()
+        };
+        let (part, tail) = rest.split_at(len);
+        if c == '\x1b' {
+            parts.push((part, 0));
+            on_char = false;
+        } else {
+            let w = char_width_after(prev, c);
+            let joined = prev.is_some_and(|p| c == '\u{FE0F}' || joins(p, c));
+            match parts.last_mut() {
+                Some((last, last_width)) if joined && on_char => {
+                    let start = s.len() - rest.len() - last.len();
+                    *last = &s[start..s.len() - tail.len()];
+                    *last_width += w;
+                }
+                _ => parts.push((part, w)),
+            }
+            prev = Some(c);
+            on_char = true;
         }
+        rest = tail;
     }
-    fn blue(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Black.fg_str(),
+    let keep = width.saturating_sub(1);
+    let (mut head, mut tail) = (keep - keep / 2, keep / 2);
+    let end = parts.len()
+        - parts
+            .iter()
+            .rev()
+            .take_while(|(_, w)| {
+                let fits = *w <= tail;
+                tail = tail.saturating_sub(*w);
+                fits
+            })
+            .count();
+    let mut t = String::with_capacity(s.len());
+    let mut cut = false;
+    for (i, (part, w)) in parts.into_iter().enumerate() {
+        // what is joined on a character that was cut goes with it
+        if part.starts_with('\x1b') || i >= end || (w == 0 && !cut) {
+            t.push_str(part);
+        } else if !cut && w <= head {
+            head -= w;
+            t.push_str(part);
+        } else if !cut {
+            cut = true;
+            if width > 0 {
+                t.push('…');
+            }
         }
     }
-    fn magenta(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Magenta.fg_str(),
+    Cow::Owned(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    /// A `Colored` drawn with colors on or off, whatever the other tests left them at
+    struct Painted<'a, D>(&'a Colored<D>, bool);
+
+    impl<D: Display> Display for Painted<'_, D> {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            self.0.paint(f, self.1)
         }
     }
-    fn cyan(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::Cyan.fg_str(),
+
+    #[test]
+    fn no_colors_leave_the_text_as_it_is() {
+        let green = "com.app1".green();
+        assert_eq!(Painted(&green, false).to_string(), "com.app1");
+        let chain = "com.app1".bold().red().white_bg();
+        assert_eq!(Painted(&chain, false).to_string(), "com.app1");
+        assert_eq!(
+            Painted(&green, true).to_string(),
+            format!("{}com.app1{RESET}", color::Green.fg_str())
+        );
+    }
+
+    #[test]
+    fn styles_inside_are_resumed_after_their_reset() {
+        let inner = format!("a{RESET}b");
+        let bold = inner.bold();
+        let code = style::Bold.to_string();
+        assert_eq!(
+            Painted(&bold, true).to_string(),
+            format!("{code}a{RESET}{code}b{RESET}")
+        );
+    }
+
+    #[test]
+    fn color_choice() {
+        let set = Some(OsStr::new("1"));
+        let empty = Some(OsStr::new(""));
+        assert!(colors(ColorChoice::Auto, None, true));
+        assert!(colors(ColorChoice::Auto, empty, true));
+        assert!(!colors(ColorChoice::Auto, set, true));
+        assert!(!colors(ColorChoice::Auto, None, false));
+        assert!(colors(ColorChoice::Always, set, false));
+        assert!(!colors(ColorChoice::Never, None, true));
+    }
+
+    const LONG: &str = "com.google.android.apps.youtube.music.premium";
+
+    #[test]
+    fn middle_cut_at_20_40_and_120_columns() {
+        assert_eq!(truncate_middle(LONG, 20), "com.google…c.premium");
+        assert_eq!(
+            truncate_middle(LONG, 40),
+            "com.google.android.a…utube.music.premium"
+        );
+        assert_eq!(truncate_middle(LONG, 120), LONG);
+        for width in [20, 40, 120] {
+            assert!(display_width(&truncate_middle(LONG, width)) <= width);
         }
     }
-    fn white(&self) -> Colored<&Self> {
-        Colored {
-            d: self,
-            code: color::White.fg_str(),
+
+    #[test]
+    fn middle_cut_keeps_the_styles() {
+        let styled = format!("\x1b[7m{LONG}\x1b[0m");
+        for width in [20, 40] {
+            let cut = truncate_middle(&styled, width);
+            assert!(cut.starts_with("\x1b[7m") && cut.ends_with("\x1b[0m"));
+            assert_eq!(strip_styles(&cut), truncate_middle(LONG, width));
         }
+        assert_eq!(truncate_middle(&styled, 120), styled);
     }
-}
 
-impl<D: Display> ToColored for D {}
+    #[test]
+    fn middle_cut_of_wide_text() {
+        let wide = "日本語のアプリケーション名前です";
+        let cut = truncate_middle(wide, 20);
+        // a column is left over, no half of a wide character is drawn in it
+        assert_eq!(cut, "日本語のア…名前です");
+        assert!(display_width(&cut) <= 20);
+        assert_eq!(truncate_middle(wide, 40), wide);
+    }
 
-impl<D> std::ops::Deref for Colored<D> {
-    type Target = D;
+    const FAMILY: &str = "👨\u{200D}👩\u{200D}👧";
+    const THUMB: &str = "👍\u{1F3FD}";
+    const HEART: &str = "❤\u{FE0F}";
 
-    fn deref(&self) -> &Self::Target {
-        &self.d
+    #[test]
+    fn widths() {
+        for (s, width) in [
+            ("com.app1", 8),
+            ("日本語", 6),
+            ("한글 앱", 7),
+            ("Ｆｕｌｌ", 8),
+            ("e\u{301}", 1),
+            ("👍", 2),
+            (THUMB, 2),
+            (FAMILY, 2),
+            ("👩\u{200D}🔬", 2),
+            ("❤", 1),
+            (HEART, 2),
+            ("⭐\u{FE0F}", 2),
+            ("❤\u{FE0F}\u{200D}🔥", 2),
+            ("ab\x1b[1m日本\x1b[0m", 6),
+        ] {
+            assert_eq!(display_width(s), width, "{s}");
+        }
+    }
+
+    #[test]
+    fn cuts_keep_joined_characters_whole() {
+        for (s, width, cut) in [
+            (format!("ab{FAMILY}cd"), 3, "ab…".to_string()),
+            (format!("{FAMILY}{FAMILY}"), 3, format!("{FAMILY}…")),
+            (format!("{THUMB}{THUMB}"), 3, format!("{THUMB}…")),
+            (format!("日本{HEART}語"), 5, "日本…".to_string()),
+        ] {
+            assert_eq!(truncate(&s, width), cut, "{s} at {width}");
+        }
+        for (s, width, cut) in [
+            (format!("ab{FAMILY}cd"), 5, "ab…cd".to_string()),
+            (format!("ab{FAMILY}cd"), 6, format!("ab{FAMILY}cd")),
+            (
+                format!("a{FAMILY}bcd{FAMILY}e"),
+                7,
+                format!("a{FAMILY}…{FAMILY}e"),
+            ),
+            (format!("abc{HEART}de{THUMB}"), 6, format!("abc…{THUMB}")),
+            (format!("abcd{HEART}"), 4, "ab…".to_string()),
+            (format!("{HEART}abcd{HEART}"), 5, format!("{HEART}…{HEART}")),
+            ("日本語のアプリ".to_string(), 9, "日本…プリ".to_string()),
+        ] {
+            let middle = truncate_middle(&s, width);
+            assert_eq!(middle, cut, "{s} at {width}");
+            assert!(display_width(&middle) <= width, "{s} at {width}");
+        }
     }
 }
@@ -0,0 +1,1406 @@
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use detach_core::log;
+
+use crate::checks::{self, Check};
+use crate::colorize::ToColored;
+use crate::config::{self, Config, Mode, CONFIG, KEEP_TXT};
+use crate::device::{self, DeviceFile};
+use crate::diff;
+use crate::error::{self, Exit};
+use crate::external;
+use crate::journal;
+use crate::json::Object;
+use crate::meta;
+use crate::profiles;
+use crate::readback;
+use crate::rules::{self, Rules, RULES_TXT};
+use crate::{
+    apply_rules, compare_apps, detach_all, detach_bin, detach_bin_changed, expand_rules,
+    export_txt, find_orphans, get_detached_apps, get_installed_apps, get_third_party_apps,
+    import_diff, import_txt, installed_anywhere, new_profile, parse_installed_apps,
+    read_detach_bin, reattach_all, rebuild_detach_bin, remove_entries, rule_entries,
+    switch_profile, sync_whitelist, verify_detach_bin, write_detach_bin, CLIResult, EXPORT_TXT,
+    MODULE_CAP,
+};
+
+pub fn usage() -> String {
+    format!(
+        "\
+Usage: detach [COMMAND]
+
+Runs the interactive menu when no command is given.
+
+Commands:
+  detach <package>... [--yes]
+                         detach the given apps, misspelled names get
+                         suggestions and the closest is offered unless --yes,
+                         - reads the names from stdin, one per line
+  detach --apk <file.apk>...
+                         detach the apps of APK files, before they are
+                         installed too
+  detach --all [--system | --no-system]
+                         detach every third-party app, or every app with --system
+                         (also system = true in the config)
+  reattach <package>... [--yes]
+                         re-attach the given apps, - reads them from stdin
+  reattach --all         re-attach every app
+  list [--json | --table]
+                         print the detached apps, as a table of their labels,
+                         installers and detach dates on a terminal
+  status [--json]        print the module, mode and detach.bin details
+  status <package> [--json]
+                         print whether the app is detached, attached or not
+                         installed, exiting with 0, 8 or 5. Tried without
+                         root first
+  export [file]          write the detached apps to a text file, one per line
+                         (default: {EXPORT_TXT})
+  import [--replace] [--diff] [file]
+                         detach the apps listed in a text file, replacing the
+                         current list with --replace, --diff only prints
+                         what would change
+  prune [--dry-run] [--yes]
+                         remove the apps that are no longer installed,
+                         --dry-run only prints them
+  compare [--json]       print the detached apps that are installed, the ones
+                         that are not and the Play Store apps not detached
+  reset [--yes] [--clear-cache]
+                         back up detach.bin, then remove it, the module's
+                         detach.txt and the sdcard copy, and restart the store,
+                         --clear-cache empties the Play Store cache too
+  inspect [--hex]        print every entry of detach.bin with its offset and length
+                         byte, flagging the ones the module would misread,
+                         --hex dumps the bytes too
+  watch [--auto] [--interval <secs>] [--rules <file>]
+                         report newly installed apps and detach those matching
+                         the rules (default: {RULES_TXT}), or all with --auto
+  doctor [--fix]         check that the module is enabled and zygisk is on, that
+                         detach.bin is what the cli wrote last and has no
+                         corrupted entries, --fix rebuilds it
+                         from the intact ones and keeps detach.bin.corrupt
+                         (alias: verify)
+  mode [whitelist|blacklist] [--yes]
+                         print or switch the mode, in whitelist mode every app
+                         but the ones in {KEEP_TXT} is detached
+  sync                   whitelist mode: detach every app not on the keep-list
+  profile [use <name> | new <name> | rename <old> <new> | delete <name>]
+                         list or switch between named detach lists, switching
+                         replaces detach.bin with the profile's apps
+  history [<count>]      print the last changes to the detached apps, newest
+                         first, with the command that made them (default: 20)
+  undo                   revert the last change to the detached apps
+  redo                   make the last undone change again
+  rules [add <pattern>... | remove <pattern> [--reattach]]
+                         list, add or remove the patterns in {RULES_TXT},
+                         installed apps matching them are detached on every
+                         detach, import, sync and in the menus
+  config                 print the settings in effect and where each one is set:
+                         the config files, a flag or the default
+  --serialize <detach.txt> <detach.bin>
+
+Options (also set in {CONFIG}, flags win over it):
+  --color=<auto|always|never>, --no-color
+  --theme=<default|contrast|plain>
+                         how the menus mark the selected item: black on white,
+                         also with a ▶ and bold, or the ▶ and bold alone on
+                         light themes. contrast and plain draw nothing faint
+  --adb[=<serial>], -s <serial>
+                         work on a phone connected with adb
+  --shizuku             work without root through Shizuku's rish, or adb shell
+                         with --adb: apps are listed and changes previewed,
+                         detach.bin needs root to be read or written
+  --no-root-check        do not restart through su when not run as root
+  --no-restart           leave the Play Store running after changing detach.bin
+  --wizard               go through the first-run steps again before the menus
+  --force                detach the packages the Play Store needs, as itself
+                         and Play Services, which is refused without it
+  --dry-run              show what would be written instead of changing anything
+                         (also dry_run = true in the config)
+  --plain                numbered lists and typed answers instead of the menus,
+                         used without a terminal (adb shell without -t)
+  --no-altscreen         draw the menus below the shell prompt instead of on the
+                         alternate screen, for terminals without one
+  --timeout <secs>       kill commands such as pm that run longer (default: 15,
+                         0 for no limit), they hang while the phone is booting
+  --module-dir <dir>     where the module lives when it is not detected
+                         (also ZYGISK_DETACH_MODULE_DIR)
+  --file <path>          list, inspect, import or export another detach.bin,
+                         leaving the phone alone (ZYGISK_DETACH_FILE moves
+                         the one in use instead)
+  -q, --quiet            print nothing but errors, for scripts going by the exit code
+  -v, --verbose          print every command run and the files read and written,
+                         -vv adds how long each took. The menus log these to
+                         the file `doctor` names instead
+  --user <id|all>        list the packages of another user or work profile,
+                         or of all of them (see `pm list users`)
+
+Exit status:
+  0 success, 1 error, 2 bad usage, 3 no root, 4 module not found,
+  5 package not found, 6 nothing to do (already detached, nothing to undo, ...),
+  7 a command timed out, worth trying again, 8 attached (status <package>),
+  130 the menus were left with Ctrl+C"
+    )
+}
+
+fn print_stopped(pkgs: &[&str]) {
+    if !pkgs.is_empty() {
+        println!("stopped: {}", pkgs.join(", "));
+    }
+}
+
+/// Detaches the apps matching the rules ahead of the commands that change the list
+fn print_rules_applied() -> CLIResult<()> {
+    for app in apply_rules()? {
+        println!("rules: detach: {app}");
+    }
+    Ok(())
+}
+
+pub fn run(cmd: &str, args: impl Iterator<Item = String>) -> ExitCode {
+    let args: Vec<String> = args.collect();
+    let _op = crate::history::operation(cmd);
+    // from reading the list to writing it, watch takes it for each change only
+    let locked = match cmd {
+        "detach" | "reattach" | "import" | "prune" | "reset" | "sync" | "mode" | "rules"
+        | "profile" | "undo" | "redo"
+            if !crate::dry_run::enabled() =>
+        {
+            detach_core::instance::lock()
+                .map(Some)
+                .map_err(|e| error::Error::Busy(e).into())
+        }
+        _ => Ok(None),
+    };
+    let applied = locked.and_then(|lock| {
+        match cmd {
+            "detach" | "import" | "sync" => print_rules_applied()?,
+            _ => {}
+        }
+        Ok(lock)
+    });
+    let ret = applied.and_then(|_lock| match cmd {
+        "detach" => detach(&args),
+        "reattach" => reattach(&args),
+        "list" => list(&args),
+        "status" => status(&args),
+        "export" => export(&args),
+        "import" => import(&args),
+        "prune" => prune(&args),
+        "compare" => compare(&args),
+        "inspect" => inspect(&args),
+        "reset" => reset(&args),
+        "watch" => watch(&args),
+        "doctor" | "verify" => doctor(&args),
+        "sync" => sync(&args),
+        "mode" => mode(&args),
+        "rules" => rules(&args),
+        "profile" => profile(&args),
+        "undo" => undo(&args, false),
+        "redo" => undo(&args, true),
+        "config" => show_config(&args),
+        "history" => history(&args),
+        _ => unreachable!("unknown command {cmd}"),
+    });
+    let ret = ret.and_then(|code| {
+        if crate::take_restart_pending() {
+            print_stopped(&crate::restart_store()?);
+        }
+        Ok(code)
+    });
+    for line in crate::dry_run::take_log() {
+        println!("dry run: {line}");
+    }
+    match ret {
+        Ok(code) => code,
+        Err(err) if args.iter().any(|a| a == "--json") => {
+            let doc = Object::document()
+                .field("error", err.to_string())
+                .field("code", err.code());
+            eprintln!("{doc}");
+            err.exit_code()
+        }
+        Err(err) => {
+            eprintln!("ERROR: {err}");
+            err.exit_code()
+        }
+    }
+}
+
+fn detach(pkgs: &[String]) -> CLIResult<ExitCode> {
+    if pkgs.first().is_some_and(|a| a == "--all") {
+        let system = match &pkgs[1..] {
+            [] => Config::load()?.system,
+            [a] if a == "--system" => true,
+            [a] if a == "--no-system" => false,
+            _ => {
+                eprintln!("Usage: detach --all [--system | --no-system]");
+                return Ok(Exit::Usage.into());
+            }
+        };
+        let res = detach_all(system)?;
+        for app in &res.skipped {
+            eprintln!("skipped: {app} cannot be stored in detach.bin");
+        }
+        println!("detached {} apps", res.added);
+        if res.size > MODULE_CAP {
+            eprintln!(
+                "warning: detach.bin is {} bytes, the module only loads up to {MODULE_CAP}",
+                res.size
+            );
+        }
+        if let Some(stopped) = &res.stopped {
+            eprintln!("{stopped}");
+            return Ok(Exit::Interrupted.into());
+        }
+        return Ok(changed(res.added > 0));
+    }
+    if pkgs.first().is_some_and(|a| a == "--apk") {
+        return detach_apks(&pkgs[1..]);
+    }
+    let (yes, pkgs) = take_yes(pkgs);
+    let pkgs = stdin_names(pkgs)?;
+    if pkgs.is_empty() {
+        eprintln!("No package names supplied.");
+        return Ok(Exit::Usage.into());
+    }
+    let installed_apps = get_installed_apps()?;
+    let installed: Vec<&str> = parse_installed_apps(&installed_apps).collect();
+    let mut content = read_detach_bin()?;
+    let mut detached = get_detached_apps(&content)?;
+
+    let mut failed = false;
+    let mut not_found = false;
+    let old_len = content.len();
+    for pkg in &pkgs {
+        let pkg = if installed.contains(&pkg.as_str()) {
+            pkg.clone()
+        } else if let Some(pkg) = did_you_mean(pkg, "is not installed", &installed, yes)? {
+            pkg
+        } else {
+            not_found = true;
+            continue;
+        };
+        let pkg = &pkg;
+        if !detach_bin::encodable(pkg) {
+            eprintln!("{pkg} cannot be stored in detach.bin");
+            failed = true;
+        } else if detached.iter().any(|(s, _)| s == pkg) {
+            println!("already detached: {pkg}");
+        } else {
+            detach_bin::serialize(pkg, &mut content)?;
+            detached.push((pkg.clone(), 0..0));
+            println!("detach: {pkg}");
+        }
+    }
+    let changes = content.len() != old_len;
+    if changes {
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+    }
+    Ok(outcome(failed, not_found, changes))
+}
+
+/// Detaches the apps of APK files ahead of installing them, so they are never attached
+fn detach_apks(files: &[String]) -> CLIResult<ExitCode> {
+    if files.is_empty() {
+        eprintln!("Usage: detach --apk <file.apk>...");
+        return Ok(Exit::Usage.into());
+    }
+    let mut content = read_detach_bin()?;
+    let mut detached = get_detached_apps(&content)?;
+    let old_len = content.len();
+    let mut failed = false;
+    for file in files {
+        let pkg = match std::fs::read(file).and_then(|apk| detach_core::apk::package_name(&apk)) {
+            Ok(pkg) => pkg,
+            Err(e) => {
+                eprintln!("{file}: {e}");
+                failed = true;
+                continue;
+            }
+        };
+        if !detach_bin::encodable(&pkg) {
+            eprintln!("{file}: {pkg} cannot be stored in detach.bin");
+            failed = true;
+        } else if detached.iter().any(|(s, _)| *s == pkg) {
+            println!("already detached: {pkg} ({file})");
+        } else {
+            detach_bin::serialize(&pkg, &mut content)?;
+            println!("detach: {pkg} ({file})");
+            detached.push((pkg, 0..0));
+        }
+    }
+    let changes = content.len() != old_len;
+    if changes {
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+    }
+    Ok(outcome(failed, false, changes))
+}
+
+/// The exit status of a command that went through several names: any that failed
+/// over any that were not found, over whether something changed
+fn outcome(failed: bool, not_found: bool, changes: bool) -> ExitCode {
+    if failed {
+        Exit::Failure.into()
+    } else if not_found {
+        Exit::NotFound.into()
+    } else {
+        changed(changes)
+    }
+}
+
+/// Success, or nothing to do when nothing was `changes`
+fn changed(changes: bool) -> ExitCode {
+    if changes {
+        Exit::Success.into()
+    } else {
+        Exit::Unchanged.into()
+    }
+}
+
+/// Splits `-y`/`--yes` off the package names
+fn take_yes(args: &[String]) -> (bool, Vec<String>) {
+    let yes = args.iter().any(|a| a == "-y" || a == "--yes");
+    let pkgs = args
+        .iter()
+        .filter(|a| *a != "-y" && *a != "--yes")
+        .cloned()
+        .collect();
+    (yes, pkgs)
+}
+
+/// Replaces a `-` among the package names with the ones read from stdin, one per line
+fn stdin_names(pkgs: Vec<String>) -> io::Result<Vec<String>> {
+    let mut names = Vec::with_capacity(pkgs.len());
+    for pkg in pkgs {
+        if pkg != "-" {
+            names.push(pkg);
+            continue;
+        }
+        for line in io::stdin().lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() {
+                names.push(line.to_string());
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Reports that `pkg` is not among `known` along with the names close to it, and asks
+/// whether the closest was meant unless `yes` is given. Returns it when it was
+fn did_you_mean(pkg: &str, problem: &str, known: &[&str], yes: bool) -> CLIResult<Option<String>> {
+    eprintln!("{pkg} {problem}");
+    let similar = crate::suggest::similar(pkg, known);
+    let Some(closest) = similar.first() else {
+        return Ok(None);
+    };
+    eprintln!("did you mean {}?", similar.join(", "));
+    if yes {
+        return Ok(None);
+    }
+    Ok(match ask(&format!("Use {closest} instead?"))? {
+        Some(true) => Some(closest.to_string()),
+        _ => None,
+    })
+}
+
+fn reattach(pkgs: &[String]) -> CLIResult<ExitCode> {
+    if let [a] = pkgs {
+        if a == "--all" {
+            let n = reattach_all()?;
+            println!("re-attached {n} apps");
+            return Ok(changed(n > 0));
+        }
+    }
+    let (yes, pkgs) = take_yes(pkgs);
+    let pkgs = stdin_names(pkgs)?;
+    if pkgs.is_empty() {
+        eprintln!("No package names supplied.");
+        return Ok(Exit::Usage.into());
+    }
+    let content = read_detach_bin()?;
+    let detached = get_detached_apps(&content)?;
+    let names: Vec<&str> = detached.iter().map(|(s, _)| s.as_str()).collect();
+    let rules = Rules::load(RULES_TXT)?;
+
+    let mut not_found = false;
+    let mut ranges = Vec::new();
+    for pkg in &pkgs {
+        let pkg = if names.contains(&pkg.as_str()) {
+            pkg.clone()
+        } else if let Some(pkg) = did_you_mean(pkg, "is not detached", &names, yes)? {
+            pkg
+        } else {
+            not_found = true;
+            continue;
+        };
+        let pkg = &pkg;
+        match detached.iter().find(|(s, _)| s == pkg) {
+            Some((_, range)) if !ranges.contains(range) => {
+                ranges.push(range.clone());
+                println!("re-attach: {pkg}");
+                if let Some(rule) = rules.rule_for(pkg) {
+                    eprintln!(
+                        "warning: {pkg} matches {rule} in {RULES_TXT} and will be detached again"
+                    );
+                }
+            }
+            Some(_) => {}
+            None => {
+                eprintln!("{pkg} is not detached");
+                not_found = true;
+            }
+        }
+    }
+    let changes = !ranges.is_empty();
+    remove_entries(content, ranges)?;
+    Ok(outcome(false, not_found, changes))
+}
+
+/// Whether the arguments are exactly `--json`, `None` for anything else
+fn json_flag(cmd: &str, args: &[String]) -> Option<bool> {
+    match args {
+        [] => Some(false),
+        [a] if a == "--json" => Some(true),
+        _ => {
+            eprintln!("Usage: {cmd} [--json]");
+            None
+        }
+    }
+}
+
+fn list(args: &[String]) -> CLIResult<ExitCode> {
+    let (json, table) = match args {
+        [] => (false, termion::is_tty(&io::stdout())),
+        [a] if a == "--json" => (true, false),
+        [a] if a == "--table" => (false, true),
+        _ => {
+            eprintln!("Usage: list [--json | --table]");
+            return Ok(Exit::Usage.into());
+        }
+    };
+    let content = read_detach_bin()?;
+    let detached = get_detached_apps(&content)?;
+    if table {
+        let apps: Vec<&str> = detached.iter().map(|(name, _)| name.as_str()).collect();
+        let ((cols, _), _) = crate::menus::screen_size();
+        let width = cols as usize;
+        for line in crate::app_table(&apps)?.render(width) {
+            println!("{line}");
+        }
+        return Ok(Exit::Success.into());
+    }
+    if !json {
+        for (name, _) in detached {
+            println!("{name}");
+        }
+        return Ok(Exit::Success.into());
+    }
+    let installed = installed_anywhere()?;
+    let times = meta::detached_times();
+    let metas = meta::load().unwrap_or_default();
+    let apps: Vec<Object> = detached
+        .iter()
+        .map(|(name, _)| {
+            Object::default()
+                .field("package", name)
+                .field("installed", installed.contains(name))
+                .field("added_at", times.get(name).copied())
+                .field("note", metas.get(name).and_then(|m| m.note.as_deref()))
+        })
+        .collect();
+    println!("{}", Object::document().field("apps", apps));
+    Ok(Exit::Success.into())
+}
+
+/// Commands that are tried without root first, `status <package>` reads a detach.bin
+/// anyone may read
+#[cfg(target_os = "android")]
+pub fn without_root(args: &[String]) -> bool {
+    matches!(args, [cmd, pkg, ..] if cmd == "status" && !pkg.starts_with('-'))
+}
+
+fn status(args: &[String]) -> CLIResult<ExitCode> {
+    if let Some(pkg) = args.first().filter(|a| !a.starts_with('-')) {
+        return status_package(pkg, &args[1..]);
+    }
+    let Some(json) = json_flag("status", args) else {
+        return Ok(Exit::Usage.into());
+    };
+    let device = device::get();
+    let env = device.environment().map_err(error::Error::detach_file)?;
+    let path = device
+        .path(DeviceFile::DetachBin)
+        .map_err(error::Error::detach_file)?;
+    let meta = match device.metadata(DeviceFile::DetachBin) {
+        Ok(meta) => Some(meta),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(error::Error::detach_file(e).into()),
+    };
+    let entries = get_detached_apps(&read_detach_bin()?)?.len();
+    let config = Config::load()?;
+    let (mode, profile) = (config.mode, config.profile);
+    if json {
+        let detach_bin = Object::default()
+            .field("path", &path)
+            .field("exists", meta.is_some())
+            .field("size", meta.as_ref().map_or(0, |m| m.size))
+            .field("modified", meta.as_ref().map(|m| m.modified))
+            .field("entries", entries);
+        let doc = Object::document()
+            .field("root", env.root.to_string())
+            .field("module_dir", &env.module_dir)
+            .field("mode", mode.name())
+            .field("profile", &profile)
+            .field("detach_bin", detach_bin);
+        println!("{doc}");
+        return Ok(Exit::Success.into());
+    }
+    println!("module:     {} in {}", env.root, env.module_dir);
+    println!("mode:       {}", mode.name());
+    println!("profile:    {profile}");
+    match meta {
+        Some(meta) => println!(
+            "detach.bin: {entries} apps, {} bytes, changed {} UTC",
+            meta.size,
+            detach_core::time::display_time(meta.modified)
+        ),
+        None => println!("detach.bin: not created yet"),
+    }
+    Ok(Exit::Success.into())
+}
+
+/// Whether one app is detached, for scripts that go by the exit code
+fn status_package(pkg: &str, args: &[String]) -> CLIResult<ExitCode> {
+    let Some(json) = json_flag("status <package>", args) else {
+        return Ok(Exit::Usage.into());
+    };
+    let content = match device::get().read(DeviceFile::DetachBin) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        // not readable without root after all, so the cli is run again through su
+        #[cfg(target_os = "android")]
+        Err(e) if unsafe { libc::geteuid() } != 0 => {
+            if let Err(code) = crate::ensure_root() {
+                return Ok(code);
+            }
+            return Err(error::Error::detach_file(e).into());
+        }
+        Err(e) => return Err(error::Error::detach_file(e).into()),
+    };
+    let detached = get_detached_apps(&content)?
+        .iter()
+        .any(|(name, _)| name == pkg);
+    let listed = crate::list_packages(&["-i"])?;
+    let installer = detach_core::parse_installers(&listed).find(|&(name, _)| name == pkg);
+    let (word, exit) = match (detached, installer.is_some()) {
+        (true, _) => ("detached", Exit::Success),
+        (false, true) => ("attached", Exit::Attached),
+        (false, false) => ("not installed", Exit::NotFound),
+    };
+    if json {
+        let doc = Object::document()
+            .field("package", pkg)
+            .field("detached", detached)
+            .field("installed", installer.is_some())
+            .field("installer", installer.and_then(|(_, i)| i));
+        println!("{doc}");
+    } else {
+        println!("{word}");
+    }
+    Ok(exit.into())
+}
+
+fn export(args: &[String]) -> CLIResult<ExitCode> {
+    let path = match args {
+        [] => EXPORT_TXT,
+        [path] => path,
+        _ => {
+            eprintln!("Usage: export [file]");
+            return Ok(Exit::Usage.into());
+        }
+    };
+    println!("exported {} apps to {path}", export_txt(path)?);
+    Ok(Exit::Success.into())
+}
+
+fn import(args: &[String]) -> CLIResult<ExitCode> {
+    let mut replace = false;
+    let mut show_diff = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--replace" => replace = true,
+            "--diff" => show_diff = true,
+            _ => paths.push(arg.as_str()),
+        }
+    }
+    let path = match paths[..] {
+        [] => EXPORT_TXT,
+        [path] => path,
+        _ => {
+            eprintln!("Usage: import [--replace] [--diff] [file]");
+            return Ok(Exit::Usage.into());
+        }
+    };
+    if show_diff {
+        let (lines, skipped) = import_diff(path, replace)?;
+        for line in &lines {
+            println!("{line}");
+        }
+        for app in &skipped {
+            eprintln!("skipped: {app} cannot be stored in detach.bin");
+        }
+        println!("{}", diff::summary(&lines));
+        return Ok(Exit::Success.into());
+    }
+    let res = import_txt(path, replace)?;
+    for app in &res.skipped {
+        eprintln!("skipped: {app} cannot be stored in detach.bin");
+    }
+    println!("{} added, {} already present", res.added, res.present);
+    if let Some(stopped) = &res.stopped {
+        eprintln!("{stopped}");
+        return Ok(Exit::Interrupted.into());
+    }
+    Ok(changed(res.added > 0 || replace))
+}
+
+/// Asks a yes/no `question` on the terminal, read from /dev/tty when stdin is piped.
+/// `None` when there is no terminal to ask on
+fn ask(question: &str) -> CLIResult<Option<bool>> {
+    let answer = answer(&format!("{question} [y/N] "))?;
+    Ok(answer.map(|a| matches!(a.trim(), "y" | "Y" | "yes")))
+}
+
+/// The line typed after `prompt` on the terminal, as for [`ask`]
+fn answer(prompt: &str) -> CLIResult<Option<String>> {
+    let mut answer = String::new();
+    // the prompt would not be seen
+    if log::level() == log::Level::Quiet {
+        return Ok(None);
+    }
+    if termion::is_tty(&io::stdin()) {
+        print!("{prompt}");
+        io::stdout().flush()?;
+        io::stdin().read_line(&mut answer)?;
+    } else if let Ok(tty) = termion::get_tty() {
+        print!("{prompt}");
+        io::stdout().flush()?;
+        io::BufReader::new(tty).read_line(&mut answer)?;
+    } else {
+        return Ok(None);
+    }
+    Ok(Some(answer))
+}
+
+fn reset(args: &[String]) -> CLIResult<ExitCode> {
+    let mut yes = false;
+    let mut clear_cache = false;
+    for arg in args {
+        match arg.as_str() {
+            "-y" | "--yes" => yes = true,
+            "--clear-cache" => clear_cache = true,
+            _ => {
+                eprintln!("Usage: reset [--yes] [--clear-cache]");
+                return Ok(Exit::Usage.into());
+            }
+        }
+    }
+    let n = get_detached_apps(&read_detach_bin()?)?.len();
+    println!("reset: {n} apps will be reattached and updated again");
+    if !yes {
+        match answer("Type yes to reset: ")? {
+            Some(a) if a.trim() == "yes" => {}
+            Some(_) => return Ok(Exit::Unchanged.into()),
+            None => {
+                eprintln!("Pass --yes to reset without a prompt.");
+                return Ok(Exit::Usage.into());
+            }
+        }
+    }
+    let mut failed = false;
+    for step in crate::reset(clear_cache)? {
+        match step.outcome {
+            Ok(done) => println!("{} {}: {done}", "ok".green(), step.what),
+            Err(e) => {
+                failed = true;
+                println!("{} {}: {e}", "failed".red(), step.what);
+            }
+        }
+    }
+    Ok(if failed {
+        Exit::Failure.into()
+    } else {
+        Exit::Success.into()
+    })
+}
+
+fn compare(args: &[String]) -> CLIResult<ExitCode> {
+    let Some(json) = json_flag("compare", args) else {
+        return Ok(Exit::Usage.into());
+    };
+    let cmp = compare_apps()?;
+    if json {
+        let doc = Object::document()
+            .field("installed", &cmp.installed)
+            .field("missing", &cmp.missing)
+            .field("not_detached", &cmp.undetached);
+        println!("{doc}");
+        return Ok(Exit::Success.into());
+    }
+    let groups = [
+        (&cmp.installed, "detached and installed".green()),
+        (
+            &cmp.missing,
+            "detached but not installed, prune removes them".red(),
+        ),
+        (
+            &cmp.undetached,
+            "from the Play Store and not detached".yellow(),
+        ),
+    ];
+    for (apps, heading) in groups {
+        println!("{} ({})", heading.bold(), apps.len());
+        for app in apps {
+            println!("  {app}");
+        }
+    }
+    Ok(Exit::Success.into())
+}
+
+/// Bytes on each line of `inspect --hex`
+const HEX_LINE: usize = 16;
+
+fn inspect(args: &[String]) -> CLIResult<ExitCode> {
+    let hex = match args {
+        [] => false,
+        [a] if a == "--hex" => true,
+        _ => {
+            eprintln!("Usage: inspect [--hex]");
+            return Ok(Exit::Usage.into());
+        }
+    };
+    let content = read_detach_bin()?;
+    let entries = detach_bin::inspect(&content);
+    let bad = (entries.iter())
+        .filter(|e| e.problems.iter().any(|p| p.malformed()))
+        .count();
+    let duplicates = (entries.iter())
+        .filter(|e| e.problems.iter().any(|p| !p.malformed()))
+        .count();
+    println!(
+        "{}: {} bytes, {} entries, {}{}",
+        device::get().path(DeviceFile::DetachBin)?,
+        content.len(),
+        entries.len(),
+        match bad {
+            0 => "all well formed".green().to_string(),
+            n => format!("{n} malformed").red().to_string(),
+        },
+        match duplicates {
+            0 => String::new(),
+            n => format!(", {n} duplicated").yellow().to_string(),
+        }
+    );
+    let problems = |entry: &detach_bin::Inspected| {
+        (entry.problems.iter())
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if hex {
+        for entry in &entries {
+            let note = match problems(entry) {
+                p if p.is_empty() => entry.name.clone(),
+                p => format!("{} {}", entry.name, p.red()),
+            };
+            println!(
+                "{:08x}  {:02x}{:pad$}  length {}: {note}",
+                entry.offset,
+                entry.declared,
+                "",
+                entry.declared,
+                pad = HEX_LINE * 3 - 3
+            );
+            let bytes = &content[entry.bytes.clone()];
+            for (i, line) in bytes.chunks(HEX_LINE).enumerate() {
+                let dump: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+                let text: String = (line.iter())
+                    .map(|&b| if b.is_ascii_graphic() { b as char } else { '.' })
+                    .collect();
+                println!(
+                    "{:08x}  {:<width$}  {}",
+                    entry.bytes.start + i * HEX_LINE,
+                    dump.join(" "),
+                    text.faint(),
+                    width = HEX_LINE * 3 - 1
+                );
+            }
+        }
+        return Ok(if bad > 0 {
+            Exit::Failure
+        } else {
+            Exit::Success
+        }
+        .into());
+    }
+    let mut table = crate::table::Table::new(&["OFFSET", "LENGTH", "NAME", "PROBLEM"]);
+    for entry in &entries {
+        table.row([
+            entry.offset.to_string(),
+            entry.declared.to_string(),
+            entry.name.clone(),
+            problems(entry).red().to_string(),
+        ]);
+    }
+    let ((cols, _), _) = crate::menus::screen_size();
+    let width = cols as usize;
+    for line in table.render(width) {
+        println!("{line}");
+    }
+    Ok(if bad > 0 {
+        Exit::Failure
+    } else {
+        Exit::Success
+    }
+    .into())
+}
+
+fn prune(args: &[String]) -> CLIResult<ExitCode> {
+    let mut dry_run = crate::dry_run::enabled();
+    let mut yes = false;
+    for arg in args {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "-y" | "--yes" => yes = true,
+            _ => {
+                eprintln!("Usage: prune [--dry-run] [--yes]");
+                return Ok(Exit::Usage.into());
+            }
+        }
+    }
+    let crate::Orphans {
+        content,
+        orphans,
+        stopped,
+    } = find_orphans()?;
+    for (name, _) in &orphans {
+        println!("not installed: {name}");
+    }
+    if let Some(stopped) = &stopped {
+        eprintln!("{stopped}, pruning the ones found so far");
+    }
+    if orphans.is_empty() || dry_run {
+        println!("{} apps to prune", orphans.len());
+        return Ok(changed(!orphans.is_empty()));
+    }
+    if !yes {
+        match ask(&format!("Remove {} entries?", orphans.len()))? {
+            Some(true) => {}
+            Some(false) => return Ok(Exit::Unchanged.into()),
+            None => {
+                eprintln!("Pass --yes to prune without a prompt.");
+                return Ok(Exit::Usage.into());
+            }
+        }
+    }
+    let n = orphans.len();
+    crate::backup::snapshot()?;
+    remove_entries(content, orphans.into_iter().map(|(_, r)| r).collect())?;
+    println!("pruned {n} apps");
+    Ok(Exit::Success.into())
+}
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+/// Sleeps for `d`, returns false if Ctrl+C was pressed meanwhile
+fn sleep_unless_stopped(d: Duration) -> bool {
+    let end = Instant::now() + d;
+    while !STOP.load(Ordering::Relaxed) {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        std::thread::sleep(left.min(Duration::from_millis(200)));
+    }
+    false
+}
+
+fn watch(args: &[String]) -> CLIResult<ExitCode> {
+    const USAGE: &str = "Usage: watch [--auto] [--interval <secs>] [--rules <file>]";
+    let mut auto = false;
+    let mut interval = 5;
+    let mut rules_path = RULES_TXT.to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--auto" => auto = true,
+            "--interval" => match args.next().and_then(|a| a.parse().ok()) {
+                Some(secs) if secs > 0 => interval = secs,
+                _ => {
+                    eprintln!("{USAGE}");
+                    return Ok(Exit::Usage.into());
+                }
+            },
+            "--rules" => match args.next() {
+                Some(path) => rules_path = path.clone(),
+                None => {
+                    eprintln!("{USAGE}");
+                    return Ok(Exit::Usage.into());
+                }
+            },
+            _ => {
+                eprintln!("{USAGE}");
+                return Ok(Exit::Usage.into());
+            }
+        }
+    }
+    let rules = Rules::load(&rules_path)?;
+    let whitelist = Config::load()?.mode == Mode::Whitelist;
+    unsafe {
+        let mut sa: libc::sigaction = std::mem::zeroed();
+        sa.sa_sigaction = on_interrupt as *const () as libc::sighandler_t;
+        libc::sigaction(libc::SIGINT, &sa, std::ptr::null_mut());
+    }
+
+    let third_party = get_third_party_apps()?;
+    let mut known: Vec<String> = parse_installed_apps(&third_party)
+        .map(str::to_string)
+        .collect();
+    if !auto {
+        // apps installed before the watch started
+        for app in expand_rules(&rules, &third_party)? {
+            println!("detach: {app}");
+        }
+    }
+    match (auto, rules.is_empty()) {
+        _ if whitelist => println!("watching for new apps, detaching all not in {KEEP_TXT}"),
+        (true, _) => println!("watching for new apps, detaching all of them"),
+        (false, true) => println!("watching for new apps, no rules in {rules_path}"),
+        (false, false) => println!("watching for new apps, detaching by {rules_path}"),
+    }
+    println!("press Ctrl+C to stop");
+    let mut detached_count = 0;
+    while sleep_unless_stopped(Duration::from_secs(interval)) {
+        if external::poll() {
+            println!("detach.bin was changed by another program, reading it again");
+            read_detach_bin()?;
+        }
+        let third_party = get_third_party_apps()?;
+        let new: Vec<&str> = parse_installed_apps(&third_party)
+            .filter(|app| !known.iter().any(|k| k == app))
+            .collect();
+        if new.is_empty() {
+            continue;
+        }
+        // read again for every batch, the file may have changed since the last one
+        let mut content = read_detach_bin()?;
+        let detached = get_detached_apps(&content)?;
+        let old_len = content.len();
+        for app in new {
+            known.push(app.to_string());
+            println!("new app: {app}");
+            let wanted = if whitelist {
+                !config::load_keep()?.iter().any(|k| k == app)
+            } else {
+                auto || rules.matches(app)
+            };
+            if !wanted {
+                continue;
+            }
+            if detached.iter().any(|(s, _)| s == app) {
+                println!("already detached: {app}");
+            } else if !detach_bin::encodable(app) {
+                eprintln!("skipped: {app} cannot be stored in detach.bin");
+            } else {
+                detach_bin::serialize(app, &mut content)?;
+                detached_count += 1;
+                println!("detach: {app}");
+            }
+        }
+        if content.len() != old_len {
+            write_detach_bin(&content)?;
+            detach_bin_changed();
+        }
+        // the new apps should not wait for the watch to end
+        if crate::take_restart_pending() {
+            print_stopped(&crate::restart_store()?);
+        }
+    }
+    println!("stopped, detached {detached_count} apps");
+    Ok(Exit::Success.into())
+}
+
+fn doctor(args: &[String]) -> CLIResult<ExitCode> {
+    let fix = match args {
+        [] => false,
+        [a] if a == "--fix" => true,
+        _ => {
+            eprintln!("Usage: doctor [--fix]");
+            return Ok(Exit::Usage.into());
+        }
+    };
+    let checks = checks::module();
+    for check in &checks {
+        println!("{check}");
+    }
+    println!(
+        "log of the last menu session, for bug reports: {}",
+        config::LOG
+    );
+    let module_ok = checks.iter().all(|c| c.pass != Some(false));
+    if let Err(e) = device::get().environment() {
+        return Ok(error::Error::detach_file(e).exit_code());
+    }
+    let content = read_detach_bin()?;
+    let written = readback::check(&content);
+    if let Some(check) = &written {
+        println!("{check}");
+    }
+    let module_ok = module_ok && written.is_none_or(|c| c.pass != Some(false));
+    let verified = verify_detach_bin(&content);
+    let duplicates = duplicate_entries(&verified.entries);
+    if verified.problems.is_empty() && duplicates > 0 {
+        println!(
+            "{}",
+            Check::fail(format_args!(
+                "detach.bin has {duplicates} duplicate entries"
+            ))
+        );
+        if !fix {
+            eprintln!("the next change writes each once, or run doctor --fix");
+            return Ok(Exit::Failure.into());
+        }
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+        println!("rewrote detach.bin with each app once");
+        return Ok(Exit::Success.into());
+    }
+    if verified.problems.is_empty() {
+        let n = verified.entries.len();
+        println!(
+            "{}",
+            Check::pass(format_args!("detach.bin is fine, {n} entries"))
+        );
+        return Ok(if module_ok {
+            Exit::Success.into()
+        } else {
+            Exit::Failure.into()
+        });
+    }
+    println!("{}", Check::fail("detach.bin is corrupted"));
+    for problem in &verified.problems {
+        eprintln!("       {problem}");
+    }
+    if !fix {
+        eprintln!(
+            "{} intact entries, run doctor --fix to rebuild detach.bin from them",
+            verified.entries.len()
+        );
+        return Ok(error::Error::Corrupted.exit_code());
+    }
+    rebuild_detach_bin(&content, &verified.entries)?;
+    println!(
+        "rebuilt detach.bin from {} entries, the original is kept as detach.bin.corrupt",
+        verified.entries.len()
+    );
+    Ok(Exit::Success.into())
+}
+
+/// How many of `entries` repeat a name listed before them
+fn duplicate_entries(entries: &[detach_bin::DetachEntry]) -> usize {
+    let mut seen = std::collections::HashSet::new();
+    entries
+        .iter()
+        .filter(|(name, _)| !seen.insert(name))
+        .count()
+}
+
+fn print_sync() -> CLIResult<()> {
+    let res = sync_whitelist()?;
+    for app in &res.skipped {
+        eprintln!("skipped: {app} cannot be stored in detach.bin");
+    }
+    println!("{} detached, {} kept", res.detached, res.kept);
+    Ok(())
+}
+
+fn sync(args: &[String]) -> CLIResult<ExitCode> {
+    if !args.is_empty() {
+        eprintln!("Usage: sync");
+        return Ok(Exit::Usage.into());
+    }
+    if Config::load()?.mode != Mode::Whitelist {
+        eprintln!("sync only applies to whitelist mode, switch with: mode whitelist");
+        return Ok(Exit::Failure.into());
+    }
+    print_sync()?;
+    Ok(Exit::Success.into())
+}
+
+fn rules(args: &[String]) -> CLIResult<ExitCode> {
+    const USAGE: &str = "Usage: rules [add <pattern>... | remove <pattern> [--reattach]]";
+    match args {
+        [] => {
+            let rules = Rules::load(RULES_TXT)?;
+            let detached = get_detached_apps(&read_detach_bin()?)?;
+            if rules.is_empty() {
+                println!("no rules in {RULES_TXT}");
+            }
+            for rule in rules.iter() {
+                let n = detached
+                    .iter()
+                    .filter(|(name, _)| rules::glob_match(rule, name))
+                    .count();
+                println!("{rule}  ({n} detached)");
+            }
+        }
+        [cmd, patterns @ ..] if cmd == "add" && !patterns.is_empty() => {
+            for pattern in patterns {
+                if rules::add(RULES_TXT, pattern)? {
+                    println!("added rule: {pattern}");
+                } else {
+                    println!("already a rule: {pattern}");
+                }
+            }
+            print_rules_applied()?;
+        }
+        [cmd, pattern, flags @ ..] if cmd == "remove" => {
+            let reattach = match flags {
+                [] => false,
+                [f] if f == "--reattach" => true,
+                _ => {
+                    eprintln!("{USAGE}");
+                    return Ok(Exit::Usage.into());
+                }
+            };
+            let rules = Rules::load(RULES_TXT)?;
+            if !rules::remove(RULES_TXT, pattern)? {
+                eprintln!("{pattern} is not a rule in {RULES_TXT}");
+                return Ok(Exit::Failure.into());
+            }
+            println!("removed rule: {pattern}");
+            let content = read_detach_bin()?;
+            let detached = get_detached_apps(&content)?;
+            let ranges = rule_entries(&rules, pattern, detached.iter());
+            if reattach {
+                println!("re-attached {} apps", ranges.len());
+                remove_entries(content, ranges)?;
+            } else if !ranges.is_empty() {
+                println!(
+                    "{} apps it matched stay detached, --reattach removes them too",
+                    ranges.len()
+                );
+            }
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return Ok(Exit::Usage.into());
+        }
+    }
+    Ok(Exit::Success.into())
+}
+
+/// `undo`, or `redo` with `redo`
+fn undo(args: &[String], redo: bool) -> CLIResult<ExitCode> {
+    let (name, verb) = if redo {
+        ("redo", "redid")
+    } else {
+        ("undo", "undid")
+    };
+    if !args.is_empty() {
+        eprintln!("Usage: {name}");
+        return Ok(Exit::Usage.into());
+    }
+    let change = if redo {
+        journal::redo()?
+    } else {
+        journal::undo()?
+    };
+    match change {
+        Some(change) => println!("{verb}: {}", change.describe()),
+        None => {
+            println!("nothing to {name}");
+            return Ok(Exit::Unchanged.into());
+        }
+    }
+    Ok(Exit::Success.into())
+}
+
+fn profile(args: &[String]) -> CLIResult<ExitCode> {
+    const USAGE: &str =
+        "Usage: profile [use <name> | new <name> | rename <old> <new> | delete <name>]";
+    let active = profiles::active()?;
+    let known = |name: &str| -> CLIResult<bool> {
+        if profiles::exists(name)? {
+            return Ok(true);
+        }
+        eprintln!("no profile {name}, see: profile");
+        Ok(false)
+    };
+    let fresh = |name: &str| -> CLIResult<bool> {
+        if !profiles::valid_name(name) {
+            eprintln!("profile names take letters, digits, '-', '_' and '.' only");
+            return Ok(false);
+        }
+        if profiles::exists(name)? {
+            eprintln!("profile {name} exists already");
+            return Ok(false);
+        }
+        Ok(true)
+    };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        [] => {
+            for name in profiles::list()? {
+                let marker = if name == active { "*" } else { " " };
+                println!("{marker} {name}");
+            }
+        }
+        ["use", name] => {
+            if !known(name)? {
+                return Ok(Exit::Failure.into());
+            }
+            if name == active {
+                println!("{name} is already the active profile");
+                return Ok(Exit::Unchanged.into());
+            } else {
+                println!("switched to {name}, {} apps", switch_profile(name)?);
+            }
+        }
+        ["new", name] => {
+            if !fresh(name)? {
+                return Ok(Exit::Failure.into());
+            }
+            new_profile(name)?;
+            println!("created {name} from the current list, it is the active profile now");
+        }
+        ["rename", old, new] => {
+            if !known(old)? || !fresh(new)? {
+                return Ok(Exit::Failure.into());
+            }
+            profiles::rename(old, new)?;
+            println!("renamed {old} to {new}");
+        }
+        ["delete", name] => {
+            if !known(name)? {
+                return Ok(Exit::Failure.into());
+            }
+            if name == active {
+                eprintln!("{name} is the active profile, switch to another one first");
+                return Ok(Exit::Failure.into());
+            }
+            profiles::delete(name)?;
+            println!("deleted {name}");
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return Ok(Exit::Usage.into());
+        }
+    }
+    Ok(Exit::Success.into())
+}
+
+fn history(args: &[String]) -> CLIResult<ExitCode> {
+    let count = match args {
+        [] => 20,
+        [n] => match n.parse() {
+            Ok(n) => n,
+            Err(_) => {
+                eprintln!("Usage: history [<count>]");
+                return Ok(Exit::Usage.into());
+            }
+        },
+        _ => {
+            eprintln!("Usage: history [<count>]");
+            return Ok(Exit::Usage.into());
+        }
+    };
+    let entries = crate::history::last(count)?;
+    if entries.is_empty() {
+        println!("no changes logged yet");
+    }
+    for entry in &entries {
+        println!("{}", crate::history::describe(entry));
+    }
+    Ok(Exit::Success.into())
+}
+
+/// The settings as TOML, each commented with where it came from
+fn show_config(args: &[String]) -> CLIResult<ExitCode> {
+    if !args.is_empty() {
+        eprintln!("Usage: config");
+        return Ok(Exit::Usage.into());
+    }
+    let config = Config::load()?;
+    for (path, _) in config::read_files()? {
+        println!("# read {path}");
+    }
+    let lines: Vec<(String, config::Source)> = config::KEYS
+        .iter()
+        .map(|key| (format!("{key} = {}", config.value(key)), config.source(key)))
+        .collect();
+    let width = lines.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+    for (line, source) in lines {
+        println!("{line:width$}  # {source}");
+    }
+    Ok(Exit::Success.into())
+}
+
+fn mode(args: &[String]) -> CLIResult<ExitCode> {
+    let mut config = Config::load()?;
+    let yes = args.iter().any(|a| a == "-y" || a == "--yes");
+    let args: Vec<&String> = args
+        .iter()
+        .filter(|a| *a != "-y" && *a != "--yes")
+        .collect();
+    let mode = match args[..] {
+        [] => {
+            println!("{}", config.mode.name());
+            return Ok(Exit::Success.into());
+        }
+        [m] => match Mode::parse(m) {
+            Some(mode) => mode,
+            None => {
+                eprintln!("Usage: mode [whitelist|blacklist] [--yes]");
+                return Ok(Exit::Usage.into());
+            }
+        },
+        _ => {
+            eprintln!("Usage: mode [whitelist|blacklist] [--yes]");
+            return Ok(Exit::Usage.into());
+        }
+    };
+    if mode == config.mode {
+        println!("already in {} mode", mode.name());
+        return Ok(Exit::Unchanged.into());
+    }
+    if mode == Mode::Whitelist && !yes {
+        match ask("detach.bin will be rewritten to every app not on the keep-list. Continue?")? {
+            Some(true) => {}
+            Some(false) => return Ok(Exit::Unchanged.into()),
+            None => {
+                eprintln!("Pass --yes to switch without a prompt.");
+                return Ok(Exit::Usage.into());
+            }
+        }
+    }
+    config::set("mode", mode.name())?;
+    config.mode = mode;
+    println!("switched to {} mode", mode.name());
+    if mode == Mode::Whitelist {
+        print_sync()?;
+    }
+    Ok(Exit::Success.into())
+}
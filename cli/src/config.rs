@@ -0,0 +1,555 @@
+//! Settings that outlive a run, kept as TOML next to the exports on the sdcard. Flags
+//! override them for a run
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::colorize::{ColorChoice, Theme};
+use crate::{dry_run, parse_txt, profiles};
+
+#[cfg(target_os = "android")]
+pub const CONFIG: &str = "/sdcard/zygisk-detach/config.toml";
+#[cfg(target_os = "linux")]
+pub const CONFIG: &str = "zygisk-detach.toml";
+/// Read when there is no config.toml yet, `set` moves it over
+#[cfg(target_os = "android")]
+const OLD_CONFIG: &str = "/sdcard/zygisk-detach/config";
+#[cfg(target_os = "linux")]
+const OLD_CONFIG: &str = "zygisk-detach.conf";
+/// Settings for every user of the phone, applied before the config on the sdcard
+#[cfg(target_os = "android")]
+const ROOT_CONFIG: Option<&str> = Some("/data/adb/zygisk-detach/config.toml");
+#[cfg(target_os = "linux")]
+const ROOT_CONFIG: Option<&str> = None;
+
+/// Where the menus log what `-v` would print, started over each time they open
+#[cfg(target_os = "android")]
+pub const LOG: &str = "/data/adb/zygisk-detach/cli.log";
+#[cfg(target_os = "linux")]
+pub const LOG: &str = "zygisk-detach-cli.log";
+
+/// Left once the first-run wizard was gone through or skipped
+#[cfg(target_os = "android")]
+pub const WIZARD_DONE: &str = "/data/adb/zygisk-detach/wizard-done";
+#[cfg(target_os = "linux")]
+pub const WIZARD_DONE: &str = "zygisk-detach-wizard-done";
+
+#[cfg(target_os = "android")]
+pub const KEEP_TXT: &str = "/sdcard/zygisk-detach/keep.txt";
+#[cfg(target_os = "linux")]
+pub const KEEP_TXT: &str = "zygisk-detach-keep.txt";
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// detach.bin lists the apps to detach
+    #[default]
+    Blacklist,
+    /// The keep-list names the apps to leave attached, `sync` detaches all the others
+    Whitelist,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Blacklist => "blacklist",
+            Self::Whitelist => "whitelist",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "blacklist" => Some(Self::Blacklist),
+            "whitelist" => Some(Self::Whitelist),
+            _ => None,
+        }
+    }
+}
+
+/// Where a setting came from, for `detach config`
+#[derive(Clone, Copy)]
+pub enum Source {
+    Default,
+    File(&'static str),
+    /// The flag or environment variable that set it
+    Flag(&'static str),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => f.write_str("default"),
+            Self::File(path) | Self::Flag(path) => f.write_str(path),
+        }
+    }
+}
+
+/// The keys `Config` knows, in the order `detach config` prints them
+pub const KEYS: &[&str] = &[
+    "mode",
+    "profile",
+    "wrap",
+    "bell",
+    "altscreen",
+    "dry_run",
+    "no_restart",
+    "restart_gms",
+    "system",
+    "timeout",
+    "color",
+    "theme",
+    "plain",
+    "module_dir",
+    "risky",
+];
+
+pub struct Config {
+    pub mode: Mode,
+    /// Up on the first item of a menu goes to the last one and back
+    pub wrap: bool,
+    /// Ring the terminal bell on keys a menu does not take
+    pub bell: bool,
+    /// Draw the menus on the alternate screen, off with `--no-altscreen`
+    pub altscreen: bool,
+    /// Report the changes instead of making them, as with `--dry-run`
+    pub dry_run: bool,
+    /// Leave the Play Store running after changes, as with `--no-restart`
+    pub no_restart: bool,
+    /// Stop Play Services along with the Play Store after changes
+    pub restart_gms: bool,
+    /// `detach --all` and the menu include system apps
+    pub system: bool,
+    /// Seconds a command may take before it is killed, 0 for no limit
+    pub timeout: u64,
+    pub color: ColorChoice,
+    /// How the menus mark the selected item and whether anything is drawn faint
+    pub theme: Theme,
+    /// Line-based prompts even on a terminal, as with `--plain`
+    pub plain: bool,
+    pub module_dir: Option<String>,
+    /// Packages added to the ones detaching takes `--force` for, `-name` removes one,
+    /// see [`crate::risky`]
+    pub risky: String,
+    /// The profile detach.bin was last switched to
+    pub profile: String,
+    sources: HashMap<&'static str, Source>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::default(),
+            wrap: false,
+            bell: true,
+            altscreen: true,
+            dry_run: false,
+            no_restart: false,
+            restart_gms: false,
+            system: false,
+            timeout: detach_core::runner::DEFAULT_TIMEOUT.as_secs(),
+            color: ColorChoice::Auto,
+            theme: Theme::Default,
+            plain: false,
+            module_dir: None,
+            risky: String::new(),
+            profile: profiles::DEFAULT.to_string(),
+            sources: HashMap::new(),
+        }
+    }
+}
+
+/// A value as written in the config, strings are unquoted
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Value {
+    Str(String),
+    Bool(bool),
+    Int(i64),
+}
+
+impl Value {
+    /// `"text"`, `'text'`, `true`, `false` or a number, with an optional `#` comment after it
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (value, rest) = if let Some(s) = s.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = s.char_indices();
+            loop {
+                match chars.next()? {
+                    (i, '"') => break (Self::Str(value), &s[i + 1..]),
+                    (_, '\\') => value.push(match chars.next()?.1 {
+                        'n' => '\n',
+                        't' => '\t',
+                        c @ ('"' | '\\') => c,
+                        _ => return None,
+                    }),
+                    (_, c) => value.push(c),
+                }
+            }
+        } else if let Some(s) = s.strip_prefix('\'') {
+            let (value, rest) = s.split_once('\'')?;
+            (Self::Str(value.to_string()), rest)
+        } else {
+            let end = s.find(['#', ' ', '\t']).unwrap_or(s.len());
+            let value = match &s[..end] {
+                "true" => Self::Bool(true),
+                "false" => Self::Bool(false),
+                n => Self::Int(n.replace('_', "").parse().ok()?),
+            };
+            (value, &s[end..])
+        };
+        let rest = rest.trim_start();
+        (rest.is_empty() || rest.starts_with('#')).then_some(value)
+    }
+
+    /// Plain booleans, or quoted ones as older versions wrote them
+    fn bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            Self::Str(s) if s == "true" => Some(true),
+            Self::Str(s) if s == "false" => Some(false),
+            Self::Str(_) | Self::Int(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Int(n) => write!(f, "{n}"),
+            Self::Str(s) => write!(f, "{s:?}"),
+        }
+    }
+}
+
+/// A key and its value, or why the line is not one
+type Pair = Result<(String, Value), String>;
+
+/// `key = value` pairs in a TOML file with their line numbers, or a warning for each
+/// line that is not one. Keys under a table are prefixed with its name
+fn parse_toml(txt: &str) -> Vec<(usize, Pair)> {
+    let mut table = String::new();
+    let mut pairs = Vec::new();
+    for (i, line) in txt.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            match name.split_once(']') {
+                Some((name, _)) => table = format!("{}.", name.trim()),
+                None => pairs.push((i + 1, Err(format!("unclosed table `{line}`")))),
+            }
+            continue;
+        }
+        let pair = match line.split_once('=') {
+            Some((key, value)) => match Value::parse(value) {
+                Some(value) => Ok((format!("{table}{}", key.trim().trim_matches('"')), value)),
+                None => Err(format!("cannot read `{}`", value.trim())),
+            },
+            None => Err(format!("`{line}` is not a `key = value` pair")),
+        };
+        pairs.push((i + 1, pair));
+    }
+    pairs
+}
+
+/// Flags that override the config, with the flag each came from
+static FLAGS: Mutex<Vec<(&'static str, Value, &'static str)>> = Mutex::new(Vec::new());
+/// The config is loaded many times a run, its warnings are printed once
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Overrides `key` for this run as given by `flag`, later calls win over earlier ones
+pub fn flag(key: &'static str, value: Value, flag: &'static str) {
+    FLAGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((key, value, flag));
+}
+
+impl Config {
+    /// The defaults, overridden by the config files and then by the flags. Unknown keys
+    /// and bad values are warned about and skipped, newer configs still load
+    pub fn load() -> io::Result<Self> {
+        let files = read_files()?;
+        let flags = FLAGS.lock().unwrap_or_else(|e| e.into_inner());
+        let (config, warnings) = Self::resolve(&files, &flags);
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            for warning in warnings {
+                eprintln!("warning: {warning}");
+            }
+        }
+        Ok(config)
+    }
+
+    /// The defaults overridden by `files` in order and then by `flags`, with a warning
+    /// for each line or flag that was skipped
+    fn resolve(
+        files: &[(&'static str, String)],
+        flags: &[(&'static str, Value, &'static str)],
+    ) -> (Self, Vec<String>) {
+        let mut config = Self::default();
+        let mut warnings = Vec::new();
+        for (path, txt) in files {
+            for (n, pair) in parse_toml(txt) {
+                if let Err(e) =
+                    pair.and_then(|(key, value)| config.assign(&key, &value, Source::File(path)))
+                {
+                    warnings.push(format!("{path}:{n}: {e}"));
+                }
+            }
+        }
+        for (key, value, flag) in flags {
+            if let Err(e) = config.assign(key, value, Source::Flag(flag)) {
+                warnings.push(format!("{flag}: {e}"));
+            }
+        }
+        (config, warnings)
+    }
+
+    fn assign(&mut self, key: &str, value: &Value, source: Source) -> Result<(), String> {
+        let invalid = || format!("{value} is not a valid {key}");
+        let bool = || value.bool().ok_or_else(invalid);
+        let str = || match value {
+            Value::Str(s) => Ok(s.as_str()),
+            Value::Bool(_) | Value::Int(_) => Err(invalid()),
+        };
+        let key = match KEYS.iter().find(|k| **k == key) {
+            Some(key) => *key,
+            None => return Err(format!("unknown key `{key}`")),
+        };
+        match key {
+            "mode" => self.mode = Mode::parse(str()?).ok_or_else(invalid)?,
+            "profile" => {
+                let name = str()?;
+                if !profiles::valid_name(name) {
+                    return Err(invalid());
+                }
+                self.profile = name.to_string();
+            }
+            "wrap" => self.wrap = bool()?,
+            "bell" => self.bell = bool()?,
+            "altscreen" => self.altscreen = bool()?,
+            "dry_run" => self.dry_run = bool()?,
+            "no_restart" => self.no_restart = bool()?,
+            "restart_gms" => self.restart_gms = bool()?,
+            "system" => self.system = bool()?,
+            "timeout" => match value {
+                Value::Int(secs) if *secs >= 0 => self.timeout = *secs as u64,
+                _ => return Err(invalid()),
+            },
+            "color" => self.color = ColorChoice::parse(str()?).ok_or_else(invalid)?,
+            "theme" => self.theme = Theme::parse(str()?).ok_or_else(invalid)?,
+            "plain" => self.plain = bool()?,
+            "module_dir" => {
+                self.module_dir = Some(str()?).filter(|d| !d.is_empty()).map(str::to_string)
+            }
+            "risky" => self.risky = str()?.to_string(),
+            _ => unreachable!("{key} is in KEYS"),
+        }
+        self.sources.insert(key, source);
+        Ok(())
+    }
+
+    /// How `key` is written in the config
+    pub fn value(&self, key: &str) -> Value {
+        let bool = Value::Bool;
+        let str = |s: &str| Value::Str(s.to_string());
+        match key {
+            "mode" => str(self.mode.name()),
+            "profile" => str(&self.profile),
+            "wrap" => bool(self.wrap),
+            "bell" => bool(self.bell),
+            "altscreen" => bool(self.altscreen),
+            "dry_run" => bool(self.dry_run),
+            "no_restart" => bool(self.no_restart),
+            "restart_gms" => bool(self.restart_gms),
+            "system" => bool(self.system),
+            "timeout" => Value::Int(self.timeout as i64),
+            "color" => str(self.color.name()),
+            "theme" => str(self.theme.name()),
+            "plain" => bool(self.plain),
+            "module_dir" => str(self.module_dir.as_deref().unwrap_or("")),
+            "risky" => str(&self.risky),
+            _ => str(""),
+        }
+    }
+
+    pub fn source(&self, key: &str) -> Source {
+        self.sources.get(key).copied().unwrap_or(Source::Default)
+    }
+}
+
+/// The config files that exist, in the order they are applied: the root-only one, then
+/// the one in the data directory or its pre-TOML name
+pub fn read_files() -> io::Result<Vec<(&'static str, String)>> {
+    let mut files = Vec::new();
+    let read = |path: &'static str| match fs::read_to_string(path) {
+        Ok(txt) => Ok(Some((path, txt))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    };
+    // unreadable without root, as with --no-root-check
+    if let Some(path) = ROOT_CONFIG {
+        if let Ok(Some(file)) = read(path) {
+            files.push(file);
+        }
+    }
+    match read(CONFIG)? {
+        Some(file) => files.push(file),
+        None => files.extend(read(OLD_CONFIG)?),
+    }
+    Ok(files)
+}
+
+/// Writes `key = "value"` to the config in the data directory, keeping its other lines
+pub fn set(key: &str, value: &str) -> io::Result<()> {
+    let txt = match fs::read_to_string(CONFIG) {
+        Ok(txt) => txt,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match fs::read_to_string(OLD_CONFIG) {
+            Ok(txt) => txt,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        },
+        Err(e) => return Err(e),
+    };
+    let line = format!("{key} = {}", Value::Str(value.to_string()));
+    let mut found = false;
+    let mut new = String::new();
+    for l in txt.lines() {
+        if l.split_once('=').is_some_and(|(k, _)| k.trim() == key) {
+            if found {
+                continue;
+            }
+            found = true;
+            new.push_str(&line);
+        } else {
+            new.push_str(l);
+        }
+        new.push('\n');
+    }
+    if !found {
+        new.push_str(&line);
+        new.push('\n');
+    }
+    create_parent(CONFIG)?;
+    dry_run::write_local(CONFIG, new.as_bytes())
+}
+
+pub fn create_parent(path: &str) -> io::Result<()> {
+    match std::path::Path::new(path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir),
+        _ => Ok(()),
+    }
+}
+
+/// The apps whitelist mode leaves attached
+pub fn load_keep() -> io::Result<Vec<String>> {
+    match fs::read_to_string(KEEP_TXT) {
+        Ok(txt) => Ok(parse_txt(&txt).map(str::to_string).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn save_keep(keep: &[String]) -> io::Result<()> {
+    let mut txt = String::from("# apps zygisk-detach leaves attached in whitelist mode\n");
+    for app in keep {
+        txt.push_str(app);
+        txt.push('\n');
+    }
+    create_parent(KEEP_TXT)?;
+    dry_run::write_local(KEEP_TXT, txt.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let str = |s: &str| Some(Value::Str(s.to_string()));
+        assert_eq!(Value::parse(" true "), Some(Value::Bool(true)));
+        assert_eq!(Value::parse("false # off"), Some(Value::Bool(false)));
+        assert_eq!(Value::parse("1_000"), Some(Value::Int(1000)));
+        assert_eq!(Value::parse("-3"), Some(Value::Int(-3)));
+        assert_eq!(Value::parse(r#""a \"b\" # c""#), str(r#"a "b" # c"#));
+        assert_eq!(Value::parse(r#""tab\there""#), str("tab\there"));
+        assert_eq!(Value::parse("'C:\\dir' # raw"), str("C:\\dir"));
+        assert_eq!(Value::parse("yes"), None);
+        assert_eq!(Value::parse(r#""unclosed"#), None);
+        assert_eq!(Value::parse(r#""a" b"#), None);
+        assert_eq!(Value::parse(r#""\q""#), None);
+    }
+
+    #[test]
+    fn toml_pairs_and_warnings() {
+        let txt =
+            "# comment\n\nwrap = true\n[ui]\ntheme = \"mono\"\nno pair\n[open\nbell = maybe\n";
+        let pairs = parse_toml(txt);
+        let lines: Vec<usize> = pairs.iter().map(|(n, _)| *n).collect();
+        assert_eq!(lines, [3, 5, 6, 7, 8]);
+        assert_eq!(pairs[0].1, Ok(("wrap".to_string(), Value::Bool(true))));
+        assert_eq!(
+            pairs[1].1,
+            Ok(("ui.theme".to_string(), Value::Str("mono".to_string())))
+        );
+        assert!(pairs[2..].iter().all(|(_, p)| p.is_err()));
+    }
+
+    #[test]
+    fn files_override_defaults_in_order() {
+        let files = [
+            ("root.toml", "wrap = true\ntimeout = 5\n".to_string()),
+            (
+                "user.toml",
+                "timeout = 9\nmode = \"whitelist\"\n".to_string(),
+            ),
+        ];
+        let (config, warnings) = Config::resolve(&files, &[]);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert!(config.wrap);
+        assert_eq!(config.timeout, 9);
+        assert!(config.mode == Mode::Whitelist);
+        assert_eq!(config.source("wrap").to_string(), "root.toml");
+        assert_eq!(config.source("timeout").to_string(), "user.toml");
+        assert_eq!(config.source("bell").to_string(), "default");
+        assert!(config.bell);
+    }
+
+    #[test]
+    fn flags_override_files() {
+        let files = [("c.toml", "no_restart = false\nplain = true\n".to_string())];
+        let flags = [
+            ("no_restart", Value::Bool(true), "--no-restart"),
+            ("plain", Value::Bool(false), "--no-plain"),
+            ("plain", Value::Bool(true), "--plain"),
+        ];
+        let (config, _) = Config::resolve(&files, &flags);
+        assert!(config.no_restart);
+        assert_eq!(config.source("no_restart").to_string(), "--no-restart");
+        // the later flag wins
+        assert!(config.plain);
+        assert_eq!(config.source("plain").to_string(), "--plain");
+    }
+
+    #[test]
+    fn unknown_keys_and_bad_values_warn() {
+        let txt = "future_key = 1\ntimeout = -1\nmode = \"greylist\"\nbell = \"false\"\n";
+        let (config, warnings) = Config::resolve(&[("c.toml", txt.to_string())], &[]);
+        assert_eq!(
+            warnings,
+            [
+                "c.toml:1: unknown key `future_key`",
+                "c.toml:2: -1 is not a valid timeout",
+                "c.toml:3: \"greylist\" is not a valid mode",
+            ]
+        );
+        // what could be read still applies, quoted booleans included
+        assert!(!config.bell);
+        assert_eq!(config.timeout, Config::default().timeout);
+        assert_eq!(config.value("bell"), Value::Bool(false));
+    }
+}
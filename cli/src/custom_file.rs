@@ -0,0 +1,183 @@
+//! detach.bin somewhere else than in the module: a copy from another phone given with
+//! `--file`, or the live list moved with `ZYGISK_DETACH_FILE`. The file is read and
+//! written where the cli runs, everything else still goes to the device
+
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+use detach_core::{atomic, log};
+
+use crate::device::{Device, DeviceFile, Environment, FileInfo};
+
+/// The detach.bin `--file` names, or else `ZYGISK_DETACH_FILE`. The copy `--file`
+/// names is not the phone's, so it comes back as foreign
+pub fn resolve(flag: Option<String>, env: Option<String>) -> Option<(String, bool)> {
+    match (flag, env.filter(|e| !e.is_empty())) {
+        (Some(flag), _) => Some((flag, true)),
+        (None, Some(env)) => Some((env, false)),
+        (None, None) => None,
+    }
+}
+
+/// Set while working on a foreign file: the phone, the journal and the history are
+/// left alone
+static FOREIGN: AtomicBool = AtomicBool::new(false);
+
+pub fn foreign() -> bool {
+    FOREIGN.load(Ordering::Relaxed)
+}
+
+pub struct CustomFile {
+    inner: Box<dyn Device>,
+    path: String,
+    foreign: bool,
+}
+
+impl CustomFile {
+    pub fn new(inner: Box<dyn Device>, path: String, foreign: bool) -> Self {
+        FOREIGN.store(foreign, Ordering::Relaxed);
+        Self {
+            inner,
+            path,
+            foreign,
+        }
+    }
+
+    /// Whether a change to `file` is one to skip, as the backups of a foreign file
+    fn skipped(&self, file: DeviceFile, what: &str) -> bool {
+        let skip = self.foreign && file != DeviceFile::DetachBin;
+        if skip {
+            log::verbose(format_args!(
+                "--file: not {what} {}",
+                self.path(file).unwrap_or_default()
+            ));
+        }
+        skip
+    }
+}
+
+impl Device for CustomFile {
+    fn environment(&self) -> io::Result<&Environment> {
+        self.inner.environment()
+    }
+
+    fn path(&self, file: DeviceFile) -> io::Result<String> {
+        match file {
+            DeviceFile::DetachBin => Ok(self.path.clone()),
+            file => self.inner.path(file),
+        }
+    }
+
+    fn list_packages(&self, flags: &[&str]) -> io::Result<Vec<u8>> {
+        self.inner.list_packages(flags)
+    }
+
+    fn list_users(&self) -> io::Result<Vec<u8>> {
+        self.inner.list_users()
+    }
+
+    fn dump_packages(&self) -> io::Result<Vec<u8>> {
+        self.inner.dump_packages()
+    }
+
+    fn read(&self, file: DeviceFile) -> io::Result<Vec<u8>> {
+        match file {
+            DeviceFile::DetachBin => {
+                log::verbose(format_args!("read: {}", self.path));
+                fs::read(&self.path)
+            }
+            file => self.inner.read(file),
+        }
+    }
+
+    fn write(&self, file: DeviceFile, content: &[u8]) -> io::Result<()> {
+        match file {
+            DeviceFile::DetachBin => atomic::write(&self.path, content),
+            file if self.skipped(file, "writing") => Ok(()),
+            file => self.inner.write(file, content),
+        }
+    }
+
+    fn remove(&self, file: DeviceFile) -> io::Result<()> {
+        match file {
+            DeviceFile::DetachBin => fs::remove_file(&self.path),
+            file if self.skipped(file, "removing") => Ok(()),
+            file => self.inner.remove(file),
+        }
+    }
+
+    fn metadata(&self, file: DeviceFile) -> io::Result<FileInfo> {
+        if file != DeviceFile::DetachBin {
+            return self.inner.metadata(file);
+        }
+        let meta = fs::metadata(&self.path)?;
+        Ok(FileInfo {
+            size: meta.len(),
+            modified: (meta.modified()?.duration_since(UNIX_EPOCH)).map_or(0, |d| d.as_secs()),
+        })
+    }
+
+    fn list_backups(&self) -> io::Result<Vec<String>> {
+        self.inner.list_backups()
+    }
+
+    fn force_stop(&self, pkg: &str) -> io::Result<()> {
+        if self.foreign {
+            return Ok(());
+        }
+        self.inner.force_stop(pkg)
+    }
+
+    fn clear_cache(&self, pkg: &str) -> io::Result<()> {
+        if self.foreign {
+            return Ok(());
+        }
+        self.inner.clear_cache(pkg)
+    }
+
+    fn relabel(&self, file: DeviceFile) -> io::Result<()> {
+        match file {
+            // a file where the cli runs, not the module's
+            DeviceFile::DetachBin => Ok(()),
+            file => self.inner.relabel(file),
+        }
+    }
+
+    fn open_store_page(&self, pkg: &str) -> io::Result<()> {
+        self.inner.open_store_page(pkg)
+    }
+
+    fn app_labels(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.inner.app_labels(pkgs)
+    }
+
+    fn app_details(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.inner.app_details(pkgs)
+    }
+
+    fn probe_module(&self) -> io::Result<Vec<u8>> {
+        self.inner.probe_module()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_flag_wins_over_the_environment() {
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(
+            resolve(some("flag.bin"), some("env.bin")),
+            Some(("flag.bin".to_string(), true))
+        );
+        assert_eq!(
+            resolve(None, some("env.bin")),
+            Some(("env.bin".to_string(), false))
+        );
+        assert_eq!(resolve(None, some("")), None);
+        assert_eq!(resolve(None, None), None);
+    }
+}
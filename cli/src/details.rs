@@ -0,0 +1,188 @@
+//! Version and APK size columns for the app lists. Every app costs a `dumpsys` and a
+//! `stat`, so only the apps that are drawn are looked up, in the background, and what
+//! was found is kept for the rest of the run
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use detach_core::{parse_package_details, PackageDetails};
+
+use crate::colorize::{display_width, truncate, ToColored};
+use crate::device;
+
+/// Narrower terminals leave the columns out
+const MIN_COLUMNS: usize = 60;
+/// From this wide on the update date is shown too
+const WIDE_COLUMNS: usize = 80;
+/// How many apps one lookup handles
+const BATCH: usize = 8;
+/// How often the resolver looks for apps that were drawn
+const POLL: Duration = Duration::from_millis(50);
+
+/// package -> details, `None` for apps that have been asked for and not found
+static FOUND: Mutex<Option<HashMap<String, Option<PackageDetails>>>> = Mutex::new(None);
+
+fn found() -> std::sync::MutexGuard<'static, Option<HashMap<String, Option<PackageDetails>>>> {
+    FOUND.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Default)]
+struct Shared {
+    /// Apps that were drawn without their details, the latest last
+    wanted: Mutex<Vec<String>>,
+    /// Set when the menu is left, the resolver stops after the batch it is on
+    stop: AtomicBool,
+}
+
+impl Shared {
+    fn wanted(&self) -> std::sync::MutexGuard<'_, Vec<String>> {
+        self.wanted.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Looks up the apps drawn through [`Details::detailed`] until dropped
+pub struct Details {
+    shared: Arc<Shared>,
+}
+
+impl Drop for Details {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Details {
+    /// `on_update` is called from the resolver thread after each batch it looked up
+    pub fn load(on_update: impl Fn() + Send + 'static) -> Self {
+        let shared = Arc::new(Shared::default());
+        let resolver = Arc::clone(&shared);
+        thread::spawn(move || {
+            while !resolver.stop.load(Ordering::Relaxed) {
+                // the apps drawn last are the ones on the screen now
+                let batch: Vec<String> = {
+                    let mut wanted = resolver.wanted();
+                    let from = wanted.len().saturating_sub(BATCH);
+                    wanted.drain(from..).collect()
+                };
+                if batch.is_empty() {
+                    thread::sleep(POLL);
+                    continue;
+                }
+                let pkgs: Vec<&str> = batch.iter().map(String::as_str).collect();
+                let mut details = device::get()
+                    .app_details(&pkgs)
+                    .map(|out| parse_package_details(&out))
+                    .unwrap_or_default();
+                {
+                    let mut found = found();
+                    let found = found.get_or_insert_with(HashMap::new);
+                    for app in batch {
+                        let details = details.remove(&app);
+                        found.insert(app, details);
+                    }
+                }
+                if !resolver.stop.load(Ordering::Relaxed) {
+                    on_update();
+                }
+            }
+        });
+        Self { shared }
+    }
+
+    /// `item` for `app` with its details right-aligned after it within `width` columns
+    pub fn detailed<'a, T: Display>(
+        &'a self,
+        item: T,
+        app: &'a str,
+        width: usize,
+    ) -> Detailed<'a, T> {
+        Detailed {
+            details: self,
+            item,
+            app,
+            width,
+        }
+    }
+
+    /// The columns of `app`, asking for them when they were never looked up
+    fn columns(&self, app: &str, wide: bool) -> Option<String> {
+        let found = found();
+        let Some(details) = found.as_ref().and_then(|found| found.get(app)) else {
+            let mut wanted = self.shared.wanted();
+            if !wanted.iter().any(|w| w == app) {
+                wanted.push(app.to_string());
+            }
+            return None;
+        };
+        let details = details.as_ref()?;
+        let version = match (&details.version_name, details.version_code) {
+            (Some(name), _) => name.clone(),
+            (None, Some(code)) => code.to_string(),
+            (None, None) => String::new(),
+        };
+        let version = truncate(&version, 12).into_owned();
+        let size = details.size.map(display_size).unwrap_or_default();
+        if wide {
+            let date = (details.updated.as_deref())
+                .and_then(|t| t.split(' ').next())
+                .unwrap_or_default();
+            Some(format!("{version:>12}  {date:>10}  {size:>8}"))
+        } else {
+            Some(format!("{version:>12}  {size:>8}"))
+        }
+    }
+
+    /// How many of `width` columns are left for the items
+    pub fn room(width: usize) -> usize {
+        match width {
+            w if w < MIN_COLUMNS => w,
+            w if w < WIDE_COLUMNS => w - 23,
+            w => w - 35,
+        }
+    }
+}
+
+/// `45.2 MB`
+fn display_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "kB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
+pub struct Detailed<'a, T> {
+    details: &'a Details,
+    item: T,
+    app: &'a str,
+    width: usize,
+}
+
+/// `{:#}` leaves the columns out, for matching against what the item says
+impl<T: Display> Display for Detailed<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let item = self.item.to_string();
+        if f.alternate() || self.width < MIN_COLUMNS {
+            return write!(f, "{item}");
+        }
+        let room = Details::room(self.width);
+        let item = truncate(&item, room);
+        match self.details.columns(self.app, self.width >= WIDE_COLUMNS) {
+            Some(columns) => {
+                let pad = self.width - display_width(&item) - display_width(&columns);
+                write!(f, "{item}{:pad$}{}", "", columns.faint())
+            }
+            None => write!(f, "{item}"),
+        }
+    }
+}
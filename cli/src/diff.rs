@@ -0,0 +1,141 @@
+//! What an import would change in the detach list, shown before it is applied
+
+use std::fmt::Display;
+
+use crate::colorize::ToColored;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    /// Only with `--replace`
+    Removed,
+    Unchanged,
+}
+
+pub struct Line {
+    pub change: Change,
+    pub app: String,
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.change {
+            Change::Added => write!(f, "{}", format_args!("+ {}", self.app).green()),
+            Change::Removed => write!(f, "{}", format_args!("- {}", self.app).red()),
+            Change::Unchanged => write!(f, "{}", format_args!("  {}", self.app).faint()),
+        }
+    }
+}
+
+/// The current apps in their order followed by the new ones in `incoming`'s.
+/// Apps missing from `incoming` are removed only when it `replace`s the list
+pub fn diff(current: &[&str], incoming: &[&str], replace: bool) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    for &app in current {
+        if lines.iter().any(|l| l.app == app) {
+            continue;
+        }
+        let change = if !replace || incoming.contains(&app) {
+            Change::Unchanged
+        } else {
+            Change::Removed
+        };
+        lines.push(Line {
+            change,
+            app: app.to_string(),
+        });
+    }
+    for &app in incoming {
+        if lines.iter().any(|l| l.app == app) {
+            continue;
+        }
+        lines.push(Line {
+            change: Change::Added,
+            app: app.to_string(),
+        });
+    }
+    lines
+}
+
+/// `N added, N removed, N unchanged`
+pub fn summary(lines: &[Line]) -> String {
+    let count = |change| lines.iter().filter(|l| l.change == change).count();
+    format!(
+        "{} added, {} removed, {} unchanged",
+        count(Change::Added),
+        count(Change::Removed),
+        count(Change::Unchanged)
+    )
+}
+
+pub fn has_changes(lines: &[Line]) -> bool {
+    lines.iter().any(|l| l.change != Change::Unchanged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes(current: &[&str], incoming: &[&str], replace: bool) -> Vec<(Change, String)> {
+        (diff(current, incoming, replace).into_iter())
+            .map(|l| (l.change, l.app))
+            .collect()
+    }
+
+    fn line(change: Change, app: &str) -> (Change, String) {
+        (change, app.to_string())
+    }
+
+    #[test]
+    fn overlapping_sets() {
+        let (current, incoming) = (["a", "b"], ["b", "c"]);
+        assert_eq!(
+            changes(&current, &incoming, false),
+            [
+                line(Change::Unchanged, "a"),
+                line(Change::Unchanged, "b"),
+                line(Change::Added, "c"),
+            ]
+        );
+        assert_eq!(
+            changes(&current, &incoming, true),
+            [
+                line(Change::Removed, "a"),
+                line(Change::Unchanged, "b"),
+                line(Change::Added, "c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn disjoint_sets() {
+        let lines = diff(&["a"], &["b", "c"], true);
+        assert_eq!(summary(&lines), "2 added, 1 removed, 0 unchanged");
+        let lines = diff(&["a"], &["b", "c"], false);
+        assert_eq!(summary(&lines), "2 added, 0 removed, 1 unchanged");
+        assert!(has_changes(&lines));
+    }
+
+    #[test]
+    fn identical_sets() {
+        for replace in [false, true] {
+            let lines = diff(&["a", "b"], &["b", "a"], replace);
+            assert_eq!(summary(&lines), "0 added, 0 removed, 2 unchanged");
+            assert!(!has_changes(&lines));
+        }
+    }
+
+    #[test]
+    fn duplicates_listed_once() {
+        assert_eq!(
+            changes(&["a", "a"], &["b", "b", "a"], false),
+            [line(Change::Unchanged, "a"), line(Change::Added, "b")]
+        );
+    }
+
+    #[test]
+    fn empty_sets() {
+        assert!(diff(&[], &[], true).is_empty());
+        assert_eq!(changes(&["a"], &[], true), [line(Change::Removed, "a")]);
+    }
+}
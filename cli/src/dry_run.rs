@@ -0,0 +1,198 @@
+//! `--dry-run`: a device that keeps every change in memory and reports it instead of
+//! touching the phone. Later reads see the changes, so the menus behave as they would
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::device::{self, Device, DeviceFile, Environment, FileInfo};
+use crate::{atomic, detach_bin};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// What would have happened since the last call
+pub fn take_log() -> Vec<String> {
+    std::mem::take(&mut *LOG.lock().unwrap())
+}
+
+fn log(msg: String) {
+    LOG.lock().unwrap().push(msg);
+}
+
+/// `atomic::write` for the cli's own files next to the exports, only reported in a dry run
+pub fn write_local(path: &str, content: &[u8]) -> io::Result<()> {
+    if enabled() {
+        log(format!("would write {path}, {} bytes", content.len()));
+        return Ok(());
+    }
+    atomic::write(path, content)
+}
+
+/// `fs::remove_file` for the cli's own files, only reported in a dry run
+pub fn remove_local(path: &str) -> io::Result<()> {
+    if enabled() {
+        if !std::path::Path::new(path).exists() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        log(format!("would remove {path}"));
+        return Ok(());
+    }
+    std::fs::remove_file(path)
+}
+
+pub struct DryRun {
+    inner: Box<dyn Device>,
+    /// Files written, or removed when `None`
+    files: Mutex<HashMap<DeviceFile, Option<Vec<u8>>>>,
+}
+
+impl DryRun {
+    pub fn new(inner: Box<dyn Device>) -> Self {
+        ENABLED.store(true, Ordering::Relaxed);
+        Self {
+            inner,
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn exists(&self, file: DeviceFile) -> io::Result<bool> {
+        match self.read(file) {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Device for DryRun {
+    fn environment(&self) -> io::Result<&Environment> {
+        self.inner.environment()
+    }
+
+    fn path(&self, file: DeviceFile) -> io::Result<String> {
+        self.inner.path(file)
+    }
+
+    fn list_packages(&self, flags: &[&str]) -> io::Result<Vec<u8>> {
+        self.inner.list_packages(flags)
+    }
+
+    fn list_users(&self) -> io::Result<Vec<u8>> {
+        self.inner.list_users()
+    }
+
+    fn dump_packages(&self) -> io::Result<Vec<u8>> {
+        self.inner.dump_packages()
+    }
+
+    fn read(&self, file: DeviceFile) -> io::Result<Vec<u8>> {
+        match self.files.lock().unwrap().get(&file) {
+            Some(Some(content)) => Ok(content.clone()),
+            Some(None) => Err(io::ErrorKind::NotFound.into()),
+            None => self.inner.read(file),
+        }
+    }
+
+    fn write(&self, file: DeviceFile, content: &[u8]) -> io::Result<()> {
+        let path = self.path(file)?;
+        let apps = match file {
+            DeviceFile::DetachBin | DeviceFile::SdcardDetachBin => detach_bin::parse(content),
+            _ => None,
+        };
+        match apps {
+            Some(apps) if apps.is_empty() => log(format!(
+                "would write {path}, {} bytes with no apps",
+                content.len()
+            )),
+            Some(apps) => log(format!(
+                "would write {path}, {} bytes with {} apps: {}",
+                content.len(),
+                apps.len(),
+                apps.iter()
+                    .map(|(name, _)| name.as_str())
+                    .intersperse(", ")
+                    .collect::<String>()
+            )),
+            None => log(format!("would write {path}, {} bytes", content.len())),
+        }
+        self.files
+            .lock()
+            .unwrap()
+            .insert(file, Some(content.to_vec()));
+        Ok(())
+    }
+
+    fn remove(&self, file: DeviceFile) -> io::Result<()> {
+        if !self.exists(file)? {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        log(format!("would remove {}", self.path(file)?));
+        self.files.lock().unwrap().insert(file, None);
+        Ok(())
+    }
+
+    fn metadata(&self, file: DeviceFile) -> io::Result<FileInfo> {
+        match self.files.lock().unwrap().get(&file) {
+            Some(Some(content)) => Ok(FileInfo {
+                size: content.len() as u64,
+                modified: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs()),
+            }),
+            Some(None) => Err(io::ErrorKind::NotFound.into()),
+            None => self.inner.metadata(file),
+        }
+    }
+
+    fn list_backups(&self) -> io::Result<Vec<String>> {
+        let mut names = self.inner.list_backups()?;
+        for (file, content) in self.files.lock().unwrap().iter() {
+            let DeviceFile::Backup(time) = *file else {
+                continue;
+            };
+            let name = device::backup_file_name(time);
+            names.retain(|n| *n != name);
+            if content.is_some() {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    fn force_stop(&self, pkg: &str) -> io::Result<()> {
+        log(format!("would stop {pkg}"));
+        Ok(())
+    }
+
+    fn clear_cache(&self, pkg: &str) -> io::Result<()> {
+        log(format!("would clear the cache of {pkg}"));
+        Ok(())
+    }
+
+    fn relabel(&self, file: DeviceFile) -> io::Result<()> {
+        log(format!("would relabel {}", self.path(file)?));
+        Ok(())
+    }
+
+    fn open_store_page(&self, pkg: &str) -> io::Result<()> {
+        self.inner.open_store_page(pkg)
+    }
+
+    fn app_labels(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.inner.app_labels(pkgs)
+    }
+
+    fn app_details(&self, pkgs: &[&str]) -> io::Result<Vec<u8>> {
+        self.inner.app_details(pkgs)
+    }
+
+    fn probe_module(&self) -> io::Result<Vec<u8>> {
+        self.inner.probe_module()
+    }
+}
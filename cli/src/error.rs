@@ -0,0 +1,210 @@
+//! What can go wrong, grouped by what the user has to do about it
+
+use std::fmt::Display;
+use std::io;
+use std::process::ExitCode;
+
+use detach_core::device::NotDetected;
+
+/// The exit statuses scripts can go by, listed under `detach --help`
+#[derive(Clone, Copy)]
+pub enum Exit {
+    /// Done, and something was changed when the command changes things
+    Success = 0,
+    Failure = 1,
+    /// The arguments do not make sense, or a prompt was needed without a terminal
+    Usage = 2,
+    /// No su, su refused, or no root solution at all
+    NoRoot = 3,
+    /// The zygisk-detach module is not installed
+    NoModule = 4,
+    /// A package named is not installed, or not detached for `reattach`
+    NotFound = 5,
+    /// There was nothing to change
+    Unchanged = 6,
+    /// A command did not finish in time, as `pm` while the phone is still booting
+    Timeout = 7,
+    /// `status <package>`: the app is installed and not detached
+    Attached = 8,
+    /// The menus were left with Ctrl+C
+    Interrupted = 130,
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The terminal could not be queried or driven
+    Terminal(io::Error),
+    /// No root access to the module files
+    Root(io::Error),
+    /// Running through Shizuku, where the module files cannot be changed
+    NoRoot(io::Error),
+    /// There is no root solution or no module to work on
+    NotDetected(io::Error),
+    /// Listing the installed packages failed
+    Pm(io::Error),
+    /// detach.bin could not be read or written
+    DetachFile(io::Error),
+    /// detach.bin does not decode
+    Corrupted,
+    /// Another program changed detach.bin since it was read, holds what was to be written
+    ChangedOutside(Vec<u8>),
+    /// The Play Store could not be stopped to pick up the changes
+    Restart(io::Error),
+    /// A command was killed for taking too long, another try may get through
+    Timeout(io::Error),
+    /// Another copy of the cli is changing the list, see [`detach_core::instance`]
+    Busy(io::Error),
+    /// The change detaches packages the Play Store needs, see [`crate::risky`]. Holds
+    /// them and what was to be written
+    Risky(Vec<String>, Vec<u8>),
+    Io(io::Error),
+}
+
+impl Error {
+    /// Exit status of the process for this error
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.code())
+    }
+
+    pub fn code(&self) -> u8 {
+        let exit = match self {
+            Self::Root(_) | Self::NoRoot(_) => Exit::NoRoot,
+            Self::NotDetected(e) => match NotDetected::of(e) {
+                Some(NotDetected::Module(_)) => Exit::NoModule,
+                _ => Exit::NoRoot,
+            },
+            Self::Timeout(_) => Exit::Timeout,
+            Self::Io(_)
+            | Self::Terminal(_)
+            | Self::Pm(_)
+            | Self::DetachFile(_)
+            | Self::Corrupted
+            | Self::ChangedOutside(_)
+            | Self::Restart(_)
+            | Self::Busy(_)
+            | Self::Risky(..) => Exit::Failure,
+        };
+        exit as u8
+    }
+
+    /// Sorts a failed access to the module files into `Root`, `NotDetected` or
+    /// `DetachFile`
+    pub fn detach_file(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::TimedOut {
+            Self::Timeout(err)
+        } else if NotDetected::of(&err).is_some() {
+            Self::NotDetected(err)
+        } else if err.kind() == io::ErrorKind::PermissionDenied {
+            Self::Root(err)
+        } else if err.kind() == io::ErrorKind::Unsupported {
+            Self::NoRoot(err)
+        } else {
+            Self::DetachFile(err)
+        }
+    }
+
+    /// `Pm`, or `Timeout` for a listing that was killed
+    pub fn pm(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::TimedOut {
+            Self::Timeout(err)
+        } else {
+            Self::Pm(err)
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Terminal(e) => write!(
+                f,
+                "terminal error: {e}; try another terminal, --plain or the subcommands"
+            ),
+            Self::Root(e) => write!(f, "{e}; run as root (su -c detach)"),
+            Self::NoRoot(e) => write!(f, "{e}; add --dry-run to see what would change"),
+            Self::NotDetected(e) => write!(f, "{e}"),
+            Self::Pm(e) => write!(f, "could not list the installed packages: {e}"),
+            Self::DetachFile(e) => write!(f, "could not access detach.bin: {e}"),
+            Self::Corrupted => write!(
+                f,
+                "Corrupted detach.bin. Run `detach doctor --fix` or reset it and try again."
+            ),
+            Self::ChangedOutside(_) => write!(
+                f,
+                "detach.bin was changed by another program since it was read, nothing was written; try again"
+            ),
+            Self::Restart(e) => write!(
+                f,
+                "detach.bin was written but the Play Store could not be restarted: {e}"
+            ),
+            Self::Timeout(e) => write!(
+                f,
+                "{e}; the package manager may still be starting, wait for the phone to finish booting and try again"
+            ),
+            Self::Busy(e) => write!(f, "{e}; try again once it is done"),
+            Self::Risky(apps, _) => {
+                for app in apps {
+                    writeln!(f, "detaching {app}: {}", crate::risky::consequence(app))?;
+                }
+                write!(f, "nothing was written; add --force to detach them anyway")
+            }
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::TimedOut {
+            Self::Timeout(err)
+        } else if NotDetected::of(&err).is_some() {
+            Self::NotDetected(err)
+        } else {
+            Self::Io(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use detach_core::device::Root;
+
+    #[test]
+    fn each_failure_has_its_exit_code() {
+        let not_detected = |n| io::Error::other(n);
+        let cases = [
+            (Error::from(not_detected(NotDetected::Root)), 3),
+            (
+                Error::from(not_detected(NotDetected::Module(Root::Magisk))),
+                4,
+            ),
+            (
+                Error::detach_file(not_detected(NotDetected::Module(Root::KernelSU))),
+                4,
+            ),
+            (
+                Error::detach_file(io::ErrorKind::PermissionDenied.into()),
+                3,
+            ),
+            (Error::detach_file(io::ErrorKind::Unsupported.into()), 3),
+            (Error::detach_file(io::ErrorKind::TimedOut.into()), 7),
+            (Error::detach_file(io::ErrorKind::NotFound.into()), 1),
+            (Error::pm(io::ErrorKind::TimedOut.into()), 7),
+            (Error::pm(io::ErrorKind::Other.into()), 1),
+            (Error::Corrupted, 1),
+            (Error::Busy(io::ErrorKind::WouldBlock.into()), 1),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.code(), code, "{err}");
+        }
+    }
+}
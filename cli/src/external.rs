@@ -0,0 +1,106 @@
+//! Changes other programs make to detach.bin while the cli has it open, as a restored
+//! backup or another root app. The cli remembers what it read or wrote last and
+//! refuses to write over anything else, so the menus can ask what to do about it
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use detach_core::device::{self, DeviceFile};
+
+/// How often the menus look at detach.bin
+pub const POLL: Duration = Duration::from_secs(5);
+
+struct Stamp {
+    hash: u64,
+    /// Filled in by the first poll, so reading the file costs no `stat`
+    modified: Option<u64>,
+    /// What the last poll found
+    changed: bool,
+}
+
+/// detach.bin as the cli last read or wrote it
+static SEEN: Mutex<Option<Stamp>> = Mutex::new(None);
+
+fn seen() -> std::sync::MutexGuard<'static, Option<Stamp>> {
+    SEEN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn hash(content: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Remembers `content` as what detach.bin holds now
+pub fn record(content: &[u8]) {
+    *seen() = Some(Stamp {
+        hash: hash(content),
+        modified: None,
+        changed: false,
+    });
+}
+
+/// Whether `current`, detach.bin as read now, is not what the cli read or wrote last
+pub fn changed(current: &[u8]) -> bool {
+    seen()
+        .as_ref()
+        .is_some_and(|stamp| stamp.hash != hash(current))
+}
+
+/// Runs `write`, which writes `content`, and remembers it when it succeeded. A poll
+/// cannot come between the two and take the cli's own write for someone else's
+pub fn writing(content: &[u8], write: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+    let mut seen = seen();
+    write()?;
+    *seen = Some(Stamp {
+        hash: hash(content),
+        modified: None,
+        changed: false,
+    });
+    Ok(())
+}
+
+/// Whether detach.bin was changed since the cli read or wrote it last. It is only read
+/// again when its modification time moved
+pub fn poll() -> bool {
+    let device = device::get();
+    let mut seen = seen();
+    let Some(stamp) = seen.as_mut() else {
+        return false;
+    };
+    let modified = device
+        .metadata(DeviceFile::DetachBin)
+        .ok()
+        .map(|m| m.modified);
+    if stamp.modified.is_some() && stamp.modified == modified {
+        return stamp.changed;
+    }
+    let current = match device.read(DeviceFile::DetachBin) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(_) => return stamp.changed,
+    };
+    stamp.modified = modified;
+    stamp.changed = stamp.hash != hash(&current);
+    stamp.changed
+}
+
+/// Polls detach.bin every [`POLL`] in the background, calling `on_change` with what
+/// [`poll`] found whenever that is different from the time before
+pub fn watch(on_change: impl Fn(bool) + Send + 'static) {
+    thread::spawn(move || {
+        let mut was = false;
+        loop {
+            thread::sleep(POLL);
+            let changed = poll();
+            if changed != was {
+                was = changed;
+                on_change(changed);
+            }
+        }
+    });
+}
@@ -0,0 +1,322 @@
+//! Subsequence matching for the app filter, so that "gmaps" finds com.google.android.apps.maps
+
+use crate::colorize::ToColored;
+use std::fmt::Display;
+
+pub struct Match {
+    pub index: usize,
+    text: String,
+    positions: Vec<usize>,
+}
+
+impl Display for Match {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, c) in self.text.chars().enumerate() {
+            if self.positions.contains(&i) {
+                write!(f, "{}", c.green())?;
+            } else {
+                write!(f, "{c}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_word_start(chars: &[char], i: usize) -> bool {
+    i == 0 || matches!(chars[i - 1], '.' | '_' | '-' | ' ')
+}
+
+/// Scores how well `query`, already lowercased, matches the candidate with `chars`
+/// and their lowercase `lower` as a subsequence. Returns the score and the char
+/// positions of the matched characters
+fn score(query: &[char], chars: &[char], lower: &[char]) -> Option<(i32, Vec<usize>)> {
+    if query.is_empty() {
+        return None;
+    }
+
+    // find where the first full match ends, then walk back from there
+    // for the tightest window that still contains the query
+    let mut qi = 0;
+    let mut end = 0;
+    for (i, &c) in lower.iter().enumerate() {
+        if c == query[qi] {
+            qi += 1;
+            if qi == query.len() {
+                end = i;
+                break;
+            }
+        }
+    }
+    if qi < query.len() {
+        return None;
+    }
+    let mut positions = vec![0; query.len()];
+    let mut qi = query.len();
+    for i in (0..=end).rev() {
+        if lower[i] == query[qi - 1] {
+            qi -= 1;
+            positions[qi] = i;
+            if qi == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut score = 0;
+    for (n, &p) in positions.iter().enumerate() {
+        score += 1;
+        if is_word_start(chars, p) {
+            score += 8;
+        }
+        if n > 0 {
+            let gap = p - positions[n - 1] - 1;
+            if gap == 0 {
+                score += 5;
+            } else {
+                score -= (gap as i32).min(5);
+            }
+        }
+    }
+    score -= (chars.len() as i32 - query.len() as i32) / 8;
+    Some((score, positions))
+}
+
+impl Match {
+    /// `text` listed without a query, nothing in it highlighted
+    pub fn plain(index: usize, text: String) -> Self {
+        Self {
+            index,
+            text,
+            positions: Vec::new(),
+        }
+    }
+
+    /// The match drawn as `shown` instead, as an item that shows more than it is
+    /// matched on does. Only the characters both start with stay highlighted
+    pub fn shown_as(mut self, shown: String) -> Self {
+        let same = (self.text.chars())
+            .zip(shown.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        self.positions.retain(|&p| p < same);
+        self.text = shown;
+        self
+    }
+}
+
+/// A query matched against the same candidates as it is typed. The candidates are
+/// split into chars and lowercased once, and a query that only grew is matched
+/// against the ones the shorter query matched, as a subsequence match of the longer
+/// one is also one of the shorter
+pub struct Filter {
+    texts: Vec<String>,
+    chars: Vec<Vec<char>>,
+    lower: Vec<Vec<char>>,
+    query: String,
+    /// index, score and positions of the candidates `query` matched
+    matched: Vec<(usize, i32, Vec<usize>)>,
+}
+
+impl Filter {
+    pub fn new(texts: Vec<String>) -> Self {
+        let chars: Vec<Vec<char>> = texts.iter().map(|t| t.chars().collect()).collect();
+        let lower = chars
+            .iter()
+            .map(|chars| {
+                chars
+                    .iter()
+                    .map(|c| c.to_lowercase().next().unwrap_or(*c))
+                    .collect()
+            })
+            .collect();
+        Self {
+            texts,
+            chars,
+            lower,
+            query: String::new(),
+            matched: Vec::new(),
+        }
+    }
+
+    /// The query last matched
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Matches `query`, going through only the previous matches when it extends the
+    /// previous query
+    pub fn update(&mut self, query: &str) {
+        if query == self.query {
+            return;
+        }
+        let lowered: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+        let score = |i: usize| {
+            let (score, positions) = score(&lowered, &self.chars[i], &self.lower[i])?;
+            Some((i, score, positions))
+        };
+        self.matched = if !self.query.is_empty() && query.starts_with(&self.query) {
+            self.matched
+                .iter()
+                .filter_map(|&(i, ..)| score(i))
+                .collect()
+        } else {
+            (0..self.texts.len()).filter_map(score).collect()
+        };
+        self.query = query.to_string();
+    }
+
+    /// The `limit` best matches, best first
+    pub fn best(&self, limit: usize) -> Vec<Match> {
+        let mut best: Vec<&(usize, i32, Vec<usize>)> = self.matched.iter().collect();
+        // stable, so equal scores stay in list order
+        best.sort_by_key(|&&(_, score, _)| std::cmp::Reverse(score));
+        best.into_iter()
+            .take(limit)
+            .map(|(index, _, positions)| Match {
+                index: *index,
+                text: self.texts[*index].clone(),
+                positions: positions.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(texts: &[&str], query: &str) -> Filter {
+        let mut filter = Filter::new(texts.iter().map(|t| t.to_string()).collect());
+        filter.update(query);
+        filter
+    }
+
+    fn best(texts: &[&str], query: &str) -> Vec<String> {
+        let best = filter(texts, query).best(usize::MAX);
+        best.into_iter().map(|m| m.text).collect()
+    }
+
+    #[test]
+    fn word_starts_and_runs_rank_first() {
+        let apps = [
+            "com.google.android.gm",
+            "com.gamespot.app",
+            "com.google.android.apps.maps",
+            "org.gmaps",
+        ];
+        assert_eq!(
+            best(&apps, "gmaps"),
+            ["org.gmaps", "com.google.android.apps.maps"]
+        );
+        assert_eq!(
+            best(&["banana", "bandana", "ananas"], "ana"),
+            ["ananas", "banana", "bandana"]
+        );
+        // case does not matter, and a longer name loses to a shorter one
+        assert_eq!(
+            best(&["x.Maps.long.name", "x.maps"], "MAPS"),
+            ["x.maps", "x.Maps.long.name"]
+        );
+    }
+
+    #[test]
+    fn equal_scores_keep_the_list_order() {
+        assert_eq!(
+            best(&["b.app", "a.app", "c.app"], "app"),
+            ["b.app", "a.app", "c.app"]
+        );
+        assert_eq!(filter(&["b.app", "a.app"], "app").best(1)[0].index, 0);
+    }
+
+    #[test]
+    fn no_query_or_no_subsequence_matches_nothing() {
+        assert!(best(&["com.app1"], "").is_empty());
+        assert!(best(&["com.app1"], "1ppa").is_empty());
+    }
+
+    #[test]
+    fn typing_on_narrows_the_matches() {
+        let mut filter = filter(&["com.app1", "org.xxx2", "com.apq"], "ap");
+        assert_eq!(filter.matched.len(), 2);
+        filter.update("app");
+        assert_eq!(filter.best(5).len(), 1);
+        filter.update("x");
+        assert_eq!(filter.best(5)[0].index, 1);
+    }
+
+    #[test]
+    fn the_first_tightest_match_is_highlighted() {
+        // "ana" is in banana twice, overlapping on the middle a
+        let banana = &filter(&["banana"], "ana").best(1)[0];
+        assert_eq!(banana.positions, [1, 2, 3]);
+        let green = |s: &str| s.chars().map(|c| c.green().to_string()).collect::<String>();
+        assert_eq!(banana.to_string(), format!("b{}na", green("ana")));
+        // the last g of google, the one nearest the rest
+        let maps = &filter(&["com.google.maps"], "gmaps").best(1)[0];
+        assert_eq!(maps.positions, [7, 11, 12, 13, 14]);
+        // wide characters count as one position
+        let wide = &filter(&["日本.app"], "本a").best(1)[0];
+        assert_eq!(
+            wide.to_string(),
+            format!("日{}.{}pp", green("本"), green("a"))
+        );
+    }
+
+    #[test]
+    fn shown_as_keeps_the_highlight_on_the_shared_start() {
+        let m = filter(&["com.app"], "capp").best(1).remove(0);
+        let shown = m.shown_as("com.app (App)".to_string());
+        assert_eq!(shown.positions, [0, 4, 5, 6]);
+        let m = filter(&["com.app"], "capp").best(1).remove(0);
+        assert_eq!(m.shown_as("App".to_string()).positions, []);
+    }
+
+    #[test]
+    fn typing_on_is_faster_than_filtering_from_scratch() {
+        use std::time::{Duration, Instant};
+
+        let texts: Vec<String> = (0..5000)
+            .map(|i| format!("com.Vendor{}.App{i}", i % 50))
+            .collect();
+        let typed = "vendor7app";
+        let prefixes = || (1..=typed.len()).map(|n| &typed[..n]);
+        // the fastest of a few runs, so a busy machine does not decide it
+        let fastest = |run: &dyn Fn() -> Vec<usize>| {
+            let mut fastest = Duration::MAX;
+            let mut found = Vec::new();
+            for _ in 0..3 {
+                let start = Instant::now();
+                found = run();
+                fastest = fastest.min(start.elapsed());
+            }
+            (fastest, found)
+        };
+        let (incremental, found) = fastest(&|| {
+            let mut filter = Filter::new(texts.clone());
+            prefixes()
+                .map(|query| {
+                    filter.update(query);
+                    filter.matched.len()
+                })
+                .collect()
+        });
+        // each key lowering and matching the whole list anew
+        let (scratch, found_anew) = fastest(&|| {
+            prefixes()
+                .map(|query| {
+                    let mut filter = Filter::new(texts.clone());
+                    filter.update(query);
+                    filter.matched.len()
+                })
+                .collect()
+        });
+        assert_eq!(found, found_anew);
+        assert_eq!(found[0], 5000);
+        // the apps of vendors 7, 17, 27, 37 and 47
+        assert_eq!(found[typed.len() - 1], 500);
+        assert!(
+            incremental < scratch,
+            "{incremental:?} typing on, {scratch:?} from scratch"
+        );
+    }
+}
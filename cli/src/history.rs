@@ -0,0 +1,158 @@
+//! Every change to detach.bin with the operation that made it, appended to a log next
+//! to the config so `detach history` can tell whether and when an app was detached.
+//! One change per line: `<time>\t<operation>\t<apps in detach.bin>\t+detached... -reattached...`
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::journal::{self, Change};
+use crate::{config, dry_run};
+
+#[cfg(target_os = "android")]
+const HISTORY: &str = "/sdcard/zygisk-detach/history.log";
+#[cfg(target_os = "linux")]
+const HISTORY: &str = "zygisk-detach-history.log";
+/// Where the log goes once it is too big, replacing the one before
+#[cfg(target_os = "android")]
+const HISTORY_OLD: &str = "/sdcard/zygisk-detach/history.log.1";
+#[cfg(target_os = "linux")]
+const HISTORY_OLD: &str = "zygisk-detach-history.log.1";
+
+/// Bytes the log grows to before it is rotated, about a thousand changes
+const MAX_SIZE: u64 = 64 * 1024;
+
+/// What the changes being made are logged as
+static OPERATION: Mutex<String> = Mutex::new(String::new());
+
+/// Names the operation until the guard is dropped, the one before is named again then
+pub fn operation(name: &str) -> Operation {
+    let mut op = OPERATION.lock().unwrap_or_else(|e| e.into_inner());
+    Operation(std::mem::replace(&mut op, name.to_string()))
+}
+
+pub struct Operation(String);
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        *OPERATION.lock().unwrap_or_else(|e| e.into_inner()) = std::mem::take(&mut self.0);
+    }
+}
+
+pub struct Entry {
+    pub time: u64,
+    pub operation: String,
+    /// How many apps detach.bin held afterwards
+    pub entries: usize,
+    pub change: Change,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        let time = fields.next()?.parse().ok()?;
+        let operation = fields.next()?.to_string();
+        let entries = fields.next()?.parse().ok()?;
+        let mut change = Change {
+            time,
+            undone: false,
+            detached: Vec::new(),
+            reattached: Vec::new(),
+        };
+        for word in fields.next().unwrap_or_default().split_whitespace() {
+            match word.split_at_checked(1)? {
+                ("+", app) => change.detached.push(app.to_string()),
+                ("-", app) => change.reattached.push(app.to_string()),
+                _ => return None,
+            }
+        }
+        Some(Self {
+            time,
+            operation,
+            entries,
+            change,
+        })
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Appends that detach.bin went from `before` to `after`. The file is opened each
+/// time, so a log removed meanwhile is started over. A dry run changes nothing to log
+pub fn log(before: &[u8], after: &[u8]) -> io::Result<()> {
+    if dry_run::enabled() {
+        return Ok(());
+    }
+    let (detached, reattached) = journal::diff(before, after);
+    if detached.is_empty() && reattached.is_empty() {
+        return Ok(());
+    }
+    let operation = OPERATION.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let operation = match operation.as_str() {
+        "" => "unknown".to_string(),
+        // the fields are split on tabs and the lines on newlines
+        op => op.replace(|c: char| c.is_control(), " "),
+    };
+    let mut line = format!("{}\t{operation}\t{}\t", now(), journal::names(after).len());
+    let apps = detached
+        .iter()
+        .map(|app| format!("+{app}"))
+        .chain(reattached.iter().map(|app| format!("-{app}")));
+    line.extend(apps.intersperse(" ".to_string()));
+    line.push('\n');
+
+    if fs::metadata(HISTORY).is_ok_and(|m| m.len() > MAX_SIZE) {
+        fs::rename(HISTORY, HISTORY_OLD)?;
+    }
+    config::create_parent(HISTORY)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(HISTORY)?
+        .write_all(line.as_bytes())
+}
+
+/// The last `n` changes, newest first, reaching into the rotated log when needed
+pub fn last(n: usize) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for path in [HISTORY, HISTORY_OLD] {
+        let txt = match fs::read_to_string(path) {
+            Ok(txt) => txt,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        entries.extend(txt.lines().rev().filter_map(Entry::parse));
+        if entries.len() >= n {
+            break;
+        }
+    }
+    entries.truncate(n);
+    Ok(entries)
+}
+
+/// How long ago `time` was, as `5m ago`
+pub fn ago(time: u64) -> String {
+    let secs = now().saturating_sub(time);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// `2h ago  detach  detached com.a (12 apps)`, without escape codes
+pub fn describe(entry: &Entry) -> String {
+    format!(
+        "{:>8}  {}  {} ({} apps)",
+        ago(entry.time),
+        entry.operation,
+        entry.change.describe(),
+        entry.entries
+    )
+}
@@ -0,0 +1,106 @@
+//! Where apps were installed from, as `pm list packages -i` tells. Only the Play Store
+//! updates what it installed, so the menus can leave the other apps out
+
+use std::fmt::Display;
+
+use crate::colorize::ToColored;
+
+pub const PLAY_STORE: &str = "com.android.vending";
+
+/// The store or tool `installer` stands for
+pub fn name(installer: Option<&str>) -> &str {
+    match installer {
+        Some(PLAY_STORE) => "Play Store",
+        Some("org.fdroid.fdroid" | "org.fdroid.basic") => "F-Droid",
+        Some("com.aurora.store") => "Aurora Store",
+        Some("com.google.android.packageinstaller" | "com.android.packageinstaller") => {
+            "package installer"
+        }
+        Some("com.android.shell") => "adb",
+        Some(installer) => installer,
+        None => "no installer",
+    }
+}
+
+/// `item` followed by where it was installed from, dimmed
+pub struct Sourced<'a, T> {
+    pub item: T,
+    pub installer: Option<&'a str>,
+}
+
+impl<T: Display> Display for Sourced<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.item,
+            format_args!("· {}", name(self.installer)).faint()
+        )
+    }
+}
+
+/// Which apps the detach menu lists, stepped through with TAB
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    #[default]
+    All,
+    PlayStore,
+    /// Anything else, apps without an installer included
+    Other,
+}
+
+impl Filter {
+    pub fn next(self) -> Self {
+        match self {
+            Self::All => Self::PlayStore,
+            Self::PlayStore => Self::Other,
+            Self::Other => Self::All,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::All => "all apps",
+            Self::PlayStore => "Play Store apps",
+            Self::Other => "apps from elsewhere",
+        }
+    }
+
+    pub fn matches(self, installer: Option<&str>) -> bool {
+        match self {
+            Self::All => true,
+            Self::PlayStore => installer == Some(PLAY_STORE),
+            Self::Other => installer != Some(PLAY_STORE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_cycle_back_to_all() {
+        let filter = Filter::default();
+        assert!(filter == Filter::All);
+        assert!(filter.next().next().next() == Filter::All);
+    }
+
+    #[test]
+    fn apps_without_an_installer_are_other() {
+        assert!(Filter::PlayStore.matches(Some(PLAY_STORE)));
+        assert!(!Filter::PlayStore.matches(None));
+        assert!(Filter::Other.matches(None));
+        assert!(Filter::Other.matches(Some("org.fdroid.fdroid")));
+        assert!(!Filter::Other.matches(Some(PLAY_STORE)));
+        assert!(Filter::All.matches(None));
+    }
+
+    #[test]
+    fn installer_names() {
+        assert_eq!(name(Some(PLAY_STORE)), "Play Store");
+        assert_eq!(name(Some("org.fdroid.basic")), "F-Droid");
+        assert_eq!(name(Some("com.example.store")), "com.example.store");
+        assert_eq!(name(None), "no installer");
+    }
+}
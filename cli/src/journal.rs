@@ -0,0 +1,222 @@
+//! The last changes to detach.bin as the apps each one detached and re-attached, so the
+//! latest can be undone and an undo redone. Kept as text next to the config, one change
+//! per line: `<time> <done|undone> +detached... -reattached...`
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    config, detach_bin, detach_bin_changed, dry_run, error, external, history, meta, readback,
+};
+use crate::{get_detached_apps, read_detach_bin, CLIResult};
+
+#[cfg(target_os = "android")]
+const JOURNAL: &str = "/sdcard/zygisk-detach/journal.txt";
+#[cfg(target_os = "linux")]
+const JOURNAL: &str = "zygisk-detach-journal.txt";
+
+/// How many changes are kept, the oldest are dropped first
+const KEEP: usize = 50;
+
+pub struct Change {
+    pub time: u64,
+    /// Undone changes are at the end of the journal, waiting to be redone
+    pub undone: bool,
+    pub detached: Vec<String>,
+    pub reattached: Vec<String>,
+}
+
+impl Change {
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let time = words.next()?.parse().ok()?;
+        let undone = match words.next()? {
+            "done" => false,
+            "undone" => true,
+            _ => return None,
+        };
+        let mut change = Self {
+            time,
+            undone,
+            detached: Vec::new(),
+            reattached: Vec::new(),
+        };
+        for word in words {
+            match word.split_at_checked(1)? {
+                ("+", app) => change.detached.push(app.to_string()),
+                ("-", app) => change.reattached.push(app.to_string()),
+                _ => return None,
+            }
+        }
+        Some(change)
+    }
+
+    fn line(&self) -> String {
+        let mut line = format!(
+            "{} {}",
+            self.time,
+            if self.undone { "undone" } else { "done" }
+        );
+        for app in &self.detached {
+            line.push_str(&format!(" +{app}"));
+        }
+        for app in &self.reattached {
+            line.push_str(&format!(" -{app}"));
+        }
+        line
+    }
+
+    /// `detached com.a, re-attached 4 apps`
+    pub fn describe(&self) -> String {
+        let apps = |apps: &[String]| match apps {
+            [] | [_] | [_, _] | [_, _, _] => apps.join(", "),
+            _ => format!("{} apps", apps.len()),
+        };
+        let mut parts = Vec::new();
+        if !self.detached.is_empty() {
+            parts.push(format!("detached {}", apps(&self.detached)));
+        }
+        if !self.reattached.is_empty() {
+            parts.push(format!("re-attached {}", apps(&self.reattached)));
+        }
+        parts.join(", ")
+    }
+}
+
+fn load() -> io::Result<Vec<Change>> {
+    match fs::read_to_string(JOURNAL) {
+        Ok(txt) => Ok(txt.lines().filter_map(Change::parse).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn save(changes: &[Change]) -> io::Result<()> {
+    let skip = changes.len().saturating_sub(KEEP);
+    let mut txt = String::new();
+    for change in &changes[skip..] {
+        txt.push_str(&change.line());
+        txt.push('\n');
+    }
+    config::create_parent(JOURNAL)?;
+    dry_run::write_local(JOURNAL, txt.as_bytes())
+}
+
+pub fn names(content: &[u8]) -> Vec<String> {
+    detach_bin::parse(content)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// The apps detached and re-attached going from `before` to `after`
+pub fn diff(before: &[u8], after: &[u8]) -> (Vec<String>, Vec<String>) {
+    let (before, after) = (names(before), names(after));
+    let detached = after
+        .iter()
+        .filter(|a| !before.contains(a))
+        .cloned()
+        .collect();
+    let reattached = before
+        .iter()
+        .filter(|a| !after.contains(a))
+        .cloned()
+        .collect();
+    (detached, reattached)
+}
+
+/// Notes that detach.bin went from `before` to `after`. The undone changes cannot be
+/// redone after that. A dry run changes nothing worth undoing
+pub fn record(before: &[u8], after: &[u8]) -> io::Result<()> {
+    if dry_run::enabled() {
+        return Ok(());
+    }
+    let (detached, reattached) = diff(before, after);
+    if detached.is_empty() && reattached.is_empty() {
+        return Ok(());
+    }
+    let mut changes = load()?;
+    changes.retain(|c| !c.undone);
+    changes.push(Change {
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        undone: false,
+        detached,
+        reattached,
+    });
+    save(&changes)
+}
+
+/// When each app was last detached, as far as the journal goes back
+pub fn detached_times() -> HashMap<String, u64> {
+    let mut times = HashMap::new();
+    for change in load().unwrap_or_default() {
+        if change.undone {
+            continue;
+        }
+        for app in change.detached {
+            times.insert(app, change.time);
+        }
+        for app in &change.reattached {
+            times.remove(app);
+        }
+    }
+    times
+}
+
+/// The change `undo` would revert
+pub fn last() -> io::Result<Option<Change>> {
+    Ok(load()?.into_iter().rfind(|c| !c.undone))
+}
+
+/// Detaches `detach` and re-attaches `reattach` on top of whatever detach.bin holds
+/// now, without journaling it
+fn apply(detach: &[String], reattach: &[String]) -> CLIResult<()> {
+    let content = read_detach_bin()?;
+    let entries = get_detached_apps(&content)?;
+    let mut new = Vec::with_capacity(content.len());
+    for (name, range) in &entries {
+        if !reattach.contains(name) {
+            new.extend_from_slice(&content[range.clone()]);
+        }
+    }
+    for app in detach {
+        if !entries.iter().any(|(name, _)| name == app) {
+            detach_bin::serialize(app, &mut new)?;
+        }
+    }
+    let new = detach_bin::normalize(&new).unwrap_or(new);
+    external::writing(&new, || readback::write(&new)).map_err(error::Error::detach_file)?;
+    let _ = history::log(&content, &new);
+    let _ = meta::update(&content, &new);
+    detach_bin_changed();
+    Ok(())
+}
+
+/// Reverts the last change that is not undone yet, returns it
+pub fn undo() -> CLIResult<Option<Change>> {
+    let mut changes = load()?;
+    let Some(i) = changes.iter().rposition(|c| !c.undone) else {
+        return Ok(None);
+    };
+    apply(&changes[i].reattached, &changes[i].detached)?;
+    changes[i].undone = true;
+    save(&changes)?;
+    Ok(changes.into_iter().nth(i))
+}
+
+/// Makes the earliest undone change again, returns it
+pub fn redo() -> CLIResult<Option<Change>> {
+    let mut changes = load()?;
+    let Some(i) = changes.iter().position(|c| c.undone) else {
+        return Ok(None);
+    };
+    apply(&changes[i].detached, &changes[i].reattached)?;
+    changes[i].undone = false;
+    save(&changes)?;
+    Ok(changes.into_iter().nth(i))
+}
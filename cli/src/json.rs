@@ -0,0 +1,257 @@
+//! Machine readable output for scripts and web UIs, written by hand to stay free of
+//! dependencies. The reading side is only as much as the cli's own files need
+
+use std::fmt::Display;
+
+/// Schema version of every document. Bumped when a field changes meaning or goes away,
+/// new fields may show up without it
+pub const VERSION: u32 = 1;
+
+pub trait ToJson {
+    fn to_json(&self) -> String;
+}
+
+impl ToJson for str {
+    fn to_json(&self) -> String {
+        let mut out = String::with_capacity(self.len() + 2);
+        out.push('"');
+        for c in self.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+        out
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> String {
+        self.as_str().to_json()
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> String {
+        self.to_string()
+    }
+}
+
+macro_rules! number {
+    ($($t:ty),*) => {$(
+        impl ToJson for $t {
+            fn to_json(&self) -> String {
+                self.to_string()
+            }
+        }
+    )*};
+}
+number!(u8, u32, u64, usize);
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> String {
+        self.as_ref().map_or_else(|| "null".to_string(), T::to_json)
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> String {
+        let items: String = self
+            .iter()
+            .map(T::to_json)
+            .intersperse(",".to_string())
+            .collect();
+        format!("[{items}]")
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> String {
+        self.as_slice().to_json()
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self) -> String {
+        (**self).to_json()
+    }
+}
+
+/// An object with its fields in the order they were added
+#[derive(Default)]
+pub struct Object(Vec<(&'static str, String)>);
+
+impl Object {
+    /// A top level document, starting with the schema version
+    pub fn document() -> Self {
+        Self::default().field("version", VERSION)
+    }
+
+    pub fn field(mut self, key: &'static str, value: impl ToJson) -> Self {
+        self.0.push((key, value.to_json()));
+        self
+    }
+}
+
+impl ToJson for Object {
+    fn to_json(&self) -> String {
+        let fields: String = self
+            .0
+            .iter()
+            .map(|(key, value)| format!("{}:{value}", key.to_json()))
+            .intersperse(",".to_string())
+            .collect();
+        format!("{{{fields}}}")
+    }
+}
+
+impl Display for Object {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_json())
+    }
+}
+
+/// A parsed document. Numbers are kept as f64, as JSON has them
+pub enum Value {
+    Number(f64),
+    String(String),
+    Object(Vec<(String, Value)>),
+    /// null, a boolean or an array, none of the cli's files read them
+    Other,
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as u64),
+            _ => None,
+        }
+    }
+}
+
+/// The document in `text`, `None` when it is not valid JSON
+pub fn parse(text: &str) -> Option<Value> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+    };
+    let value = parser.value()?;
+    parser.space();
+    parser.chars.peek().is_none().then_some(value)
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn space(&mut self) {
+        while self.chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+    }
+
+    fn eat(&mut self, word: &str) -> Option<()> {
+        word.chars()
+            .all(|c| self.chars.next() == Some(c))
+            .then_some(())
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.space();
+        Some(match *self.chars.peek()? {
+            'n' => self.eat("null").map(|_| Value::Other)?,
+            't' => self.eat("true").map(|_| Value::Other)?,
+            'f' => self.eat("false").map(|_| Value::Other)?,
+            '"' => Value::String(self.string()?),
+            '[' => {
+                self.chars.next();
+                self.space();
+                if self.chars.next_if_eq(&']').is_none() {
+                    loop {
+                        self.value()?;
+                        self.space();
+                        match self.chars.next()? {
+                            ',' => {}
+                            ']' => break,
+                            _ => return None,
+                        }
+                    }
+                }
+                Value::Other
+            }
+            '{' => {
+                self.chars.next();
+                let mut fields = Vec::new();
+                self.space();
+                if self.chars.next_if_eq(&'}').is_none() {
+                    loop {
+                        self.space();
+                        let key = self.string()?;
+                        self.space();
+                        self.eat(":")?;
+                        fields.push((key, self.value()?));
+                        self.space();
+                        match self.chars.next()? {
+                            ',' => {}
+                            '}' => break,
+                            _ => return None,
+                        }
+                    }
+                }
+                Value::Object(fields)
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+                {
+                    number.push(c);
+                }
+                Value::Number(number.parse().ok()?)
+            }
+        })
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.eat("\"")?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => return Some(out),
+                '\\' => out.push(match self.chars.next()? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let hex: String =
+                            (0..4).map(|_| self.chars.next()).collect::<Option<_>>()?;
+                        char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                    }
+                    c => c,
+                }),
+                c => out.push(c),
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! The keys each kind of menu takes and what they do. The menus look every key up in
+//! their keymap and `?` lists the same bindings, so the two cannot drift apart
+
+use std::borrow::Cow;
+
+use termion::event::Key;
+
+use crate::menus::KeyName;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Pick,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    First,
+    Last,
+    Copy,
+    /// Letters not bound to anything else, in lists without a filter
+    Search,
+    /// The items' numbers and the letters past 9, in numbered lists
+    Number,
+    Check,
+    CheckAll,
+    Invert,
+    /// TAB in the filter menus that switch between lists
+    Cycle,
+    /// The key a caller asked to be handed back, as `s` for sorting
+    Pass,
+    /// Opens the highlighted app's Play Store page, where the menu was given a way to
+    Open,
+    /// `*` in the multi-select lists the caller checks several items at once for
+    Bulk,
+    LineStart,
+    LineEnd,
+    Left,
+    Right,
+    DeleteBack,
+    DeleteForward,
+    DeleteWord,
+    DeleteToStart,
+    Help,
+    Leave,
+    /// Ctrl+C on the main menu, which exits with its own status
+    Interrupt,
+}
+
+impl Action {
+    /// The key `navigate` moves the selection by
+    pub fn motion(self) -> Option<Key> {
+        Some(match self {
+            Self::Up => Key::Up,
+            Self::Down => Key::Down,
+            Self::PageUp => Key::PageUp,
+            Self::PageDown => Key::PageDown,
+            Self::First => Key::Home,
+            Self::Last => Key::End,
+            _ => return None,
+        })
+    }
+
+    /// What stands for the keys of the actions bound to no key in particular
+    fn typed(self) -> &'static str {
+        match self {
+            Self::Search => "letters",
+            Self::Number => "numbers, a-z",
+            _ => "",
+        }
+    }
+}
+
+/// The keys of a binding, what they do and the line `?` shows for them
+type Base = &'static [(&'static [Key], Action, &'static str)];
+
+const MOVES: Base = &[
+    (&[Key::Up, Key::Char('k')], Action::Up, "move up"),
+    (&[Key::Down, Key::Char('j')], Action::Down, "move down"),
+    (&[Key::PageUp], Action::PageUp, "a page up"),
+    (&[Key::PageDown], Action::PageDown, "a page down"),
+    (
+        &[Key::Home, Key::Char('g')],
+        Action::First,
+        "to the first item",
+    ),
+    (
+        &[Key::End, Key::Char('G')],
+        Action::Last,
+        "to the last item",
+    ),
+];
+
+const COMMON: Base = &[
+    (
+        &[Key::Char('?'), Key::F(1)],
+        Action::Help,
+        "show these keys",
+    ),
+    (&[Key::Esc, Key::Ctrl('c')], Action::Leave, "leave"),
+];
+
+/// Lists to pick one item from
+pub const LIST: &[Base] = &[
+    &[(
+        &[Key::Char('\n')],
+        Action::Pick,
+        "pick the highlighted item",
+    )],
+    MOVES,
+    &[
+        (&[Key::Char('y')], Action::Copy, "copy the highlighted item"),
+        (&[], Action::Search, "jump to the item starting with them"),
+    ],
+    COMMON,
+];
+
+/// Lists to check items in
+pub const MULTI: &[Base] = &[
+    &[
+        (
+            &[Key::Char(' ')],
+            Action::Check,
+            "check or uncheck the highlighted item",
+        ),
+        (&[Key::Char('\n')], Action::Pick, "take the checked items"),
+        (&[Key::Char('a')], Action::CheckAll, "check all"),
+        (&[Key::Char('i')], Action::Invert, "invert what is checked"),
+    ],
+    MOVES,
+    &[
+        (&[Key::Char('y')], Action::Copy, "copy the highlighted item"),
+        (
+            &[],
+            Action::Search,
+            "show only the items containing them, ESC shows all again",
+        ),
+    ],
+    COMMON,
+];
+
+/// Lists filtered by what is typed, the letters go to the filter
+pub const INPUT: &[Base] = &[
+    &[
+        (
+            &[Key::Char('\n')],
+            Action::Pick,
+            "pick the highlighted item",
+        ),
+        (&[Key::Up], Action::Up, "move up"),
+        (&[Key::Down], Action::Down, "move down"),
+        (&[Key::PageUp], Action::PageUp, "a page up"),
+        (&[Key::PageDown], Action::PageDown, "a page down"),
+        (&[Key::Left], Action::Left, "move the cursor left"),
+        (&[Key::Right], Action::Right, "move the cursor right"),
+        (
+            &[Key::Home, Key::Ctrl('a')],
+            Action::LineStart,
+            "to the start of the line",
+        ),
+        (
+            &[Key::End, Key::Ctrl('e')],
+            Action::LineEnd,
+            "to the end of the line",
+        ),
+        (
+            &[Key::Backspace],
+            Action::DeleteBack,
+            "delete before the cursor",
+        ),
+        (
+            &[Key::Delete],
+            Action::DeleteForward,
+            "delete under the cursor",
+        ),
+        (
+            &[Key::Ctrl('w')],
+            Action::DeleteWord,
+            "delete the word before the cursor",
+        ),
+        (
+            &[Key::Ctrl('u')],
+            Action::DeleteToStart,
+            "delete to the start of the line",
+        ),
+    ],
+    COMMON,
+];
+
+/// Lists picked from by number, as the main menu
+pub const NUMBERED: &[Base] = &[
+    &[
+        (
+            &[],
+            Action::Number,
+            "pick the item with that number or letter",
+        ),
+        (
+            &[Key::Char('\n')],
+            Action::Pick,
+            "pick the highlighted item",
+        ),
+    ],
+    MOVES,
+    &[
+        (
+            &[Key::Char('?'), Key::F(1)],
+            Action::Help,
+            "show these keys",
+        ),
+        (&[Key::Esc], Action::Leave, "leave"),
+        (&[Key::Ctrl('c')], Action::Interrupt, "leave at once"),
+    ],
+];
+
+pub struct Binding {
+    keys: Vec<Key>,
+    action: Action,
+    help: Cow<'static, str>,
+}
+
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Keymap {
+    pub fn new(base: &[Base]) -> Self {
+        let bindings = (base.iter().flat_map(|b| b.iter()))
+            .map(|&(keys, action, help)| Binding {
+                keys: keys.to_vec(),
+                action,
+                help: Cow::Borrowed(help),
+            })
+            .collect();
+        Self { bindings }
+    }
+
+    /// `key` doing what the binding for `action` does, as the quit key a menu is given
+    pub fn also(mut self, key: Option<Key>, action: Action) -> Self {
+        if let Some(key) = key {
+            match self.bindings.iter_mut().find(|b| b.action == action) {
+                Some(binding) if !binding.keys.contains(&key) => binding.keys.push(key),
+                _ => {}
+            }
+        }
+        self
+    }
+
+    /// A binding of `key` of its own, as `s` for sorting
+    pub fn with(
+        mut self,
+        key: Option<Key>,
+        action: Action,
+        help: impl Into<Cow<'static, str>>,
+    ) -> Self {
+        if let Some(key) = key {
+            let binding = Binding {
+                keys: vec![key],
+                action,
+                help: help.into(),
+            };
+            self.bindings.push(binding);
+        }
+        self
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        (self.bindings.iter())
+            .find(|b| b.keys.contains(&key))
+            .map(|b| b.action)
+    }
+
+    /// Whether `c` does something, and so is no letter to search for
+    pub fn binds(&self, c: char) -> bool {
+        self.action(Key::Char(c))
+            .is_some_and(|a| !matches!(a, Action::Search))
+    }
+
+    /// `keys  what they do` for each binding, the keys padded to one width
+    pub fn lines(&self) -> Vec<String> {
+        let keys: Vec<String> = (self.bindings.iter())
+            .map(|b| match b.keys.is_empty() {
+                true => b.action.typed().to_string(),
+                false => b
+                    .keys
+                    .iter()
+                    .map(|&k| KeyName(k).to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+            .collect();
+        let width = keys.iter().map(|k| k.chars().count()).max().unwrap_or(0);
+        (keys.iter().zip(&self.bindings))
+            .map(|(keys, b)| format!("{keys:width$}  {}", b.help))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_listed_by_the_names_the_menus_use() {
+        let lines = Keymap::new(LIST).lines();
+        assert!(lines[0].starts_with("Enter "), "{}", lines[0]);
+        assert!(lines.iter().any(|l| l.starts_with("Up, ")), "{lines:?}");
+        for line in &lines {
+            assert!(!line.contains("ENTER") && !line.contains('↑'), "{line}");
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! Human readable app names for the package lists. Resolving them means reading every APK,
+//! so they are cached on disk per package and version and looked up in the background
+//! while the menu is already up
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::colorize::{self, ToColored};
+use crate::progress::Progress;
+use crate::{atomic, device, users};
+
+#[cfg(target_os = "android")]
+pub const LABELS_CACHE: &str = "/data/adb/zygisk-detach/labels.cache";
+#[cfg(target_os = "linux")]
+const LABELS_CACHE: &str = "labels.cache";
+
+/// How many packages one resolver call handles, so labels show up while the rest load
+const BATCH: usize = 16;
+
+/// package -> (versionCode, label), the label empty when the APK has none
+type Cache = HashMap<String, (String, String)>;
+
+fn parse_cache(content: &str) -> Cache {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let pkg = fields.next()?;
+            let version = fields.next()?;
+            let label = fields.next()?;
+            Some((pkg.to_string(), (version.to_string(), label.to_string())))
+        })
+        .collect()
+}
+
+fn serialize_cache(cache: &Cache) -> String {
+    let mut out = String::new();
+    for (pkg, (version, label)) in cache {
+        out.push_str(&format!("{pkg}\t{version}\t{label}\n"));
+    }
+    out
+}
+
+/// `package:<name> versionCode:<code>` lines from `pm list packages --show-versioncode`
+fn parse_versions(out: &[u8]) -> HashMap<String, String> {
+    String::from_utf8_lossy(out)
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().strip_prefix("package:")?;
+            let (pkg, version) = line.split_once(" versionCode:").unwrap_or((line, ""));
+            Some((pkg.to_string(), version.to_string()))
+        })
+        .collect()
+}
+
+fn parse_labels(out: &[u8]) -> impl Iterator<Item = (String, String)> + '_ {
+    out.split(|&b| b == b'\n')
+        .filter_map(|line| std::str::from_utf8(line).ok())
+        .filter_map(|line| {
+            let (pkg, rest) = line.split_once(' ').unwrap_or((line.trim(), ""));
+            if pkg.is_empty() {
+                return None;
+            }
+            let label = rest
+                .trim()
+                .strip_prefix("application-label:'")
+                .and_then(|l| l.strip_suffix('\''))
+                .unwrap_or("");
+            // tabs and newlines would break the cache lines
+            let label = label.replace(['\t', '\r', '\n'], " ");
+            Some((pkg.to_string(), label))
+        })
+}
+
+/// Frames of the spinner shown next to apps whose label is still being looked up
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+/// How often the spinner turns while labels load
+const TICK: Duration = Duration::from_millis(120);
+
+#[derive(Default)]
+struct Shared {
+    labels: Mutex<HashMap<String, String>>,
+    /// The apps the resolver has not got to yet
+    loading: Mutex<HashSet<String>>,
+    /// Set when the menu is left, the resolver stops after the batch it is on
+    stop: AtomicBool,
+}
+
+/// Labels of installed apps, filled in by a background thread that stops once
+/// these are dropped
+pub struct Labels {
+    shared: Arc<Shared>,
+    started: Instant,
+}
+
+impl Drop for Labels {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Shared {
+    fn loading(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.loading.lock().unwrap()
+    }
+}
+
+/// Empties the loading set when the resolver thread ends, early or not
+struct Done<'a>(&'a Shared);
+
+impl Drop for Done<'_> {
+    fn drop(&mut self) {
+        self.0.loading().clear();
+    }
+}
+
+impl Labels {
+    fn new(labels: HashMap<String, String>, loading: HashSet<String>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                labels: Mutex::new(labels),
+                loading: Mutex::new(loading),
+                stop: AtomicBool::new(false),
+            }),
+            started: Instant::now(),
+        }
+    }
+
+    /// Starts resolving the labels of `apps`. Whatever is cached is usable right away,
+    /// `on_update` is called from the resolver thread as more labels come in and while
+    /// the spinner turns
+    pub fn load(apps: &[&str], on_update: impl Fn() + Send + Sync + 'static) -> Self {
+        let mut cache = fs::read_to_string(LABELS_CACHE)
+            .map(|c| parse_cache(&c))
+            .unwrap_or_default();
+        let known = apps
+            .iter()
+            .filter_map(|app| Some((app.to_string(), cache.get(*app)?.1.clone())))
+            .collect();
+        let labels = Self::new(known, apps.iter().map(|a| a.to_string()).collect());
+
+        let on_update = Arc::new(on_update);
+        let shared = Arc::clone(&labels.shared);
+        let tick = Arc::clone(&on_update);
+        thread::spawn(move || {
+            while !shared.stop.load(Ordering::Relaxed) && !shared.loading().is_empty() {
+                thread::sleep(TICK);
+                tick();
+            }
+        });
+
+        let apps: Vec<String> = apps.iter().map(|a| a.to_string()).collect();
+        let shared = Arc::clone(&labels.shared);
+        thread::spawn(move || {
+            // the spinner stops however this ends
+            let _done = Done(&shared);
+            let device = device::get();
+            let Ok(out) = device.list_packages(&["--show-versioncode"]) else {
+                return;
+            };
+            let versions = parse_versions(&out);
+            let misses: Vec<&str> = apps
+                .iter()
+                .filter(|app| {
+                    let version = versions.get(*app).map_or("", String::as_str);
+                    cache.get(*app).is_none_or(|(v, _)| v != version)
+                })
+                .map(String::as_str)
+                .collect();
+            shared
+                .loading()
+                .retain(|app| misses.contains(&app.as_str()));
+            let mut progress = Progress::logged(misses.len());
+            for batch in misses.chunks(BATCH) {
+                if shared.stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                let Ok(out) = device.app_labels(batch) else {
+                    return;
+                };
+                {
+                    let mut map = shared.labels.lock().unwrap();
+                    for (pkg, label) in parse_labels(&out) {
+                        let version = versions.get(&pkg).cloned().unwrap_or_default();
+                        if label.is_empty() {
+                            map.remove(&pkg);
+                        } else {
+                            map.insert(pkg.clone(), label.clone());
+                        }
+                        cache.insert(pkg, (version, label));
+                    }
+                }
+                shared
+                    .loading()
+                    .retain(|app| !batch.contains(&app.as_str()));
+                progress.advance(batch.len(), batch.last().copied().unwrap_or_default());
+                let _ = atomic::write(LABELS_CACHE, serialize_cache(&cache).as_bytes());
+                if !shared.stop.load(Ordering::Relaxed) {
+                    on_update();
+                }
+            }
+        });
+        labels
+    }
+
+    /// The labels of `apps` found in the cache, for output that cannot wait on new ones
+    pub fn cached(apps: &[&str]) -> Self {
+        let cache = fs::read_to_string(LABELS_CACHE)
+            .map(|c| parse_cache(&c))
+            .unwrap_or_default();
+        let map = apps
+            .iter()
+            .filter_map(|app| {
+                let (_, label) = cache.get(*app)?;
+                (!label.is_empty()).then(|| (app.to_string(), label.clone()))
+            })
+            .collect();
+        Self::new(map, HashSet::new())
+    }
+
+    pub fn get(&self, app: &str) -> Option<String> {
+        self.shared.labels.lock().unwrap().get(app).cloned()
+    }
+
+    /// The spinner frame for `app` while its label is being looked up
+    fn spinner(&self, app: &str) -> Option<char> {
+        if !self.shared.loading().contains(app) {
+            return None;
+        }
+        let frame = self.started.elapsed().as_millis() / TICK.as_millis();
+        Some(SPINNER[frame as usize % SPINNER.len()])
+    }
+
+    /// `app` shown with its label, or bare when the label is not known (yet)
+    pub fn labeled<'a>(&'a self, app: &'a str) -> Labeled<'a> {
+        Labeled { labels: self, app }
+    }
+}
+
+pub struct Labeled<'a> {
+    labels: &'a Labels,
+    app: &'a str,
+}
+
+impl Display for Labeled<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.labels.get(self.app) {
+            Some(label) => write!(f, "{label} ({})", self.app)?,
+            None => write!(f, "{}", self.app)?,
+        }
+        // only where it turns, plain output would be left with a stray frame
+        if let Some(frame) = self
+            .labels
+            .spinner(self.app)
+            .filter(|_| colorize::enabled())
+        {
+            write!(f, " {}", frame.faint())?;
+        }
+        match users::found_under(self.app) {
+            Some(users) => write!(f, " {}", users.faint()),
+            None => Ok(()),
+        }
+    }
+}
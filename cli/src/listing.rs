@@ -0,0 +1,243 @@
+//! Which packages the detach menu lists: the apps the user installed by default, or the
+//! system apps some OEM stores update through Play, or both. Disabled apps can be in
+//! either and are marked
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use detach_core::{parse_installed_apps, parse_installers};
+
+use crate::colorize::ToColored;
+
+/// The `pm list packages` flags of the listings [`merge`] takes, in its order
+pub const THIRD_PARTY: &[&str] = &["-i", "-3"];
+pub const SYSTEM: &[&str] = &["-i", "-s"];
+pub const DISABLED: &[&str] = &["-d"];
+
+pub struct Listed<'a> {
+    pub name: &'a str,
+    pub installer: Option<&'a str>,
+    pub system: bool,
+    pub disabled: bool,
+}
+
+/// The packages of the `-i -3` and `-i -s` listings, each once, with the ones the `-d`
+/// listing has flagged. A package in both, as a listing merged over users can have,
+/// counts as a third-party app, and one only `-d` lists is left out
+pub fn merge<'a>(third_party: &'a [u8], system: &'a [u8], disabled: &'a [u8]) -> Vec<Listed<'a>> {
+    let disabled: HashSet<&str> = parse_installed_apps(disabled).collect();
+    let mut listed: Vec<Listed> = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (out, system) in [(third_party, false), (system, true)] {
+        for (name, installer) in parse_installers(out) {
+            if let Some(&i) = seen.get(name) {
+                listed[i].installer = listed[i].installer.or(installer);
+                continue;
+            }
+            seen.insert(name, listed.len());
+            listed.push(Listed {
+                name,
+                installer,
+                system,
+                disabled: disabled.contains(name),
+            });
+        }
+    }
+    listed
+}
+
+/// Which of the listed packages the menu shows, stepped through with Ctrl+S
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scope {
+    #[default]
+    ThirdParty,
+    System,
+    Both,
+}
+
+impl Scope {
+    pub fn next(self) -> Self {
+        match self {
+            Self::ThirdParty => Self::System,
+            Self::System => Self::Both,
+            Self::Both => Self::ThirdParty,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ThirdParty => "installed apps",
+            Self::System => "system apps",
+            Self::Both => "installed and system apps",
+        }
+    }
+
+    pub fn matches(self, listed: &Listed) -> bool {
+        match self {
+            Self::ThirdParty => !listed.system,
+            Self::System => listed.system,
+            Self::Both => true,
+        }
+    }
+}
+
+/// `item` followed by `system` and `disabled` where they apply
+pub struct Tagged<T> {
+    pub item: T,
+    pub system: bool,
+    pub disabled: bool,
+}
+
+impl<T: Display> Display for Tagged<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.item)?;
+        if self.system {
+            write!(f, " {}", "system".cyan())?;
+        }
+        if self.disabled {
+            write!(f, " {}", "disabled".yellow())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The name, installer and flags of each listed package
+    fn merged(
+        third_party: &str,
+        system: &str,
+        disabled: &str,
+    ) -> Vec<(String, Option<String>, bool, bool)> {
+        (merge(
+            third_party.as_bytes(),
+            system.as_bytes(),
+            disabled.as_bytes(),
+        )
+        .iter())
+        .map(|l| {
+            (
+                l.name.to_string(),
+                l.installer.map(str::to_string),
+                l.system,
+                l.disabled,
+            )
+        })
+        .collect()
+    }
+
+    fn row(
+        name: &str,
+        installer: Option<&str>,
+        system: bool,
+        disabled: bool,
+    ) -> (String, Option<String>, bool, bool) {
+        (
+            name.to_string(),
+            installer.map(str::to_string),
+            system,
+            disabled,
+        )
+    }
+
+    #[test]
+    fn third_party_apps_come_before_system_apps() {
+        let listed = merged(
+            "package:com.app1  installer=com.android.vending\npackage:org.xxx2  installer=null\n",
+            "package:com.oem.gallery  installer=com.android.vending\n",
+            "",
+        );
+        assert_eq!(
+            listed,
+            [
+                row("com.app1", Some("com.android.vending"), false, false),
+                row("org.xxx2", None, false, false),
+                row("com.oem.gallery", Some("com.android.vending"), true, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_package_in_both_listings_is_third_party_once() {
+        let listed = merged(
+            "package:com.both  installer=null\npackage:com.both  installer=null\n",
+            "package:com.both  installer=com.oem.store\n",
+            "",
+        );
+        // the installer only the system listing knew is kept
+        assert_eq!(
+            listed,
+            [row("com.both", Some("com.oem.store"), false, false)]
+        );
+    }
+
+    #[test]
+    fn disabled_apps_are_flagged_in_either_listing() {
+        let listed = merged(
+            "package:com.off  installer=null\npackage:com.on  installer=null\n",
+            "package:com.oem.off  installer=null\n",
+            "package:com.off\npackage:com.oem.off\npackage:com.only.disabled\n",
+        );
+        assert_eq!(
+            listed,
+            [
+                row("com.off", None, false, true),
+                row("com.on", None, false, false),
+                row("com.oem.off", None, true, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_listed() {
+        assert!(merged("", "", "package:com.off\n").is_empty());
+    }
+
+    #[test]
+    fn scopes_cycle_through_what_they_show() {
+        let listed = merge(
+            b"package:com.app1  installer=null\n",
+            b"package:com.oem  installer=null\n",
+            b"",
+        );
+        let mut scope = Scope::default();
+        let mut shown = Vec::new();
+        for _ in 0..4 {
+            let names: Vec<&str> = (listed.iter())
+                .filter(|l| scope.matches(l))
+                .map(|l| l.name)
+                .collect();
+            shown.push((scope.name(), names));
+            scope = scope.next();
+        }
+        assert_eq!(
+            shown,
+            [
+                ("installed apps", vec!["com.app1"]),
+                ("system apps", vec!["com.oem"]),
+                ("installed and system apps", vec!["com.app1", "com.oem"]),
+                ("installed apps", vec!["com.app1"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_follow_the_item() {
+        let tagged = |system, disabled| {
+            Tagged {
+                item: "com.app1",
+                system,
+                disabled,
+            }
+            .to_string()
+        };
+        assert_eq!(tagged(false, false), "com.app1");
+        assert_eq!(tagged(true, false), format!("com.app1 {}", "system".cyan()));
+        assert_eq!(
+            tagged(true, true),
+            format!("com.app1 {} {}", "system".cyan(), "disabled".yellow())
+        );
+    }
+}
@@ -1,373 +1,2855 @@
-#![feature(iter_intersperse, print_internals)]
+#![feature(iter_intersperse)]
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display};
-use std::fs::{self, OpenOptions};
-use std::io::{self, Seek};
-use std::io::{BufWriter, Read, Write};
-use std::mem::size_of;
+use std::fs;
+use std::io;
+use std::io::Write;
 use std::ops::Range;
+use std::os::fd::AsRawFd;
 use std::panic::Location;
-use std::process::{Command, ExitCode};
-use termion::raw::IntoRawMode;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use termion::event::Key;
+use termion::event::{Event, Key};
 use termion::{clear, cursor};
 
 mod colorize;
-use colorize::ToColored;
+use colorize::{ColorChoice, ToColored};
+use error::Exit;
 
 mod menus;
-use menus::Menus;
+use menus::{Checked, Level, Menus, Row, Selection};
 
-#[cfg(target_os = "android")]
-const MODULE_DETACH: &str = "/data/adb/modules/zygisk-detach/detach.bin";
-#[cfg(target_os = "android")]
-const DETACH_TXT: &str = "/data/adb/modules/zygisk-detach/detach.txt";
+use detach_core::detach_bin::{self, DetachEntry, MODULE_CAP};
+use detach_core::device::{self, DeviceFile};
+use detach_core::{
+    atomic, instance, log, parse_installed_apps, parse_installers, parse_txt, runner, PackageTimes,
+};
 
-#[cfg(target_os = "linux")]
-const MODULE_DETACH: &str = "detach.bin";
-#[cfg(target_os = "linux")]
-const DETACH_TXT: &str = "detach.txt";
+mod backup;
+mod bulk;
+mod checks;
+mod clipboard;
+mod commands;
+mod config;
+mod custom_file;
+use config::Mode;
+mod details;
+mod diff;
+mod dry_run;
+mod error;
+mod external;
+mod fuzzy;
+mod history;
+mod installers;
+mod journal;
+mod json;
+mod keymap;
+mod labels;
+mod listing;
+mod meta;
+mod packages;
+mod profiles;
+mod progress;
+mod readback;
+mod recent;
+mod risky;
+mod rules;
+mod sort;
+use sort::Sort;
+mod suggest;
+mod table;
+mod termux;
+use rules::{Rules, RULES_TXT};
+mod users;
 
-extern "C" {
-    fn kill(pid: i32, sig: i32) -> i32;
-}
+#[cfg(target_os = "android")]
+const EXPORT_TXT: &str = "/sdcard/zygisk-detach.txt";
+#[cfg(target_os = "linux")]
+const EXPORT_TXT: &str = "zygisk-detach.txt";
 
 struct CLIErr<E: Error> {
     source: E,
     loc: &'static Location<'static>,
 }
-impl<E: Error> Error for CLIErr<E> {}
+impl Error for CLIErr<error::Error> {}
 impl<E: Error> Debug for CLIErr<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}\r\nat {}", self.source, self.loc)
     }
 }
-impl<E: Error> Display for CLIErr<E> {
+impl Display for CLIErr<error::Error> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(self, f)
+        match self.source {
+            // only unexpected errors are worth pointing at the code for
+            error::Error::Io(_) => Debug::fmt(self, f),
+            _ => Display::fmt(&self.source, f),
+        }
     }
 }
-impl From<io::Error> for CLIErr<io::Error> {
+impl From<io::Error> for CLIErr<error::Error> {
     #[track_caller]
     fn from(err: io::Error) -> Self {
+        error::Error::Io(err).into()
+    }
+}
+impl From<error::Error> for CLIErr<error::Error> {
+    #[track_caller]
+    fn from(err: error::Error) -> Self {
         Self {
             source: err,
             loc: Location::caller(),
         }
     }
 }
+impl CLIErr<error::Error> {
+    fn exit_code(&self) -> ExitCode {
+        self.source.exit_code()
+    }
+
+    fn code(&self) -> u8 {
+        self.source.code()
+    }
+}
 
-type IOResult<T> = Result<T, CLIErr<io::Error>>;
+type CLIResult<T> = Result<T, CLIErr<error::Error>>;
 
 fn main() -> ExitCode {
     std::panic::set_hook(Box::new(|panic| {
-        if let Ok(mut stdout) = io::stdout().into_raw_mode() {
-            let _ = writeln!(stdout, "\r\n{panic}\r\n");
-            let _ = writeln!(stdout, "This should not have happened.\r");
-            let _ = writeln!(
-                stdout,
-                "Report at https://github.com/j-hc/zygisk-detach/issues\r"
-            );
-            let _ = write!(stdout, "{}", cursor::Show);
-        }
+        let mut stdout = io::stdout();
+        menus::restore_terminal(&mut stdout);
+        let _ = writeln!(stdout, "\n{panic}\n");
+        let _ = writeln!(stdout, "This should not have happened.");
+        let _ = writeln!(
+            stdout,
+            "Report at https://github.com/j-hc/zygisk-detach/issues"
+        );
     }));
+    termux::fix_path();
 
-    let mut args = std::env::args().skip(1);
-    if matches!(args.next().as_deref(), Some("--serialize")) {
-        let Some(dtxt) = args.next() else {
-            eprintln!("detach.txt path not supplied.");
-            return ExitCode::FAILURE;
-        };
-        let Some(dbin) = args.next() else {
-            eprintln!("detach.bin path not supplied.");
-            return ExitCode::FAILURE;
-        };
-
-        if let Err(err) = serialize_txt(&dtxt, &dbin) {
-            eprintln!("ERROR: {err}");
-            return ExitCode::FAILURE;
-        } else {
-            println!("Serialized detach.txt");
-            return ExitCode::SUCCESS;
+    use config::Value;
+    let mut adb = false;
+    let mut shizuku = false;
+    let mut root_check = true;
+    let mut serial = None;
+    let mut file = None;
+    let mut wizard = false;
+    if let Some(d) = std::env::var("ZYGISK_DETACH_MODULE_DIR")
+        .ok()
+        .filter(|d| !d.is_empty())
+    {
+        config::flag("module_dir", Value::Str(d), "$ZYGISK_DETACH_MODULE_DIR");
+    }
+    let color = |c: &str| Value::Str(c.to_string());
+    let mut args = Vec::new();
+    let mut all_args = std::env::args().skip(1);
+    while let Some(arg) = all_args.next() {
+        match arg.as_str() {
+            "--no-color" => config::flag("color", color("never"), "--no-color"),
+            "--color=never" => config::flag("color", color("never"), "--color"),
+            "--color=always" => config::flag("color", color("always"), "--color"),
+            "--color=auto" => config::flag("color", color("auto"), "--color"),
+            "--adb" => adb = true,
+            "--shizuku" => shizuku = true,
+            "--dry-run" => config::flag("dry_run", Value::Bool(true), "--dry-run"),
+            "--plain" => config::flag("plain", Value::Bool(true), "--plain"),
+            "--no-altscreen" => config::flag("altscreen", Value::Bool(false), "--no-altscreen"),
+            "-q" | "--quiet" => log::set_level(log::Level::Quiet),
+            // given twice it is -vv
+            "-v" | "--verbose" => log::set_level(match log::level() {
+                log::Level::Verbose | log::Level::Debug => log::Level::Debug,
+                _ => log::Level::Verbose,
+            }),
+            "-vv" => log::set_level(log::Level::Debug),
+            "--no-root-check" => root_check = false,
+            "--no-restart" => config::flag("no_restart", Value::Bool(true), "--no-restart"),
+            "--wizard" => wizard = true,
+            "--force" => risky::force(),
+            "-s" => {
+                let Some(s) = all_args.next() else {
+                    eprintln!("-s needs a device serial.");
+                    return Exit::Usage.into();
+                };
+                serial = Some(s);
+            }
+            "--user" => {
+                let Some(scope) = all_args.next().as_deref().and_then(users::Scope::parse) else {
+                    eprintln!("--user needs a user id or `all`.");
+                    return Exit::Usage.into();
+                };
+                users::set_scope(scope);
+            }
+            "--theme" => {
+                let Some(t) = all_args
+                    .next()
+                    .filter(|t| colorize::Theme::parse(t).is_some())
+                else {
+                    eprintln!("--theme needs one of default, contrast or plain.");
+                    return Exit::Usage.into();
+                };
+                config::flag("theme", Value::Str(t), "--theme");
+            }
+            "--timeout" => {
+                let Some(secs) = all_args.next().and_then(|t| t.parse::<u64>().ok()) else {
+                    eprintln!("--timeout needs a number of seconds, 0 for no limit.");
+                    return Exit::Usage.into();
+                };
+                config::flag("timeout", Value::Int(secs as i64), "--timeout");
+            }
+            "--file" => {
+                let Some(f) = all_args.next() else {
+                    eprintln!("--file needs the path of a detach.bin.");
+                    return Exit::Usage.into();
+                };
+                file = Some(f);
+            }
+            "--module-dir" => {
+                let Some(d) = all_args.next() else {
+                    eprintln!("--module-dir needs a directory.");
+                    return Exit::Usage.into();
+                };
+                config::flag("module_dir", Value::Str(d), "--module-dir");
+            }
+            _ => {
+                if let Some(s) = arg.strip_prefix("--adb=") {
+                    adb = true;
+                    serial = Some(s.to_string());
+                } else if let Some(t) = arg.strip_prefix("--theme=") {
+                    if colorize::Theme::parse(t).is_none() {
+                        eprintln!("--theme needs one of default, contrast or plain.");
+                        return Exit::Usage.into();
+                    }
+                    config::flag("theme", Value::Str(t.to_string()), "--theme");
+                } else if let Some(t) = arg.strip_prefix("--timeout=") {
+                    let Ok(secs) = t.parse::<u64>() else {
+                        eprintln!("--timeout needs a number of seconds, 0 for no limit.");
+                        return Exit::Usage.into();
+                    };
+                    config::flag("timeout", Value::Int(secs as i64), "--timeout");
+                } else if let Some(f) = arg.strip_prefix("--file=") {
+                    file = Some(f.to_string());
+                } else if let Some(d) = arg.strip_prefix("--module-dir=") {
+                    config::flag("module_dir", Value::Str(d.to_string()), "--module-dir");
+                } else if let Some(u) = arg.strip_prefix("--user=") {
+                    let Some(scope) = users::Scope::parse(u) else {
+                        eprintln!("--user needs a user id or `all`.");
+                        return Exit::Usage.into();
+                    };
+                    users::set_scope(scope);
+                } else {
+                    args.push(arg);
+                }
+            }
         }
     }
+    let config = config::Config::load().unwrap_or_else(|e| {
+        eprintln!("warning: could not read the config: {e}");
+        config::Config::default()
+    });
+    let mut color = config.color;
+    // plain prompts are for tools that would read the escape codes out loud
+    if config.plain && color == ColorChoice::Auto {
+        color = ColorChoice::Never;
+    }
+    NO_RESTART.store(config.no_restart, Ordering::Relaxed);
+    colorize::init(color);
+    colorize::set_theme(config.theme);
+    runner::set_timeout(std::time::Duration::from_secs(config.timeout));
     #[cfg(target_os = "android")]
-    if let Ok(true) = check_denylist() {
-        eprintln!("Stop putting Play Store in denylist!");
+    if root_check
+        && !adb
+        && !shizuku
+        && !matches!(args.first().map(String::as_str), Some("-h" | "--help"))
+        && !commands::without_root(&args)
+    {
+        match ensure_root() {
+            Ok(root) => shizuku = !root,
+            Err(code) => return code,
+        }
+    }
+    #[cfg(not(target_os = "android"))]
+    let _ = root_check;
+    if !adb && !shizuku {
+        termux::migrate();
+    }
+    let device: Box<dyn device::Device> = if shizuku && adb {
+        Box::new(device::Shizuku::adb(serial, config.module_dir))
+    } else if shizuku {
+        Box::new(device::Shizuku::rish(config.module_dir))
+    } else if adb {
+        Box::new(device::Adb::new(serial, config.module_dir))
+    } else if serial.is_some() {
+        eprintln!("-s only applies to --adb.");
+        return Exit::Usage.into();
+    } else {
+        Box::new(device::Local::new(config.module_dir))
+    };
+    if file.is_some()
+        && !matches!(
+            args.first().map(String::as_str),
+            Some("list" | "inspect" | "import" | "export")
+        )
+    {
+        eprintln!("--file only applies to list, inspect, import and export.");
+        return Exit::Usage.into();
+    }
+    if wizard && !args.is_empty() {
+        eprintln!("--wizard only applies to the menus.");
+        return Exit::Usage.into();
     }
-    let mut menus = Menus::new();
-    let ret = match interactive(&mut menus) {
-        Ok(()) => ExitCode::SUCCESS,
-        Err(err) => {
-            eprintln!("\rERROR: {err}");
-            ExitCode::FAILURE
+    let device = match custom_file::resolve(file, std::env::var("ZYGISK_DETACH_FILE").ok()) {
+        Some((path, foreign)) => {
+            if foreign {
+                NO_RESTART.store(true, Ordering::Relaxed);
+            }
+            Box::new(custom_file::CustomFile::new(device, path, foreign)) as Box<dyn device::Device>
+        }
+        None => device,
+    };
+    if config.dry_run {
+        device::set(Box::new(dry_run::DryRun::new(device)));
+    } else {
+        device::set(device);
+    }
+
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        Some("--serialize") => serialize(args),
+        Some(
+            cmd @ ("detach" | "reattach" | "list" | "export" | "import" | "prune" | "watch"
+            | "doctor" | "verify" | "sync" | "mode" | "status" | "rules" | "profile"
+            | "undo" | "redo" | "config" | "history" | "compare" | "inspect" | "reset"),
+        ) => {
+            if log::level() == log::Level::Quiet {
+                silence_stdout();
+            }
+            commands::run(cmd, args)
+        }
+        Some("-h" | "--help") => {
+            println!("{}", commands::usage());
+            Exit::Success.into()
         }
+        Some(arg) => {
+            eprintln!("Unknown argument: {arg}");
+            eprintln!("{}", commands::usage());
+            Exit::Usage.into()
+        }
+        None => {
+            #[cfg(target_os = "android")]
+            if !adb
+                && matches!(device::get().environment(), Ok(e) if matches!(e.root, device::Root::Magisk))
+                && matches!(check_denylist(), Ok(true))
+            {
+                eprintln!("Stop putting Play Store in denylist!");
+            }
+            // the log is there from every earlier time the menus were opened
+            let wizard = wizard
+                || !(fs::metadata(config::LOG).is_ok()
+                    || fs::metadata(config::WIZARD_DONE).is_ok());
+            if let Err(e) =
+                config::create_parent(config::LOG).and_then(|()| log::to_file(config::LOG))
+            {
+                eprintln!("warning: could not open {}: {e}", config::LOG);
+            }
+            // the menus only tell success from failure, and Ctrl+C
+            let mut menus = match Menus::new(config.plain, config.altscreen) {
+                Ok(menus) => menus,
+                Err(err) => {
+                    eprintln!("ERROR: {err}");
+                    return Exit::Failure.into();
+                }
+            };
+            match interactive(&mut menus, wizard) {
+                Ok(exit) => exit.into(),
+                Err(err) => {
+                    drop(menus);
+                    eprintln!("\rERROR: {err}");
+                    Exit::Failure.into()
+                }
+            }
+        }
+    }
+}
+
+/// Points stdout at /dev/null for `-q`, leaving stderr for the errors
+fn silence_stdout() {
+    if let Ok(null) = fs::OpenOptions::new().write(true).open("/dev/null") {
+        unsafe { libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO) };
+    }
+}
+
+fn serialize(mut args: impl Iterator<Item = String>) -> ExitCode {
+    let Some(dtxt) = args.next() else {
+        eprintln!("detach.txt path not supplied.");
+        return Exit::Usage.into();
+    };
+    let Some(dbin) = args.next() else {
+        eprintln!("detach.bin path not supplied.");
+        return Exit::Usage.into();
+    };
+
+    if let Err(err) = serialize_txt(&dtxt, &dbin) {
+        eprintln!("ERROR: {err}");
+        err.exit_code()
+    } else {
+        println!("Serialized detach.txt");
+        Exit::Success.into()
+    }
+}
+
+/// Set for the copy of the cli started through su, so a su that does not actually
+/// give root cannot make it start itself over and over
+#[cfg(target_os = "android")]
+const REEXEC_ENV: &str = "ZYGISK_DETACH_REEXEC";
+
+/// Makes sure the cli runs as root before anything touches the terminal or the module,
+/// by starting itself again through su with the same arguments. Only returns when
+/// already root, or with `false` when there is no su but Shizuku is set up, with the
+/// exit code to use when root cannot be had
+#[cfg(target_os = "android")]
+fn ensure_root() -> Result<bool, ExitCode> {
+    use detach_core::su::{self, SuError};
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    if unsafe { libc::geteuid() } == 0 {
+        return Ok(true);
+    }
+    let explain = |why: &str| {
+        eprintln!("zygisk-detach needs root to change detach.bin, but {why}.");
+        eprintln!("Grant root to this terminal app in your root manager and run `su -c detach`,");
+        eprintln!("or run `detach --adb` on a computer the phone is connected to.");
+        eprintln!("Pass --no-root-check to skip this check.");
+        error::Error::Root(io::ErrorKind::PermissionDenied.into()).exit_code()
+    };
+    if std::env::var_os(REEXEC_ENV).is_some() {
+        return Err(explain("su did not make it root"));
+    }
+    let refused = match su::local("true") {
+        Ok(_) => None,
+        Err(SuError::Missing) => Some("su is not installed"),
+        Err(SuError::Denied) => Some("root was denied"),
+        Err(SuError::Failed { .. } | SuError::Io(_)) => Some("su did not work"),
     };
-    menus.cursor_show().unwrap();
-    ret
+    if let Some(why) = refused {
+        if device::Shizuku::available() {
+            eprintln!(
+                "No root, running through Shizuku: apps can be listed and changes previewed,"
+            );
+            eprintln!("but detach.bin cannot be read or written.");
+            return Ok(false);
+        }
+        return Err(explain(why));
+    }
+    let exe = std::env::current_exe().map_or_else(|_| "detach".into(), |e| e.display().to_string());
+    let cmdline: Vec<String> = std::iter::once(exe)
+        .chain(std::env::args().skip(1))
+        .map(|a| format!("'{}'", a.replace('\'', r"'\''")))
+        .collect();
+    let err = Command::new("su")
+        .args(["-c", &cmdline.join(" ")])
+        .env(REEXEC_ENV, "1")
+        .exec();
+    Err(explain(&format!("su could not be started: {err}")))
 }
 
+#[cfg(target_os = "android")]
 fn check_denylist() -> io::Result<bool> {
-    let op = Command::new("magisk")
-        .args(["--denylist", "ls"])
-        .stdout(std::process::Stdio::piped())
-        .output()?
-        .stdout;
+    let op = runner::output(
+        std::process::Command::new("magisk").args(["--denylist", "ls"]),
+        "magisk --denylist ls",
+    )?
+    .stdout;
     let op = String::from_utf8_lossy(&op);
     if op.contains("com.android.vending") {
-        Command::new("magisk")
-            .args(["--denylist", "rm", "com.android.vending"])
-            .spawn()?
-            .wait()?;
+        runner::output(
+            std::process::Command::new("magisk").args(["--denylist", "rm", "com.android.vending"]),
+            "magisk --denylist rm com.android.vending",
+        )?;
         Ok(true)
     } else {
         Ok(false)
     }
 }
 
+/// Set by `--no-restart` or the config, the Play Store is then left running after changes
+static NO_RESTART: AtomicBool = AtomicBool::new(false);
+/// detach.bin changed and the Play Store was not restarted since
+static RESTART_PENDING: AtomicBool = AtomicBool::new(false);
+
 fn detach_bin_changed() {
-    let _ = fs::remove_file(DETACH_TXT);
-    let _ = kill_store();
-}
-
-fn serialize_txt(txt: &str, bin: &str) -> IOResult<()> {
-    let detach_bin = OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(bin)?;
-    let mut sink = BufWriter::new(detach_bin);
-    for app in std::fs::read_to_string(txt)?
-        .lines()
-        .map(|s| s.trim())
-        .filter(|l| !l.is_empty() && !l.starts_with('#'))
-    {
+    let _ = device::get().remove(DeviceFile::DetachTxt);
+    RESTART_PENDING.store(true, Ordering::Relaxed);
+}
+
+/// Whether detach.bin changed with the Play Store still to be restarted, clearing the flag
+fn take_restart_pending() -> bool {
+    RESTART_PENDING.swap(false, Ordering::Relaxed) && !NO_RESTART.load(Ordering::Relaxed)
+}
+
+/// Stops the Play Store, and Play Services when the config asks for it, so detach.bin
+/// is reread. Returns the packages stopped, none in a dry run which only logs them
+fn restart_store() -> CLIResult<Vec<&'static str>> {
+    let gms = config::Config::load().is_ok_and(|c| c.restart_gms);
+    let pkgs = detach_core::restart_play_store(gms).map_err(error::Error::Restart)?;
+    Ok(if dry_run::enabled() { Vec::new() } else { pkgs })
+}
+
+/// The apps of `apps` detach.bin can store, the others named as skipped
+fn storable<'a>(
+    menus: &mut Menus,
+    apps: impl IntoIterator<Item = &'a str>,
+) -> CLIResult<Vec<&'a str>> {
+    let (apps, skipped): (Vec<&str>, Vec<&str>) =
+        (apps.into_iter()).partition(|app| detach_bin::encodable(app));
+    if !skipped.is_empty() {
+        textln!(
+            menus,
+            "{} {} cannot be stored in detach.bin",
+            "skipped:".red(),
+            skipped.join(", ")
+        );
+    }
+    Ok(apps)
+}
+
+/// Replaces detach.bin with `content`, journaling the change so it can be undone and
+/// logging it in the history. A journal that cannot be written does not hold up the
+/// change. Nothing is written when another program changed detach.bin since it was read
+fn write_detach_bin(content: &[u8]) -> CLIResult<()> {
+    let before = current_detach_bin()?;
+    if external::changed(&before) {
+        return Err(error::Error::ChangedOutside(content.to_vec()).into());
+    }
+    overwrite_detach_bin(&before, content)
+}
+
+/// `write_detach_bin` over what `before` held, changed from outside or not. Nothing is
+/// written when detach.bin no longer holds `before`
+fn overwrite_detach_bin(before: &[u8], content: &[u8]) -> CLIResult<()> {
+    // held from the check on, so no other copy of the cli writes in between
+    let _lock = instance::lock().map_err(error::Error::Busy)?;
+    if current_detach_bin()? != before {
+        return Err(error::Error::ChangedOutside(content.to_vec()).into());
+    }
+    let normalized = detach_bin::normalize(content);
+    let content = normalized.as_deref().unwrap_or(content);
+    let risky = risky::added(before, content);
+    if !risky.is_empty() {
+        return Err(error::Error::Risky(risky, content.to_vec()).into());
+    }
+    external::writing(content, || readback::write(content)).map_err(error::Error::detach_file)?;
+    record_change(before, content);
+    Ok(())
+}
+
+fn record_change(before: &[u8], after: &[u8]) {
+    // the copy of another phone's list is not this one's history
+    if custom_file::foreign() {
+        return;
+    }
+    let _ = journal::record(before, after);
+    let _ = history::log(before, after);
+    let _ = meta::update(before, after);
+    let _ = recent::update(before, after);
+}
+
+fn serialize_txt(txt: &str, bin: &str) -> CLIResult<()> {
+    let mut content = Vec::new();
+    for app in parse_txt(&std::fs::read_to_string(txt)?) {
         println!("  '{}'", app);
-        bin_serialize(app, &mut sink)?;
+        detach_bin::serialize(app, &mut content)?;
     }
+    let _lock = instance::lock().map_err(error::Error::Busy)?;
+    atomic::write(bin, &content)?;
     Ok(())
 }
 
-fn interactive(menus: &mut Menus) -> IOResult<()> {
+fn interactive(menus: &mut Menus, wizard: bool) -> CLIResult<Exit> {
     menus.cursor_hide()?;
-    print!("zygisk-detach cli by github.com/j-hc\r\n\n");
+    print!("zygisk-detach cli by github.com/j-hc\r\n");
+    if dry_run::enabled() {
+        print!("{}\r\n", dry_run_badge());
+    }
+    let env = device::get()
+        .environment()
+        .map_err(error::Error::detach_file)?;
+    print!("{} module in {}\r\n", env.root, env.module_dir.faint());
+    if matches!(env.root, device::Root::Shizuku) {
+        print!(
+            "{} without root detach.bin reads as empty and changes need --dry-run\r\n",
+            "WARNING:".red().bold()
+        );
+    }
+    let checks = checks::module();
+    let warnings = checks::warnings(&checks);
+    for warning in &warnings {
+        print!("{} {warning}\r\n", "WARNING:".red().bold());
+    }
+    if !warnings.is_empty() {
+        print!(
+            "{}\r\n",
+            "Detached apps stay visible to the Play Store until then, list edits still work.".red()
+        );
+    }
+    print!("\r\n");
+    let module_ok = warnings.is_empty();
+    if wizard {
+        wizard_menu(menus, env, &warnings)?;
+    }
+    verify_menu(menus)?;
+    let mut config = config::Config::load()?;
+    menus.set_wrap(config.wrap);
+    menus.set_bell(config.bell);
+    match apply_rules_menu(menus) {
+        // a rule matching the Play Store or Play Services
+        Err(CLIErr {
+            source: error::Error::Risky(apps, wanted),
+            ..
+        }) => risky_menu(menus, &apps, &wanted)?,
+        res => res?,
+    }
+    finish_op(menus)?;
+    if !menus.is_plain() {
+        runner::on_wait(menus::draw_waiting);
+        progress::on_draw(menus::draw_bottom);
+    }
+    let wake = menus::waker();
+    external::watch(move |changed| {
+        menus::set_alert(changed.then(|| "! detach.bin changed outside the cli".to_string()));
+        wake();
+    });
+    let mut last = None;
     loop {
-        match main_menu(menus)? {
-            Op::DetachSelect => detach_menu(menus)?,
-            Op::ReattachSelect => reattach_menu(menus)?,
-            Op::Reset => {
-                if fs::remove_file(MODULE_DETACH).is_ok() {
-                    let _ = kill_store();
-                    text!(menus, "Reset");
-                } else {
-                    text!(menus, "Already empty");
+        menus.set_status(status_line(module_ok, last.as_deref()))?;
+        let op = main_menu(menus, config.mode)?;
+        let _op = history::operation(op.name());
+        match op {
+            Op::Quit => return Ok(Exit::Success),
+            Op::Interrupted => return Ok(Exit::Interrupted),
+            op => match run_op(menus, op, &mut config) {
+                Err(CLIErr {
+                    source: error::Error::ChangedOutside(wanted),
+                    ..
+                }) => changed_outside_menu(menus, &wanted)?,
+                Err(CLIErr {
+                    source: error::Error::Risky(apps, wanted),
+                    ..
+                }) => risky_menu(menus, &apps, &wanted)?,
+                Err(CLIErr {
+                    source: e @ (error::Error::Timeout(_) | error::Error::Busy(_)),
+                    ..
+                }) => {
+                    // what the op wrote before it gave up is not what it did
+                    menus.take_note();
+                    menus.toast(e, Level::Warn)?;
                 }
-            }
-            Op::CopyToSd => {
-                #[cfg(target_os = "android")]
-                const SDCARD_DETACH: &str = "/sdcard/detach.bin";
-                #[cfg(target_os = "linux")]
-                const SDCARD_DETACH: &str = "detach_sdcard.bin";
-                match fs::copy(MODULE_DETACH, SDCARD_DETACH) {
-                    Ok(_) => text!(menus, "Copied"),
-                    Err(err) if err.kind() == io::ErrorKind::NotFound => {
-                        text!(menus, "detach.bin not found");
-                    }
-                    Err(err) => return Err(err.into()),
+                res => res?,
+            },
+        }
+        last = menus.take_note().or(last);
+        finish_op(menus)?;
+        menus.take_note();
+    }
+}
+
+/// Walks a first-time user through what the cli needs and does. Each step can be
+/// skipped, and the wizard is not shown again after
+fn wizard_menu(menus: &mut Menus, env: &device::Environment, warnings: &[&str]) -> CLIResult<()> {
+    let done = || {
+        if let Err(e) = config::create_parent(config::WIZARD_DONE)
+            .and_then(|()| fs::write(config::WIZARD_DONE, b""))
+        {
+            log::verbose(format_args!("{}: {e}", config::WIZARD_DONE));
+        }
+    };
+    // a question takes the place of the line above it
+    let next = |menus: &mut Menus| -> CLIResult<bool> {
+        textln!(menus, "");
+        Ok(menus.confirm("Next step? No skips the rest", true)?)
+    };
+    textln!(menus, "{}", "Welcome to zygisk-detach".bold());
+    textln!(menus, "{} Root", "1/4".faint());
+    if matches!(env.root, device::Root::Shizuku) {
+        textln!(
+            menus,
+            "  No root: apps can be listed and changes previewed, but detach.bin cannot be written."
+        );
+        textln!(
+            menus,
+            "  Grant root to this terminal app and run `su -c detach`."
+        );
+    } else {
+        textln!(menus, "  Running as root through {}.", env.root);
+    }
+    if !next(menus)? {
+        done();
+        return Ok(());
+    }
+    textln!(menus, "{} Module", "2/4".faint());
+    if warnings.is_empty() {
+        textln!(menus, "  The module is active in {}.", env.module_dir);
+    } else {
+        for warning in warnings {
+            textln!(menus, "  {warning}");
+        }
+        textln!(
+            menus,
+            "  Detached apps stay visible to the Play Store until then."
+        );
+    }
+    if !next(menus)? {
+        done();
+        return Ok(());
+    }
+    textln!(menus, "{} Detaching", "3/4".faint());
+    textln!(
+        menus,
+        "  A detached app is hidden from the Play Store, so it stops updating it."
+    );
+    textln!(
+        menus,
+        "  The app itself keeps working and can be re-attached at any time."
+    );
+    textln!(
+        menus,
+        "  After each change the Play Store is restarted to reread the list."
+    );
+    textln!(
+        menus,
+        "  \"Compare detached and installed apps\" or `detach doctor` check it took."
+    );
+    if !next(menus)? {
+        done();
+        return Ok(());
+    }
+    textln!(menus, "{} Detached apps", "4/4".faint());
+    let content = read_detach_bin()?;
+    let detached = detach_bin::parse(&content).map_or(0, |apps| apps.len());
+    let device = device::get();
+    let copy = match device.read(DeviceFile::SdcardDetachBin) {
+        Ok(copy) => copy,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(error::Error::detach_file(e).into()),
+    };
+    let copied = detach_bin::parse(&copy).map_or(0, |apps| apps.len());
+    if detached > 0 {
+        textln!(
+            menus,
+            "  detach.bin in the module already detaches {detached} apps."
+        );
+    } else if copied > 0 {
+        let question = format!(
+            "  detach.bin on /sdcard from an earlier install has {copied} apps, detach them?"
+        );
+        textln!(menus, "");
+        if menus.confirm(question, true)? {
+            let copy = detach_bin::normalize(&copy).unwrap_or(copy);
+            overwrite_detach_bin(&content, &copy)?;
+            textln!(menus, "  {} {copied} apps", "detached:".green());
+        }
+    } else {
+        textln!(
+            menus,
+            "  Nothing is detached yet, start with \"Detach\" in the menu."
+        );
+    }
+    textln!(menus, "Done, `detach --wizard` shows this again.");
+    done();
+    // the status bar is for what the menus did
+    menus.take_note();
+    Ok(())
+}
+
+/// Runs the menu `op` picked from the main menu
+fn run_op(menus: &mut Menus, op: Op, config: &mut config::Config) -> CLIResult<()> {
+    match op {
+        Op::DetachSelect => detach_menu(menus)?,
+        Op::DetachMulti => detach_multi_menu(menus)?,
+        Op::Toggle => toggle_menu(menus)?,
+        Op::DetachAll => detach_all_menu(menus)?,
+        Op::ReattachAll => reattach_all_menu(menus)?,
+        Op::Export => export_menu(menus)?,
+        Op::Import => import_menu(menus)?,
+        Op::ReattachSelect => reattach_menu(menus)?,
+        Op::Manage => manage_menu(menus)?,
+        Op::Prune => prune_menu(menus)?,
+        Op::Compare => compare_menu(menus)?,
+        Op::Restore => restore_menu(menus)?,
+        Op::Sync => sync_menu(menus)?,
+        Op::Keep => keep_menu(menus)?,
+        Op::SwitchMode => switch_mode_menu(menus, config)?,
+        Op::SelectUser => select_user_menu(menus)?,
+        Op::Profiles => profile_menu(menus)?,
+        Op::Undo => undo_menu(menus)?,
+        Op::History => history_menu(menus)?,
+        Op::Reset => reset_menu(menus)?,
+        Op::CopyToSd => {
+            let device = device::get();
+            match device
+                .read(DeviceFile::DetachBin)
+                .and_then(|c| device.write(DeviceFile::SdcardDetachBin, &c))
+            {
+                Ok(()) => menus.toast("Copied", Level::Success)?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    menus.toast("detach.bin not found", Level::Warn)?;
                 }
+                Err(err) => return Err(error::Error::detach_file(err).into()),
             }
-            Op::Quit => return Ok(()),
-            Op::Nop => {}
         }
+        Op::Refresh => {
+            packages::invalidate();
+            menu_packages(menus, &[])?;
+            menus.toast("Package list reloaded", Level::Success)?;
+            apply_rules_menu(menus)?;
+        }
+        Op::Quit | Op::Interrupted | Op::Nop => {}
     }
+    Ok(())
 }
 
-fn reattach_menu(menus: &mut Menus) -> IOResult<()> {
-    let mut detach_txt = match fs::OpenOptions::new()
-        .write(true)
-        .read(true)
-        .open(MODULE_DETACH)
-    {
+/// Asks what to do with the change `wanted` when another program changed detach.bin
+/// while it was being made: drop it and go on with the file as it is now, write it
+/// over the file anyway, or add the apps of both
+fn changed_outside_menu(menus: &mut Menus, wanted: &[u8]) -> CLIResult<()> {
+    let current = read_detach_bin()?;
+    let Some(i) = menus.select_menu(
+        [
+            "Reload it, dropping this change",
+            "Overwrite it with this change",
+            "Merge, keeping the apps of both",
+        ]
+        .iter(),
+        "detach.bin was changed by another program while this change was made:",
+        "!".yellow(),
+        None,
+    )?
+    else {
+        menus.toast("Change dropped, detach.bin reloaded", Level::Info)?;
+        return Ok(());
+    };
+    let content = match i {
+        0 => {
+            menus.toast("Change dropped, detach.bin reloaded", Level::Info)?;
+            return Ok(());
+        }
+        1 => wanted.to_vec(),
+        _ => {
+            let theirs = get_detached_apps(&current)?;
+            let ours = get_detached_apps(wanted)?;
+            let theirs: Vec<&str> = theirs.iter().map(|e| e.0.as_str()).collect();
+            let ours: Vec<&str> = ours.iter().map(|e| e.0.as_str()).collect();
+            let lines = diff::diff(&theirs, &ours, false);
+            if !diff::has_changes(&lines) {
+                menus.toast("Nothing to merge, detach.bin reloaded", Level::Info)?;
+                return Ok(());
+            }
+            if menus
+                .select_menu(
+                    lines.iter(),
+                    format_args!(
+                        "Merge: {} (ENTER applies, 'q' to leave):",
+                        diff::summary(&lines)
+                    ),
+                    "↪".green(),
+                    Some(Key::Char('q')),
+                )?
+                .is_none()
+            {
+                return Ok(());
+            }
+            let mut content = current.clone();
+            for line in lines.iter().filter(|l| l.change == diff::Change::Added) {
+                detach_bin::serialize(&line.app, &mut content)?;
+            }
+            content
+        }
+    };
+    overwrite_detach_bin(&current, &content)?;
+    detach_bin_changed();
+    menus.toast("detach.bin written", Level::Success)?;
+    Ok(())
+}
+
+/// Explains what detaching `apps` breaks and writes `wanted` after `yes` is typed
+fn risky_menu<K: Iterator<Item = io::Result<Event>>, S: menus::Screen>(
+    menus: &mut Menus<K, S>,
+    apps: &[String],
+    wanted: &[u8],
+) -> CLIResult<()> {
+    // what the op wrote before it was stopped is not what it did
+    menus.take_note();
+    for app in apps {
+        textln!(
+            menus,
+            "{} detaching {app}: {}",
+            "warning:".red(),
+            risky::consequence(app)
+        );
+    }
+    let answer = menus.read_line(format_args!(
+        "{} Type yes to detach {} anyway: ",
+        "risky:".red(),
+        if apps.len() == 1 { "it" } else { "them" }
+    ))?;
+    if answer.as_deref().map(str::trim) != Some("yes") {
+        menus.toast("Nothing was written", Level::Info)?;
+        return Ok(());
+    }
+    risky::allow(apps);
+    write_detach_bin(wanted)?;
+    detach_bin_changed();
+    menus.toast("detach.bin written", Level::Success)?;
+    Ok(())
+}
+
+/// Offers the Play Store restart a change asked for and reports what a dry run skipped
+fn finish_op(menus: &mut Menus) -> CLIResult<()> {
+    if take_restart_pending() && menus.confirm("Restart Play Store now?", true)? {
+        match restart_store() {
+            Ok(pkgs) if pkgs.is_empty() => {}
+            Ok(pkgs) => textln!(menus, "{} {}", "stopped:".green(), pkgs.join(", ")),
+            Err(err) => menus.toast(format_args!("ERROR: {err}"), Level::Error)?,
+        }
+    }
+    for line in dry_run::take_log() {
+        textln!(menus, "{} {line}", "dry run:".yellow());
+    }
+    Ok(())
+}
+
+/// `N detached · N installed · last: <message>` for the status bar. The installed
+/// count is left out until the packages are listed
+fn status_line(module_ok: bool, last: Option<&str>) -> String {
+    let mut parts = Vec::new();
+    if !module_ok {
+        parts.push("! module not active, see doctor".to_string());
+    }
+    if let Ok((_, detached)) = load_detached() {
+        parts.push(format!("{} detached", detached.len()));
+    }
+    if let Some(raw) = packages::get(&[]) {
+        parts.push(format!("{} installed", parse_installed_apps(&raw).count()));
+    }
+    if let Some(last) = last {
+        parts.push(format!("last: {last}"));
+    }
+    parts.join(" · ")
+}
+
+fn dry_run_badge() -> impl Display {
+    " DRY RUN ".black().bold().bg(colorize::Color::Yellow)
+}
+
+fn reattach_menu(menus: &mut Menus) -> CLIResult<()> {
+    let mut content = match device::get().read(DeviceFile::DetachBin) {
         Ok(v) => v,
         Err(e) if e.kind() == io::ErrorKind::NotFound => {
-            text!(menus, "detach.bin not found");
+            menus.toast("detach.bin not found", Level::Warn)?;
             return Ok(());
         }
-        Err(e) => return Err(e.into()),
+        Err(e) => return Err(error::Error::detach_file(e).into()),
     };
-    let mut content = Vec::new();
-    detach_txt.read_to_end(&mut content)?;
-    detach_txt.seek(io::SeekFrom::Start(0))?;
-    let detached_apps = get_detached_apps(menus, &content);
+    let detached_apps = get_detached_apps(&content)?;
     let detach_len = detached_apps.len();
     if detach_len == 0 {
-        text!(menus, "detach.bin is empty");
+        menus.toast("detach.bin is empty", Level::Info)?;
         return Ok(());
     }
     let list = detached_apps.iter().map(|e| e.0.as_str());
-    let Some(i) = menus.select_menu(
-        list,
-        "Select the app to re-attach ('q' to leave):",
-        "✖".red(),
-        Some(Key::Char('q')),
-    )?
+    let Some(i) = menus
+        .select_menu_kept(
+            "reattach",
+            list,
+            None,
+            "Select the app to re-attach ('q' to leave):",
+            "✖".red(),
+            Some(Key::Char('q')),
+        )?
+        .picked()
     else {
         return Ok(());
     };
 
     textln!(menus, "{}: {}", "re-attach".red(), detached_apps[i].0);
     content.drain(detached_apps[i].1.clone());
-    detach_txt.set_len(0)?;
-    detach_txt.write_all(&content)?;
+    write_detach_bin(&content)?;
     detach_bin_changed();
     Ok(())
 }
 
-fn get_detached_apps(menus: &mut Menus, detach_txt: &[u8]) -> Vec<(String, Range<usize>)> {
-    let mut i = 0;
-    let mut detached = Vec::new();
-    while i < detach_txt.len() {
-        let len: u8 = detach_txt[i];
-        const SZ_LEN: usize = size_of::<u8>();
-        i += SZ_LEN;
-        let Some(encoded_name) = &detach_txt.get(i..i + len as usize) else {
-            eprintln!("Corrupted detach.bin. Reset and try again.");
-            let _ = menus.cursor_show();
-            std::process::exit(1);
-        };
-        let name = String::from_utf8(encoded_name.iter().step_by(2).cloned().collect()).unwrap();
-        detached.push((name, i - SZ_LEN..i + len as usize));
-        i += len as usize;
-    }
-    detached
+struct ManagedApp {
+    entry: DetachEntry,
+    installed: bool,
+    installer: Option<String>,
 }
 
-#[cfg(target_os = "linux")]
-fn get_installed_apps() -> IOResult<Vec<u8>> {
-    Ok("package:com.app1\npackage:org.xxx2".as_bytes().to_vec())
+/// Reads detach.bin in order and flags the entries whose app is still installed
+fn read_managed_apps(menus: &mut Menus) -> CLIResult<(Vec<u8>, Vec<ManagedApp>)> {
+    let installed_apps = menu_packages(menus, &["-i"])?;
+    let installed: HashMap<&str, Option<&str>> = parse_installers(&installed_apps).collect();
+    let content = read_detach_bin()?;
+    let apps = get_detached_apps(&content)?
+        .into_iter()
+        .map(|entry| {
+            let found = installed.get(entry.0.as_str());
+            ManagedApp {
+                installed: found.is_some(),
+                installer: found.copied().flatten().map(str::to_string),
+                entry,
+            }
+        })
+        .collect();
+    Ok((content, apps))
 }
 
-#[cfg(target_os = "android")]
-fn get_installed_apps() -> IOResult<Vec<u8>> {
-    Ok(Command::new("pm")
-        .args(["list", "packages"])
-        .stdout(std::process::Stdio::piped())
-        .output()?
-        .stdout)
+/// `2024-05-01`, the day of `time` in UTC
+fn display_date(time: u64) -> String {
+    let time = detach_core::time::display_time(time);
+    time.split(' ').next().unwrap_or_default().to_string()
 }
 
-#[derive(Clone, Copy)]
-enum Op {
-    DetachSelect,
-    ReattachSelect,
-    Reset,
-    CopyToSd,
-    Quit,
-    Nop,
+/// The table `list` prints of `apps`: their labels as far as they are cached, whether
+/// and where from they are installed, when they were detached and their notes
+fn app_table(apps: &[&str]) -> CLIResult<table::Table> {
+    let installed = installed_anywhere()?;
+    let listing = list_packages(&["-i"])?;
+    let installers: HashMap<&str, Option<&str>> = parse_installers(&listing).collect();
+    let labels = labels::Labels::cached(apps);
+    let detached = meta::detached_times();
+    let notes = meta::load().unwrap_or_default();
+    let mut table = table::Table::new(&[
+        "PACKAGE",
+        "LABEL",
+        "INSTALLED",
+        "INSTALLER",
+        "DETACHED",
+        "NOTE",
+    ]);
+    for &app in apps {
+        let is_installed = installed.iter().any(|i| i == app);
+        table.row([
+            app.to_string(),
+            labels.get(app).unwrap_or_default(),
+            if is_installed { "yes" } else { "no" }.to_string(),
+            match installers.get(app) {
+                Some(&installer) => installers::name(installer).to_string(),
+                None => String::new(),
+            },
+            detached
+                .get(app)
+                .map(|&t| display_date(t))
+                .unwrap_or_default(),
+            notes
+                .get(app)
+                .and_then(|m| m.note.clone())
+                .unwrap_or_default(),
+        ]);
+    }
+    Ok(table)
 }
 
-fn main_menu(menus: &mut Menus) -> IOResult<Op> {
-    struct OpText {
-        desc: &'static str,
-        op: Op,
+/// The install and update times `sort` needs, none when they cannot be read
+fn sort_times(menus: &mut Menus, sort: Sort) -> CLIResult<HashMap<String, PackageTimes>> {
+    if !sort.needs_times() {
+        return Ok(HashMap::new());
     }
-    impl OpText {
-        fn new(desc: &'static str, op: Op) -> Self {
-            Self { desc, op }
+    match sort::times() {
+        Ok(times) => Ok(times),
+        Err(e) => {
+            menus.toast(
+                format_args!("could not read install times: {e}"),
+                Level::Warn,
+            )?;
+            Ok(HashMap::new())
         }
     }
-    impl Display for OpText {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(f, "{}", self.desc)
+}
+
+fn manage_menu(menus: &mut Menus) -> CLIResult<()> {
+    // the app to keep highlighted after the list was sorted again
+    let mut follow = None;
+    let details = details::Details::load(menus::waker());
+    loop {
+        let (mut content, apps) = read_managed_apps(menus)?;
+        if apps.is_empty() {
+            menus.toast("detach.bin is empty", Level::Info)?;
+            return Ok(());
         }
-    }
-    let ops = [
-        OpText::new("Detach", Op::DetachSelect),
-        OpText::new("Re-attach", Op::ReattachSelect),
-        OpText::new("Reset detached apps", Op::Reset),
-        OpText::new("Copy detach.bin to /sdcard", Op::CopyToSd),
-    ];
-    let i = menus.select_menu_numbered(ops.iter(), Key::Char('q'), "- Selection:")?;
-    use menus::SelectNumberedResp as SN;
-    match i {
-        SN::Index(i) => Ok(ops[i].op),
-        SN::UndefinedKey(Key::Char(c)) => {
-            text!(menus, "Undefined key {c:?}");
-            Ok(Op::Nop)
+        let rules = Rules::load(RULES_TXT)?;
+        let names: Vec<&str> = apps.iter().map(|app| app.entry.0.as_str()).collect();
+        let labels = labels::Labels::cached(&names);
+        let sort = sort::get("manage");
+        let times = sort_times(menus, sort)?;
+        let order = sort::order(&names, sort, |app| labels.get(app), &times);
+        if let Some(app) = follow.take() {
+            let at = order.iter().position(|&i| names[i] == app).unwrap_or(0);
+            menus.keep("manage", at);
         }
-        SN::UndefinedKey(k @ (Key::Down | Key::Up | Key::Left | Key::Right)) => {
-            text!(menus, "Undefined key {k:?}");
-            Ok(Op::Nop)
+        let detached = meta::detached_times();
+        let metas = meta::load().unwrap_or_default();
+        let note = |name: &str| metas.get(name).and_then(|m| m.note.clone());
+        let mut table =
+            table::Table::new(&["PACKAGE", "LABEL", "INSTALLER", "DETACHED", "NOTE", ""]);
+        for app in order.iter().map(|&i| &apps[i]) {
+            let name = &app.entry.0;
+            let mut notes = Vec::new();
+            if !app.installed {
+                notes.push("(not installed)".red().underline().to_string());
+            }
+            if let Some(rule) = rules.rule_for(name) {
+                notes.push(format_args!("(rule {rule})").faint().to_string());
+            }
+            table.row([
+                name.clone(),
+                labels.get(name).unwrap_or_default(),
+                if app.installed {
+                    installers::name(app.installer.as_deref()).to_string()
+                } else {
+                    String::new()
+                },
+                detached
+                    .get(name)
+                    .map(|&t| display_date(t))
+                    .unwrap_or_default(),
+                note(name).unwrap_or_default(),
+                notes.join(" "),
+            ]);
+        }
+        // the highlighted item is drawn after the prompt
+        let width = menus.width()?.saturating_sub(2) as usize;
+        let rows = table.render_rows(details::Details::room(width));
+        let shown: Vec<String> = order.iter().map(|&i| names[i].to_string()).collect();
+        menus.set_open(Some(Box::new(move |i| open_store_page(&shown[i]))));
+        let selection = menus.select_menu_kept(
+            "manage",
+            (rows.iter().zip(&order)).map(|(row, &i)| details.detailed(row, names[i], width)),
+            Some((
+                Key::Char('s'),
+                "sort by package, label, install or update date",
+            )),
+            format_args!(
+                "Detached apps: {}, by {} ('s' sort, 'y' copy, 'o' Play Store, 'q' to leave):",
+                apps.len(),
+                sort.name()
+            ),
+            "▶".green(),
+            Some(Key::Char('q')),
+        );
+        menus.set_open(None);
+        let i = match selection? {
+            Selection::Picked(i) => order[i],
+            Selection::Pressed(i) => {
+                sort::set("manage", sort.next());
+                follow = Some(names[order[i]].to_string());
+                continue;
+            }
+            Selection::Left(_) => return Ok(()),
+        };
+
+        let (name, range) = &apps[i].entry;
+        let rule = rules.rule_for(name);
+        let mut actions = vec![
+            "Re-attach".to_string(),
+            "Copy name".to_string(),
+            "Edit note".to_string(),
+        ];
+        if let Some(rule) = rule {
+            actions.push(format!("Remove rule {rule}"));
+        }
+        actions.push("Keep".to_string());
+        let Some(action) =
+            menus.select_menu(actions.iter(), name, "↪".green(), Some(Key::Char('q')))?
+        else {
+            continue;
+        };
+        match (action, rule) {
+            (0, _) => {
+                content.drain(range.clone());
+                write_detach_bin(&content)?;
+                detach_bin_changed();
+                textln!(menus, "{}: {}", "re-attach".red(), name);
+                if let Some(rule) = rule {
+                    textln!(
+                        menus,
+                        "{} still matches {rule} and will be detached again, remove the rule to keep it attached",
+                        "warning:".red()
+                    );
+                }
+            }
+            (1, _) => {
+                menus.copy_to_clipboard(name)?;
+                menus.toast(format_args!("Copied {name}"), Level::Success)?;
+            }
+            (2, _) => edit_note_menu(menus, name, &note(name).unwrap_or_default())?,
+            (3, Some(rule)) => remove_rule_menu(menus, &rules, rule, content, &apps)?,
+            _ => {}
         }
-        SN::Quit => Ok(Op::Quit),
-        _ => Ok(Op::Nop),
     }
 }
 
-fn bin_serialize(app: &str, sink: impl Write) -> IOResult<()> {
-    let w = app
-        .as_bytes()
-        .iter()
-        .intersperse(&0)
-        .cloned()
-        .collect::<Vec<u8>>();
-    let mut f = BufWriter::new(sink);
-    f.write_all(std::slice::from_ref(
-        &w.len()
-            .try_into()
-            .expect("app name cannot be longer than 255"),
-    ))?;
-    f.write_all(&w)?;
-    f.flush()?;
+fn edit_note_menu(menus: &mut Menus, name: &str, note: &str) -> CLIResult<()> {
+    let Some(note) = menus.edit_line(format_args!("note for {name}: "), note)? else {
+        return Ok(());
+    };
+    if dry_run::enabled() {
+        menus.toast("dry run, the note is not saved", Level::Info)?;
+        return Ok(());
+    }
+    meta::set_note(name, &note)?;
+    menus.toast(format_args!("Saved the note of {name}"), Level::Success)?;
     Ok(())
 }
 
-fn detach_menu(menus: &mut Menus) -> IOResult<()> {
-    let installed_apps = get_installed_apps()?;
-    let apps: Vec<&str> = installed_apps[..installed_apps.len() - 1]
-        .split(|&e| e == b'\n')
-        .map(|e| {
-            e.get("package:".len()..)
-                .expect("unexpected output from pm")
-        })
-        .map(|e| std::str::from_utf8(e).expect("non utf-8 package names?"))
-        .collect();
-    menus.cursor_show()?;
-    let selected = menus.select_menu_with_input(
-        |input| {
-            let input = input.trim();
-            if !input.is_empty() {
-                apps.iter()
-                    .filter(|app| {
-                        app.to_ascii_lowercase()
-                            .contains(&input.to_ascii_lowercase())
-                    })
-                    .take(5)
-                    .collect()
+/// Drops `rule` from RULES_TXT and offers to re-attach the apps only it matched
+fn remove_rule_menu(
+    menus: &mut Menus,
+    rules: &Rules,
+    rule: &str,
+    content: Vec<u8>,
+    apps: &[ManagedApp],
+) -> CLIResult<()> {
+    rules::remove(RULES_TXT, rule)?;
+    textln!(menus, "{} {rule}", "rule removed:".red());
+    let ranges = rule_entries(rules, rule, apps.iter().map(|app| &app.entry));
+    if !ranges.is_empty()
+        && menus.confirm(
+            format_args!("Also re-attach the {} apps it matched?", ranges.len()),
+            false,
+        )?
+    {
+        let n = ranges.len();
+        remove_entries(content, ranges)?;
+        textln!(menus, "{} {n} apps", "re-attach:".red());
+    }
+    Ok(())
+}
+
+/// Where the entries are that `rule` detached, leaving out the ones another rule
+/// would detach all the same
+fn rule_entries<'a>(
+    rules: &Rules,
+    rule: &str,
+    entries: impl Iterator<Item = &'a DetachEntry>,
+) -> Vec<Range<usize>> {
+    entries
+        .filter(|(name, _)| {
+            rules::glob_match(rule, name)
+                && rules
+                    .iter()
+                    .all(|r| r == rule || !rules::glob_match(r, name))
+        })
+        .map(|(_, range)| range.clone())
+        .collect()
+}
+
+/// What a full check of detach.bin found
+struct Verified {
+    /// The entries that decoded cleanly
+    entries: Vec<DetachEntry>,
+    problems: Vec<String>,
+}
+
+/// Checks every entry of detach.bin instead of giving up at the first bad one
+fn verify_detach_bin(content: &[u8]) -> Verified {
+    let mut entries = Vec::new();
+    let mut problems = Vec::new();
+    let mut i = 0;
+    while i < content.len() {
+        let len = content[i] as usize;
+        let start = i;
+        i += 1;
+        let Some(encoded) = content.get(i..i + len) else {
+            problems.push(format!(
+                "entry at byte {start} is cut off: {len} bytes long but only {} left",
+                content.len() - i
+            ));
+            break;
+        };
+        i += len;
+        let nulls_ok = encoded.iter().skip(1).step_by(2).all(|&b| b == 0);
+        let name: String = encoded.iter().step_by(2).map(|&b| b as char).collect();
+        if len.is_multiple_of(2) || !nulls_ok {
+            problems.push(format!("entry at byte {start} is not UTF-16 encoded"));
+        } else if !detach_bin::is_package_name(&name) {
+            problems.push(format!(
+                "entry at byte {start} is not a package name: {name:?}"
+            ));
+        } else {
+            entries.push((name, start..i));
+        }
+    }
+    Verified { entries, problems }
+}
+
+/// Rewrites detach.bin from `entries`, keeping the original as detach.bin.corrupt
+fn rebuild_detach_bin(original: &[u8], entries: &[DetachEntry]) -> CLIResult<()> {
+    let device = device::get();
+    device
+        .write(DeviceFile::CorruptBackup, original)
+        .map_err(error::Error::detach_file)?;
+    let mut content = Vec::new();
+    for (name, _) in entries {
+        detach_bin::serialize(name, &mut content)?;
+    }
+    write_detach_bin(&content)?;
+    detach_bin_changed();
+    Ok(())
+}
+
+/// Offers to rebuild detach.bin before the menus run into a corrupted one
+fn verify_menu(menus: &mut Menus) -> CLIResult<()> {
+    let content = read_detach_bin()?;
+    let verified = verify_detach_bin(&content);
+    if verified.problems.is_empty() {
+        return Ok(());
+    }
+    textln!(menus, "{}", "detach.bin is corrupted:".red());
+    for problem in &verified.problems {
+        textln!(menus, "  {problem}");
+    }
+    let question = format!(
+        "Rebuild it from the {} intact entries? The original is kept as detach.bin.corrupt",
+        verified.entries.len()
+    );
+    if menus.confirm(&question, true)? {
+        rebuild_detach_bin(&content, &verified.entries)?;
+        textln!(
+            menus,
+            "{} {} entries",
+            "rebuilt:".green(),
+            verified.entries.len()
+        );
+    }
+    Ok(())
+}
+
+/// Decodes detach.bin into the app names and the byte ranges of their entries.
+/// Fails with [`error::Error::Corrupted`] if the file is corrupted
+fn get_detached_apps(detach_txt: &[u8]) -> CLIResult<Vec<DetachEntry>> {
+    Ok(detach_bin::parse(detach_txt).ok_or(error::Error::Corrupted)?)
+}
+
+/// Reads and decodes detach.bin, failing if it is corrupted
+fn load_detached() -> CLIResult<(Vec<u8>, Vec<DetachEntry>)> {
+    let content = read_detach_bin()?;
+    let detached = get_detached_apps(&content)?;
+    Ok((content, detached))
+}
+
+/// Reads detach.bin and remembers it, for `write_detach_bin` to tell when it was
+/// changed from outside since
+fn read_detach_bin() -> CLIResult<Vec<u8>> {
+    let content = current_detach_bin()?;
+    external::record(&content);
+    Ok(content)
+}
+
+fn current_detach_bin() -> CLIResult<Vec<u8>> {
+    match device::get().read(DeviceFile::DetachBin) {
+        Ok(v) => Ok(v),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(error::Error::detach_file(e).into()),
+    }
+}
+
+fn get_installed_apps() -> CLIResult<Vec<u8>> {
+    list_packages(&[])
+}
+
+fn get_third_party_apps() -> CLIResult<Vec<u8>> {
+    list_packages(&["-3"])
+}
+
+fn list_packages(flags: &[&str]) -> CLIResult<Vec<u8>> {
+    Ok(users::list_packages(flags).map_err(error::Error::pm)?)
+}
+
+/// `list_packages` for the menus, answered from the package cache when possible
+fn menu_packages(menus: &mut Menus, flags: &[&str]) -> CLIResult<Vec<u8>> {
+    if let Some(cached) = packages::get(flags) {
+        return Ok(cached);
+    }
+    text!(menus, "{}", "loading packages…".faint());
+    let out = list_packages(flags)?;
+    text!(menus, "");
+    // not what the menu did
+    menus.take_note();
+    packages::put(flags, &out);
+    Ok(out)
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    DetachSelect,
+    DetachMulti,
+    Toggle,
+    DetachAll,
+    ReattachSelect,
+    ReattachAll,
+    Manage,
+    Prune,
+    Compare,
+    Restore,
+    Sync,
+    Keep,
+    SwitchMode,
+    SelectUser,
+    Profiles,
+    Undo,
+    History,
+    Export,
+    Import,
+    Reset,
+    CopyToSd,
+    Refresh,
+    Quit,
+    /// Ctrl+C on the main menu
+    Interrupted,
+    Nop,
+}
+
+impl Op {
+    /// What the history logs the changes made by the menu item as
+    fn name(self) -> &'static str {
+        match self {
+            Op::DetachSelect => "menu: detach",
+            Op::DetachMulti => "menu: detach multiple",
+            Op::Toggle => "menu: toggle",
+            Op::DetachAll => "menu: detach all",
+            Op::ReattachSelect => "menu: re-attach",
+            Op::ReattachAll => "menu: re-attach all",
+            Op::Manage => "menu: manage",
+            Op::Prune => "menu: prune",
+            Op::Compare => "menu: compare",
+            Op::Restore => "menu: restore backup",
+            Op::Sync => "menu: sync",
+            Op::Keep => "menu: keep-list",
+            Op::SwitchMode => "menu: switch mode",
+            Op::SelectUser => "menu: select user",
+            Op::Profiles => "menu: profiles",
+            Op::Undo => "menu: undo",
+            Op::History => "menu: history",
+            Op::Export => "menu: export",
+            Op::Import => "menu: import",
+            Op::Reset => "menu: reset",
+            Op::CopyToSd => "menu: copy",
+            Op::Refresh | Op::Quit | Op::Interrupted | Op::Nop => "menu",
+        }
+    }
+
+    /// The line the main menu shows under the list for the highlighted item
+    fn help(self) -> &'static str {
+        match self {
+            Op::DetachSelect => "Search the installed apps and detach one",
+            Op::DetachMulti => "Check any number of installed apps and detach them at once",
+            Op::Toggle => "Detach and re-attach Play Store apps in one list, written together",
+            Op::DetachAll => "Detach every third-party app, or every app with system apps",
+            Op::ReattachSelect => "Pick a detached app so the Play Store updates it again",
+            Op::ReattachAll => "Empty the list so the Play Store updates every app again",
+            Op::Manage => "Browse the detached apps to re-attach them, copy names or edit notes",
+            Op::Prune => "Drop detached apps that are no longer installed",
+            Op::Compare => "Set the detached apps against the installed ones and the Play Store's",
+            Op::Restore => "Put back one of the detach.bin copies taken before changes",
+            Op::Sync => "Detach every installed app that is not on the keep-list",
+            Op::Keep => "Pick the apps whitelist mode leaves attached",
+            Op::SwitchMode => "Change between detaching the listed apps and all but the kept ones",
+            Op::SelectUser => "List the packages of another user or the work profile",
+            Op::Profiles => "Switch between saved detach lists or save this one",
+            Op::Undo => "Revert the last change to the detach list",
+            Op::History => "See every change with the command or menu item that made it",
+            Op::Export => "Write the package names to a text file on /sdcard",
+            Op::Import => "Detach the packages named in the text file on /sdcard",
+            Op::Reset => {
+                "Delete the whole detach list after a backup, every app gets updated again"
+            }
+            Op::CopyToSd => "Copy detach.bin to /sdcard, to put it in the module zip",
+            Op::Refresh | Op::Quit | Op::Interrupted | Op::Nop => "",
+        }
+    }
+}
+
+fn main_menu(menus: &mut Menus, mode: Mode) -> CLIResult<Op> {
+    struct OpText {
+        desc: &'static str,
+        op: Op,
+    }
+    impl OpText {
+        fn new(desc: &'static str, op: Op) -> Self {
+            Self { desc, op }
+        }
+    }
+    impl Display for OpText {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}", self.desc)
+        }
+    }
+    let (mut ops, mut title) = match mode {
+        Mode::Blacklist => (
+            vec![
+                OpText::new("Detach", Op::DetachSelect),
+                OpText::new("Detach multiple", Op::DetachMulti),
+                OpText::new("Detach and re-attach", Op::Toggle),
+                OpText::new("Detach all installed apps", Op::DetachAll),
+                OpText::new("Re-attach", Op::ReattachSelect),
+                OpText::new("Re-attach all", Op::ReattachAll),
+                OpText::new("Manage detached apps", Op::Manage),
+                OpText::new("Prune uninstalled apps", Op::Prune),
+                OpText::new("Compare detached and installed apps", Op::Compare),
+                OpText::new("Reset detached apps", Op::Reset),
+                OpText::new("Restore backup", Op::Restore),
+                OpText::new("Undo last change", Op::Undo),
+                OpText::new("Change history", Op::History),
+                OpText::new("Copy detach.bin to /sdcard", Op::CopyToSd),
+                OpText::new("Export detach list to /sdcard", Op::Export),
+                OpText::new("Import detach list from /sdcard", Op::Import),
+                OpText::new("Profiles", Op::Profiles),
+                OpText::new("Switch to whitelist mode", Op::SwitchMode),
+            ],
+            format!("- Selection {}:", "('r' reloads the app list)".faint()),
+        ),
+        // detaching by hand would be undone by the next sync
+        Mode::Whitelist => (
+            vec![
+                OpText::new("Sync detached apps with the keep-list", Op::Sync),
+                OpText::new("Edit keep-list", Op::Keep),
+                OpText::new("Manage detached apps", Op::Manage),
+                OpText::new("Restore backup", Op::Restore),
+                OpText::new("Undo last change", Op::Undo),
+                OpText::new("Change history", Op::History),
+                OpText::new("Copy detach.bin to /sdcard", Op::CopyToSd),
+                OpText::new("Export detach list to /sdcard", Op::Export),
+                OpText::new("Profiles", Op::Profiles),
+                OpText::new("Switch to blacklist mode", Op::SwitchMode),
+            ],
+            format!(
+                "- Selection {}:",
+                "(whitelist mode, everything but the keep-list is detached)".yellow()
+            ),
+        ),
+    };
+    // a picker is only worth showing with a work profile or a second user around
+    if users::users().is_ok_and(|u| u.len() > 1) {
+        ops.push(OpText::new("Select user", Op::SelectUser));
+        if let Some(scope) = scope_name() {
+            title = format!("{title} {}", format!("(packages of {scope})").faint());
+        }
+    }
+    if profiles::in_use()? {
+        let profile = profiles::active()?;
+        title = format!("{title} {}", format!("(profile {profile})").cyan());
+    }
+    if dry_run::enabled() {
+        title = format!("{} {title}", dry_run_badge());
+    }
+    let help: Vec<&str> = ops.iter().map(|o| o.op.help()).collect();
+    let i = menus.select_menu_numbered(
+        ops.iter(),
+        &help,
+        Key::Char('q'),
+        Some((Key::Char('r'), "reload the package list")),
+        &title,
+    )?;
+    use menus::SelectNumberedResp as SN;
+    match i {
+        SN::Index(i) => Ok(ops[i].op),
+        SN::UndefinedKey(Key::Char('r')) => Ok(Op::Refresh),
+        SN::UndefinedKey(k) => {
+            menus.reject()?;
+            menus.toast(
+                format_args!("unknown key: {}", menus::KeyName(k)),
+                Level::Warn,
+            )?;
+            Ok(Op::Nop)
+        }
+        SN::Quit => Ok(Op::Quit),
+        SN::Interrupted => Ok(Op::Interrupted),
+    }
+}
+
+struct DetachAll {
+    added: usize,
+    skipped: Vec<String>,
+    size: usize,
+    /// Ctrl+C stopped it, the apps before were still detached
+    stopped: Option<progress::Stopped>,
+}
+
+/// Appends every installed app that is not detached yet to detach.bin in one write
+fn detach_all(system: bool) -> CLIResult<DetachAll> {
+    let installed_apps = if system {
+        get_installed_apps()?
+    } else {
+        get_third_party_apps()?
+    };
+    let (mut content, detached) = load_detached()?;
+    let apps: Vec<&str> = parse_installed_apps(&installed_apps).collect();
+    let mut progress = progress::Progress::new(apps.len());
+    let mut added = Vec::new();
+    let mut skipped = Vec::new();
+    for app in apps {
+        if progress.stopped() {
+            break;
+        }
+        if !detach_bin::encodable(app) {
+            skipped.push(app.to_string());
+        } else if !detached.iter().any(|(s, _)| s == app) && !added.contains(&app) {
+            detach_bin::serialize(app, &mut content)?;
+            added.push(app);
+        }
+        progress.step(app);
+    }
+    let stopped = progress.stop();
+    if !added.is_empty() {
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+    }
+    Ok(DetachAll {
+        added: added.len(),
+        skipped,
+        size: content.len(),
+        stopped,
+    })
+}
+
+/// Saves detach.bin as the active profile and replaces it with the apps of profile
+/// `name`, returns how many those are
+fn switch_profile(name: &str) -> CLIResult<usize> {
+    save_active_profile()?;
+    let apps = profiles::load(name)?;
+    let mut content = Vec::new();
+    for app in apps.iter().filter(|app| detach_bin::encodable(app)) {
+        detach_bin::serialize(app, &mut content)?;
+    }
+    if content != read_detach_bin()? {
+        backup::snapshot()?;
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+    }
+    profiles::set_active(name)?;
+    Ok(apps.len())
+}
+
+/// Brings the active profile's file up to date with detach.bin
+fn save_active_profile() -> CLIResult<()> {
+    let (_, detached) = load_detached()?;
+    let apps: Vec<&str> = detached.iter().map(|(name, _)| name.as_str()).collect();
+    profiles::save(&profiles::active()?, &apps)?;
+    Ok(())
+}
+
+/// Starts profile `name` with a copy of the current list, making it the active one
+fn new_profile(name: &str) -> CLIResult<()> {
+    save_active_profile()?;
+    profiles::set_active(name)?;
+    save_active_profile()
+}
+
+fn profile_menu(menus: &mut Menus) -> CLIResult<()> {
+    let active = profiles::active()?;
+    let names = profiles::list()?;
+    let mut items: Vec<String> = names
+        .iter()
+        .map(|name| {
+            if *name == active {
+                format!("{name} {}", "(active)".green())
             } else {
-                Vec::new()
+                name.clone()
+            }
+        })
+        .collect();
+    items.push("New profile from the current list".to_string());
+    let Some(i) = menus
+        .select_menu_kept(
+            "profiles",
+            items.iter(),
+            None,
+            "Profiles ('q' to leave):",
+            "↪".green(),
+            Some(Key::Char('q')),
+        )?
+        .picked()
+    else {
+        return Ok(());
+    };
+    let Some(name) = names.get(i) else {
+        let Some(name) = read_profile_name(menus)? else {
+            return Ok(());
+        };
+        new_profile(&name)?;
+        menus.toast(format_args!("profile created: {name}"), Level::Success)?;
+        return Ok(());
+    };
+    let Some(action) = menus.select_menu(
+        ["Switch to it", "Rename", "Delete"].iter(),
+        name,
+        "↪".green(),
+        Some(Key::Char('q')),
+    )?
+    else {
+        return Ok(());
+    };
+    match action {
+        0 if *name == active => menus.toast(
+            format_args!("{name} is already the active profile"),
+            Level::Info,
+        )?,
+        0 => {
+            let n = switch_profile(name)?;
+            menus.toast(format_args!("switched to {name}, {n} apps"), Level::Success)?;
+        }
+        1 => {
+            let Some(new) = read_profile_name(menus)? else {
+                return Ok(());
+            };
+            profiles::rename(name, &new)?;
+            menus.toast(format_args!("renamed {name} to {new}"), Level::Success)?;
+        }
+        _ if *name == active => {
+            menus.toast(
+                format_args!("{name} is active, switch to another profile to delete it"),
+                Level::Warn,
+            )?;
+        }
+        _ => {
+            if menus.confirm(format_args!("Delete profile {name}?"), false)? {
+                profiles::delete(name)?;
+                menus.toast(format_args!("profile deleted: {name}"), Level::Success)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Asks for the name of a profile that does not exist yet
+fn read_profile_name(menus: &mut Menus) -> CLIResult<Option<String>> {
+    let mut prompt = "- profile name: ".magenta().to_string();
+    loop {
+        let Some(name) = menus.read_line(&prompt)? else {
+            return Ok(None);
+        };
+        let name = name.trim().to_string();
+        if !profiles::valid_name(&name) {
+            prompt = format!(
+                "{} {}",
+                "letters, digits, '-', '_' and '.' only:".red(),
+                "- profile name: ".magenta()
+            );
+        } else if profiles::exists(&name)? {
+            prompt = format!(
+                "{} {}",
+                format_args!("{name} exists:").red(),
+                "- profile name: ".magenta()
+            );
+        } else {
+            return Ok(Some(name));
+        }
+    }
+}
+
+/// Detaches the apps in the `installed` listing that match `rules` and are not
+/// detached yet, returns their names. In whitelist mode the keep-list decides instead
+fn expand_rules(rules: &Rules, installed: &[u8]) -> CLIResult<Vec<String>> {
+    let _op = history::operation("rules");
+    if rules.is_empty() || config::Config::load()?.mode == Mode::Whitelist {
+        return Ok(Vec::new());
+    }
+    let (mut content, detached) = load_detached()?;
+    let mut added: Vec<String> = Vec::new();
+    for app in parse_installed_apps(installed) {
+        if rules.matches(app)
+            && detach_bin::encodable(app)
+            && !detached.iter().any(|(s, _)| s == app)
+            && !added.iter().any(|a| a == app)
+        {
+            detach_bin::serialize(app, &mut content)?;
+            added.push(app.to_string());
+        }
+    }
+    if !added.is_empty() {
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+    }
+    Ok(added)
+}
+
+/// `expand_rules` with the rules in RULES_TXT against every installed app
+fn apply_rules() -> CLIResult<Vec<String>> {
+    let rules = Rules::load(RULES_TXT)?;
+    if rules.is_empty() {
+        return Ok(Vec::new());
+    }
+    expand_rules(&rules, &get_installed_apps()?)
+}
+
+fn apply_rules_menu(menus: &mut Menus) -> CLIResult<()> {
+    let rules = Rules::load(RULES_TXT)?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let installed = menu_packages(menus, &[])?;
+    let added = expand_rules(&rules, &installed)?;
+    if !added.is_empty() {
+        textln!(menus, "{} {}", "rules detached:".green(), added.join(", "));
+    }
+    Ok(())
+}
+
+struct Sync {
+    detached: usize,
+    kept: usize,
+    skipped: Vec<String>,
+}
+
+/// Whitelist mode: rewrites detach.bin to every third-party app not on the keep-list
+fn sync_whitelist() -> CLIResult<Sync> {
+    let keep = config::load_keep()?;
+    let installed_apps = get_third_party_apps()?;
+    let old_content = read_detach_bin()?;
+    let mut content = Vec::new();
+    let mut res = Sync {
+        detached: 0,
+        kept: 0,
+        skipped: Vec::new(),
+    };
+    for app in parse_installed_apps(&installed_apps) {
+        if keep.iter().any(|k| k == app) {
+            res.kept += 1;
+        } else if !detach_bin::encodable(app) {
+            res.skipped.push(app.to_string());
+        } else {
+            detach_bin::serialize(app, &mut content)?;
+            res.detached += 1;
+        }
+    }
+    if content != old_content {
+        backup::snapshot()?;
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+    }
+    Ok(res)
+}
+
+/// Empties detach.bin, returns how many entries it had
+fn reattach_all() -> CLIResult<usize> {
+    let content = read_detach_bin()?;
+    let n = detach_bin::parse(&content).map_or(0, |d| d.len());
+    if !content.is_empty() {
+        backup::snapshot()?;
+        write_detach_bin(&[])?;
+        detach_bin_changed();
+    }
+    Ok(n)
+}
+
+/// The packages installed for any user. Apps uninstalled with their data kept are still
+/// listed by `pm list packages -u` and count as installed
+fn installed_anywhere() -> CLIResult<Vec<String>> {
+    let packages = users::list_everywhere(&["-u"]).map_err(error::Error::pm)?;
+    Ok(parse_installed_apps(&packages)
+        .map(str::to_string)
+        .collect())
+}
+
+struct Orphans {
+    content: Vec<u8>,
+    orphans: Vec<DetachEntry>,
+    /// Ctrl+C stopped the search, the entries after were not looked at
+    stopped: Option<progress::Stopped>,
+}
+
+/// Detached entries whose app is gone from the device. detach.bin is shared, so an app
+/// only installed in another profile is not an orphan
+fn find_orphans() -> CLIResult<Orphans> {
+    let (content, detached) = load_detached()?;
+    let installed = installed_anywhere()?;
+    let mut progress = progress::Progress::new(detached.len());
+    let mut orphans = Vec::new();
+    for entry in detached {
+        if progress.stopped() {
+            break;
+        }
+        progress.step(&entry.0);
+        if !installed.contains(&entry.0) {
+            orphans.push(entry);
+        }
+    }
+    Ok(Orphans {
+        content,
+        orphans,
+        stopped: progress.stop(),
+    })
+}
+
+/// The detached apps split by whether they are installed, and the Play Store apps
+/// that are not detached
+struct Comparison {
+    installed: Vec<String>,
+    /// Candidates for prune
+    missing: Vec<String>,
+    /// Candidates to detach
+    undetached: Vec<String>,
+}
+
+fn compare_apps() -> CLIResult<Comparison> {
+    let (_, detached) = load_detached()?;
+    let everywhere = installed_anywhere()?;
+    let listing = list_packages(&["-i"])?;
+    let (installed, missing) = detached
+        .iter()
+        .map(|(name, _)| name.clone())
+        .partition(|name| everywhere.contains(name));
+    let undetached = parse_installers(&listing)
+        .filter(|&(app, installer)| {
+            installer == Some(installers::PLAY_STORE) && !detached.iter().any(|(d, _)| d == app)
+        })
+        .map(|(app, _)| app.to_string())
+        .collect();
+    Ok(Comparison {
+        installed,
+        missing,
+        undetached,
+    })
+}
+
+/// Cuts the entries at `ranges` out of detach.bin, touching the file and the store
+/// only when there is something to remove
+fn remove_entries(mut content: Vec<u8>, mut ranges: Vec<Range<usize>>) -> CLIResult<()> {
+    if ranges.is_empty() {
+        return Ok(());
+    }
+    ranges.sort_by_key(|r| std::cmp::Reverse(r.start));
+    ranges.dedup();
+    for range in ranges {
+        content.drain(range);
+    }
+    write_detach_bin(&content)?;
+    detach_bin_changed();
+    Ok(())
+}
+
+/// Writes the detached apps to `path` one per line, returns how many were written
+fn export_txt(path: &str) -> CLIResult<usize> {
+    let (_, detached) = load_detached()?;
+    dry_run::write_local(path, export_lines(&detached).as_bytes())?;
+    Ok(detached.len())
+}
+
+/// The text file [`export_txt`] writes for `detached`
+fn export_lines(detached: &[DetachEntry]) -> String {
+    let mut txt = String::new();
+    for (name, _) in detached {
+        txt.push_str(name);
+        txt.push('\n');
+    }
+    txt
+}
+
+struct Import {
+    added: usize,
+    present: usize,
+    skipped: Vec<String>,
+    /// Ctrl+C stopped it, the apps before were still imported
+    stopped: Option<progress::Stopped>,
+}
+
+/// Adds the apps listed in the text file at `path` to detach.bin,
+/// or makes them the whole list with `replace`
+fn import_txt(path: &str, replace: bool) -> CLIResult<Import> {
+    let txt = fs::read_to_string(path)?;
+    let (old_content, old) = load_detached()?;
+    let apps: Vec<&str> = parse_txt(&txt).collect();
+    let mut progress = progress::Progress::new(apps.len());
+    let apps = apps.into_iter().take_while(|app| {
+        let going = !progress.stopped();
+        if going {
+            progress.step(app);
+        }
+        going
+    });
+    let (content, mut res) = import_apps(&old_content, &old, apps, replace)?;
+    res.stopped = progress.stop();
+    if content != old_content {
+        if replace {
+            backup::snapshot()?;
+        }
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+    }
+    Ok(res)
+}
+
+/// detach.bin with `apps` added to `old_content`, which decodes to `old`, or made the
+/// whole list with `replace`
+fn import_apps<'a>(
+    old_content: &[u8],
+    old: &[DetachEntry],
+    apps: impl IntoIterator<Item = &'a str>,
+    replace: bool,
+) -> CLIResult<(Vec<u8>, Import)> {
+    let mut content = if replace {
+        Vec::new()
+    } else {
+        old_content.to_vec()
+    };
+    let mut res = Import {
+        added: 0,
+        present: 0,
+        skipped: Vec::new(),
+        stopped: None,
+    };
+    let mut seen = Vec::new();
+    for app in apps {
+        if seen.contains(&app) {
+            continue;
+        }
+        seen.push(app);
+        let present = old.iter().any(|(s, _)| s == app);
+        if present {
+            res.present += 1;
+            if !replace {
+                continue;
+            }
+        }
+        if !detach_bin::encodable(app) {
+            res.skipped.push(app.to_string());
+            continue;
+        }
+        detach_bin::serialize(app, &mut content)?;
+        if !present {
+            res.added += 1;
+        }
+    }
+    Ok((content, res))
+}
+
+/// What importing the text file at `path` would change, and the apps in it that
+/// cannot be stored in detach.bin
+fn import_diff(path: &str, replace: bool) -> CLIResult<(Vec<diff::Line>, Vec<String>)> {
+    let txt = fs::read_to_string(path)?;
+    let (_, current) = load_detached()?;
+    let (incoming, skipped): (Vec<&str>, Vec<&str>) =
+        parse_txt(&txt).partition(|app| detach_bin::encodable(app));
+    let current: Vec<&str> = current.iter().map(|(name, _)| name.as_str()).collect();
+    let skipped = skipped.into_iter().map(str::to_string).collect();
+    Ok((diff::diff(&current, &incoming, replace), skipped))
+}
+
+fn export_menu(menus: &mut Menus) -> CLIResult<()> {
+    let n = export_txt(EXPORT_TXT)?;
+    menus.toast(
+        format_args!("Exported {n} apps to {EXPORT_TXT}"),
+        Level::Success,
+    )?;
+    Ok(())
+}
+
+fn import_menu(menus: &mut Menus) -> CLIResult<()> {
+    let Some(i) = menus.select_menu(
+        ["Merge with the current list", "Replace the current list"].iter(),
+        format_args!("Import {EXPORT_TXT} ('q' to leave):"),
+        "↪".green(),
+        Some(Key::Char('q')),
+    )?
+    else {
+        return Ok(());
+    };
+    let replace = i == 1;
+    let (lines, _) = match import_diff(EXPORT_TXT, replace) {
+        Ok(diff) => diff,
+        Err(CLIErr {
+            source: error::Error::Io(err),
+            ..
+        }) if err.kind() == io::ErrorKind::NotFound => {
+            menus.toast(format_args!("{EXPORT_TXT} not found"), Level::Warn)?;
+            return Ok(());
+        }
+        Err(err) => return Err(err),
+    };
+    if !diff::has_changes(&lines) {
+        menus.toast(
+            format_args!("Nothing to import, {}", diff::summary(&lines)),
+            Level::Info,
+        )?;
+        return Ok(());
+    }
+    if menus
+        .select_menu(
+            lines.iter(),
+            format_args!(
+                "Import: {} (ENTER applies, 'q' to leave):",
+                diff::summary(&lines)
+            ),
+            "↪".green(),
+            Some(Key::Char('q')),
+        )?
+        .is_none()
+    {
+        return Ok(());
+    }
+    let removed = lines
+        .iter()
+        .filter(|l| l.change == diff::Change::Removed)
+        .count();
+    if removed > 0
+        && !menus.confirm(
+            format_args!("Remove {removed} apps from the detach list?"),
+            false,
+        )?
+    {
+        return Ok(());
+    }
+    let res = import_txt(EXPORT_TXT, replace)?;
+    if !res.skipped.is_empty() {
+        textln!(menus, "{} {}", "skipped:".red(), res.skipped.join(", "));
+    }
+    if let Some(stopped) = &res.stopped {
+        textln!(menus, "{} {stopped}", "import:".yellow());
+    }
+    menus.toast(
+        format_args!(
+            "import: {} added, {} already present",
+            res.added, res.present
+        ),
+        Level::Success,
+    )?;
+    Ok(())
+}
+
+fn prune_menu(menus: &mut Menus) -> CLIResult<()> {
+    let Orphans {
+        content,
+        orphans,
+        stopped,
+    } = find_orphans()?;
+    if let Some(stopped) = stopped {
+        textln!(menus, "{} {stopped}", "prune:".red());
+    }
+    if orphans.is_empty() {
+        menus.toast("No uninstalled apps in detach.bin", Level::Info)?;
+        return Ok(());
+    }
+    let Some(selected) = menus.select_menu_multi(
+        orphans.iter().map(|(name, _)| name),
+        format_args!(
+            "{} detached apps are not installed, select the ones to remove \
+             (SPACE toggle, 'a' all, 'i' invert, 'q' to leave):",
+            orphans.len()
+        ),
+        "↪".green(),
+        Some(Key::Char('q')),
+    )?
+    else {
+        return Ok(());
+    };
+    if selected.is_empty() {
+        menus.toast("No entries selected", Level::Info)?;
+        return Ok(());
+    }
+    if !menus.confirm(format_args!("Remove {} entries?", selected.len()), false)? {
+        return Ok(());
+    }
+    backup::snapshot()?;
+    remove_entries(
+        content,
+        selected.iter().map(|&i| orphans[i].1.clone()).collect(),
+    )?;
+    textln!(menus, "{} {} entries", "prune:".red(), selected.len());
+    Ok(())
+}
+
+/// One step of a reset, done or failed with why
+struct ResetStep {
+    what: String,
+    outcome: Result<String, String>,
+}
+
+/// Empties the detach list everywhere it is kept: detach.bin and detach.txt in the
+/// module, which the module would turn back into a detach.bin, and the copy on the
+/// sdcard. A backup is taken first and nothing is removed when that fails, so a reset
+/// can always be restored. With `clear_cache` the Play Store's cache goes too, then the
+/// store is restarted. Every step is tried and reported, whether the ones before
+/// worked or not
+fn reset(clear_cache: bool) -> CLIResult<Vec<ResetStep>> {
+    let device = device::get();
+    let before = read_detach_bin()?;
+    let mut steps = vec![ResetStep {
+        what: "back up detach.bin".to_string(),
+        outcome: match backup::snapshot() {
+            Ok(Some(time)) => Ok(format!(
+                "saved as {}",
+                device.path(DeviceFile::Backup(time))?
+            )),
+            Ok(None) => Ok("nothing to back up".to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+    }];
+    if steps[0].outcome.is_err() {
+        return Ok(steps);
+    }
+    for file in [
+        DeviceFile::DetachBin,
+        DeviceFile::DetachTxt,
+        DeviceFile::SdcardDetachBin,
+    ] {
+        let outcome = match device.remove(file) {
+            Ok(()) => Ok("removed".to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok("not there".to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if file == DeviceFile::DetachBin && outcome.is_ok() {
+            external::record(&[]);
+            record_change(&before, &[]);
+        }
+        steps.push(ResetStep {
+            what: format!("remove {}", device.path(file)?),
+            outcome,
+        });
+    }
+    if clear_cache {
+        steps.push(ResetStep {
+            what: "clear the Play Store cache".to_string(),
+            outcome: (device.clear_cache(device::STORE_PKG))
+                .map(|()| "cleared".to_string())
+                .map_err(|e| e.to_string()),
+        });
+    }
+    steps.push(ResetStep {
+        what: "restart the Play Store".to_string(),
+        outcome: if NO_RESTART.load(Ordering::Relaxed) {
+            Ok("skipped, --no-restart".to_string())
+        } else {
+            match restart_store() {
+                Ok(pkgs) if pkgs.is_empty() => Ok("dry run".to_string()),
+                Ok(pkgs) => Ok(format!("stopped {}", pkgs.join(", "))),
+                Err(e) => Err(e.to_string()),
             }
         },
+    });
+    Ok(steps)
+}
+
+fn reset_menu(menus: &mut Menus) -> CLIResult<()> {
+    let n = get_detached_apps(&read_detach_bin()?)?.len();
+    let answer = menus.read_line(format_args!(
+        "{} {n} apps will be reattached and updated again. Type yes to reset: ",
+        "reset:".red()
+    ))?;
+    if answer.as_deref().map(str::trim) != Some("yes") {
+        menus.toast("Not reset", Level::Info)?;
+        return Ok(());
+    }
+    let clear_cache = menus.confirm("Also clear the Play Store cache?", false)?;
+    for step in reset(clear_cache)? {
+        match step.outcome {
+            Ok(done) => textln!(menus, "{} {}: {done}", "✓".green(), step.what),
+            Err(e) => textln!(menus, "{} {}: {e}", "✗".red(), step.what),
+        }
+    }
+    Ok(())
+}
+
+fn compare_menu(menus: &mut Menus) -> CLIResult<()> {
+    loop {
+        let cmp = compare_apps()?;
+        let all: Vec<&str> = (cmp.installed.iter())
+            .chain(&cmp.missing)
+            .chain(&cmp.undetached)
+            .map(String::as_str)
+            .collect();
+        let labels = labels::Labels::cached(&all);
+        let mut rows = Vec::new();
+        // per item, the app Enter detaches
+        let mut to_detach = Vec::new();
+        let groups = [
+            (&cmp.installed, "detached and installed".green()),
+            (
+                &cmp.missing,
+                "detached but not installed, prune removes them".red(),
+            ),
+            (
+                &cmp.undetached,
+                "from the Play Store and not detached".yellow(),
+            ),
+        ];
+        for (group, (apps, heading)) in groups.into_iter().enumerate() {
+            if group > 0 {
+                rows.push(Row::Separator);
+            }
+            rows.push(Row::Header(format!("{heading} ({})", apps.len())));
+            for app in apps {
+                rows.push(Row::Item(format!("  {}", labels.labeled(app))));
+                to_detach.push((group == 2).then_some(app.as_str()));
+            }
+        }
+        let Some(i) = menus
+            .select_rows_kept(
+                "compare",
+                rows.iter().cloned(),
+                None,
+                "Detached and installed apps (ENTER on a Play Store app detaches it, 'q' to leave):",
+                "▶".green(),
+                Some(Key::Char('q')),
+            )?
+            .picked()
+        else {
+            return Ok(());
+        };
+        let Some(app) = to_detach.get(i).copied().flatten() else {
+            menus.reject()?;
+            continue;
+        };
+        if storable(menus, [app])?.is_empty() {
+            continue;
+        }
+        if !menus.confirm(format_args!("Detach {app}?"), true)? {
+            continue;
+        }
+        let mut content = read_detach_bin()?;
+        detach_bin::serialize(app, &mut content)?;
+        write_detach_bin(&content)?;
+        detach_bin_changed();
+        menus.toast(format_args!("detached {app}"), Level::Success)?;
+    }
+}
+
+fn sync_menu(menus: &mut Menus) -> CLIResult<()> {
+    let res = sync_whitelist()?;
+    if !res.skipped.is_empty() {
+        textln!(menus, "{} {}", "skipped:".red(), res.skipped.join(", "));
+    }
+    menus.toast(
+        format_args!("sync: {} detached, {} kept", res.detached, res.kept),
+        Level::Success,
+    )?;
+    Ok(())
+}
+
+fn keep_menu(menus: &mut Menus) -> CLIResult<()> {
+    let installed_apps = menu_packages(menus, &["-3"])?;
+    let apps: Vec<&str> = parse_installed_apps(&installed_apps).collect();
+    let mut keep = config::load_keep()?;
+    let checked = apps
+        .iter()
+        .map(|app| keep.iter().any(|k| k == app))
+        .collect();
+    let Some(selected) = menus.select_menu_multi_from(
+        apps.iter(),
+        checked,
+        "Select the apps to keep attached (SPACE toggle, 'a' all, 'i' invert, 'q' to leave):",
+        "↪".green(),
+        Some(Key::Char('q')),
+    )?
+    else {
+        return Ok(());
+    };
+    // entries for apps that are not installed right now stay on the list
+    keep.retain(|k| !apps.contains(&k.as_str()));
+    keep.extend(selected.iter().map(|&i| apps[i].to_string()));
+    config::save_keep(&keep)?;
+    sync_menu(menus)
+}
+
+fn switch_mode_menu(menus: &mut Menus, config: &mut config::Config) -> CLIResult<()> {
+    let (mode, question) = match config.mode {
+        Mode::Blacklist => (
+            Mode::Whitelist,
+            "Switch to whitelist mode? detach.bin is rewritten to every app not on the keep-list",
+        ),
+        Mode::Whitelist => (
+            Mode::Blacklist,
+            "Switch to blacklist mode? detach.bin stays as it is",
+        ),
+    };
+    if !menus.confirm(question, false)? {
+        return Ok(());
+    }
+    config::set("mode", mode.name())?;
+    config.mode = mode;
+    textln!(menus, "{} {} mode", "switched to".green(), mode.name());
+    if mode == Mode::Whitelist {
+        sync_menu(menus)?;
+    }
+    Ok(())
+}
+
+/// The selected user scope for titles, `None` for the current user
+fn scope_name() -> Option<String> {
+    match users::scope() {
+        users::Scope::Current => None,
+        users::Scope::All => Some("all users".to_string()),
+        users::Scope::One(id) => Some(
+            users::users()
+                .ok()
+                .and_then(|u| u.iter().find(|u| u.id == id))
+                .map_or_else(|| format!("user {id}"), |u| u.to_string()),
+        ),
+    }
+}
+
+fn select_user_menu(menus: &mut Menus) -> CLIResult<()> {
+    let users = users::users().map_err(error::Error::pm)?;
+    let mut scopes = vec![users::Scope::Current, users::Scope::All];
+    scopes.extend(users.iter().map(|u| users::Scope::One(u.id)));
+    let list = ["Current user".to_string(), "All users".to_string()]
+        .into_iter()
+        .chain(users.iter().map(|u| u.to_string()));
+    let Some(i) = menus
+        .select_menu_kept(
+            "users",
+            list,
+            None,
+            "List the packages of ('q' to leave):",
+            "↪".green(),
+            Some(Key::Char('q')),
+        )?
+        .picked()
+    else {
+        return Ok(());
+    };
+    users::set_scope(scopes[i]);
+    textln!(
+        menus,
+        "{} {}",
+        "listing packages of".green(),
+        scope_name().unwrap_or_else(|| "the current user".to_string())
+    );
+    Ok(())
+}
+
+fn undo_menu(menus: &mut Menus) -> CLIResult<()> {
+    let Some(change) = journal::last()? else {
+        menus.toast("Nothing to undo", Level::Info)?;
+        return Ok(());
+    };
+    if !menus.confirm(format_args!("Undo: {}?", change.describe()), true)? {
+        return Ok(());
+    }
+    if let Some(change) = journal::undo()? {
+        menus.toast(
+            format_args!(
+                "undid: {} {}",
+                change.describe(),
+                "(`detach redo` brings it back)".faint()
+            ),
+            Level::Success,
+        )?;
+    }
+    Ok(())
+}
+
+/// How many changes the history screen lists
+const HISTORY_SHOWN: usize = 50;
+
+fn history_menu(menus: &mut Menus) -> CLIResult<()> {
+    let entries = history::last(HISTORY_SHOWN)?;
+    if entries.is_empty() {
+        menus.toast("No changes logged yet", Level::Info)?;
+        return Ok(());
+    }
+    menus.select_menu(
+        entries.iter().map(history::describe),
+        "Changes to detach.bin, newest first ('q' to leave):",
+        "↪".green(),
+        Some(Key::Char('q')),
+    )?;
+    Ok(())
+}
+
+fn restore_menu(menus: &mut Menus) -> CLIResult<()> {
+    let backups = backup::list()?;
+    if backups.is_empty() {
+        menus.toast("No backups yet", Level::Info)?;
+        return Ok(());
+    }
+    let list = backups.iter().map(|b| {
+        let date = detach_core::time::display_time(b.time);
+        match b.entries {
+            Some(n) => format!("{date} UTC, {n} apps"),
+            None => format!("{date} UTC, {}", "corrupted".red()),
+        }
+    });
+    let Some(i) = menus.select_menu(
+        list,
+        "Select the backup to restore ('q' to leave):",
+        "↪".green(),
+        Some(Key::Char('q')),
+    )?
+    else {
+        return Ok(());
+    };
+    let n = backup::restore(backups[i].time)?;
+    textln!(menus, "{} {n} apps", "restored:".green());
+    Ok(())
+}
+
+fn detach_all_menu(menus: &mut Menus) -> CLIResult<()> {
+    // the configured choice comes first
+    let mut choices = [
+        (false, "Third-party apps only"),
+        (true, "Include system apps"),
+    ];
+    if config::Config::load()?.system {
+        choices.reverse();
+    }
+    let Some(i) = menus.select_menu(
+        choices.iter().map(|(_, text)| text),
+        "Detach all installed apps ('q' to leave):",
+        "↪".green(),
+        Some(Key::Char('q')),
+    )?
+    else {
+        return Ok(());
+    };
+    let res = detach_all(choices[i].0)?;
+    if !res.skipped.is_empty() {
+        textln!(menus, "{} {}", "skipped:".red(), res.skipped.join(", "));
+    }
+    match &res.stopped {
+        Some(stopped) => textln!(menus, "{} {} apps, {stopped}", "detach:".green(), res.added),
+        None => textln!(menus, "{} {} apps", "detach:".green(), res.added),
+    }
+    if res.size > MODULE_CAP {
+        textln!(
+            menus,
+            "{} detach.bin is {} bytes, the module only loads up to {MODULE_CAP}",
+            "warning:".red(),
+            res.size
+        );
+    }
+    Ok(())
+}
+
+fn reattach_all_menu(menus: &mut Menus) -> CLIResult<()> {
+    if !menus.confirm("Re-attach all apps?", false)? {
+        return Ok(());
+    }
+    let n = reattach_all()?;
+    textln!(menus, "{} {n} apps", "re-attach:".red());
+    Ok(())
+}
+
+fn detach_multi_menu(menus: &mut Menus) -> CLIResult<()> {
+    let installed_apps = menu_packages(menus, &["-i"])?;
+    let content = read_detach_bin()?;
+    let detached_apps = get_detached_apps(&content)?;
+    let apps: Vec<&str> = parse_installed_apps(&installed_apps)
+        .filter(|app| !detached_apps.iter().any(|(s, _)| s == app))
+        .collect();
+    if apps.is_empty() {
+        menus.toast("All apps are already detached", Level::Info)?;
+        return Ok(());
+    }
+    let installers: HashMap<&str, Option<&str>> = parse_installers(&installed_apps).collect();
+    let labels = labels::Labels::load(&apps, menus::waker());
+    let details = details::Details::load(menus::waker());
+    // the checkbox and the prompt go before each item
+    let width = menus.width()?.saturating_sub(6) as usize;
+    // by app, whatever order they are listed in
+    let mut checked = vec![false; apps.len()];
+    let mut follow = None;
+    let selected: Vec<usize> = loop {
+        let sort = sort::get("detach");
+        let times = sort_times(menus, sort)?;
+        let order = sort::order(&apps, sort, |app| labels.get(app), &times);
+        let at = follow
+            .take()
+            .and_then(|app| order.iter().position(|&i| i == app))
+            .unwrap_or(0);
+        menus.set_bulk(true);
+        let selection = menus.select_menu_multi_at(
+            order
+                .iter()
+                .map(|&i| details.detailed(labels.labeled(apps[i]), apps[i], width)),
+            order.iter().map(|&i| checked[i]).collect(),
+            at,
+            Some((Key::Char('s'), "sort by package, label, install or update date")),
+            format_args!(
+                "Select the apps to detach, by {} (SPACE toggle, 'a' all, 'i' invert, '*' check many, 's' sort, type to narrow, 'q' to leave):",
+                sort.name()
+            ),
+            "↪".green(),
+            Some(Key::Char('q')),
+        );
+        menus.set_bulk(false);
+        match selection? {
+            Checked::Done(picked) => break picked.into_iter().map(|k| order[k]).collect(),
+            Checked::Bulk(i, now, shown) => {
+                for (k, c) in now.into_iter().enumerate() {
+                    checked[order[k]] = c;
+                }
+                follow = Some(order[i]);
+                let listed = (shown.iter())
+                    .map(|&k| order[k])
+                    .map(|i| (i, apps[i], installers[apps[i]]));
+                bulk_menu(menus, listed, &mut checked)?;
+            }
+            Checked::Pressed(i, now) => {
+                for (k, c) in now.into_iter().enumerate() {
+                    checked[order[k]] = c;
+                }
+                sort::set("detach", sort.next());
+                follow = Some(order[i]);
+            }
+            Checked::Left | Checked::Unsaved(..) => return Ok(()),
+        }
+    };
+    let selected = storable(menus, selected.iter().map(|&i| apps[i]))?;
+    if selected.is_empty() {
+        menus.toast("No apps selected", Level::Info)?;
+        return Ok(());
+    }
+
+    let mut content = content;
+    for app in &selected {
+        detach_bin::serialize(app, &mut content)?;
+    }
+    write_detach_bin(&content)?;
+    textln!(menus, "{} {} apps", "detach:".green(), selected.len());
+    textln!(menus, "Changes are applied. No need for a reboot!");
+    detach_bin_changed();
+    Ok(())
+}
+
+/// Asks what the listed apps to check at once start with or match, or which installer
+/// installed them, and checks them
+fn bulk_menu<'a>(
+    menus: &mut Menus,
+    listed: impl Iterator<Item = (usize, &'a str, Option<&'a str>)> + Clone,
+    checked: &mut [bool],
+) -> CLIResult<()> {
+    let counts = bulk::installers(listed.clone().map(|(_, _, installer)| installer));
+    let choices = std::iter::once("type a package prefix or glob, as com.google.".to_string())
+        .chain(
+            (counts.iter()).map(|&(installer, n)| {
+                format!("installed by {} ({n})", installers::name(installer))
+            }),
+        );
+    let typed;
+    let pattern = match menus.select_menu(
+        choices,
+        "Check the listed apps:",
+        "↪".green(),
+        Some(Key::Char('q')),
+    )? {
+        Some(0) => {
+            typed = menus.read_line("- prefix or glob: ".magenta())?;
+            match typed.as_deref().map(str::trim) {
+                Some(name) if !name.is_empty() => bulk::Pattern::Name(name),
+                _ => return Ok(()),
+            }
+        }
+        Some(k) => bulk::Pattern::Installer(counts[k - 1].0),
+        None => return Ok(()),
+    };
+    let (matched, added) = bulk::check(listed, checked, &pattern);
+    if matched == 0 {
+        menus.toast("No listed app matches", Level::Warn)?;
+    } else {
+        menus.toast(
+            format_args!("{added} more checked, {matched} matched"),
+            Level::Success,
+        )?;
+    }
+    Ok(())
+}
+
+/// The Play Store apps and the detached ones in one list, checked when detached.
+/// SPACE stages a change and ENTER or `w` writes them all at once
+fn toggle_menu(menus: &mut Menus) -> CLIResult<()> {
+    let installed_apps = menu_packages(menus, &["-i"])?;
+    let (content, detached) = load_detached()?;
+    let mut apps: Vec<&str> = parse_installers(&installed_apps)
+        .filter(|&(_, installer)| installer == Some(installers::PLAY_STORE))
+        .map(|(app, _)| app)
+        .collect();
+    // detached apps from elsewhere are listed too, so they can be re-attached here
+    for (name, _) in &detached {
+        if !apps.contains(&name.as_str()) {
+            apps.push(name);
+        }
+    }
+    if apps.is_empty() {
+        menus.toast("No Play Store apps installed", Level::Info)?;
+        return Ok(());
+    }
+    let saved: Vec<bool> = (apps.iter())
+        .map(|app| detached.iter().any(|(d, _)| d == app))
+        .collect();
+    let labels = labels::Labels::load(&apps, menus::waker());
+    let details = details::Details::load(menus::waker());
+    // the checkbox and the prompt go before each item
+    let width = menus.width()?.saturating_sub(6) as usize;
+    // by app, whatever order they are listed in
+    let mut checked = saved.clone();
+    let mut follow = None;
+    loop {
+        let sort = sort::get("toggle");
+        let times = sort_times(menus, sort)?;
+        let order = sort::order(&apps, sort, |app| labels.get(app), &times);
+        let at = follow
+            .take()
+            .and_then(|app| order.iter().position(|&i| i == app))
+            .unwrap_or(0);
+        match menus.select_menu_toggle_at(
+            order
+                .iter()
+                .map(|&i| details.detailed(labels.labeled(apps[i]), apps[i], width)),
+            &order.iter().map(|&i| saved[i]).collect::<Vec<_>>(),
+            order.iter().map(|&i| checked[i]).collect(),
+            at,
+            Some((Key::Char('s'), "sort by package, label, install or update date")),
+            format_args!(
+                "Play Store apps, checked when detached, by {} (SPACE toggle, ENTER or 'w' write, 's' sort, 'q' to leave):",
+                sort.name()
+            ),
+            "↪".green(),
+            Some(Key::Char('q')),
+        )? {
+            Checked::Done(picked) => {
+                checked.fill(false);
+                for k in picked {
+                    checked[order[k]] = true;
+                }
+                break;
+            }
+            Checked::Pressed(i, now) => {
+                for (k, c) in now.into_iter().enumerate() {
+                    checked[order[k]] = c;
+                }
+                sort::set("toggle", sort.next());
+                follow = Some(order[i]);
+            }
+            Checked::Unsaved(i, now) => {
+                for (k, c) in now.into_iter().enumerate() {
+                    checked[order[k]] = c;
+                }
+                let changes = (0..apps.len()).filter(|&i| checked[i] != saved[i]).count();
+                if menus.confirm(format_args!("Drop {changes} unsaved changes?"), false)? {
+                    return Ok(());
+                }
+                follow = Some(order[i]);
+            }
+            Checked::Left | Checked::Bulk(..) => return Ok(()),
+        }
+    }
+
+    let reattach: Vec<Range<usize>> = (detached.iter())
+        .filter(|(name, _)| {
+            let i = apps.iter().position(|app| app == name);
+            i.is_some_and(|i| !checked[i])
+        })
+        .map(|(_, range)| range.clone())
+        .collect();
+    let detach = storable(
+        menus,
+        (0..apps.len())
+            .filter(|&i| checked[i] && !saved[i])
+            .map(|i| apps[i]),
+    )?;
+    if reattach.is_empty() && detach.is_empty() {
+        menus.toast("Nothing changed", Level::Info)?;
+        return Ok(());
+    }
+    let mut content = content;
+    let mut ranges = reattach.clone();
+    ranges.sort_by_key(|r| std::cmp::Reverse(r.start));
+    for range in ranges {
+        content.drain(range);
+    }
+    for app in &detach {
+        detach_bin::serialize(app, &mut content)?;
+    }
+    write_detach_bin(&content)?;
+    detach_bin_changed();
+    menus.toast(
+        format_args!("{} detached, {} re-attached", detach.len(), reattach.len()),
+        Level::Success,
+    )?;
+    Ok(())
+}
+
+/// Shows the Play Store page of `app`, where it can be told whether the store still
+/// offers to update it, and says so for a toast
+fn open_store_page(app: &str) -> Result<String, String> {
+    match device::get().open_store_page(app) {
+        Ok(()) => Ok(format!("opened {app} in the Play Store")),
+        Err(e) => Err(format!("could not open {app}: {e}")),
+    }
+}
+
+fn detach_menu(menus: &mut Menus) -> CLIResult<()> {
+    let third_party = menu_packages(menus, listing::THIRD_PARTY)?;
+    let system = menu_packages(menus, listing::SYSTEM)?;
+    let disabled = menu_packages(menus, listing::DISABLED)?;
+    let listed = listing::merge(&third_party, &system, &disabled);
+    let apps: Vec<&str> = listed.iter().map(|l| l.name).collect();
+    let labels = labels::Labels::load(&apps, menus::waker());
+    let details = details::Details::load(menus::waker());
+    // the highlighted item is drawn after the prompt
+    let width = menus.width()?.saturating_sub(2) as usize;
+    let items: Vec<_> = listed
+        .iter()
+        .map(|l| {
+            let item = listing::Tagged {
+                item: installers::Sourced {
+                    item: labels.labeled(l.name),
+                    installer: l.installer,
+                },
+                system: l.system,
+                disabled: l.disabled,
+            };
+            details.detailed(item, l.name, width)
+        })
+        .collect();
+    let filter = std::cell::Cell::new(installers::Filter::All);
+    let scope = std::cell::Cell::new(listing::Scope::default());
+    let cycles = [
+        menus::Cycle {
+            key: Key::Char('\t'),
+            what: "switch between all apps, Play Store apps and the rest",
+            next: &|| filter.set(filter.get().next()),
+            label: &|| filter.get().name().to_string(),
+        },
+        menus::Cycle {
+            key: Key::Ctrl('s'),
+            what: "switch between installed apps, system apps and both",
+            next: &|| scope.set(scope.get().next()),
+            label: &|| scope.get().name().to_string(),
+        },
+    ];
+    // the ones not installed now are skipped
+    let recent: Vec<usize> = (recent::apps().iter())
+        .filter_map(|app| apps.iter().position(|a| a == app))
+        .collect();
+    let names: Vec<String> = apps.iter().map(|app| app.to_string()).collect();
+    menus.set_open(Some(Box::new(move |i| open_store_page(&names[i]))));
+    menus.cursor_show()?;
+    let selected = menus.select_menu_fuzzy(
+        &items,
+        |i| filter.get().matches(listed[i].installer) && scope.get().matches(&listed[i]),
+        &recent,
         "↪".green(),
         "- app: ",
         None,
-    )?;
+        &cycles,
+    );
+    menus.set_open(None);
+    let selected = selected?;
     menus.cursor_hide()?;
-    if let Some(detach_app) = selected {
-        let mut f = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .read(true)
-            .open(MODULE_DETACH)?;
-        let mut buf: Vec<u8> = Vec::new();
-        f.read_to_end(&mut buf)?;
-        if !get_detached_apps(menus, &buf)
+    if let Some(detach_app) = selected.map(|i| apps[i]) {
+        if storable(menus, [detach_app])?.is_empty() {
+            return Ok(());
+        }
+        let mut buf = read_detach_bin()?;
+        if !get_detached_apps(&buf)?
             .iter()
             .any(|(s, _)| s == detach_app)
         {
-            bin_serialize(detach_app, f)?;
+            detach_bin::serialize(detach_app, &mut buf)?;
+            write_detach_bin(&buf)?;
             textln!(menus, "{} {}", "detach:".green(), detach_app);
             textln!(menus, "Changes are applied. No need for a reboot!");
             detach_bin_changed();
@@ -378,39 +2860,115 @@ fn detach_menu(menus: &mut Menus) -> IOResult<()> {
     Ok(())
 }
 
-fn _kill_store_am() -> IOResult<()> {
-    Command::new("am")
-        .args(["force-stop", "com.android.vending"])
-        .spawn()?
-        .wait()?;
-    Ok(())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn kill_store() -> IOResult<()> {
-    const PKG: &str = "com.android.vending";
-    let mut buf = [0u8; PKG.len()];
-    for proc in fs::read_dir("/proc")? {
-        let mut proc = proc?.path();
-        if !proc.is_dir() {
-            continue;
+    fn bin(apps: &[&str]) -> Vec<u8> {
+        let mut bin = Vec::new();
+        for app in apps {
+            detach_bin::serialize(app, &mut bin).unwrap();
         }
-        proc.push("cmdline");
-        let Ok(mut cmdline) = fs::OpenOptions::new().read(true).open(&proc) else {
-            continue;
+        bin
+    }
+
+    /// What importing `txt` over `content` makes detach.bin
+    fn import(content: &[u8], txt: &str, replace: bool) -> (Vec<u8>, Import) {
+        let old = detach_bin::parse(content).unwrap();
+        import_apps(content, &old, parse_txt(txt), replace).unwrap()
+    }
+
+    #[test]
+    fn export_then_import_gives_the_same_detach_bin() {
+        let content = bin(&["org.xxx2", "com.app1", "com.example.b"]);
+        let txt = export_lines(&detach_bin::parse(&content).unwrap());
+        assert_eq!(txt, "org.xxx2\ncom.app1\ncom.example.b\n");
+        let (imported, res) = import(&[], &txt, false);
+        assert_eq!(imported, content);
+        assert_eq!((res.added, res.present), (3, 0));
+        let (replaced, _) = import(&bin(&["gone.app"]), &txt, true);
+        assert_eq!(replaced, content);
+    }
+
+    #[test]
+    fn import_merges_and_counts() {
+        let txt = "# phone\n\ncom.app1\n  new.app  \nnew.app\n";
+        let (merged, res) = import(&bin(&["com.app1", "org.xxx2"]), txt, false);
+        assert_eq!(merged, bin(&["com.app1", "org.xxx2", "new.app"]));
+        assert_eq!((res.added, res.present), (1, 1));
+        let (replaced, res) = import(&bin(&["com.app1", "org.xxx2"]), txt, true);
+        assert_eq!(replaced, bin(&["com.app1", "new.app"]));
+        assert_eq!((res.added, res.present), (1, 1));
+        let long = "a".repeat(129);
+        let (_, res) = import(&[], &long, false);
+        assert_eq!(res.skipped, [long]);
+    }
+
+    #[test]
+    fn verify_keeps_the_entries_before_a_cut() {
+        let whole = bin(&["com.app1", "org.xxx2"]);
+        let verified = verify_detach_bin(&whole[..20]);
+        assert_eq!(verified.entries, [("com.app1".to_string(), 0..16)]);
+        assert_eq!(
+            verified.problems,
+            ["entry at byte 16 is cut off: 15 bytes long but only 3 left"]
+        );
+        assert!(verify_detach_bin(&whole).problems.is_empty());
+        assert!(verify_detach_bin(&[]).entries.is_empty());
+    }
+
+    #[test]
+    fn verify_skips_garbage_entries() {
+        let mut content = bin(&["com.app1"]);
+        // high bytes where the nulls go
+        content.extend([3, b'a', b'b', b'c']);
+        content.extend([5, b'n', 0, b'o', 0, b'_']);
+        content.extend([2, b'a', 0]);
+        content.extend(bin(&["org.xxx2"]));
+        let verified = verify_detach_bin(&content);
+        let names: Vec<&str> = verified.entries.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["com.app1", "org.xxx2"]);
+        assert_eq!(
+            verified.problems,
+            [
+                "entry at byte 16 is not UTF-16 encoded",
+                "entry at byte 20 is not a package name: \"no_\"",
+                "entry at byte 26 is not UTF-16 encoded",
+            ]
+        );
+        assert_eq!(verified.entries[1].1, 29..45);
+    }
+
+    #[test]
+    fn risky_menu_writes_only_after_yes() {
+        use menus::tests::{scripted, typed};
+
+        let dir = std::env::temp_dir().join(format!("detach-risky-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("detach.bin").to_string_lossy().into_owned();
+        // a foreign file, so that nothing but it is written
+        let device = Box::new(device::Local::new(None));
+        device::set(Box::new(custom_file::CustomFile::new(
+            device,
+            path.clone(),
+            true,
+        )));
+        assert_eq!(device::get().path(DeviceFile::DetachBin).unwrap(), path);
+        let before = bin(&["com.app1"]);
+        fs::write(&path, &before).unwrap();
+        let apps = ["com.google.android.gsf".to_string()];
+        let wanted = bin(&["com.app1", "com.google.android.gsf"]);
+        let answered = |answer: &str| {
+            let mut menus = scripted(&[typed(answer), vec![Key::Char('\n')]].concat());
+            risky_menu(&mut menus, &apps, &wanted).unwrap();
+            fs::read(&path).unwrap()
         };
-        match cmdline.read(&mut buf) {
-            Ok(n) if n > 0 => {}
-            _ => continue,
-        }
-        if buf.eq(PKG.as_bytes()) {
-            if let Some(pid) = proc.components().nth(2) {
-                let pid = pid.as_os_str().to_string_lossy();
-                let Ok(pid) = pid.parse::<i32>() else {
-                    continue;
-                };
-                unsafe { kill(pid, 9) };
-            }
+        for answer in ["", "no", "y", "YES", "yes please"] {
+            assert_eq!(answered(answer), before, "{answer:?}");
         }
+        assert_eq!(answered("yes"), wanted);
+        let _ = fs::remove_dir_all(&dir);
+        // the lock of the write is taken where the cli runs
+        let _ = fs::remove_file("zygisk-detach-cli.lock");
     }
-    Ok(())
 }
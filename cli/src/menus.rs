@@ -1,23 +1,46 @@
-use crate::colorize::ToColored;
+use crate::clipboard;
+use crate::colorize::{
+    self, display_width, highlight, highlight_width, joins, str_width, strip_styles, truncate,
+    truncate_middle, ToColored,
+};
+use crate::error::Error;
+use crate::fuzzy;
+use crate::keymap::{self, Action, Keymap};
+use detach_core::log;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::Display;
-use std::io::{self, BufWriter, StdoutLock, Write};
-use termion::cursor::DetectCursorPos;
-use termion::input::TermRead;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, StdinLock, StdoutLock, Write};
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use termion::event::{Event, MouseButton, MouseEvent};
+use termion::input::{Events, TermRead};
 use termion::raw::{IntoRawMode, RawTerminal};
 use termion::terminal_size;
-use termion::{clear, cursor, event::Key};
+use termion::{clear, cursor, event::Key, screen};
+
+type Result<T> = std::result::Result<T, Error>;
 
 #[macro_export]
 macro_rules! text {
     ($dst:expr, $($arg:tt)*) => {{
-            write!(
-                $dst.stdout,
-                "{}{}{}{}\r",
-                cursor::Up(1),
-                clear::CurrentLine,
-                format_args!($($arg)*),
-                cursor::Down(1)
-            )?;
+            $dst.note(format_args!($($arg)*));
+            if $dst.is_plain() {
+                $dst.plain_text(format_args!($($arg)*))?;
+            } else {
+                write!(
+                    $dst.stdout,
+                    "{}{}{}{}\r",
+                    cursor::Up(1),
+                    clear::CurrentLine,
+                    format_args!($($arg)*),
+                    cursor::Down(1)
+                )?;
+            }
             $dst.stdout.flush()?;
     }};
 }
@@ -29,238 +52,3864 @@ macro_rules! textln {
     }};
 }
 
-pub enum SelectNumberedResp {
-    Index(usize),
-    UndefinedKey(Key),
-    Quit,
+/// Smallest terminal height the menus can be drawn in
+const MIN_ROWS: u16 = 5;
+
+/// Rows a list menu needs besides its items: the title, the line `text!` writes
+/// above the menu and the trailing newline
+const MENU_CHROME: usize = 3;
+
+/// The window of a list that fits on the screen
+#[derive(Default)]
+struct Viewport {
+    offset: usize,
+    height: usize,
+    width: usize,
+    clipped: bool,
+    size: (u16, u16),
+}
+impl Viewport {
+    fn fit(&mut self, size @ (cols, rows): (u16, u16), list_len: usize) {
+        self.size = size;
+        self.width = cols as usize;
+        let avail = (rows as usize).saturating_sub(MENU_CHROME);
+        self.clipped = list_len > avail;
+        // the "more" indicators above and below take a row each
+        self.height = if self.clipped {
+            avail.saturating_sub(2).max(1)
+        } else {
+            list_len
+        };
+    }
+
+    fn follow(&mut self, select_idx: usize, list_len: usize) {
+        if select_idx < self.offset {
+            self.offset = select_idx;
+        } else if select_idx >= self.offset + self.height {
+            self.offset = select_idx + 1 - self.height;
+        }
+        self.offset = self.offset.min(list_len.saturating_sub(self.height));
+    }
+
+    /// Rows drawn below the title
+    fn rows(&self) -> usize {
+        self.height + if self.clipped { 2 } else { 0 }
+    }
+
+    /// The item drawn on screen row `row` of a list whose title is on `title_row`
+    fn item_at(&self, title_row: u16, row: u16, list_len: usize) -> Option<usize> {
+        let first = title_row as usize + 1 + self.clipped as usize;
+        let i = (row as usize).checked_sub(first)?;
+        (i < self.height && self.offset + i < list_len).then_some(self.offset + i)
+    }
+
+    fn more(&self, n: usize) -> String {
+        if n > 0 {
+            format!("… {n} more").faint().to_string()
+        } else {
+            String::new()
+        }
+    }
 }
-pub struct Menus {
-    pub(crate) stdout: BufWriter<RawTerminal<StdoutLock<'static>>>,
+
+/// The rows a menu drew last, so the next frame only rewrites the ones that changed,
+/// usually the rows the highlight moved between
+#[derive(Default)]
+struct Frame {
+    lines: Vec<String>,
 }
-impl Menus {
-    pub fn new() -> Self {
-        let (r, c) = terminal_size().unwrap();
-        if r < 46 || c < 29 {
-            eprintln!("Terminal screen too small");
-            std::process::exit(1);
+impl Frame {
+    /// Draws `lines` from the cursor's row down and leaves the cursor on the row after
+    /// them. Returns whether everything below was cleared, which takes the status bar
+    /// with it
+    fn draw(&mut self, w: &mut impl Write, lines: Vec<String>) -> io::Result<bool> {
+        let mut cleared = self.lines.is_empty();
+        let mut skipped = 0;
+        for (i, line) in lines.iter().enumerate() {
+            if self.lines.get(i) == Some(line) {
+                skipped += 1;
+                continue;
+            }
+            if skipped > 0 {
+                write!(w, "{}", cursor::Down(skipped as u16))?;
+                skipped = 0;
+            }
+            write!(w, "\r{}{line}\r\n", clear::CurrentLine)?;
         }
-        Self {
-            stdout: BufWriter::new(io::stdout().lock().into_raw_mode().unwrap()),
+        if skipped > 0 {
+            write!(w, "{}", cursor::Down(skipped as u16))?;
         }
+        if lines.len() < self.lines.len() {
+            write!(w, "\r{}", clear::AfterCursor)?;
+            cleared = true;
+        }
+        self.lines = lines;
+        Ok(cleared)
     }
 
-    pub fn cursor_hide(&mut self) -> io::Result<()> {
-        write!(self.stdout, "{}", cursor::Hide)?;
-        Ok(())
+    /// Rows drawn by the last frame
+    fn len(&self) -> usize {
+        self.lines.len()
     }
 
-    pub fn cursor_show(&mut self) -> io::Result<()> {
-        write!(self.stdout, "{}", cursor::Show)?;
-        Ok(())
+    /// Makes the next frame draw every row, for when the screen was wiped
+    fn invalidate(&mut self) {
+        self.lines.clear();
     }
+}
 
-    pub fn select_menu<L: Display, I: Iterator<Item = L> + Clone>(
-        &mut self,
-        list: I,
-        title: impl Display,
-        prompt: impl Display,
-        quit: Option<Key>,
-    ) -> io::Result<Option<usize>> {
-        let mut select_idx = 0;
-        let list_len = list.clone().count();
-        let mut keys = io::stdin().lock().keys();
-        let pos = self.stdout.cursor_pos().unwrap();
+/// Where the first row of a menu is on the screen
+#[derive(Clone, Copy)]
+enum Anchor {
+    /// On this row, as the terminal said or at the top of a cleared screen
+    Row(u16),
+    /// Wherever the cursor was when the menu started. It is found again by moving up
+    /// over the rows drawn since, so the terminal is never asked where it is
+    Relative,
+}
 
-        write!(self.stdout, "{}\r\n", title)?;
-        let ret = loop {
-            for (i, selection) in list.clone().enumerate() {
-                if i == select_idx {
-                    write!(
-                        self.stdout,
-                        "{} {}\r\n",
-                        prompt,
-                        selection.black().white_bg()
-                    )?;
-                } else {
-                    write!(self.stdout, "{}\r\n", selection.faint())?;
-                }
-            }
-            self.stdout.flush()?;
+impl Anchor {
+    /// The move to the start of the menu's first row from the row after the `drawn`
+    /// rows of it
+    fn first_row(self, drawn: usize) -> String {
+        match self {
+            Self::Row(row) => format!("\r{}", cursor::Goto(1, row)),
+            Self::Relative if drawn > 0 => format!("\r{}", cursor::Up(drawn as u16)),
+            Self::Relative => "\r".to_string(),
+        }
+    }
 
-            let key = keys
-                .next()
-                .expect("keys() should block")
-                .expect("faulty keyboard?");
-            write!(
-                self.stdout,
-                "\r{}{}",
-                cursor::Goto(pos.0, pos.1),
-                clear::AfterCursor
-            )?;
-            match key {
-                Key::Char('\n') => {
-                    break Ok(Some(select_idx));
-                }
-                Key::Up => select_idx = select_idx.saturating_sub(1),
-                Key::Down => {
-                    if select_idx + 1 < list_len {
-                        select_idx += 1;
-                    }
-                }
-                k if k == Key::Ctrl('c') || quit.is_some_and(|q| q == key) => {
-                    break Ok(None);
-                }
-                _ => {}
-            }
-        };
-        write!(self.stdout, "{}{}", cursor::Up(1), clear::CurrentLine)?;
-        self.stdout.flush()?;
-        ret
+    /// The move to the row above the menu, where toasts go. `None` when the menu
+    /// starts on the top row
+    fn row_above(self, drawn: usize) -> Option<String> {
+        match self {
+            Self::Row(row) if row <= 1 => None,
+            Self::Row(row) => Some(cursor::Goto(1, row - 1).to_string()),
+            Self::Relative => Some(format!("\r{}", cursor::Up(drawn as u16 + 1))),
+        }
     }
+}
 
-    pub fn select_menu_with_input<F: Fn(&str) -> Vec<L>, L: Display>(
-        &mut self,
-        lister: F,
-        prompt: impl Display,
-        input_prompt: &str,
-        quit: Option<Key>,
-    ) -> io::Result<Option<L>> {
-        let mut select_idx = 0;
-        let mut cursor = 0;
-        let mut input = String::new();
-        let pos = self.stdout.cursor_pos().unwrap();
+/// Byte index of the grapheme boundary after `i`. A grapheme here is a character
+/// followed by any zero-width ones, with ZWJ joining the next character too
+fn next_grapheme(s: &str, i: usize) -> usize {
+    let mut chars = s[i..].char_indices().peekable();
+    let Some((_, mut prev)) = chars.next() else {
+        return i;
+    };
+    while let Some(&(_, c)) = chars.peek() {
+        if !joins(prev, c) {
+            break;
+        }
+        prev = c;
+        chars.next();
+    }
+    chars.peek().map_or(s.len(), |&(j, _)| i + j)
+}
 
-        let mut keys = io::stdin().lock().keys();
-        let ret = loop {
-            write!(
-                self.stdout,
-                "\r{}{}{}",
-                clear::AfterCursor,
-                input_prompt.magenta(),
-                input,
-            )?;
-            let mut list = lister(&input);
-            let list_len = list.len();
+/// Byte index of the grapheme boundary before `i`
+fn prev_grapheme(s: &str, i: usize) -> usize {
+    let mut b = 0;
+    loop {
+        let next = next_grapheme(s, b);
+        if next >= i || next == b {
+            return b;
+        }
+        b = next;
+    }
+}
 
-            select_idx = select_idx.min(list_len);
-            if list_len > 0 {
-                write!(self.stdout, "\r\n\n↑ and ↓ to navigate")?;
-                write!(self.stdout, "\n\rENTER to select\r\n")?;
-            }
+/// Start of the word before `i`, package names split into words at `.` and `_`
+fn prev_word(s: &str, i: usize) -> usize {
+    let sep = |c: char| c == '.' || c == '_' || c.is_whitespace();
+    let end = s[..i].trim_end_matches(sep).len();
+    s[..end].rfind(sep).map_or(0, |j| j + 1)
+}
 
-            for (i, selection) in list.iter().enumerate() {
-                if i == select_idx {
-                    write!(
-                        self.stdout,
-                        "{} {}\r\n",
-                        prompt,
-                        selection.black().white_bg()
-                    )?;
-                } else {
-                    write!(self.stdout, "{}\r\n", selection.faint())?;
-                }
-            }
-            if list_len > 0 {
-                write!(self.stdout, "{}", cursor::Goto(pos.0, pos.1))?;
+extern "C" fn on_resize(_: libc::c_int) {}
+
+/// Makes SIGWINCH interrupt the blocking key reads so menus can redraw on resize
+fn watch_resize() {
+    unsafe {
+        let mut sa: libc::sigaction = std::mem::zeroed();
+        sa.sa_sigaction = on_resize as *const () as libc::sighandler_t;
+        libc::sigaction(libc::SIGWINCH, &sa, std::ptr::null_mut());
+    }
+}
+
+/// Read errors in a row after which the input is given up on
+const MAX_READ_ERRORS: usize = 3;
+
+/// Next input event, `Key::Null` when the read was interrupted by a resize. The end of
+/// the input, or one that keeps failing, comes as Ctrl+C so every menu is left the way
+/// it would be by hand
+fn read_event(events: &mut impl Iterator<Item = io::Result<Event>>) -> Result<Event> {
+    let mut errors = 0;
+    loop {
+        match events.next() {
+            Some(Ok(Event::Key(key))) => return Ok(Event::Key(enter(key))),
+            Some(Ok(event)) => return Ok(event),
+            Some(Err(e)) if e.kind() == io::ErrorKind::Interrupted => {
+                return Ok(Event::Key(Key::Null))
             }
-            write!(
-                self.stdout,
-                "\r{}",
-                cursor::Right(input_prompt.len() as u16 + cursor as u16)
-            )?;
-            self.stdout.flush()?;
-            write!(self.stdout, "\r{}", clear::AfterCursor)?;
+            Some(Err(_)) if errors + 1 < MAX_READ_ERRORS => errors += 1,
+            Some(Err(_)) | None => return Ok(Event::Key(Key::Ctrl('c'))),
+        }
+    }
+}
 
-            match keys
-                .next()
-                .expect("keys() should block")
-                .expect("faulty keyboard?")
-            {
-                Key::Char('\n') => {
-                    break Ok(if list_len > select_idx {
-                        Some(list.remove(select_idx))
-                    } else {
-                        None
-                    });
-                }
-                Key::Up => select_idx = select_idx.saturating_sub(1),
-                Key::Down => {
-                    if select_idx + 1 < list_len {
-                        select_idx += 1;
-                    }
-                }
-                Key::Backspace => {
-                    if cursor > 0 {
-                        cursor -= 1;
-                        input.remove(cursor);
-                    }
-                }
-                Key::Char(c) => {
-                    if c.is_ascii() {
-                        input.insert(cursor, c);
-                        cursor += 1;
-                    } else {
-                        write!(
-                            self.stdout,
-                            "{}{}{}{}\r",
-                            cursor::Up(1),
-                            clear::CurrentLine,
-                            format_args!("Only ASCII characters"),
-                            cursor::Down(1)
-                        )?;
-                    }
-                }
-                Key::Right => {
-                    if cursor < input.len() {
-                        cursor += 1
-                    }
-                }
-                Key::Left => cursor = cursor.saturating_sub(1),
-                k if k == Key::Ctrl('c') || quit.is_some_and(|q| q == k) => {
-                    break Ok(None);
-                }
-                _ => {}
+/// Enter as `'\n'` however the terminal sends it: a carriage return, or Ctrl+M and
+/// Ctrl+J which are the same bytes
+fn enter(key: Key) -> Key {
+    match key {
+        Key::Char('\r') | Key::Ctrl('m' | 'j') => Key::Char('\n'),
+        k => k,
+    }
+}
+
+/// The keys that leave any menu, besides the quit key it may offer
+fn cancels(key: Key) -> bool {
+    matches!(key, Key::Esc | Key::Ctrl('c'))
+}
+
+/// Next key press, or `Key::Null` when the read was interrupted by a resize
+fn read_key(events: &mut impl Iterator<Item = io::Result<Event>>) -> Result<Key> {
+    loop {
+        if let Event::Key(key) = read_event(events)? {
+            return Ok(key);
+        }
+    }
+}
+
+/// Turns on reporting of clicks and the wheel, in the SGR encoding so columns past
+/// 223 come through too
+const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1006h";
+const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1000l";
+/// Has the terminal mark pasted text, so a newline in it is not taken for Enter
+const PASTE_ON: &str = "\x1b[?2004h";
+const PASTE_OFF: &str = "\x1b[?2004l";
+/// What comes before and after the pasted text
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// The key a mouse event in a list stands for. `hit` tells which item is on a row:
+/// the wheel moves the selection, a click selects the item under it, and a click on
+/// the selected one is `activate`. Anything else, clicks beside the list included,
+/// is `Key::Null`
+fn mouse_key(
+    event: MouseEvent,
+    hit: impl Fn(u16) -> Option<usize>,
+    select_idx: &mut usize,
+    activate: Key,
+) -> Key {
+    match event {
+        MouseEvent::Press(MouseButton::WheelUp, ..) => Key::Up,
+        MouseEvent::Press(MouseButton::WheelDown, ..) => Key::Down,
+        MouseEvent::Press(MouseButton::Left, _, row) => match hit(row) {
+            Some(i) if i == *select_idx => activate,
+            Some(i) => {
+                *select_idx = i;
+                Key::Null
             }
-        };
-        write!(self.stdout, "\r{}{}\r\n", cursor::Up(1), clear::AfterCursor)?;
-        self.stdout.flush()?;
-        ret
+            None => Key::Null,
+        },
+        _ => Key::Null,
     }
+}
 
-    pub fn select_menu_numbered<L: Display, I: Iterator<Item = L> + Clone>(
-        &mut self,
-        list: I,
-        quit: Key,
-        title: &str,
-    ) -> io::Result<SelectNumberedResp> {
-        let list_len = list.clone().count();
-        let pos = self.stdout.cursor_pos().unwrap();
+/// A handle other threads can use to make the calling thread's menu redraw,
+/// by interrupting its key read the same way a resize does
+pub fn waker() -> impl Fn() + Send + 'static {
+    let thread = unsafe { libc::pthread_self() };
+    move || unsafe {
+        libc::pthread_kill(thread, libc::SIGWINCH);
+    }
+}
+
+/// Shown at the start of the status bar, set from any thread with [`set_alert`]
+static ALERT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Puts `alert` ahead of the status, or takes it away. Menus show it when they redraw
+pub fn set_alert(alert: Option<String>) {
+    *ALERT.lock().unwrap_or_else(|e| e.into_inner()) = alert;
+}
+
+/// Shows on the bottom row how long `what` has been running, so a slow command does
+/// not look like a hang. `None` clears the row, the next menu puts the status back
+pub fn draw_waiting(what: &str, elapsed: Option<Duration>) {
+    let text = elapsed.map(|elapsed| format!("waiting for {what} · {}s", elapsed.as_secs()));
+    draw_bottom(text.as_deref());
+}
 
-        write!(self.stdout, "\r{title}\r\n")?;
-        for (i, s) in list.enumerate() {
-            write!(self.stdout, "{}. {}\r\n", (i + 1).green(), s)?;
+/// Draws `text` over the status bar while no menu is up, as how far a batch got.
+/// `None` clears the row
+pub fn draw_bottom(text: Option<&str>) {
+    let ((cols, rows), _) = screen_size();
+    let text = truncate(text.unwrap_or_default(), cols as usize);
+    let pad = (cols as usize).saturating_sub(display_width(&text));
+    let mut stdout = io::stdout().lock();
+    let _ = write!(
+        stdout,
+        "\x1b7{}{}{}\x1b8",
+        cursor::Goto(1, rows),
+        clear::CurrentLine,
+        format_args!("{text}{:pad$}", "").black().white_bg()
+    );
+    let _ = stdout.flush();
+}
+
+/// Terminal attributes from before raw mode was entered
+static ORIG_TERMIOS: OnceLock<libc::termios> = OnceLock::new();
+/// The menus draw on the alternate screen, the one from before is still to come back
+static ALT_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// Puts the terminal back into the state it was in before the menus took it over.
+/// Safe to call from the panic hook where `Menus` itself is out of reach. Does nothing
+/// when the menus never took it over
+pub fn restore_terminal(w: &mut impl Write) {
+    let Some(termios) = ORIG_TERMIOS.get() else {
+        return;
+    };
+    undo_modes(w);
+    unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, termios) };
+}
+
+/// Turns off what the menus turned on, clears the lines of the one left open and
+/// shows the cursor again
+fn undo_modes(w: &mut impl Write) {
+    let _ = write!(
+        w,
+        "{MOUSE_OFF}{PASTE_OFF}{}\r{}{}",
+        scroll_region(None),
+        clear::AfterCursor,
+        cursor::Show
+    );
+    if ALT_SCREEN.swap(false, Ordering::Relaxed) {
+        let _ = write!(w, "{}", screen::ToMainScreen);
+    }
+    let _ = w.flush();
+}
+
+/// Limits scrolling to the rows above `bottom`, or gives the whole screen back.
+/// Setting the region moves the cursor home, so it is saved around that
+fn scroll_region(bottom: Option<u16>) -> String {
+    match bottom {
+        Some(bottom) => format!("\x1b7\x1b[1;{bottom}r\x1b8"),
+        None => "\x1b7\x1b[r\x1b8".to_string(),
+    }
+}
+
+/// Moves `select_idx` for the navigation keys every list menu shares,
+/// `page` being how many items are visible at once. With `wrap` Up on the first item
+/// goes to the last and Down on the last to the first
+fn navigate(key: Key, select_idx: &mut usize, list_len: usize, page: usize, wrap: bool) -> bool {
+    let last = list_len.saturating_sub(1);
+    match key {
+        Key::Up if wrap && *select_idx == 0 => *select_idx = last,
+        Key::Down if wrap && *select_idx >= last => *select_idx = 0,
+        Key::Up => *select_idx = select_idx.saturating_sub(1),
+        Key::Down => *select_idx = (*select_idx + 1).min(last),
+        Key::PageUp => *select_idx = select_idx.saturating_sub(page.max(1)),
+        Key::PageDown => *select_idx = (*select_idx + page.max(1)).min(last),
+        Key::Home => *select_idx = 0,
+        Key::End => *select_idx = last,
+        _ => return false,
+    }
+    true
+}
+
+/// How long the title of a menu is shown inverted after a key it does not take
+const FLASH: Duration = Duration::from_millis(80);
+
+/// How long the typed letters of a type-ahead search are kept after the last one
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The letters typed in a list without a filter box, the highlight jumps to the first
+/// item that starts with them, or else contains them
+#[derive(Default)]
+struct TypeAhead {
+    typed: String,
+    last: Option<Instant>,
+    /// Whether an item matched the last time
+    found: bool,
+}
+
+impl TypeAhead {
+    fn active(&self) -> bool {
+        !self.typed.is_empty() && self.last.is_some_and(|t| t.elapsed() < TYPE_AHEAD_TIMEOUT)
+    }
+
+    fn clear(&mut self) {
+        self.typed.clear();
+        self.last = None;
+    }
+
+    /// Clears a search that timed out, returns whether it did
+    fn expire(&mut self) -> bool {
+        let expired = !self.typed.is_empty() && !self.active();
+        if expired {
+            self.clear();
         }
-        write!(self.stdout, "{}. Quit\r\n", 'q'.green())?;
-        self.stdout.flush()?;
-        let key = io::stdin()
-            .lock()
-            .keys()
-            .next()
-            .expect("keys() should block")
-            .expect("faulty keyboard?");
-        write!(
-            self.stdout,
-            "\r{}{}",
-            cursor::Goto(pos.0, pos.1),
-            clear::AfterCursor,
-        )?;
-        self.stdout.flush()?;
-        match key {
-            Key::Char(c) if c.to_digit(10).is_some_and(|c| c as usize <= list_len) => Ok(
-                SelectNumberedResp::Index(c.to_digit(10).unwrap() as usize - 1),
-            ),
-            k if k == Key::Ctrl('c') || k == quit => Ok(SelectNumberedResp::Quit),
-            k => Ok(SelectNumberedResp::UndefinedKey(k)),
+        expired
+    }
+
+    /// Adds `c`, starting over after a pause. The menu is woken once it times out
+    /// so the letters go away from the screen too
+    fn push(&mut self, c: char) {
+        self.expire();
+        self.typed.push(c);
+        self.last = Some(Instant::now());
+        let wake = waker();
+        std::thread::spawn(move || {
+            std::thread::sleep(TYPE_AHEAD_TIMEOUT);
+            wake();
+        });
+    }
+
+    fn pop(&mut self) {
+        self.typed.pop();
+        self.last = Some(Instant::now());
+    }
+
+    /// Where the highlight jumps to for what is typed, ignoring case and styling
+    fn find<L: Display>(&mut self, list: impl Iterator<Item = L> + Clone) -> Option<usize> {
+        let typed = self.typed.to_lowercase();
+        let texts = list.map(|item| strip_styles(&item.to_string()).to_lowercase());
+        let found = texts
+            .clone()
+            .position(|t| t.starts_with(&typed))
+            .or_else(|| texts.clone().position(|t| t.contains(&typed)));
+        self.found = found.is_some() || typed.is_empty();
+        found
+    }
+
+    /// The letters as shown after the title, red when nothing matches
+    fn label(&self) -> String {
+        let typed = format!("[{}]", self.typed);
+        if self.found {
+            typed.bold().to_string()
+        } else {
+            typed.red().bold().to_string()
+        }
+    }
+}
+
+/// What a multi-select list is narrowed down to, the items containing it whatever the
+/// case. While it is typed the letters of other keys go to it too, after a pause or
+/// another key they are keys again and the list stays narrowed
+#[derive(Default)]
+struct Narrow {
+    text: String,
+    last: Option<Instant>,
+}
+
+impl Narrow {
+    fn typing(&self) -> bool {
+        !self.text.is_empty() && self.last.is_some_and(|t| t.elapsed() < TYPE_AHEAD_TIMEOUT)
+    }
+
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.last = Some(Instant::now());
+    }
+
+    fn pop(&mut self) {
+        self.text.pop();
+        self.last = Some(Instant::now());
+    }
+
+    /// Ends the typing, the list stays narrowed
+    fn stop(&mut self) {
+        self.last = None;
+    }
+
+    /// The indices of the items of `list` it leaves
+    fn shown<L: Display>(&self, list: impl Iterator<Item = L>) -> Vec<usize> {
+        let text = self.text.to_lowercase();
+        (list.enumerate())
+            .filter(|(_, item)| {
+                text.is_empty()
+                    || strip_styles(&item.to_string())
+                        .to_lowercase()
+                        .contains(&text)
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The text with how many of `len` items it leaves, red when none
+    fn label(&self, shown: usize, len: usize) -> String {
+        let label = format!("[{}] {shown}/{len}", self.text);
+        if shown > 0 {
+            label.bold().to_string()
+        } else {
+            label.red().bold().to_string()
+        }
+    }
+}
+
+/// A setting of a filter menu that `key` steps through, named under the input
+pub struct Cycle<'a> {
+    pub key: Key,
+    /// What stepping does, for the `?` overlay
+    pub what: &'static str,
+    pub next: &'a dyn Fn(),
+    /// What the setting is at now
+    pub label: &'a dyn Fn() -> String,
+}
+
+/// How a toast is colored
+#[derive(Clone, Copy)]
+pub enum Level {
+    Info,
+    Success,
+    Warn,
+    Error,
+}
+
+/// Keys a toast stays up for
+const TOAST_KEYS: u8 = 3;
+
+/// Opens what the item at an index stands for, as the Play Store page of an app,
+/// with what to toast on success or failure, see [`Menus::set_open`]
+pub type Open = Box<dyn Fn(usize) -> std::result::Result<String, String>>;
+
+/// A message on the row above the menus until a few keys were pressed
+struct Toast {
+    text: String,
+    level: Level,
+    keys_left: u8,
+}
+
+impl Display for Toast {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.level {
+            Level::Info => write!(f, "{}", self.text.blue()),
+            Level::Success => write!(f, "{}", self.text.green()),
+            Level::Warn => write!(f, "{}", self.text.yellow()),
+            Level::Error => write!(f, "{}", self.text.red()),
+        }
+    }
+}
+
+/// A key as it is named to the user, `Ctrl+X` or `F5`
+pub struct KeyName(pub Key);
+
+impl Display for KeyName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Key::Char('\n') => f.write_str("Enter"),
+            Key::Char('\t') => f.write_str("Tab"),
+            Key::Char(' ') => f.write_str("Space"),
+            Key::Char(c) => write!(f, "{c}"),
+            Key::Ctrl(c) => write!(f, "Ctrl+{}", c.to_ascii_uppercase()),
+            Key::Alt(c) => write!(f, "Alt+{c}"),
+            Key::F(n) => write!(f, "F{n}"),
+            Key::Backspace => f.write_str("Backspace"),
+            Key::Left => f.write_str("Left"),
+            Key::Right => f.write_str("Right"),
+            Key::Up => f.write_str("Up"),
+            Key::Down => f.write_str("Down"),
+            Key::Home => f.write_str("Home"),
+            Key::End => f.write_str("End"),
+            Key::PageUp => f.write_str("PageUp"),
+            Key::PageDown => f.write_str("PageDown"),
+            Key::BackTab => f.write_str("Shift+Tab"),
+            Key::Delete => f.write_str("Delete"),
+            Key::Insert => f.write_str("Insert"),
+            Key::Esc => f.write_str("Esc"),
+            _ => f.write_str("an unknown key"),
+        }
+    }
+}
+
+/// The item a list was left on, and whether it was picked with ENTER or the list was
+/// left without picking
+pub enum Selection {
+    Picked(usize),
+    Left(usize),
+    /// The key the caller handles was pressed on the item
+    Pressed(usize),
+}
+
+impl Selection {
+    pub fn index(&self) -> usize {
+        match *self {
+            Self::Picked(i) | Self::Left(i) | Self::Pressed(i) => i,
+        }
+    }
+
+    pub fn picked(&self) -> Option<usize> {
+        match *self {
+            Self::Picked(i) => Some(i),
+            Self::Left(_) | Self::Pressed(..) => None,
+        }
+    }
+}
+
+/// A row of a list with sections. Only items can be highlighted, headers and
+/// separators are passed over and left out of the indices the menu returns
+#[derive(Clone)]
+pub enum Row<L> {
+    Item(L),
+    /// Drawn bold
+    Header(String),
+    /// A faint line across the list
+    Separator,
+}
+
+impl<L> Row<L> {
+    fn item(self) -> Option<L> {
+        match self {
+            Self::Item(item) => Some(item),
+            Self::Header(_) | Self::Separator => None,
         }
     }
+
+    fn is_item(&self) -> bool {
+        matches!(self, Self::Item(_))
+    }
+}
+
+/// What a multi-select ended with
+pub enum Checked {
+    Done(Vec<usize>),
+    Left,
+    /// The key the caller handles was pressed on the item, with what was checked
+    Pressed(usize, Vec<bool>),
+    /// A toggle list was left on the item with changes not written, with what was
+    /// checked, for the caller to ask whether to drop them
+    Unsaved(usize, Vec<bool>),
+    /// `*` was pressed on the item, with what was checked and the items shown, for the
+    /// caller to ask which of those to check at once
+    Bulk(usize, Vec<bool>, Vec<usize>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SelectNumberedResp {
+    Index(usize),
+    UndefinedKey(Key),
+    Quit,
+    /// Left with Ctrl+C
+    Interrupted,
+}
+/// What the menus draw on. The terminal in production, anything else lets the
+/// menus run against scripted keys
+pub trait Screen: Write {
+    fn size(&self) -> io::Result<(u16, u16)>;
+    fn cursor_pos(&mut self) -> io::Result<(u16, u16)>;
+    /// Whether `size` is a guess, the menus then start each one from the top of a
+    /// cleared screen instead of asking the terminal where the cursor is
+    fn guessed(&self) -> bool {
+        false
+    }
+}
+
+/// The terminal's size, or COLUMNS and LINES when it cannot tell, as inside some adb
+/// shells where it says 0x0, or else 80x24. The flag is false for a guess
+pub fn screen_size() -> ((u16, u16), bool) {
+    pick_size(
+        terminal_size(),
+        std::env::var("COLUMNS").ok(),
+        std::env::var("LINES").ok(),
+    )
+}
+
+fn pick_size(
+    measured: io::Result<(u16, u16)>,
+    columns: Option<String>,
+    lines: Option<String>,
+) -> ((u16, u16), bool) {
+    if let Ok((cols, rows)) = measured {
+        if cols > 0 && rows > 0 {
+            return ((cols, rows), true);
+        }
+    }
+    let var = |var: Option<String>, default| {
+        var.and_then(|v| v.trim().parse().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(default)
+    };
+    ((var(columns, 80), var(lines, 24)), false)
+}
+
+impl<W: Write> Screen for BufWriter<RawTerminal<W>> {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        Ok(screen_size().0)
+    }
+
+    fn cursor_pos(&mut self) -> io::Result<(u16, u16)> {
+        query_cursor(self)
+    }
+
+    fn guessed(&self) -> bool {
+        !screen_size().1
+    }
+}
+
+/// How long the terminal has to say where the cursor is
+const DSR_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Asks the terminal where the cursor is. Unlike termion's it leaves no thread behind
+/// eating the keys when no answer comes, and a garbled one is an error, not a panic
+fn query_cursor(out: &mut impl Write) -> io::Result<(u16, u16)> {
+    query_cursor_on(out, &mut termion::get_tty()?)
+}
+
+/// `query_cursor` with the answer read from `tty`
+fn query_cursor_on(
+    out: &mut impl Write,
+    tty: &mut (impl Read + AsRawFd),
+) -> io::Result<(u16, u16)> {
+    write!(out, "\x1b[6n")?;
+    out.flush()?;
+    let deadline = Instant::now() + DSR_TIMEOUT;
+    let mut reply = Vec::new();
+    while reply.last() != Some(&b'R') {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut fd = libc::pollfd {
+            fd: tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut fd, 1, left.as_millis() as libc::c_int) } {
+            0 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the terminal did not answer",
+                ))
+            }
+            n if n < 0 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            _ => {}
+        }
+        let mut byte = [0];
+        if tty.read(&mut byte)? == 0 || reply.len() >= 32 {
+            break;
+        }
+        reply.push(byte[0]);
+    }
+    parse_cursor_reply(&reply).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "garbled cursor position {:?}",
+                String::from_utf8_lossy(&reply)
+            ),
+        )
+    })
+}
+
+/// The column and row of an `ESC [ row ; col R` answer, keys typed before it are skipped
+fn parse_cursor_reply(reply: &[u8]) -> Option<(u16, u16)> {
+    let start = reply.windows(2).rposition(|w| w == b"\x1b[")? + 2;
+    let reply = std::str::from_utf8(&reply[start..]).ok()?;
+    let (row, col) = reply.strip_suffix('R')?.split_once(';')?;
+    Some((col.parse().ok()?, row.parse().ok()?))
+}
+
+/// An 80x24 screen that records the output, with the cursor always at the top
+impl Screen for Vec<u8> {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        Ok((80, 24))
+    }
+
+    fn cursor_pos(&mut self) -> io::Result<(u16, u16)> {
+        Ok((1, 1))
+    }
+}
+
+/// Where the keys come from: the terminal itself when it can be opened, so stdin is
+/// left for data piped in, or else stdin
+pub enum Keyboard {
+    Tty(File),
+    Stdin(StdinLock<'static>),
+}
+
+impl Keyboard {
+    fn open() -> Self {
+        match termion::get_tty() {
+            Ok(tty) if termion::is_tty(&tty) => Self::Tty(tty),
+            _ => Self::Stdin(io::stdin().lock()),
+        }
+    }
+
+    fn is_tty(&self) -> bool {
+        match self {
+            Self::Tty(_) => true,
+            Self::Stdin(stdin) => termion::is_tty(stdin),
+        }
+    }
+}
+
+impl Read for Keyboard {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tty(tty) => tty.read(buf),
+            Self::Stdin(stdin) => stdin.read(buf),
+        }
+    }
+}
+
+type KeyboardEvents = Events<Keyboard>;
+
+/// The terminal in raw mode, or stdout as it is for the plain prompts
+pub enum Stdout {
+    Raw(BufWriter<RawTerminal<StdoutLock<'static>>>),
+    Plain(BufWriter<StdoutLock<'static>>),
+}
+
+impl Write for Stdout {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Raw(w) => w.write(buf),
+            Self::Plain(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Raw(w) => w.flush(),
+            Self::Plain(w) => w.flush(),
+        }
+    }
+}
+
+impl Screen for Stdout {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        match self {
+            Self::Raw(w) => w.size(),
+            Self::Plain(_) => Ok(screen_size().0),
+        }
+    }
+
+    fn cursor_pos(&mut self) -> io::Result<(u16, u16)> {
+        match self {
+            Self::Raw(w) => Screen::cursor_pos(w),
+            Self::Plain(_) => Ok((1, 1)),
+        }
+    }
+
+    fn guessed(&self) -> bool {
+        match self {
+            Self::Raw(w) => w.guessed(),
+            Self::Plain(_) => false,
+        }
+    }
+}
+
+pub struct Menus<K: Iterator<Item = io::Result<Event>> = KeyboardEvents, S: Screen = Stdout> {
+    pub(crate) stdout: S,
+    keys: K,
+    /// Whether Up and Down wrap around the ends of the lists
+    wrap: bool,
+    /// Whether keys a menu does not take ring the bell
+    bell: bool,
+    /// A key was not taken, the next menu drawn flashes its title
+    flash: bool,
+    /// The bar on the bottom row, none while empty
+    status: String,
+    /// Screen height the bottom row was kept out of scrolling for, 0 when it is not
+    status_rows: u16,
+    /// The first message `text!` wrote since `take_note`
+    note: Option<String>,
+    /// Numbered lists and whole lines read from stdin instead of drawing on a terminal
+    plain: bool,
+    /// The item each `select_menu_kept` list was left on
+    kept: HashMap<&'static str, usize>,
+    toast: Option<Toast>,
+    /// A toast ran out and its row is still to be cleared
+    toast_expired: bool,
+    open: Option<Open>,
+    /// Whether `*` in the multi-select lists leaves with [`Checked::Bulk`]
+    bulk: bool,
+    /// What the multi-select list was narrowed to when it was left with `*` or its pass
+    /// key, it is narrowed the same when it opens again
+    bulk_narrow: String,
+    /// The terminal did not say where the cursor is, each menu is drawn from the top of
+    /// a cleared screen from then on
+    no_cursor_pos: bool,
+}
+impl<K: Iterator<Item = io::Result<Event>>, S: Screen> Drop for Menus<K, S> {
+    fn drop(&mut self) {
+        if self.plain {
+            return;
+        }
+        // on any screen, not only the terminal, so what is left on it can be checked
+        undo_modes(&mut self.stdout);
+        if let Some(termios) = ORIG_TERMIOS.get() {
+            unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, termios) };
+        }
+    }
+}
+
+impl Menus {
+    /// Takes over the terminal when stdout is one and keys can be read from it, through
+    /// /dev/tty or stdin. Falls back to plain prompts on stdin without it, when raw mode
+    /// cannot be entered or when asked to with `plain`. With `altscreen` the menus draw
+    /// on the alternate screen from the top, so the scrollback is left as it was
+    pub fn new(plain: bool, altscreen: bool) -> Result<Self> {
+        let plain_menus = || {
+            let mut menus = Self::with_io(
+                Keyboard::Stdin(io::stdin().lock()).events(),
+                Stdout::Plain(BufWriter::new(io::stdout().lock())),
+            );
+            menus.plain = true;
+            menus
+        };
+        if plain || !termion::is_tty(&io::stdout()) {
+            return Ok(plain_menus());
+        }
+        let keyboard = Keyboard::open();
+        if !keyboard.is_tty() {
+            return Ok(plain_menus());
+        }
+        let ((_, rows), _) = screen_size();
+        if rows < MIN_ROWS {
+            return Err(Error::Terminal(io::Error::other(format!(
+                "screen is too small, at least {MIN_ROWS} rows are needed, or use --plain"
+            ))));
+        }
+        let mut termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) } != 0 {
+            return Ok(plain_menus());
+        }
+        let Ok(raw) = io::stdout().lock().into_raw_mode() else {
+            return Ok(plain_menus());
+        };
+        let _ = ORIG_TERMIOS.set(termios);
+        watch_resize();
+        let mut stdout = BufWriter::new(raw);
+        if altscreen {
+            write!(
+                stdout,
+                "{}{}",
+                screen::ToAlternateScreen,
+                cursor::Goto(1, 1)
+            )?;
+            stdout.flush()?;
+            ALT_SCREEN.store(true, Ordering::Relaxed);
+        }
+        Ok(Self::with_io(keyboard.events(), Stdout::Raw(stdout)))
+    }
+}
+
+impl<K: Iterator<Item = io::Result<Event>>, S: Screen> Menus<K, S> {
+    /// Menus reading the key and mouse events from `keys` and drawing on `screen`
+    pub fn with_io(keys: K, screen: S) -> Self {
+        Self {
+            stdout: screen,
+            keys,
+            wrap: false,
+            bell: true,
+            flash: false,
+            status: String::new(),
+            status_rows: 0,
+            note: None,
+            plain: false,
+            kept: HashMap::new(),
+            toast: None,
+            toast_expired: false,
+            open: None,
+            bulk: false,
+            bulk_narrow: String::new(),
+            no_cursor_pos: false,
+        }
+    }
+
+    /// Has `o`, or Ctrl+O in the filter menus, open the highlighted item with `open`
+    /// until it is set again. The menu is left as it was
+    pub fn set_open(&mut self, open: Option<Open>) {
+        self.open = open;
+    }
+
+    /// Has `*` in the multi-select lists leave with [`Checked::Bulk`] until it is set
+    /// again
+    pub fn set_bulk(&mut self, bulk: bool) {
+        self.bulk = bulk;
+    }
+
+    /// Runs [`Self::set_open`]'s `open` for the item `i`, its toast is drawn with the menu
+    fn open_item(&mut self, i: usize) {
+        let Some(open) = &self.open else {
+            return;
+        };
+        let (text, level) = match open(i) {
+            Ok(text) => (text, Level::Success),
+            Err(text) => (text, Level::Warn),
+        };
+        self.toast = Some(Toast {
+            text,
+            level,
+            keys_left: TOAST_KEYS,
+        });
+    }
+
+    /// Whether the menus are plain prompts instead of drawn on the terminal
+    pub fn is_plain(&self) -> bool {
+        self.plain
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    pub fn set_bell(&mut self, bell: bool) {
+        self.bell = bell;
+    }
+
+    /// Lets the user know a key did nothing: the bell rings, unless turned off, and
+    /// the title of the menu drawn next flashes
+    pub fn reject(&mut self) -> Result<()> {
+        if self.plain {
+            return Ok(());
+        }
+        if self.bell {
+            write!(self.stdout, "\x07")?;
+        }
+        self.flash = true;
+        Ok(())
+    }
+
+    /// Shows `title` on the first row of a menu at `anchor` inverted for a moment when
+    /// a key was rejected, `drawn` rows below it. The cursor is saved around it, so it
+    /// is left where the menu had it
+    fn flash_title(&mut self, anchor: Anchor, drawn: usize, title: &str) -> Result<()> {
+        if !std::mem::take(&mut self.flash) {
+            return Ok(());
+        }
+        let to_title = anchor.first_row(drawn);
+        let line = |text: &dyn Display| format!("\x1b7{to_title}{}{text}\x1b8", clear::CurrentLine);
+        write!(self.stdout, "{}", line(&strip_styles(title).invert()))?;
+        self.stdout.flush()?;
+        std::thread::sleep(FLASH);
+        write!(self.stdout, "{}", line(&title))?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// Lists what the keys of `keys` do over the menu at `anchor`, whose first row the
+    /// cursor is on, a page at a time when they do not fit, until another key is
+    /// pressed. The rows are cleared after, for the menu to draw itself again as it was
+    fn show_keys(&mut self, keys: &Keymap, anchor: Anchor) -> Result<()> {
+        let lines = keys.lines();
+        let size = self.size()?;
+        let cols = size.0 as usize;
+        let rows = match anchor {
+            Anchor::Row(row) => size.1.saturating_sub(row) as usize + 1,
+            // as many rows as the list takes are scrolled into view below the menu
+            Anchor::Relative => {
+                let rows = (lines.len() + 2).min(size.1.max(1) as usize);
+                self.make_room(rows as u16)?;
+                rows
+            }
+        };
+        // the title and the page line take a row each
+        let room = rows.saturating_sub(2).max(1);
+        let pages = lines.len().div_ceil(room);
+        let mut page = 0;
+        // rows the page before left the cursor below the first
+        let mut drawn = 0;
+        loop {
+            write!(
+                self.stdout,
+                "{}{}",
+                anchor.first_row(drawn),
+                clear::AfterCursor
+            )?;
+            write!(self.stdout, "{}\r\n", truncate("Keys", cols).bold())?;
+            drawn = 1;
+            for line in lines.iter().skip(page * room).take(room) {
+                write!(self.stdout, "{}\r\n", truncate(line, cols))?;
+                drawn += 1;
+            }
+            let footer = match pages {
+                1 => "any key closes".to_string(),
+                _ if page + 1 == pages => format!("page {pages}/{pages}, any key closes"),
+                _ => format!(
+                    "page {}/{pages}, SPACE or ↓ for the next, any other key closes",
+                    page + 1
+                ),
+            };
+            write!(self.stdout, "{}", truncate(&footer, cols).faint())?;
+            self.draw_status()?;
+            self.stdout.flush()?;
+            let key = match self.next_event()? {
+                Event::Key(key) => key,
+                Event::Mouse(_) => Key::Esc,
+                Event::Unsupported(_) => Key::Null,
+            };
+            match key {
+                // a redraw, unless the terminal was resized
+                Key::Null if self.size()? == size => {}
+                Key::Char(' ') | Key::Down | Key::PageDown if page + 1 < pages => page += 1,
+                Key::Up | Key::PageUp if page > 0 => page -= 1,
+                _ => break,
+            }
+        }
+        write!(
+            self.stdout,
+            "{}{}",
+            anchor.first_row(drawn),
+            clear::AfterCursor
+        )?;
+        Ok(())
+    }
+
+    /// Shows `status` on the bottom row, where every menu keeps it until it is changed
+    pub fn set_status(&mut self, status: impl Display) -> Result<()> {
+        self.status = status.to_string();
+        self.draw_status()?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// Remembers what `text!` wrote, blank messages are not worth keeping
+    pub fn note(&mut self, text: impl Display) {
+        let text = strip_styles(&text.to_string());
+        let text = text.trim();
+        if self.note.is_none() && !text.is_empty() {
+            self.note = Some(text.to_string());
+        }
+    }
+
+    /// Shows `msg` on the row above the menus, replacing the toast before. The menus
+    /// redraw it until three keys were pressed
+    pub fn toast(&mut self, msg: impl Display, level: Level) -> Result<()> {
+        let text = msg.to_string();
+        self.note(&text);
+        if self.plain {
+            self.plain_text(&text)?;
+            self.stdout.flush()?;
+            return Ok(());
+        }
+        let toast = Toast {
+            text,
+            level,
+            keys_left: TOAST_KEYS,
+        };
+        let cols = self.size()?.0 as usize;
+        write!(
+            self.stdout,
+            "{}{}{}{}\r",
+            cursor::Up(1),
+            clear::CurrentLine,
+            truncate(&toast.to_string(), cols),
+            cursor::Down(1)
+        )?;
+        self.stdout.flush()?;
+        self.toast = Some(toast);
+        Ok(())
+    }
+
+    /// Draws the toast on the row above a menu at `anchor` the cursor is `drawn` rows
+    /// below, or clears it once it ran out
+    fn draw_toast(&mut self, anchor: Anchor, drawn: usize) -> Result<()> {
+        match anchor.row_above(drawn) {
+            Some(to_row) => self.paint_toast(to_row),
+            None => Ok(()),
+        }
+    }
+
+    /// Draws the toast on the row `to_row` moves to, the cursor is put back after
+    fn paint_toast(&mut self, to_row: impl Display) -> Result<()> {
+        let text = match &self.toast {
+            Some(toast) => truncate(&toast.to_string(), self.size()?.0 as usize).into_owned(),
+            None if self.toast_expired => String::new(),
+            None => return Ok(()),
+        };
+        self.toast_expired = false;
+        write!(
+            self.stdout,
+            "\x1b7{to_row}{}{text}\x1b8",
+            clear::CurrentLine
+        )?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    /// The text pasted after [`PASTE_START`], up to its first line break. Whatever
+    /// follows that is read and dropped, so none of it is taken for keys
+    fn read_paste(&mut self) -> Result<String> {
+        let mut pasted = String::new();
+        let mut line_ended = false;
+        loop {
+            match read_event(&mut self.keys)? {
+                Event::Unsupported(seq) if seq == PASTE_END => return Ok(pasted),
+                // the input ended before the paste did
+                Event::Key(Key::Ctrl('c')) => return Ok(pasted),
+                Event::Key(Key::Char('\n')) => line_ended = true,
+                Event::Key(Key::Char(c)) if !line_ended && !c.is_control() => pasted.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    /// The next event for a menu, counting the keys toward the toast running out
+    fn next_event(&mut self) -> Result<Event> {
+        let event = read_event(&mut self.keys)?;
+        if matches!(event, Event::Key(k) if k != Key::Null) {
+            if let Some(toast) = &mut self.toast {
+                toast.keys_left -= 1;
+                if toast.keys_left == 0 {
+                    self.toast = None;
+                    self.toast_expired = true;
+                }
+            }
+        }
+        Ok(event)
+    }
+
+    /// The first message `text!` wrote since the previous call
+    pub fn take_note(&mut self) -> Option<String> {
+        self.note.take()
+    }
+
+    fn draw_status(&mut self) -> Result<()> {
+        let alert = ALERT.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let status = match alert {
+            Some(alert) if self.status.is_empty() => alert,
+            Some(alert) => format!("{alert} · {}", self.status),
+            None => self.status.clone(),
+        };
+        if status.is_empty() || self.plain {
+            return Ok(());
+        }
+        let (cols, rows) = self.stdout.size().map_err(Error::Terminal)?;
+        if rows < MIN_ROWS {
+            return Ok(());
+        }
+        if self.status_rows != rows {
+            // the cursor would be stuck below the region on the bottom row
+            if self.draws_from_top() || self.cursor_pos()?.1 >= rows {
+                write!(self.stdout, "\n{}", cursor::Up(1))?;
+            }
+            write!(self.stdout, "{}", scroll_region(Some(rows - 1)))?;
+            self.status_rows = rows;
+        }
+        let status = truncate(&status, cols as usize);
+        let pad = (cols as usize).saturating_sub(display_width(&status));
+        write!(
+            self.stdout,
+            "\x1b7{}{}{}\x1b8",
+            cursor::Goto(1, rows),
+            clear::CurrentLine,
+            format_args!("{status}{:pad$}", "").black().white_bg()
+        )?;
+        Ok(())
+    }
+
+    /// Columns the menus have to draw in
+    pub fn width(&self) -> Result<u16> {
+        Ok(self.size()?.0)
+    }
+
+    /// The screen size, less the status bar's row when it is shown
+    fn size(&self) -> Result<(u16, u16)> {
+        let (cols, rows) = self.stdout.size().map_err(Error::Terminal)?;
+        if self.status.is_empty() {
+            Ok((cols, rows))
+        } else {
+            Ok((cols, rows.saturating_sub(1)))
+        }
+    }
+
+    /// Whether menus start from the top of a cleared screen instead of where the cursor is
+    fn draws_from_top(&self) -> bool {
+        self.stdout.guessed() || self.no_cursor_pos
+    }
+
+    /// Where the cursor is, or the top left of a cleared screen when the size had to be
+    /// guessed and the terminal may not answer, or it did not
+    fn cursor_pos(&mut self) -> Result<(u16, u16)> {
+        if !self.draws_from_top() {
+            match self.stdout.cursor_pos() {
+                Ok(pos) => return Ok(pos),
+                Err(e) => {
+                    log::verbose(format_args!(
+                        "cursor position: {e}, drawing the menus from the top"
+                    ));
+                    self.no_cursor_pos = true;
+                }
+            }
+        }
+        self.redraw_from_top()?;
+        Ok((1, 1))
+    }
+
+    pub fn copy_to_clipboard(&mut self, text: &str) -> Result<()> {
+        if !self.plain {
+            write!(self.stdout, "{}", clipboard::osc52(text))?;
+            self.stdout.flush()?;
+        }
+        clipboard::termux_set(text);
+        Ok(())
+    }
+
+    /// Copies the text of the `i`th item of `list`, returns the toast saying so
+    fn copy_item<L: Display>(
+        &mut self,
+        mut list: impl Iterator<Item = L>,
+        i: usize,
+    ) -> Result<String> {
+        let text = list
+            .nth(i)
+            .map(|l| strip_styles(&l.to_string()))
+            .unwrap_or_default();
+        self.copy_to_clipboard(&text)?;
+        Ok(format!("copied {text}").green().to_string())
+    }
+
+    pub fn cursor_hide(&mut self) -> Result<()> {
+        if !self.plain {
+            write!(self.stdout, "{}", cursor::Hide)?;
+        }
+        Ok(())
+    }
+
+    pub fn cursor_show(&mut self) -> Result<()> {
+        if !self.plain {
+            write!(self.stdout, "{}", cursor::Show)?;
+        }
+        Ok(())
+    }
+
+    /// Wipes the screen after a resize, the old layout cannot be cleared line by line
+    /// once the terminal has reflowed it
+    fn redraw_from_top(&mut self) -> Result<()> {
+        write!(
+            self.stdout,
+            "{}{}{}",
+            scroll_region(None),
+            clear::All,
+            cursor::Goto(1, 1)
+        )?;
+        self.status_rows = 0;
+        self.draw_status()?;
+        Ok(())
+    }
+
+    fn reserve_rows(&mut self, n: u16) -> Result<(u16, u16)> {
+        self.make_room(n)?;
+        self.cursor_pos()
+    }
+
+    /// Scrolls `n` rows into view below the cursor, which is left where it was
+    fn make_room(&mut self, n: u16) -> Result<()> {
+        if n > 0 {
+            for _ in 0..n {
+                writeln!(self.stdout)?;
+            }
+            write!(self.stdout, "{}", cursor::Up(n))?;
+            self.stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    /// `reserve_rows` without asking the terminal where the cursor is, for the menus
+    /// that can be drawn relative to it. Those whose screen size is a guess still
+    /// start from the top of a cleared screen
+    fn reserve_relative(&mut self, n: u16) -> Result<Anchor> {
+        if self.stdout.guessed() {
+            return Ok(Anchor::Row(self.reserve_rows(n)?.1));
+        }
+        self.make_room(n)?;
+        Ok(Anchor::Relative)
+    }
+
+    /// The screen row of the first row of a menu at `anchor`, the cursor `drawn` rows
+    /// below it. A relative menu only asks the terminal to place a click, and `None`
+    /// once it did not answer
+    fn first_row(&mut self, anchor: Anchor, drawn: usize) -> Option<u16> {
+        match anchor {
+            Anchor::Row(row) => Some(row),
+            Anchor::Relative if self.no_cursor_pos => None,
+            Anchor::Relative => match self.stdout.cursor_pos() {
+                Ok((_, row)) => row.checked_sub(drawn as u16),
+                Err(e) => {
+                    log::verbose(format_args!("cursor position: {e}, ignoring the click"));
+                    self.no_cursor_pos = true;
+                    None
+                }
+            },
+        }
+    }
+
+    /// Asks a yes/no question. Enter picks `default_yes`, Esc and Ctrl+C always answer no
+    pub fn confirm(&mut self, question: impl Display, default_yes: bool) -> Result<bool> {
+        if self.plain {
+            return self.plain_confirm(question, default_yes);
+        }
+        let question = question.to_string();
+        let hint = if default_yes {
+            format!("[{}/n]", "Y".green().bold())
+        } else {
+            format!("[y/{}]", "N".red().bold())
+        };
+        let mut size = self.size()?;
+        let mut pos = self.reserve_rows(1)?;
+        let mut invalid = None;
+        let ret = loop {
+            if self.size()? != size {
+                size = self.size()?;
+                self.redraw_from_top()?;
+                pos = self.reserve_rows(1)?;
+            }
+            let line = match invalid.take() {
+                Some(k) => format!(
+                    "{question} {hint} {}",
+                    format_args!("{}?", KeyName(k)).red()
+                ),
+                None => format!("{question} {hint} "),
+            };
+            write!(self.stdout, "{}", truncate(&line, size.0 as usize))?;
+            self.draw_status()?;
+            self.stdout.flush()?;
+
+            let key = read_key(&mut self.keys)?;
+            write!(
+                self.stdout,
+                "\r{}{}",
+                cursor::Goto(pos.0, pos.1),
+                clear::AfterCursor
+            )?;
+            match key {
+                Key::Char('y' | 'Y') => break Ok(true),
+                Key::Char('n' | 'N') | Key::Esc | Key::Ctrl('c') => break Ok(false),
+                Key::Char('\n') => break Ok(default_yes),
+                Key::Null => {}
+                k => invalid = Some(k),
+            }
+        };
+        write!(self.stdout, "{}{}", cursor::Up(1), clear::CurrentLine)?;
+        self.draw_status()?;
+        self.stdout.flush()?;
+        ret
+    }
+
+    /// Reads a line of text typed after `prompt`, `None` when left with Esc or Ctrl+C
+    pub fn read_line(&mut self, prompt: impl Display) -> Result<Option<String>> {
+        self.edit_line(prompt, "")
+    }
+
+    /// `read_line` starting with `initial` typed. Plain prompts show it in brackets and
+    /// keep it when the line is left empty
+    pub fn edit_line(&mut self, prompt: impl Display, initial: &str) -> Result<Option<String>> {
+        if self.plain {
+            if initial.is_empty() {
+                write!(self.stdout, "{prompt}")?;
+            } else {
+                write!(self.stdout, "{prompt}[{initial}] ")?;
+            }
+            return Ok(self.plain_line()?.map(|line| {
+                if line.is_empty() {
+                    initial.to_string()
+                } else {
+                    line
+                }
+            }));
+        }
+        let prompt = prompt.to_string();
+        let mut input = initial.to_string();
+        let mut size = self.size()?;
+        let mut pos = self.reserve_rows(1)?;
+        self.cursor_show()?;
+        let ret = loop {
+            if self.size()? != size {
+                size = self.size()?;
+                self.redraw_from_top()?;
+                pos = self.reserve_rows(1)?;
+            }
+            let line = format!("{prompt}{input}");
+            write!(self.stdout, "{}", truncate(&line, size.0 as usize))?;
+            self.draw_status()?;
+            self.stdout.flush()?;
+
+            let key = read_key(&mut self.keys)?;
+            write!(
+                self.stdout,
+                "\r{}{}",
+                cursor::Goto(pos.0, pos.1),
+                clear::AfterCursor
+            )?;
+            match key {
+                Key::Char('\n') => break Ok(Some(input)),
+                k if cancels(k) => break Ok(None),
+                Key::Backspace => {
+                    input.pop();
+                }
+                Key::Ctrl('u') => input.clear(),
+                Key::Char(c) if !c.is_control() => input.push(c),
+                _ => {}
+            }
+        };
+        self.cursor_hide()?;
+        write!(self.stdout, "{}{}", cursor::Up(1), clear::CurrentLine)?;
+        self.stdout.flush()?;
+        ret
+    }
+
+    /// Picks an item of `list`. It is drawn below the cursor and moved back to over
+    /// the rows drawn, so it works in terminals that never say where the cursor is
+    pub fn select_menu<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Option<usize>> {
+        Ok(self
+            .select_menu_at(list, 0, None, title, prompt, quit)?
+            .picked())
+    }
+
+    /// `select_menu` where the list was left the last time the one called `name` was
+    /// open, the last item when the list got shorter since
+    pub fn select_menu_kept<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        name: &'static str,
+        list: I,
+        pass: Option<(Key, &'static str)>,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Selection> {
+        let at = self.kept.get(name).copied().unwrap_or(0);
+        let selection = self.select_menu_at(list, at, pass, title, prompt, quit)?;
+        self.kept.insert(name, selection.index());
+        Ok(selection)
+    }
+
+    /// Moves where the list called `name` is opened next, as when it was reordered
+    pub fn keep(&mut self, name: &'static str, at: usize) {
+        self.kept.insert(name, at);
+    }
+
+    /// `select_menu_kept` for a list with section headers and separators
+    pub fn select_rows_kept<L: Display, I: Iterator<Item = Row<L>> + Clone>(
+        &mut self,
+        name: &'static str,
+        rows: I,
+        pass: Option<(Key, &'static str)>,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Selection> {
+        let at = self.kept.get(name).copied().unwrap_or(0);
+        let selection = self.select_rows_at(rows, at, pass, title, prompt, quit)?;
+        self.kept.insert(name, selection.index());
+        Ok(selection)
+    }
+
+    /// `select_menu` starting on the item at `at`, or the last one when there are fewer.
+    /// Pressing `pass` leaves with the item it was pressed on, for the caller to handle
+    pub fn select_menu_at<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        at: usize,
+        pass: Option<(Key, &'static str)>,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Selection> {
+        self.select_rows_at(list.map(Row::Item), at, pass, title, prompt, quit)
+    }
+
+    /// `select_menu_at` over `rows`, where `at` and the selection count the items only
+    fn select_rows_at<L: Display, I: Iterator<Item = Row<L>> + Clone>(
+        &mut self,
+        rows: I,
+        at: usize,
+        pass: Option<(Key, &'static str)>,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Selection> {
+        let list = rows.clone().filter_map(Row::item);
+        // the row each item is drawn on
+        let item_rows: Vec<usize> = (rows.clone().enumerate())
+            .filter(|(_, row)| row.is_item())
+            .map(|(i, _)| i)
+            .collect();
+        let rows_len = rows.clone().count();
+        let list_len = item_rows.len();
+        let mut select_idx = at.min(list_len.saturating_sub(1));
+        if self.plain {
+            return Ok(match self.plain_select(rows, title, quit)? {
+                Some(i) => Selection::Picked(i),
+                None => Selection::Left(select_idx),
+            });
+        }
+        let keys = Keymap::new(keymap::LIST)
+            .also(quit, Action::Leave)
+            .with(pass.map(|p| p.0), Action::Pass, pass.map_or("", |p| p.1))
+            .with(
+                self.open.is_some().then_some(Key::Char('o')),
+                Action::Open,
+                "open the highlighted app's Play Store page",
+            );
+        let title = title.to_string();
+        let prompt = prompt.to_string();
+        let prompt_len = display_width(&prompt) + 1;
+        let mut viewport = Viewport::default();
+        viewport.fit(self.size()?, rows_len);
+        let mut anchor = self.reserve_relative(viewport.rows() as u16 + 1)?;
+        let mut frame = Frame::default();
+        // shown next to the title until the next key
+        let mut toast = None;
+        let mut search = TypeAhead::default();
+
+        write!(self.stdout, "{MOUSE_ON}")?;
+        let ret = loop {
+            let size = self.size()?;
+            if size != viewport.size {
+                self.redraw_from_top()?;
+                viewport.fit(size, rows_len);
+                anchor = self.reserve_relative(viewport.rows() as u16 + 1)?;
+                frame.invalidate();
+            }
+            let select_row = item_rows.get(select_idx).copied();
+            // the headers right above the item are kept in view with it
+            let section = item_rows[..select_idx.min(list_len)]
+                .last()
+                .map_or(0, |above| above + 1);
+            viewport.follow(section, rows_len);
+            viewport.follow(select_row.unwrap_or(0), rows_len);
+            let header = match toast.take() {
+                Some(toast) => Cow::Owned(format!("{title} {toast}")),
+                None if search.active() => Cow::Owned(format!("{title} {}", search.label())),
+                None => Cow::Borrowed(&title),
+            };
+            let header = truncate(&header, viewport.width).into_owned();
+            let mut lines = vec![header.clone()];
+            if viewport.clipped {
+                lines.push(viewport.more(viewport.offset));
+            }
+            for (i, row) in rows
+                .clone()
+                .enumerate()
+                .skip(viewport.offset)
+                .take(viewport.height)
+            {
+                lines.push(match row {
+                    Row::Item(selection) if Some(i) == select_row => {
+                        let selection = selection.to_string();
+                        let selection = truncate_middle(
+                            &selection,
+                            viewport
+                                .width
+                                .saturating_sub(prompt_len + highlight_width()),
+                        );
+                        format!("{} {}", prompt, highlight(&selection))
+                    }
+                    Row::Item(selection) => truncate_middle(&selection.to_string(), viewport.width)
+                        .faint()
+                        .to_string(),
+                    Row::Header(header) => truncate(&header, viewport.width).bold().to_string(),
+                    Row::Separator => "─".repeat(viewport.width).faint().to_string(),
+                });
+            }
+            if viewport.clipped {
+                let below = rows_len - viewport.offset - viewport.height;
+                lines.push(viewport.more(below));
+            }
+            if frame.draw(&mut self.stdout, lines)? {
+                self.draw_status()?;
+            }
+            self.stdout.flush()?;
+            self.flash_title(anchor, frame.len(), &header)?;
+            self.draw_toast(anchor, frame.len())?;
+
+            let key = match self.next_event()? {
+                Event::Key(key) => key,
+                Event::Mouse(event) => {
+                    let title_row = match event {
+                        MouseEvent::Press(MouseButton::Left, ..) => {
+                            self.first_row(anchor, frame.len())
+                        }
+                        _ => None,
+                    };
+                    mouse_key(
+                        event,
+                        |row| {
+                            let row = viewport.item_at(title_row?, row, rows_len)?;
+                            item_rows.binary_search(&row).ok()
+                        },
+                        &mut select_idx,
+                        Key::Char('\n'),
+                    )
+                }
+                Event::Unsupported(_) => Key::Null,
+            };
+            write!(self.stdout, "{}", anchor.first_row(frame.len()))?;
+            match key {
+                Key::Null => {
+                    search.expire();
+                    continue;
+                }
+                Key::Esc if search.active() => {
+                    search.clear();
+                    continue;
+                }
+                Key::Backspace if search.active() => {
+                    search.pop();
+                    if let Some(i) = search.find(list.clone()) {
+                        select_idx = i;
+                    }
+                    continue;
+                }
+                // the letters that do something else are typed once a search is on
+                Key::Char(c) if !c.is_control() && (search.active() || !keys.binds(c)) => {
+                    search.push(c);
+                    match search.find(list.clone()) {
+                        Some(i) => select_idx = i,
+                        None => self.reject()?,
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            match keys.action(key) {
+                Some(Action::Pick) if list_len > 0 => break Ok(Selection::Picked(select_idx)),
+                Some(Action::Copy) if list_len > 0 => {
+                    toast = Some(self.copy_item(list.clone(), select_idx)?);
+                }
+                Some(Action::Pass) => break Ok(Selection::Pressed(select_idx)),
+                Some(Action::Open) if list_len > 0 => {
+                    self.open_item(select_idx);
+                    frame.invalidate();
+                }
+                Some(Action::Help) => {
+                    self.show_keys(&keys, anchor)?;
+                    frame.invalidate();
+                }
+                Some(Action::Leave) => break Ok(Selection::Left(select_idx)),
+                Some(action)
+                    if action.motion().is_some_and(|k| {
+                        navigate(k, &mut select_idx, list_len, viewport.height, self.wrap)
+                    }) =>
+                {
+                    search.clear()
+                }
+                _ => self.reject()?,
+            }
+        };
+        write!(
+            self.stdout,
+            "{MOUSE_OFF}{}{}{}",
+            clear::AfterCursor,
+            cursor::Up(1),
+            clear::CurrentLine
+        )?;
+        self.draw_status()?;
+        self.stdout.flush()?;
+        ret
+    }
+
+    pub fn select_menu_multi<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Option<Vec<usize>>> {
+        let checked = vec![false; list.clone().count()];
+        self.select_menu_multi_from(list, checked, title, prompt, quit)
+    }
+
+    /// `select_menu_multi` starting with the items flagged in `checked` already checked
+    pub fn select_menu_multi_from<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        checked: Vec<bool>,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Option<Vec<usize>>> {
+        Ok(
+            match self.select_menu_multi_at(list, checked, 0, None, title, prompt, quit)? {
+                Checked::Done(picked) => Some(picked),
+                Checked::Left | Checked::Pressed(..) | Checked::Unsaved(..) | Checked::Bulk(..) => {
+                    None
+                }
+            },
+        )
+    }
+
+    /// `select_menu_multi_from` starting on the item at `at`. Pressing `pass` leaves
+    /// with the item it was pressed on, for the caller to handle
+    #[allow(clippy::too_many_arguments)]
+    pub fn select_menu_multi_at<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        checked: Vec<bool>,
+        at: usize,
+        pass: Option<(Key, &'static str)>,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Checked> {
+        self.multi_at(list, checked, None, at, pass, title, prompt, quit)
+    }
+
+    /// `select_menu_multi_at` where `saved` is what is written now. The items checked
+    /// differently are marked as pending, `w` takes them like ENTER and leaving with
+    /// any ends in [`Checked::Unsaved`]
+    #[allow(clippy::too_many_arguments)]
+    pub fn select_menu_toggle_at<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        saved: &[bool],
+        checked: Vec<bool>,
+        at: usize,
+        pass: Option<(Key, &'static str)>,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Checked> {
+        self.multi_at(list, checked, Some(saved), at, pass, title, prompt, quit)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn multi_at<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        mut checked: Vec<bool>,
+        saved: Option<&[bool]>,
+        at: usize,
+        pass: Option<(Key, &'static str)>,
+        title: impl Display,
+        prompt: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Checked> {
+        let list_len = list.clone().count();
+        checked.resize(list_len, false);
+        let pending = |checked: &[bool], i: usize| {
+            saved.is_some_and(|saved| saved.get(i).copied().unwrap_or(false) != checked[i])
+        };
+        if self.plain {
+            return Ok(match self.plain_multi(list, checked, title, quit)? {
+                Some(picked) => Checked::Done(picked),
+                None => Checked::Left,
+            });
+        }
+        // the item highlighted, kept as the list is narrowed
+        let mut select_item = at.min(list_len.saturating_sub(1));
+        let mut narrow = Narrow::default();
+        if self.bulk {
+            narrow.text = std::mem::take(&mut self.bulk_narrow);
+        }
+        let keys = Keymap::new(keymap::MULTI)
+            .also(quit, Action::Leave)
+            .with(pass.map(|p| p.0), Action::Pass, pass.map_or("", |p| p.1))
+            .with(
+                saved.map(|_| Key::Char('w')),
+                Action::Pick,
+                "write the changes, as ENTER",
+            )
+            .with(
+                self.bulk.then_some(Key::Char('*')),
+                Action::Bulk,
+                "check the apps with a prefix, matching a glob or from one installer",
+            );
+        let title = title.to_string();
+        let prompt = prompt.to_string();
+        let prompt_len = display_width(&prompt) + 1;
+        const MARKER_LEN: usize = "[x] ".len();
+        let mut viewport = Viewport::default();
+        // room for the whole list, narrowing it only leaves rows blank
+        viewport.fit(self.size()?, list_len);
+        let mut pos = self.reserve_rows(viewport.rows() as u16 + 1)?;
+        let mut frame = Frame::default();
+        // shown next to the title until the next key
+        let mut toast = None;
+
+        write!(self.stdout, "{MOUSE_ON}")?;
+        let ret = loop {
+            let size = self.size()?;
+            if size != viewport.size {
+                self.redraw_from_top()?;
+                viewport.fit(size, list_len);
+                pos = self.reserve_rows(viewport.rows() as u16 + 1)?;
+                frame.invalidate();
+            }
+            let shown = narrow.shown(list.clone());
+            let shown_len = shown.len();
+            viewport.fit(size, shown_len);
+            // the highlight stays on its item while it is shown, or else goes to the first
+            let mut select_idx = shown.iter().position(|&i| i == select_item).unwrap_or(0);
+            viewport.follow(select_idx, shown_len);
+            let changes = (0..list_len).filter(|&i| pending(&checked, i)).count();
+            let title = match narrow.text.is_empty() {
+                true => Cow::Borrowed(&title),
+                false => Cow::Owned(format!("{title} {}", narrow.label(shown_len, list_len))),
+            };
+            let header = match toast.take() {
+                Some(toast) => Cow::Owned(format!("{title} {toast}")),
+                None if changes > 0 => Cow::Owned(format!(
+                    "{title} {}",
+                    format_args!("[{changes} pending]").yellow().bold()
+                )),
+                None => title,
+            };
+            let mut lines = vec![truncate(&header, viewport.width).into_owned()];
+            if viewport.clipped {
+                lines.push(viewport.more(viewport.offset));
+            }
+            let items = (list.clone().enumerate()).filter(|(i, _)| shown.binary_search(i).is_ok());
+            for (row, (i, selection)) in items
+                .enumerate()
+                .skip(viewport.offset)
+                .take(viewport.height)
+            {
+                let selection = selection.to_string();
+                let marker = match (checked[i], pending(&checked, i)) {
+                    (true, true) => "[+]".yellow(),
+                    (false, true) => "[-]".yellow(),
+                    (true, false) => "[x]".green(),
+                    (false, false) => "[ ]".faint(),
+                };
+                lines.push(if row == select_idx {
+                    let selection = truncate_middle(
+                        &selection,
+                        viewport
+                            .width
+                            .saturating_sub(prompt_len + MARKER_LEN + highlight_width()),
+                    );
+                    format!("{} {} {}", prompt, marker, highlight(&selection))
+                } else {
+                    let selection =
+                        truncate_middle(&selection, viewport.width.saturating_sub(MARKER_LEN));
+                    format!("{} {}", marker, selection.faint())
+                });
+            }
+            if viewport.clipped {
+                let below = shown_len - viewport.offset - viewport.height;
+                lines.push(viewport.more(below));
+            }
+            if frame.draw(&mut self.stdout, lines)? {
+                self.draw_status()?;
+            }
+            self.stdout.flush()?;
+            self.draw_toast(Anchor::Row(pos.1), 0)?;
+
+            let key = match self.next_event()? {
+                Event::Key(key) => key,
+                Event::Mouse(event) => mouse_key(
+                    event,
+                    |row| viewport.item_at(pos.1, row, shown_len),
+                    &mut select_idx,
+                    Key::Char(' '),
+                ),
+                Event::Unsupported(_) => Key::Null,
+            };
+            write!(self.stdout, "\r{}", cursor::Goto(pos.0, pos.1))?;
+            select_item = shown.get(select_idx).copied().unwrap_or(select_item);
+            match key {
+                Key::Esc if !narrow.text.is_empty() => {
+                    narrow = Narrow::default();
+                    continue;
+                }
+                Key::Backspace if !narrow.text.is_empty() => {
+                    narrow.pop();
+                    continue;
+                }
+                // space always checks
+                Key::Char(c)
+                    if c != ' ' && !c.is_control() && (narrow.typing() || !keys.binds(c)) =>
+                {
+                    narrow.push(c);
+                    continue;
+                }
+                Key::Null => {}
+                _ => narrow.stop(),
+            }
+            match keys.action(key) {
+                Some(Action::Pick) => {
+                    break Ok(Checked::Done(
+                        checked
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, &c)| c)
+                            .map(|(i, _)| i)
+                            .collect(),
+                    ));
+                }
+                Some(Action::Pass) => {
+                    if self.bulk {
+                        self.bulk_narrow = narrow.text;
+                    }
+                    break Ok(Checked::Pressed(select_item, std::mem::take(&mut checked)));
+                }
+                Some(Action::Bulk) => {
+                    self.bulk_narrow = narrow.text;
+                    let checked = std::mem::take(&mut checked);
+                    break Ok(Checked::Bulk(select_item, checked, shown));
+                }
+                Some(Action::Check) if shown_len > 0 => {
+                    checked[select_item] = !checked[select_item];
+                }
+                Some(Action::Copy) if shown_len > 0 => {
+                    toast = Some(self.copy_item(list.clone(), select_item)?);
+                }
+                // the items shown only, so that a narrowed list is checked at once
+                Some(Action::CheckAll) => shown.iter().for_each(|&i| checked[i] = true),
+                Some(Action::Invert) => shown.iter().for_each(|&i| checked[i] = !checked[i]),
+                Some(Action::Help) => {
+                    self.show_keys(&keys, Anchor::Row(pos.1))?;
+                    frame.invalidate();
+                }
+                Some(action)
+                    if action.motion().is_some_and(|k| {
+                        navigate(k, &mut select_idx, shown_len, viewport.height, self.wrap)
+                    }) =>
+                {
+                    select_item = shown[select_idx];
+                }
+                Some(Action::Leave) => {
+                    if (0..list_len).any(|i| pending(&checked, i)) {
+                        break Ok(Checked::Unsaved(select_item, checked));
+                    }
+                    break Ok(Checked::Left);
+                }
+                _ => {}
+            }
+        };
+        write!(
+            self.stdout,
+            "{MOUSE_OFF}{}{}{}",
+            clear::AfterCursor,
+            cursor::Up(1),
+            clear::CurrentLine
+        )?;
+        self.draw_status()?;
+        self.stdout.flush()?;
+        ret
+    }
+
+    /// Lists what `lister` finds for the typed input, under `heading` while nothing is
+    /// typed. The key of each of `cycles` steps it on, the highlight stays on the item
+    /// with the same `id` when it is still listed
+    #[allow(clippy::too_many_arguments)]
+    pub fn select_menu_with_input<F: Fn(&str) -> Vec<L>, L: Display>(
+        &mut self,
+        lister: F,
+        id: impl Fn(&L) -> usize,
+        heading: Option<&str>,
+        prompt: impl Display,
+        input_prompt: &str,
+        quit: Option<Key>,
+        cycles: &[Cycle],
+        at: usize,
+    ) -> Result<Option<L>> {
+        if self.plain {
+            return self.plain_filter(lister, input_prompt, quit);
+        }
+        let keys = (cycles.iter())
+            .fold(
+                Keymap::new(keymap::INPUT).also(quit, Action::Leave),
+                |keys, cycle| keys.with(Some(cycle.key), Action::Cycle, cycle.what),
+            )
+            .with(
+                self.open.is_some().then_some(Key::Ctrl('o')),
+                Action::Open,
+                "open the highlighted app's Play Store page",
+            );
+        let mut select_idx = at;
+        let mut cursor = 0;
+        let mut input = String::new();
+        let prompt = prompt.to_string();
+        let prompt_len = display_width(&prompt) + 1;
+        let mut size = self.size()?;
+        let mut frame = Frame::default();
+        // the toast goes on the row above the input line, when there is one
+        let mut top = self.cursor_pos()?.1 <= 1;
+        write!(self.stdout, "{PASTE_ON}")?;
+
+        let ret = loop {
+            if self.size()? != size {
+                size = self.size()?;
+                self.redraw_from_top()?;
+                frame.invalidate();
+                top = true;
+            }
+            let mut lines = vec![format!("{}{}", input_prompt.magenta(), input)];
+            let mut list = lister(&input);
+            let list_len = list.len();
+
+            select_idx = select_idx.min(list_len.saturating_sub(1));
+            if list_len > 0 || !cycles.is_empty() {
+                lines.push(String::new());
+            }
+            if list_len > 0 {
+                lines.push("↑ and ↓ to navigate".to_string());
+                lines.push("ENTER to select".to_string());
+            }
+            for cycle in cycles {
+                lines.push(format!(
+                    "{} lists {}",
+                    KeyName(cycle.key),
+                    (cycle.label)().cyan()
+                ));
+            }
+            if let Some(heading) = heading.filter(|_| list_len > 0 && input.trim().is_empty()) {
+                lines.push(heading.bold().to_string());
+            }
+            let cols = size.0 as usize;
+            for line in &mut lines[1..] {
+                *line = truncate(line, cols).into_owned();
+            }
+            // the item rows left above the one the cursor ends up on
+            let page = (size.1 as usize).saturating_sub(lines.len() + 1);
+
+            for (i, selection) in list.iter().enumerate() {
+                let selection = selection.to_string();
+                lines.push(if i == select_idx {
+                    let selection = truncate_middle(
+                        &selection,
+                        cols.saturating_sub(prompt_len + highlight_width()),
+                    );
+                    format!("{} {}", prompt, highlight(&selection))
+                } else {
+                    truncate_middle(&selection, cols).faint().to_string()
+                });
+            }
+            if frame.draw(&mut self.stdout, lines)? {
+                self.draw_status()?;
+            }
+            // back on the input line, `Right(0)` would still move one column
+            let col = str_width(input_prompt) + str_width(&input[..cursor]);
+            write!(self.stdout, "{}\r", cursor::Up(frame.len() as u16))?;
+            if col > 0 {
+                write!(self.stdout, "{}", cursor::Right(col as u16))?;
+            }
+            self.stdout.flush()?;
+            if !top {
+                self.paint_toast(format_args!("{}\r", cursor::Up(1)))?;
+            }
+
+            let key = match self.next_event()? {
+                Event::Key(key) => key,
+                Event::Unsupported(seq) if seq == PASTE_START => {
+                    let pasted = self.read_paste()?;
+                    input.insert_str(cursor, &pasted);
+                    cursor += pasted.len();
+                    continue;
+                }
+                _ => continue,
+            };
+            match keys.action(key) {
+                Some(Action::Pick) => {
+                    break Ok(if list_len > select_idx {
+                        Some(list.remove(select_idx))
+                    } else {
+                        None
+                    });
+                }
+                Some(Action::Open) => {
+                    if let Some(item) = list.get(select_idx) {
+                        self.open_item(id(item));
+                        frame.invalidate();
+                    }
+                }
+                Some(Action::Cycle) => {
+                    let was = list.get(select_idx).map(&id);
+                    if let Some(cycle) = cycles.iter().find(|c| c.key == key) {
+                        (cycle.next)();
+                    }
+                    select_idx = was
+                        .and_then(|was| lister(&input).iter().position(|l| id(l) == was))
+                        .unwrap_or(0);
+                }
+                Some(Action::LineStart) => cursor = 0,
+                Some(Action::LineEnd) => cursor = input.len(),
+                Some(Action::DeleteWord) => {
+                    let start = prev_word(&input, cursor);
+                    input.replace_range(start..cursor, "");
+                    cursor = start;
+                }
+                Some(Action::DeleteToStart) => {
+                    input.replace_range(..cursor, "");
+                    cursor = 0;
+                }
+                Some(Action::DeleteForward) => {
+                    let end = next_grapheme(&input, cursor);
+                    input.replace_range(cursor..end, "");
+                }
+                Some(Action::DeleteBack) => {
+                    if cursor > 0 {
+                        let start = prev_grapheme(&input, cursor);
+                        input.replace_range(start..cursor, "");
+                        cursor = start;
+                    }
+                }
+                Some(Action::Right) => cursor = next_grapheme(&input, cursor),
+                Some(Action::Left) => cursor = prev_grapheme(&input, cursor),
+                Some(Action::Help) => {
+                    // the input line is where the menu starts
+                    write!(self.stdout, "\r")?;
+                    let row = self.cursor_pos()?.1;
+                    self.show_keys(&keys, Anchor::Row(row))?;
+                    frame.invalidate();
+                    top = row <= 1;
+                }
+                Some(Action::Leave) => break Ok(None),
+                Some(action) => {
+                    if let Some(k) = action.motion() {
+                        navigate(k, &mut select_idx, list_len, page, self.wrap);
+                    }
+                }
+                None => {
+                    if let Key::Char(c) = key {
+                        if !c.is_control() {
+                            input.insert(cursor, c);
+                            cursor += c.len_utf8();
+                        }
+                    }
+                }
+            }
+        };
+        write!(
+            self.stdout,
+            "{PASTE_OFF}\r{}{}\r\n",
+            cursor::Up(1),
+            clear::AfterCursor
+        )?;
+        self.draw_status()?;
+        self.stdout.flush()?;
+        ret
+    }
+
+    /// Filter menu over the `items` that are `shown`, with fuzzy matching, returns the
+    /// index of the chosen item. Items are formatted again on every redraw so they may
+    /// change while the menu is open, `cycles` can change which are shown. The first
+    /// of the `recent` ones shown are listed under "Recent" before anything is typed
+    #[allow(clippy::too_many_arguments)]
+    pub fn select_menu_fuzzy<L: Display>(
+        &mut self,
+        items: &[L],
+        shown: impl Fn(usize) -> bool,
+        recent: &[usize],
+        prompt: impl Display,
+        input_prompt: &str,
+        quit: Option<Key>,
+        cycles: &[Cycle],
+    ) -> Result<Option<usize>> {
+        const MATCH_LIMIT: usize = 5;
+        // the items shown and their text, kept while the query is typed on
+        let filter: RefCell<Option<(Vec<usize>, fuzzy::Filter)>> = RefCell::new(None);
+        let selected = self.select_menu_with_input(
+            |input| {
+                let query = input.trim();
+                if query.is_empty() {
+                    return (recent.iter())
+                        .filter(|&&i| shown(i))
+                        .take(MATCH_LIMIT)
+                        .map(|&i| fuzzy::Match::plain(i, items[i].to_string()))
+                        .collect();
+                }
+                let shown: Vec<usize> = (0..items.len()).filter(|&i| shown(i)).collect();
+                let mut filter = filter.borrow_mut();
+                // anything but a longer query, as a redraw for new labels, formats the
+                // items again
+                let typed_on = filter.as_ref().is_some_and(|(was, f)| {
+                    *was == shown && query != f.query() && query.starts_with(f.query())
+                });
+                if !typed_on {
+                    // `{:#}` for what to match on, items may draw more than that
+                    let texts = shown.iter().map(|&i| format!("{:#}", items[i])).collect();
+                    *filter = Some((shown, fuzzy::Filter::new(texts)));
+                }
+                let (shown, filter) = filter.as_mut().expect("set above");
+                filter.update(query);
+                filter
+                    .best(MATCH_LIMIT)
+                    .into_iter()
+                    .map(|mut m| {
+                        m.index = shown[m.index];
+                        let item = items[m.index].to_string();
+                        m.shown_as(item)
+                    })
+                    .collect()
+            },
+            |m| m.index,
+            Some("Recent"),
+            prompt,
+            input_prompt,
+            quit,
+            cycles,
+            0,
+        )?;
+        Ok(selected.map(|m| m.index))
+    }
+
+    /// Items picked by their number, or their letter past 9. Arrows highlight one to show
+    /// its line of `help` under the list, Enter then picks it the same as its key does
+    pub fn select_menu_numbered<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        help: &[&str],
+        quit: Key,
+        pass: Option<(Key, &'static str)>,
+        title: &str,
+    ) -> Result<SelectNumberedResp> {
+        if self.plain {
+            return self.plain_numbered(list, quit, title);
+        }
+        write!(self.stdout, "{MOUSE_ON}")?;
+        let ret = self.numbered_loop(list, help, quit, pass, title);
+        write!(self.stdout, "{MOUSE_OFF}")?;
+        self.stdout.flush()?;
+        ret
+    }
+
+    fn numbered_loop<L: Display, I: Iterator<Item = L> + Clone>(
+        &mut self,
+        list: I,
+        help: &[&str],
+        quit: Key,
+        pass: Option<(Key, &'static str)>,
+        title: &str,
+    ) -> Result<SelectNumberedResp> {
+        let list_len = list.clone().count();
+        let keys = Keymap::new(keymap::NUMBERED)
+            .also(Some(quit), Action::Leave)
+            .with(pass.map(|p| p.0), Action::Pass, pass.map_or("", |p| p.1));
+        let labels = item_labels(list_len, &keys);
+        let mut pos = self.cursor_pos()?;
+        let mut size = self.size()?;
+
+        // past 9 items a number is typed out and confirmed with ENTER, or the letter
+        // of the item pressed
+        let multi_digit = list_len > 9;
+        let mut input = String::new();
+        let mut msg: Option<String> = None;
+        // the item the arrows or the typed number are on, whose help is shown
+        let mut highlight: Option<usize> = None;
+        loop {
+            if self.size()? != size {
+                size = self.size()?;
+                self.redraw_from_top()?;
+                pos = (1, 1);
+            }
+            let cols = size.0 as usize;
+            write!(self.stdout, "\r{}\r\n", truncate(title, cols))?;
+            for (i, (s, label)) in list.clone().zip(&labels).enumerate() {
+                let label = match label {
+                    Some(c) if i < 9 => c.to_string(),
+                    Some(c) => format!("{}/{c}", i + 1),
+                    None => (i + 1).to_string(),
+                };
+                let s = s.to_string();
+                if highlight == Some(i) {
+                    let s = truncate_middle(
+                        &s,
+                        cols.saturating_sub(label.len() + 2 + highlight_width()),
+                    );
+                    write!(
+                        self.stdout,
+                        "{}. {}\r\n",
+                        label.green(),
+                        colorize::highlight(&s)
+                    )?;
+                } else {
+                    let s = truncate_middle(&s, cols.saturating_sub(label.len() + 2));
+                    write!(self.stdout, "{}. {}\r\n", label.green(), s)?;
+                }
+            }
+            write!(self.stdout, "{}. Quit\r\n", KeyName(quit).green())?;
+            // always a row, so the menu keeps its height as the arrows move
+            if !help.is_empty() {
+                let line = match highlight {
+                    Some(i) => help.get(i).copied().unwrap_or_default(),
+                    None => "↑↓ explain an item",
+                };
+                write!(self.stdout, "{}\r\n", truncate(line, cols).faint())?;
+            }
+            if multi_digit {
+                write!(self.stdout, "{}{}", "> ".magenta(), input)?;
+                if let Some(msg) = msg.take() {
+                    write!(self.stdout, "  {}", msg.red())?;
+                }
+            }
+            self.draw_status()?;
+            self.stdout.flush()?;
+            self.flash_title(Anchor::Row(pos.1), 0, &truncate(title, cols))?;
+            self.draw_toast(Anchor::Row(pos.1), 0)?;
+            let event = self.next_event()?;
+            write!(
+                self.stdout,
+                "\r{}{}",
+                cursor::Goto(pos.0, pos.1),
+                clear::AfterCursor,
+            )?;
+            self.draw_status()?;
+            self.stdout.flush()?;
+            let key = match event {
+                Event::Key(key) => key,
+                // the items are on the rows below the title, then Quit
+                Event::Mouse(MouseEvent::Press(MouseButton::Left, _, row)) => {
+                    match (row as usize).checked_sub(pos.1 as usize + 1) {
+                        Some(i) if i < list_len => return Ok(SelectNumberedResp::Index(i)),
+                        Some(i) if i == list_len => quit,
+                        _ => Key::Null,
+                    }
+                }
+                _ => Key::Null,
+            };
+            let in_range = |n: usize| (1..=list_len).contains(&n);
+            let action = keys.action(key);
+            match key {
+                Key::Null => {}
+                _ if action == Some(Action::Interrupt) => {
+                    return Ok(SelectNumberedResp::Interrupted)
+                }
+                _ if action == Some(Action::Leave) => return Ok(SelectNumberedResp::Quit),
+                _ if action == Some(Action::Help) => {
+                    self.show_keys(&keys, Anchor::Row(pos.1))?;
+                }
+                _ if list_len > 0 && action.and_then(Action::motion).is_some() => {
+                    let motion = action.and_then(Action::motion).unwrap_or(key);
+                    highlight = Some(match highlight {
+                        Some(mut i) => {
+                            navigate(motion, &mut i, list_len, list_len, self.wrap);
+                            i
+                        }
+                        None if matches!(motion, Key::Up | Key::End) => list_len - 1,
+                        None => 0,
+                    });
+                    input.clear();
+                }
+                _ if action == Some(Action::Pick) && input.is_empty() && highlight.is_some() => {
+                    return Ok(SelectNumberedResp::Index(highlight.unwrap_or_default()));
+                }
+                Key::Char(c @ '0'..='9') if multi_digit => {
+                    input.push(c);
+                    let n: usize = input.parse().unwrap_or(usize::MAX);
+                    if in_range(n) {
+                        highlight = Some(n - 1);
+                    }
+                    // picked right away once no further digit could name an item
+                    if in_range(n) && n.saturating_mul(10) > list_len {
+                        return Ok(SelectNumberedResp::Index(n - 1));
+                    }
+                    if !in_range(n) && n.saturating_mul(10) > list_len {
+                        msg = Some(format!("no option {input}"));
+                        input.clear();
+                    }
+                }
+                Key::Backspace if multi_digit => {
+                    input.pop();
+                }
+                Key::Char('\n') if multi_digit && !input.is_empty() => {
+                    match input.parse() {
+                        Ok(n) if in_range(n) => return Ok(SelectNumberedResp::Index(n - 1)),
+                        _ => msg = Some(format!("no option {input}")),
+                    }
+                    input.clear();
+                }
+                Key::Char(c) if labels.contains(&Some(c)) => {
+                    let i = labels
+                        .iter()
+                        .position(|&l| l == Some(c))
+                        .unwrap_or_default();
+                    return Ok(SelectNumberedResp::Index(i));
+                }
+                k => return Ok(SelectNumberedResp::UndefinedKey(k)),
+            }
+        }
+    }
+}
+
+/// The menus as numbered lists and prompts reading whole lines, for when there is no
+/// terminal to draw on. Nothing here writes escape codes
+impl<K: Iterator<Item = io::Result<Event>>, S: Screen> Menus<K, S> {
+    /// What `text!` writes, on a line of its own
+    pub fn plain_text(&mut self, text: impl Display) -> Result<()> {
+        let text = text.to_string();
+        let text = text.trim_end_matches(['\r', '\n']);
+        if !text.is_empty() {
+            writeln!(self.stdout, "{text}")?;
+        }
+        Ok(())
+    }
+
+    /// The next line of input without its line ending, `None` once the input ended
+    fn plain_line(&mut self) -> Result<Option<String>> {
+        self.stdout.flush()?;
+        let mut line = String::new();
+        loop {
+            match read_key(&mut self.keys)? {
+                Key::Char('\n') => return Ok(Some(line)),
+                Key::Ctrl('c') => {
+                    writeln!(self.stdout)?;
+                    return Ok(None);
+                }
+                Key::Backspace => {
+                    line.pop();
+                }
+                Key::Char(c) if !c.is_control() => line.push(c),
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether `line` is the character of the `quit` key
+    fn plain_quit(line: &str, quit: Option<Key>) -> bool {
+        let mut chars = line.chars();
+        matches!((chars.next(), chars.next(), quit), (Some(c), None, Some(Key::Char(q))) if c == q)
+    }
+
+    fn plain_list<L: Display>(&mut self, list: impl Iterator<Item = L>) -> Result<()> {
+        for (i, item) in list.enumerate() {
+            writeln!(self.stdout, "{:>3}. {item}", i + 1)?;
+        }
+        Ok(())
+    }
+
+    fn plain_confirm(&mut self, question: impl Display, default_yes: bool) -> Result<bool> {
+        let hint = if default_yes { "[Y/n]" } else { "[y/N]" };
+        loop {
+            write!(self.stdout, "{question} {hint} ")?;
+            let Some(answer) = self.plain_line()? else {
+                return Ok(false);
+            };
+            match answer.trim() {
+                "" => return Ok(default_yes),
+                "y" | "Y" | "yes" => return Ok(true),
+                "n" | "N" | "no" => return Ok(false),
+                answer => writeln!(self.stdout, "{answer}? answer y or n")?,
+            }
+        }
+    }
+
+    fn plain_select<L: Display>(
+        &mut self,
+        rows: impl Iterator<Item = Row<L>> + Clone,
+        title: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Option<usize>> {
+        let list_len = rows.clone().filter(Row::is_item).count();
+        writeln!(self.stdout, "{title}")?;
+        // headers are not numbered, so the numbers are the items' indices
+        let mut n = 0;
+        for row in rows {
+            match row {
+                Row::Item(item) => {
+                    n += 1;
+                    writeln!(self.stdout, "{n:>3}. {item}")?;
+                }
+                Row::Header(header) => writeln!(self.stdout, "{header}")?,
+                Row::Separator => writeln!(self.stdout)?,
+            }
+        }
+        loop {
+            write!(self.stdout, "number, empty to leave> ")?;
+            let Some(line) = self.plain_line()? else {
+                return Ok(None);
+            };
+            let line = line.trim();
+            if line.is_empty() || Self::plain_quit(line, quit) {
+                return Ok(None);
+            }
+            match line.parse::<usize>() {
+                Ok(n) if (1..=list_len).contains(&n) => return Ok(Some(n - 1)),
+                _ => writeln!(self.stdout, "no option {line}")?,
+            }
+        }
+    }
+
+    /// Numbers and ranges like `2 4-6` toggle items, `a` checks all, `i` inverts and an
+    /// empty line takes the checked ones
+    fn plain_multi<L: Display>(
+        &mut self,
+        list: impl Iterator<Item = L> + Clone,
+        mut checked: Vec<bool>,
+        title: impl Display,
+        quit: Option<Key>,
+    ) -> Result<Option<Vec<usize>>> {
+        writeln!(self.stdout, "{title}")?;
+        loop {
+            for (i, item) in list.clone().enumerate() {
+                let marker = if checked[i] { "[x]" } else { "[ ]" };
+                writeln!(self.stdout, "{:>3}. {marker} {item}", i + 1)?;
+            }
+            write!(
+                self.stdout,
+                "numbers or ranges to toggle, a all, i invert, empty when done> "
+            )?;
+            let Some(line) = self.plain_line()? else {
+                return Ok(None);
+            };
+            let line = line.trim();
+            if Self::plain_quit(line, quit) {
+                return Ok(None);
+            }
+            match line {
+                "" => {
+                    return Ok(Some(
+                        checked
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, &c)| c)
+                            .map(|(i, _)| i)
+                            .collect(),
+                    ))
+                }
+                "a" => checked.fill(true),
+                "i" => checked.iter_mut().for_each(|c| *c = !*c),
+                _ => match parse_ranges(line, checked.len()) {
+                    Some(picked) => picked.into_iter().for_each(|i| checked[i] = !checked[i]),
+                    None => writeln!(self.stdout, "no option {line}")?,
+                },
+            }
+        }
+    }
+
+    /// Reads a filter, then picks one of what `lister` found for it. An empty filter leaves
+    fn plain_filter<L: Display>(
+        &mut self,
+        lister: impl Fn(&str) -> Vec<L>,
+        input_prompt: &str,
+        quit: Option<Key>,
+    ) -> Result<Option<L>> {
+        loop {
+            write!(self.stdout, "{input_prompt}")?;
+            let Some(input) = self.plain_line()?.filter(|i| !i.trim().is_empty()) else {
+                return Ok(None);
+            };
+            let mut list = lister(&input);
+            if list.is_empty() {
+                writeln!(self.stdout, "nothing matches {input}")?;
+                continue;
+            }
+            self.plain_list(list.iter())?;
+            write!(self.stdout, "number, empty to search again> ")?;
+            let Some(line) = self.plain_line()? else {
+                return Ok(None);
+            };
+            let line = line.trim();
+            if Self::plain_quit(line, quit) {
+                return Ok(None);
+            }
+            match line.parse::<usize>() {
+                Ok(n) if (1..=list.len()).contains(&n) => return Ok(Some(list.remove(n - 1))),
+                _ if line.is_empty() => {}
+                _ => writeln!(self.stdout, "no option {line}")?,
+            }
+        }
+    }
+
+    /// A line that is neither a number nor the quit key comes back as the key of its
+    /// first character
+    fn plain_numbered<L: Display>(
+        &mut self,
+        list: impl Iterator<Item = L> + Clone,
+        quit: Key,
+        title: &str,
+    ) -> Result<SelectNumberedResp> {
+        let list_len = list.clone().count();
+        writeln!(self.stdout, "{title}")?;
+        self.plain_list(list)?;
+        if let Key::Char(q) = quit {
+            writeln!(self.stdout, "{q:>3}. Quit")?;
+        }
+        loop {
+            write!(self.stdout, "> ")?;
+            let Some(line) = self.plain_line()? else {
+                return Ok(SelectNumberedResp::Quit);
+            };
+            let line = line.trim();
+            if Self::plain_quit(line, Some(quit)) {
+                return Ok(SelectNumberedResp::Quit);
+            }
+            match line.parse::<usize>() {
+                Ok(n) if (1..=list_len).contains(&n) => {
+                    return Ok(SelectNumberedResp::Index(n - 1))
+                }
+                Ok(_) => writeln!(self.stdout, "no option {line}")?,
+                Err(_) => {
+                    if let Some(c) = line.chars().next() {
+                        return Ok(SelectNumberedResp::UndefinedKey(Key::Char(c)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The key that picks each item of a numbered list: 1 to 9, then the letters none of
+/// `keys` takes, so the quit key or `j` still do what they do. `None` once they ran out
+fn item_labels(len: usize, keys: &Keymap) -> Vec<Option<char>> {
+    let mut free = ('1'..='9')
+        .chain('a'..='z')
+        .chain('A'..='Z')
+        .filter(|&c| keys.action(Key::Char(c)).is_none());
+    (0..len).map(|_| free.next()).collect()
+}
+
+/// The items named by `2 4-6` style numbers, `None` when one is out of `1..=len`
+fn parse_ranges(line: &str, len: usize) -> Option<Vec<usize>> {
+    let mut picked = Vec::new();
+    for part in line.split([' ', ',']).filter(|p| !p.is_empty()) {
+        let (start, end): (usize, usize) = match part.split_once('-') {
+            Some((a, b)) => (a.parse().ok()?, b.parse().ok()?),
+            None => {
+                let n = part.parse().ok()?;
+                (n, n)
+            }
+        };
+        if start < 1 || end > len || start > end {
+            return None;
+        }
+        picked.extend(start - 1..end);
+    }
+    Some(picked)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::colorize::char_width_after;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    /// The rows a `cols` x `rows` terminal shows after `out`, without trailing blanks,
+    /// and where its cursor is left, from 1 as the terminal reports it. Knows the
+    /// moves, clears and line endings the menus write, styles and modes are dropped
+    pub(crate) fn render(out: &[u8], (cols, rows): (u16, u16)) -> (Vec<String>, (u16, u16)) {
+        let (cols, rows) = (cols.max(1) as usize, rows.max(1) as usize);
+        let blank = || vec![" ".to_string(); cols];
+        let mut grid = vec![blank(); rows];
+        let (mut x, mut y): (usize, usize) = (0, 0);
+        let mut saved = (0, 0);
+        let mut prev = None;
+        let text = String::from_utf8_lossy(out);
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    prev = None;
+                    match chars.next() {
+                        Some('[') => {
+                            let mut params = String::new();
+                            let mut end = ' ';
+                            for c in chars.by_ref() {
+                                if ('@'..='~').contains(&c) {
+                                    end = c;
+                                    break;
+                                }
+                                params.push(c);
+                            }
+                            let nums: Vec<usize> = (params.trim_start_matches('?').split(';'))
+                                .map(|n| n.parse().unwrap_or(0))
+                                .collect();
+                            let n = nums[0].max(1);
+                            match end {
+                                'A' => y = y.saturating_sub(n),
+                                'B' => y = (y + n).min(rows - 1),
+                                'C' => x = (x + n).min(cols - 1),
+                                'D' => x = x.min(cols - 1).saturating_sub(n),
+                                'G' => x = (n - 1).min(cols - 1),
+                                'H' | 'f' => {
+                                    y = (n - 1).min(rows - 1);
+                                    x = (nums.get(1).copied().unwrap_or(1).max(1) - 1)
+                                        .min(cols - 1);
+                                }
+                                'J' if nums[0] == 2 => grid = vec![blank(); rows],
+                                'J' => {
+                                    grid[y][x.min(cols)..].fill(" ".to_string());
+                                    grid[y + 1..].fill(blank());
+                                }
+                                'K' if nums[0] == 2 => grid[y] = blank(),
+                                'K' => grid[y][x.min(cols)..].fill(" ".to_string()),
+                                _ => {}
+                            }
+                        }
+                        Some('7') => saved = (x, y),
+                        Some('8') => (x, y) = saved,
+                        // OSC, as the clipboard's, up to BEL or ST
+                        Some(']') => {
+                            while let Some(c) = chars.next() {
+                                if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some())
+                                {
+                                    break;
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                '\r' => {
+                    x = 0;
+                    prev = None;
+                }
+                '\n' => {
+                    if y + 1 == rows {
+                        grid.remove(0);
+                        grid.push(blank());
+                    } else {
+                        y += 1;
+                    }
+                    prev = None;
+                }
+                c if c.is_control() => prev = None,
+                c => {
+                    let w = char_width_after(prev, c);
+                    let attached = prev.is_some_and(|p| c == '\u{FE0F}' || joins(p, c));
+                    prev = Some(c);
+                    if attached && x > 0 {
+                        let last = (0..x).rev().find(|&i| !grid[y][i].is_empty()).unwrap_or(0);
+                        grid[y][last].push(c);
+                        if w > 0 && x < cols {
+                            grid[y][x] = String::new();
+                            x += 1;
+                        }
+                        continue;
+                    }
+                    if x + w > cols {
+                        x = 0;
+                        if y + 1 == rows {
+                            grid.remove(0);
+                            grid.push(blank());
+                        } else {
+                            y += 1;
+                        }
+                    }
+                    grid[y][x] = c.to_string();
+                    for cell in &mut grid[y][x + 1..(x + w).min(cols)] {
+                        cell.clear();
+                    }
+                    x += w.max(1);
+                }
+            }
+        }
+        let lines = (grid.iter())
+            .map(|row| row.concat().trim_end().to_string())
+            .collect();
+        (lines, (x.min(cols - 1) as u16 + 1, y as u16 + 1))
+    }
+
+    /// A screen of `size` that keeps what is drawn on it. Its clones share the output,
+    /// so the scripted keys can see each frame
+    #[derive(Clone)]
+    pub(crate) struct Tape {
+        out: Rc<RefCell<Vec<u8>>>,
+        size: (u16, u16),
+        /// Whether it says where the cursor is, as a terminal answering the query
+        answers: bool,
+        /// Whether its size is a guess
+        guessed: bool,
+        /// How many times it was asked where the cursor is
+        asked: Rc<std::cell::Cell<usize>>,
+    }
+
+    impl Tape {
+        pub(crate) fn new(size: (u16, u16), answers: bool) -> Self {
+            Self {
+                out: Rc::default(),
+                size,
+                answers,
+                guessed: false,
+                asked: Rc::default(),
+            }
+        }
+
+        /// The tape with its size a guess, as when COLUMNS and LINES had to be used
+        fn size_guessed(mut self) -> Self {
+            self.guessed = true;
+            self
+        }
+
+        /// What the screen shows now
+        pub(crate) fn screen(&self) -> Vec<String> {
+            render(&self.out.borrow(), self.size).0
+        }
+
+        pub(crate) fn output(&self) -> String {
+            String::from_utf8_lossy(&self.out.borrow()).into_owned()
+        }
+    }
+
+    impl Write for Tape {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.out.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Screen for Tape {
+        fn size(&self) -> io::Result<(u16, u16)> {
+            Ok(self.size)
+        }
+
+        fn cursor_pos(&mut self) -> io::Result<(u16, u16)> {
+            self.asked.set(self.asked.get() + 1);
+            match self.answers {
+                true => Ok(render(&self.out.borrow(), self.size).1),
+                false => Err(io::Error::new(io::ErrorKind::TimedOut, "no answer")),
+            }
+        }
+
+        fn guessed(&self) -> bool {
+            self.guessed
+        }
+    }
+
+    /// Events handed to the menus one at a time, each with the screen as it was when
+    /// the menu asked for it
+    pub(crate) struct Script {
+        events: VecDeque<Event>,
+        tape: Tape,
+        pub(crate) frames: Vec<Vec<String>>,
+        /// Where the cursor was in each frame
+        pub(crate) cursors: Vec<(u16, u16)>,
+    }
+
+    impl Iterator for Script {
+        type Item = io::Result<Event>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let (frame, cursor) = render(&self.tape.out.borrow(), self.tape.size);
+            self.frames.push(frame);
+            self.cursors.push(cursor);
+            self.events.pop_front().map(Ok)
+        }
+    }
+
+    pub(crate) type Scripted = Menus<Script, Tape>;
+
+    /// Menus on `tape` that are pressed `keys`, then Ctrl+C as when the input ends
+    pub(crate) fn scripted_on(tape: Tape, keys: &[Key]) -> Scripted {
+        scripted_events(tape, keys.iter().map(|&k| Event::Key(k)).collect())
+    }
+
+    /// [`scripted_on`] with mouse events among the keys
+    fn scripted_events(tape: Tape, events: VecDeque<Event>) -> Scripted {
+        let script = Script {
+            events,
+            tape: tape.clone(),
+            frames: Vec::new(),
+            cursors: Vec::new(),
+        };
+        Menus::with_io(script, tape)
+    }
+
+    /// [`scripted_on`] an 80x24 terminal that says where the cursor is
+    pub(crate) fn scripted(keys: &[Key]) -> Scripted {
+        scripted_on(Tape::new((80, 24), true), keys)
+    }
+
+    /// The keys typing `s`
+    pub(crate) fn typed(s: &str) -> Vec<Key> {
+        s.chars().map(Key::Char).collect()
+    }
+
+    const FRUITS: [&str; 3] = ["apple", "banana", "cherry"];
+
+    fn select(keys: &[Key], quit: Option<Key>) -> (Option<usize>, Scripted) {
+        let mut menus = scripted(keys);
+        let picked = menus
+            .select_menu(FRUITS.iter(), "Fruits", ">", quit)
+            .unwrap();
+        (picked, menus)
+    }
+
+    #[test]
+    fn arrows_stop_at_the_ends() {
+        let (picked, _) = select(&[Key::Up, Key::Up, Key::Char('\n')], None);
+        assert_eq!(picked, Some(0));
+        let down = [Key::Down; 5];
+        let (picked, _) = select(&[&down[..], &[Key::Char('\n')]].concat(), None);
+        assert_eq!(picked, Some(2));
+    }
+
+    #[test]
+    fn arrows_wrap_when_asked_to() {
+        let mut menus = scripted(&[Key::Up, Key::Char('\n')]);
+        menus.set_wrap(true);
+        let picked = menus.select_menu(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(picked.unwrap(), Some(2));
+    }
+
+    #[test]
+    fn vim_keys_move_in_the_list() {
+        let keys = |s: &str| [typed(s), vec![Key::Char('\n')]].concat();
+        assert_eq!(select(&keys("j"), None).0, Some(1));
+        assert_eq!(select(&keys("jjk"), None).0, Some(1));
+        assert_eq!(select(&keys("G"), None).0, Some(2));
+        assert_eq!(select(&keys("Gg"), None).0, Some(0));
+        // past the ends as the arrows
+        assert_eq!(select(&keys("jjjj"), None).0, Some(2));
+        let mut menus = scripted(&keys("k"));
+        menus.set_wrap(true);
+        let picked = menus.select_menu(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(picked.unwrap(), Some(2));
+    }
+
+    #[test]
+    fn quit_keys_leave_without_a_pick() {
+        for quit in [Key::Esc, Key::Ctrl('c'), Key::Char('q')] {
+            let (picked, _) = select(&[Key::Down, quit], Some(Key::Char('q')));
+            assert_eq!(picked, None, "{quit:?}");
+        }
+        // the input running out is Ctrl+C
+        assert_eq!(select(&[Key::Down], None).0, None);
+    }
+
+    /// Every key that confirms, as terminals and `adb shell -t` send Enter
+    const ENTERS: [Key; 4] = [
+        Key::Char('\n'),
+        Key::Char('\r'),
+        Key::Ctrl('m'),
+        Key::Ctrl('j'),
+    ];
+
+    #[test]
+    fn every_enter_confirms_in_every_menu() {
+        for enter in ENTERS {
+            assert_eq!(select(&[Key::Down, enter], None).0, Some(1), "{enter:?}");
+            let keys = [typed("an"), vec![enter]].concat();
+            assert_eq!(filter(&keys).0, Some("banana"), "{enter:?}");
+            let mut menus = scripted(&[Key::Char(' '), enter]);
+            let picked = menus.select_menu_multi(FRUITS.iter(), "Fruits", ">", None);
+            assert_eq!(picked.unwrap(), Some(vec![0]), "{enter:?}");
+            let keys = [Key::Char('1'), enter];
+            assert_eq!(
+                numbered(30, &keys).0,
+                SelectNumberedResp::Index(0),
+                "{enter:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn every_cancel_leaves_every_menu() {
+        for cancel in [Key::Esc, Key::Ctrl('c')] {
+            assert_eq!(select(&[Key::Down, cancel], None).0, None, "{cancel:?}");
+            assert_eq!(filter(&[Key::Char('a'), cancel]).0, None, "{cancel:?}");
+            let mut menus = scripted(&[Key::Char(' '), cancel]);
+            let picked = menus.select_menu_multi(FRUITS.iter(), "Fruits", ">", None);
+            assert_eq!(picked.unwrap(), None, "{cancel:?}");
+        }
+        assert_eq!(numbered(3, &[Key::Esc]).0, SelectNumberedResp::Quit);
+        assert_eq!(
+            numbered(3, &[Key::Ctrl('c')]).0,
+            SelectNumberedResp::Interrupted
+        );
+    }
+
+    #[test]
+    fn every_menu_leaves_when_the_input_ends() {
+        let (picked, menus) = select(&[], None);
+        assert_eq!(picked, None);
+        assert!(menus.stdout.screen().iter().all(|l| l.is_empty()));
+        assert_eq!(filter(&[]).0, None);
+        let mut menus = scripted(&[]);
+        let picked = menus.select_menu_multi(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(picked.unwrap(), None);
+        assert_eq!(numbered(3, &[]).0, SelectNumberedResp::Interrupted);
+    }
+
+    #[test]
+    fn read_errors_are_retried_then_given_up_on() {
+        let failed = || Err(io::Error::other("faulty keyboard"));
+        let mut events = [failed(), failed(), Ok(Event::Key(Key::Char('x')))].into_iter();
+        assert_eq!(read_key(&mut events).unwrap(), Key::Char('x'));
+        let mut events = std::iter::repeat_with(failed).take(MAX_READ_ERRORS + 1);
+        assert_eq!(read_key(&mut events).unwrap(), Key::Ctrl('c'));
+        let interrupted = io::Error::from(io::ErrorKind::Interrupted);
+        let mut events = [Err(interrupted)].into_iter();
+        assert_eq!(read_key(&mut events).unwrap(), Key::Null);
+    }
+
+    #[test]
+    fn enter_picks_the_highlighted_item() {
+        let (picked, menus) = select(&[Key::Down, Key::Char('\r')], None);
+        assert_eq!(picked, Some(1));
+        let before_enter = &menus.keys.frames[1];
+        assert_eq!(before_enter[..4], ["Fruits", "apple", "> banana", "cherry"]);
+        // the menu is wiped once left
+        assert!(menus.stdout.screen().iter().all(|l| l.is_empty()));
+    }
+
+    /// The filter menu over [`FRUITS`] with the ones containing the input listed,
+    /// what it returned and what the input line showed before each key
+    fn filter(keys: &[Key]) -> (Option<&'static str>, Vec<String>) {
+        let (picked, menus) = filter_menu(keys);
+        let inputs = menus.keys.frames.iter().map(|f| f[0].clone()).collect();
+        (picked, inputs)
+    }
+
+    fn filter_menu(keys: &[Key]) -> (Option<&'static str>, Scripted) {
+        let mut menus = scripted(keys);
+        let picked = menus
+            .select_menu_with_input(
+                |input| FRUITS.into_iter().filter(|f| f.contains(input)).collect(),
+                |_| 0,
+                None,
+                ">",
+                "Filter: ",
+                None,
+                &[],
+                0,
+            )
+            .unwrap();
+        (picked, menus)
+    }
+
+    #[test]
+    fn filter_edits_the_input() {
+        let keys = [typed("cx"), vec![Key::Backspace], typed("h")].concat();
+        let (_, inputs) = filter(&keys);
+        assert_eq!(inputs.last().unwrap(), "Filter: ch");
+        let keys = [typed("ac"), vec![Key::Left, Key::Char('b')]].concat();
+        assert_eq!(filter(&keys).1.last().unwrap(), "Filter: abc");
+        let keys = [typed("one two"), vec![Key::Ctrl('w')]].concat();
+        assert_eq!(filter(&keys).1.last().unwrap(), "Filter: one");
+        let keys = [typed("ab"), vec![Key::Left, Key::Ctrl('u')]].concat();
+        assert_eq!(filter(&keys).1.last().unwrap(), "Filter: b");
+        let keys = [typed("ab"), vec![Key::Home, Key::Delete]].concat();
+        assert_eq!(filter(&keys).1.last().unwrap(), "Filter: b");
+    }
+
+    #[test]
+    fn filter_line_shortcuts() {
+        let prompt = "Filter: ".len() as u16;
+        // what the input line shows and the column the cursor is left at
+        let edited = |keys: &[Key]| {
+            let (_, menus) = filter_menu(keys);
+            let input = menus.keys.frames.last().unwrap()[0].clone();
+            (input, menus.keys.cursors.last().unwrap().0 - prompt - 1)
+        };
+        let pkg = typed("com.google_maps");
+        let with = |keys: &[Key]| [&pkg[..], keys].concat();
+        assert_eq!(
+            edited(&with(&[Key::Ctrl('w')])),
+            ("Filter: com.google_".to_string(), 11)
+        );
+        assert_eq!(
+            edited(&with(&[Key::Ctrl('w'), Key::Ctrl('w')])),
+            ("Filter: com.".to_string(), 4)
+        );
+        assert_eq!(
+            edited(&with(&[Key::Ctrl('w'); 3])),
+            ("Filter:".to_string(), 0)
+        );
+        assert_eq!(
+            edited(&with(&[Key::Left, Key::Left, Key::Ctrl('u')])),
+            ("Filter: ps".to_string(), 0)
+        );
+        for start in [Key::Home, Key::Ctrl('a')] {
+            assert_eq!(
+                edited(&with(&[start, Key::Delete])),
+                ("Filter: om.google_maps".to_string(), 0)
+            );
+        }
+        for end in [Key::End, Key::Ctrl('e')] {
+            assert_eq!(
+                edited(&with(&[Key::Home, end, Key::Char('!')])),
+                ("Filter: com.google_maps!".to_string(), 16)
+            );
+        }
+        // nothing before the cursor to delete
+        assert_eq!(
+            edited(&with(&[Key::Home, Key::Ctrl('w'), Key::Ctrl('u')])),
+            ("Filter: com.google_maps".to_string(), 0)
+        );
+        assert_eq!(
+            edited(&with(&[Key::Delete])),
+            ("Filter: com.google_maps".to_string(), 15)
+        );
+    }
+
+    #[test]
+    fn filter_picks_among_the_matches() {
+        let keys = [typed("an"), vec![Key::Char('\n')]].concat();
+        assert_eq!(filter(&keys).0, Some("banana"));
+        let keys = [typed("e"), vec![Key::Down, Key::Down, Key::Char('\n')]].concat();
+        assert_eq!(filter(&keys).0, Some("cherry"));
+        let keys = [typed("zz"), vec![Key::Char('\n')]].concat();
+        assert_eq!(filter(&keys).0, None);
+        assert_eq!(filter(&[Key::Char('a'), Key::Esc]).0, None);
+    }
+
+    #[test]
+    fn vim_keys_are_typed_in_the_filter() {
+        let (_, inputs) = filter(&typed("jkgG"));
+        assert_eq!(inputs.last().unwrap(), "Filter: jkgG");
+        // "e" lists apple and cherry, j is typed and leaves no match
+        let keys = [typed("ej"), vec![Key::Char('\n')]].concat();
+        assert_eq!(filter(&keys).0, None);
+        let keys = [typed("e"), vec![Key::Down, Key::Char('\n')]].concat();
+        assert_eq!(filter(&keys).0, Some("cherry"));
+    }
+
+    #[test]
+    fn viewport_on_a_terminal_without_rows() {
+        let mut viewport = Viewport::default();
+        viewport.fit((80, 0), 10);
+        assert!(viewport.clipped);
+        assert_eq!(viewport.height, 1);
+        viewport.follow(9, 10);
+        assert_eq!(viewport.offset, 9);
+    }
+
+    #[test]
+    fn viewport_of_a_short_list() {
+        let mut viewport = Viewport::default();
+        viewport.fit((80, 24), 3);
+        assert!(!viewport.clipped);
+        assert_eq!((viewport.height, viewport.rows()), (3, 3));
+        viewport.follow(2, 3);
+        assert_eq!(viewport.offset, 0);
+        assert_eq!(viewport.item_at(1, 2, 3), Some(0));
+        assert_eq!(viewport.item_at(1, 4, 3), Some(2));
+        assert_eq!(viewport.item_at(1, 5, 3), None);
+        assert_eq!(viewport.item_at(1, 1, 3), None);
+    }
+
+    #[test]
+    fn viewport_scrolls_at_both_ends() {
+        let mut viewport = Viewport::default();
+        // 7 rows for the list, less the two "more" rows
+        viewport.fit((80, 10), 20);
+        assert!(viewport.clipped);
+        assert_eq!((viewport.height, viewport.rows()), (5, 7));
+        viewport.follow(4, 20);
+        assert_eq!(viewport.offset, 0);
+        viewport.follow(5, 20);
+        assert_eq!(viewport.offset, 1);
+        viewport.follow(19, 20);
+        assert_eq!(viewport.offset, 15);
+        viewport.follow(15, 20);
+        assert_eq!(viewport.offset, 15);
+        viewport.follow(14, 20);
+        assert_eq!(viewport.offset, 14);
+        viewport.follow(0, 20);
+        assert_eq!(viewport.offset, 0);
+        // the first item is below the "more" row
+        assert_eq!(viewport.item_at(1, 3, 20), Some(0));
+        // the list shrank under the offset
+        viewport.offset = 15;
+        viewport.follow(2, 8);
+        assert_eq!(viewport.offset, 2);
+    }
+
+    #[test]
+    fn a_long_list_is_drawn_clipped() {
+        let items: Vec<String> = (1..=20).map(|i| format!("item {i}")).collect();
+        let mut menus = scripted_on(Tape::new((40, 10), true), &[Key::End, Key::Char('\n')]);
+        let picked = menus.select_menu(items.iter(), "Items", ">", None).unwrap();
+        assert_eq!(picked, Some(19));
+        let frames = &menus.keys.frames;
+        assert_eq!(
+            frames[0][..8],
+            [
+                "Items",
+                "",
+                "> item 1",
+                "item 2",
+                "item 3",
+                "item 4",
+                "item 5",
+                "… 15 more"
+            ]
+        );
+        assert_eq!(
+            frames[1][..8],
+            [
+                "Items",
+                "… 15 more",
+                "item 16",
+                "item 17",
+                "item 18",
+                "item 19",
+                "> item 20",
+                ""
+            ]
+        );
+    }
+
+    #[test]
+    fn a_panic_in_a_menu_restores_the_terminal() {
+        let tape = Tape::new((80, 24), true);
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut menus = scripted_on(tape.clone(), &typed("ab"));
+            let lister = |input: &str| {
+                assert_ne!(input, "ab", "lister failed");
+                FRUITS.to_vec()
+            };
+            let _ = menus.select_menu_with_input(lister, |_| 0, None, ">", "", None, &[], 0);
+        }));
+        assert!(panicked.is_err());
+        let output = tape.output();
+        let restore = output.rfind(MOUSE_OFF).expect("restore sequence written");
+        let restore = &output[restore..];
+        assert!(restore.contains(PASTE_OFF));
+        assert!(restore.ends_with(&cursor::Show.to_string()));
+        assert!(tape.screen().iter().all(|l| l.is_empty()));
+    }
+
+    #[test]
+    fn graphemes_of_wide_text() {
+        let s = "日本語";
+        assert_eq!(next_grapheme(s, 0), 3);
+        assert_eq!(next_grapheme(s, 6), 9);
+        assert_eq!(next_grapheme(s, 9), 9);
+        assert_eq!(prev_grapheme(s, 9), 6);
+        assert_eq!(prev_grapheme(s, 3), 0);
+        // a skin tone and the emoji a ZWJ joins on go with the one before
+        let s = "a👍🏽👨‍👩‍👧b";
+        assert_eq!(next_grapheme(s, 1), 9);
+        assert_eq!(next_grapheme(s, 9), s.len() - 1);
+        assert_eq!(prev_grapheme(s, s.len() - 1), 9);
+        assert_eq!(prev_grapheme(s, 9), 1);
+        // an accent typed as a combining mark
+        assert_eq!(next_grapheme("e\u{301}x", 0), 3);
+        assert_eq!(prev_word("日本.語", "日本.語".len()), 7);
+    }
+
+    #[test]
+    fn filter_edits_wide_text() {
+        let keys = [typed("日本語"), vec![Key::Left, Key::Backspace]].concat();
+        let (_, menus) = filter_menu(&keys);
+        assert_eq!(menus.keys.frames.last().unwrap()[0], "Filter: 日語");
+        // the cursor is left of 語, which takes two columns
+        let prompt = "Filter: ".len() as u16;
+        assert_eq!(*menus.keys.cursors.last().unwrap(), (prompt + 3, 1));
+
+        let keys = [typed("a👍🏽b"), vec![Key::Left, Key::Left, Key::Backspace]].concat();
+        let (_, menus) = filter_menu(&keys);
+        assert_eq!(menus.keys.frames.last().unwrap()[0], "Filter: 👍🏽b");
+        assert_eq!(*menus.keys.cursors.last().unwrap(), (prompt + 1, 1));
+
+        let keys = [typed("x👨‍👩‍👧"), vec![Key::Backspace]].concat();
+        assert_eq!(filter(&keys).1.last().unwrap(), "Filter: x");
+        let keys = [
+            typed("日本語"),
+            vec![Key::Home, Key::Delete, Key::Right, Key::Char('x')],
+        ]
+        .concat();
+        assert_eq!(filter(&keys).1.last().unwrap(), "Filter: 本x語");
+    }
+
+    #[test]
+    fn filter_keeps_a_highlight_as_the_list_shrinks_and_grows() {
+        // on cherry, then only banana is left
+        let keys = [
+            vec![Key::Down, Key::Down],
+            typed("an"),
+            vec![Key::Char('\n')],
+        ]
+        .concat();
+        assert_eq!(filter(&keys).0, Some("banana"));
+        // down to nothing and back
+        let keys = [typed("anx"), vec![Key::Backspace, Key::Char('\n')]].concat();
+        let (picked, menus) = filter_menu(&keys);
+        assert_eq!(picked, Some("banana"));
+        let frames = &menus.keys.frames;
+        assert_eq!(frames[3][..2], ["Filter: anx", ""]);
+        assert!(frames[4].contains(&"> banana".to_string()));
+        let keys = [
+            typed("a"),
+            vec![Key::Down, Key::Down],
+            typed("p"),
+            vec![Key::Backspace],
+        ]
+        .concat();
+        for typed in [&keys[..], &keys[..4]] {
+            let (picked, menus) = filter_menu(&[typed, &[Key::Char('\n')]].concat());
+            let frame = menus.keys.frames.last().unwrap();
+            let shown = frame.iter().find_map(|l| l.strip_prefix("> "));
+            assert_eq!(picked, shown, "{typed:?}");
+        }
+    }
+
+    #[test]
+    fn page_keys_move_by_the_rows_shown() {
+        let items: Vec<String> = (0..20).map(|i| format!("item {i}")).collect();
+        // 5 rows for the items on a 10 row screen
+        let keys = [
+            (Key::PageDown, 5),
+            (Key::PageDown, 10),
+            (Key::PageDown, 15),
+            (Key::PageDown, 19),
+            (Key::PageUp, 14),
+            (Key::Home, 0),
+            (Key::PageUp, 0),
+            (Key::End, 19),
+        ];
+        for n in 1..=keys.len() {
+            let mut pressed: Vec<Key> = keys[..n].iter().map(|k| k.0).collect();
+            pressed.push(Key::Char('\n'));
+            let mut menus = scripted_on(Tape::new((80, 10), true), &pressed);
+            let picked = menus.select_menu(items.iter(), "Items", ">", None).unwrap();
+            assert_eq!(picked, Some(keys[n - 1].1), "{pressed:?}");
+        }
+    }
+
+    #[test]
+    fn page_keys_in_the_filter_menu() {
+        let items: Vec<usize> = (0..5).collect();
+        // the input line, a blank one and two lines of help leave 3 rows on 8
+        // Home and End move in the input line
+        let keys = [
+            (Key::PageDown, 3),
+            (Key::PageDown, 4),
+            (Key::PageUp, 1),
+            (Key::End, 1),
+        ];
+        for n in 1..=keys.len() {
+            let mut pressed: Vec<Key> = keys[..n].iter().map(|k| k.0).collect();
+            pressed.push(Key::Char('\n'));
+            let mut menus = scripted_on(Tape::new((80, 8), true), &pressed);
+            let picked = menus
+                .select_menu_with_input(|_| items.clone(), |&i| i, None, ">", "", None, &[], 0)
+                .unwrap();
+            assert_eq!(picked, Some(keys[n - 1].1), "{pressed:?}");
+        }
+    }
+
+    /// The numbered menu over `len` items pressed `keys`, with its frames
+    fn numbered(len: usize, keys: &[Key]) -> (SelectNumberedResp, Scripted) {
+        let items: Vec<String> = (1..=len).map(|i| format!("item {i}")).collect();
+        let mut menus = scripted_on(Tape::new((80, 30), true), keys);
+        let resp = menus
+            .select_menu_numbered(items.iter(), &[], Key::Char('q'), None, "Items")
+            .unwrap();
+        (resp, menus)
+    }
+
+    #[test]
+    fn short_numbered_lists_pick_on_the_key() {
+        use SelectNumberedResp::*;
+        assert_eq!(numbered(5, &[Key::Char('3')]).0, Index(2));
+        assert_eq!(
+            numbered(5, &[Key::Char('6')]).0,
+            UndefinedKey(Key::Char('6'))
+        );
+        assert_eq!(
+            numbered(5, &[Key::Char('0')]).0,
+            UndefinedKey(Key::Char('0'))
+        );
+        assert_eq!(numbered(5, &[Key::Char('q')]).0, Quit);
+        assert_eq!(numbered(5, &[Key::Ctrl('c')]).0, Interrupted);
+        assert_eq!(
+            numbered(5, &[Key::Down, Key::Down, Key::Char('\n')]).0,
+            Index(1)
+        );
+    }
+
+    #[test]
+    fn long_numbered_lists_take_numbers_typed_out() {
+        use SelectNumberedResp::*;
+        let enter = Key::Char('\n');
+        assert_eq!(
+            numbered(12, &[Key::Char('1'), Key::Char('2'), enter]).0,
+            Index(11)
+        );
+        // no item 13 and up, so 1 and 2 cannot be the start of anything longer
+        assert_eq!(numbered(12, &[Key::Char('1'), Key::Char('2')]).0, Index(11));
+        assert_eq!(numbered(12, &[Key::Char('4')]).0, Index(3));
+        assert_eq!(numbered(12, &[Key::Char('1'), enter]).0, Index(0));
+        let backspaced = [Key::Char('1'), Key::Backspace, Key::Char('3')];
+        assert_eq!(numbered(12, &backspaced).0, Index(2));
+        assert_eq!(numbered(12, &[Key::Char('1'), Key::Char('q')]).0, Quit);
+    }
+
+    #[test]
+    fn numbers_out_of_range_are_said_to_be() {
+        let enter = Key::Char('\n');
+        let keys = [
+            Key::Char('0'),
+            enter,
+            Key::Char('1'),
+            Key::Char('3'),
+            Key::Char('3'),
+        ];
+        let (resp, menus) = numbered(12, &keys);
+        assert_eq!(resp, SelectNumberedResp::Index(2));
+        let frames = &menus.keys.frames;
+        assert_eq!(frames[1][14], "> 0");
+        assert_eq!(frames[2][14], ">   no option 0");
+        assert_eq!(frames[3][14], "> 1");
+        // past the end with no shorter number to wait on, it is dropped
+        assert_eq!(frames[4][14], ">   no option 13");
+    }
+
+    #[test]
+    fn items_past_nine_have_letters_too() {
+        let (resp, menus) = numbered(12, &[Key::Char('b')]);
+        assert_eq!(resp, SelectNumberedResp::Index(10));
+        let frame = &menus.keys.frames[0];
+        assert_eq!(frame[..2], ["Items", "1. item 1"]);
+        assert_eq!(
+            frame[10..14],
+            ["10/a. item 10", "11/b. item 11", "12/c. item 12", "q. Quit"]
+        );
+    }
+
+    #[test]
+    fn key_names() {
+        let names = [
+            (Key::Char('\n'), "Enter"),
+            (Key::Char(' '), "Space"),
+            (Key::Char('x'), "x"),
+            (Key::Ctrl('w'), "Ctrl+W"),
+            (Key::Alt('x'), "Alt+x"),
+            (Key::F(5), "F5"),
+            (Key::BackTab, "Shift+Tab"),
+            (Key::Null, "an unknown key"),
+        ];
+        for (key, name) in names {
+            assert_eq!(KeyName(key).to_string(), name);
+        }
+    }
+
+    #[test]
+    fn confirm_names_the_key_it_does_not_take() {
+        let mut menus = scripted(&[Key::F(5), Key::Char('y')]);
+        assert!(menus.confirm("Go on?", false).unwrap());
+        let asked = &menus.keys.frames.last().unwrap()[0];
+        assert_eq!(asked, "Go on? [y/N] F5?");
+    }
+
+    #[test]
+    fn long_items_are_cut_to_the_width_in_the_middle() {
+        let items = ["com.google.android.apps.youtube.music.premium", "short"];
+        for cols in [20, 40, 120] {
+            let tape = Tape::new((cols, 24), true);
+            let mut menus = scripted_on(tape, &[Key::Char('\n')]);
+            menus.select_menu(items.iter(), "Apps", ">", None).unwrap();
+            let shown = &menus.keys.frames[0];
+            // nothing wrapped onto a line of its own
+            assert!(shown[1].starts_with("> com.goog"), "{shown:?}");
+            assert_eq!(shown[2], "short");
+            for line in shown {
+                assert!(display_width(line) <= cols as usize, "{line:?} at {cols}");
+            }
+            let cut = shown[1].contains('…');
+            assert_eq!(cut, cols < 50, "{shown:?}");
+            assert!(shown[1].ends_with("premium"), "{shown:?}");
+        }
+    }
+
+    #[test]
+    fn a_start_past_the_end_is_clamped_to_the_last_item() {
+        for (at, expected) in [(1, 1), (2, 2), (3, 2), (usize::MAX, 2)] {
+            let mut menus = scripted(&[Key::Char('\n')]);
+            let picked = menus.select_menu_at(FRUITS.iter(), at, None, "Fruits", ">", None);
+            assert_eq!(picked.unwrap().picked(), Some(expected), "at {at}");
+        }
+        let keys = [Key::Char('\n')];
+        let mut menus = scripted(&keys);
+        let picked =
+            menus.select_menu_with_input(|_| FRUITS.to_vec(), |_| 0, None, ">", "", None, &[], 7);
+        assert_eq!(picked.unwrap(), Some("cherry"));
+    }
+
+    #[test]
+    fn a_kept_place_is_clamped_when_the_list_shrank() {
+        let mut menus = scripted(&[Key::End, Key::Esc, Key::Char('\n')]);
+        let all = ["a", "b", "c", "d", "e"];
+        let left = menus.select_menu_kept("apps", all.iter(), None, "Apps", ">", None);
+        // left without a pick, the place is still kept
+        let left = left.unwrap();
+        assert_eq!((left.picked(), left.index()), (None, 4));
+        let shrunk = menus.select_menu_kept("apps", all[..2].iter(), None, "Apps", ">", None);
+        assert_eq!(shrunk.unwrap().picked(), Some(1));
+    }
+
+    #[test]
+    fn a_terminal_that_never_answers_times_out() {
+        let (mut tty, _answers) = io::pipe().unwrap();
+        let mut out = Vec::new();
+        let err = query_cursor_on(&mut out, &mut tty).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(out, b"\x1b[6n");
+    }
+
+    #[test]
+    fn the_cursor_is_read_from_the_answer() {
+        let (mut tty, mut answers) = io::pipe().unwrap();
+        // a key typed just before the answer is skipped
+        answers.write_all(b"x\x1b[12;34R").unwrap();
+        assert_eq!(
+            query_cursor_on(&mut Vec::new(), &mut tty).unwrap(),
+            (34, 12)
+        );
+        answers.write_all(b"\x1b[12R").unwrap();
+        drop(answers);
+        let err = query_cursor_on(&mut Vec::new(), &mut tty).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn the_measured_size_comes_first() {
+        let env = |s: &str| Some(s.to_string());
+        let measured = Ok((120, 40));
+        assert_eq!(
+            pick_size(measured, env("100"), env("30")),
+            ((120, 40), true)
+        );
+    }
+
+    #[test]
+    fn columns_and_lines_when_the_size_is_not_measured() {
+        let env = |s: &str| Some(s.to_string());
+        let failed = || Err(io::Error::other("not a terminal"));
+        assert_eq!(
+            pick_size(failed(), env("100"), env("30")),
+            ((100, 30), false)
+        );
+        // as some adb shells say
+        assert_eq!(
+            pick_size(Ok((0, 0)), env(" 132 "), env("50")),
+            ((132, 50), false)
+        );
+        assert_eq!(pick_size(Ok((90, 0)), env("100"), None), ((100, 24), false));
+    }
+
+    #[test]
+    fn a_default_when_nothing_tells_the_size() {
+        let env = |s: &str| Some(s.to_string());
+        let failed = || Err(io::Error::other("not a terminal"));
+        assert_eq!(pick_size(failed(), None, None), ((80, 24), false));
+        assert_eq!(
+            pick_size(failed(), env("0"), env("wide")),
+            ((80, 24), false)
+        );
+        assert_eq!(pick_size(failed(), env("-5"), env("")), ((80, 24), false));
+    }
+
+    fn sections() -> Vec<Row<&'static str>> {
+        vec![
+            Row::Header("Fruits".into()),
+            Row::Header("Sweet".into()),
+            Row::Item("apple"),
+            Row::Item("banana"),
+            Row::Separator,
+            Row::Header("Sour".into()),
+            Row::Header("Citrus".into()),
+            Row::Item("lemon"),
+        ]
+    }
+
+    #[test]
+    fn a_header_first_leaves_the_first_item_highlighted() {
+        let mut menus = scripted(&ENTERS[..1]);
+        let picked = menus
+            .select_rows_at(sections().into_iter(), 0, None, "Rows", ">", None)
+            .unwrap();
+        assert_eq!(picked.picked(), Some(0));
+        assert_eq!(
+            menus.keys.frames[0][..7],
+            [
+                "Rows",
+                "Fruits",
+                "Sweet",
+                "> apple",
+                "banana",
+                "─".repeat(80).as_str(),
+                "Sour"
+            ]
+        );
+    }
+
+    #[test]
+    fn moving_skips_headers_one_after_another() {
+        let mut menus = scripted(&[Key::Down, Key::Down, Key::Char('\n')]);
+        let picked = menus
+            .select_rows_at(sections().into_iter(), 0, None, "Rows", ">", None)
+            .unwrap();
+        assert_eq!(picked.picked(), Some(2));
+        assert!(menus.keys.frames[2].contains(&"> lemon".to_string()));
+        let mut menus = scripted(&[Key::Up, Key::Char('\n')]);
+        let picked = menus
+            .select_rows_at(sections().into_iter(), 2, None, "Rows", ">", None)
+            .unwrap();
+        assert_eq!(picked.picked(), Some(1));
+    }
+
+    #[test]
+    fn headers_only_cannot_be_picked() {
+        let rows = [Row::<&str>::Header("Nothing".into()), Row::Separator];
+        let mut menus = scripted(&[Key::Char('\n'), Key::Esc]);
+        let picked = menus
+            .select_rows_at(rows.into_iter(), 0, None, "Rows", ">", None)
+            .unwrap();
+        assert!(picked.picked().is_none());
+    }
+
+    /// An 80x24 tape with two lines of a shell already on it
+    fn shell(answers: bool) -> Tape {
+        let mut tape = Tape::new((80, 24), answers);
+        write!(tape, "$ detach\r\nstarting\r\n").unwrap();
+        tape
+    }
+
+    #[test]
+    fn select_menu_draws_below_the_cursor_without_asking_where_it_is() {
+        for answers in [true, false] {
+            let tape = shell(answers);
+            let mut menus = scripted_on(tape.clone(), &[Key::Down, Key::Char('\n')]);
+            let picked = menus.select_menu(FRUITS.iter(), "Fruits", ">", None);
+            assert_eq!(picked.unwrap(), Some(1));
+            assert_eq!(tape.asked.get(), 0);
+            assert_eq!(
+                menus.keys.frames[1][..7],
+                ["$ detach", "starting", "Fruits", "apple", "> banana", "cherry", ""]
+            );
+            assert!(!tape.output().contains("\x1b[2J"));
+            // the menu and the row above it, where toasts go, are cleared after
+            assert_eq!(tape.screen()[..3], ["$ detach", "", ""]);
+        }
+    }
+
+    #[test]
+    fn keys_listed_over_a_relative_menu_are_cleared_for_it() {
+        let tape = shell(false);
+        let mut menus = scripted_on(tape.clone(), &[Key::Char('?'), Key::Esc, Key::Esc]);
+        let picked = menus.select_menu(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(picked.unwrap(), None);
+        let frames = &menus.keys.frames;
+        assert_eq!(frames[1][..3], ["$ detach", "starting", "Keys"]);
+        assert!(frames[1][3].starts_with("Enter "));
+        assert!(frames[1].iter().any(|l| l.contains("any key closes")));
+        assert_eq!(frames[2], frames[0]);
+        assert_eq!(tape.asked.get(), 0);
+    }
+
+    #[test]
+    fn a_relative_menu_asks_where_it_is_only_for_a_click() {
+        let click = || Event::Mouse(MouseEvent::Press(MouseButton::Left, 3, 5));
+        let events = || VecDeque::from([click(), click(), Event::Key(Key::Esc)]);
+        let tape = shell(true);
+        let mut menus = scripted_events(tape.clone(), events());
+        let picked = menus.select_menu(FRUITS.iter(), "Fruits", ">", None);
+        // the title is on row 3, so row 5 has banana, the second click picks it
+        assert_eq!(picked.unwrap(), Some(1));
+        assert_eq!(tape.asked.get(), 2);
+        // without an answer the clicks do nothing, and the terminal is asked once
+        let tape = shell(false);
+        let mut menus = scripted_events(tape.clone(), events());
+        let picked = menus.select_menu(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(picked.unwrap(), None);
+        assert_eq!(tape.asked.get(), 1);
+    }
+
+    #[test]
+    fn select_menu_starts_from_the_top_when_the_size_is_a_guess() {
+        let tape = shell(true).size_guessed();
+        let mut menus = scripted_on(tape.clone(), &[Key::Char('\n')]);
+        let picked = menus.select_menu(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(picked.unwrap(), Some(0));
+        assert_eq!(menus.keys.frames[0][..2], ["Fruits", "> apple"]);
+        assert_eq!(tape.asked.get(), 0);
+    }
+
+    #[test]
+    fn other_menus_fall_back_to_the_top_without_an_answer() {
+        let tape = shell(false);
+        let mut menus = scripted_on(tape.clone(), &[Key::Char('\n'), Key::Char('\n')]);
+        let checked = menus.select_menu_multi(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(checked.unwrap(), Some(vec![]));
+        assert_eq!(menus.keys.frames[0][0], "Fruits");
+        assert!(tape.output().contains("\x1b[2J"));
+        // the next menu is not held up asking again
+        let picked = menus.select_menu(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(picked.unwrap(), Some(0));
+        assert_eq!(tape.asked.get(), 1);
+        // and with an answer they stay where the cursor is
+        let tape = shell(true);
+        let mut menus = scripted_on(tape.clone(), &[Key::Char('\n')]);
+        let checked = menus.select_menu_multi(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(checked.unwrap(), Some(vec![]));
+        assert_eq!(
+            menus.keys.frames[0][..3],
+            ["$ detach", "starting", "Fruits"]
+        );
+    }
+
+    fn multi(keys: &[Key]) -> (Option<Vec<usize>>, Scripted) {
+        let mut menus = scripted(keys);
+        let checked = menus.select_menu_multi(FRUITS.iter(), "Fruits", ">", None);
+        (checked.unwrap(), menus)
+    }
+
+    #[test]
+    fn typing_narrows_the_multi_select_list() {
+        // the letters of other keys go to it while it is typed
+        let keys = [typed("ban"), vec![Key::Char(' '), Key::Char('\n')]].concat();
+        let (checked, menus) = multi(&keys);
+        assert_eq!(checked, Some(vec![1]));
+        let frame = &menus.keys.frames[3];
+        assert!(frame[0].starts_with("Fruits [ban] 1/3"), "{frame:?}");
+        assert!(frame[1].ends_with("banana"), "{frame:?}");
+        assert!(!frame
+            .iter()
+            .any(|l| l.contains("apple") || l.contains("cherry")));
+        // nothing left to check
+        let (checked, _) = multi(&[typed("x"), vec![Key::Char(' '), Key::Char('\n')]].concat());
+        assert_eq!(checked, Some(vec![]));
+        let (checked, _) = multi(&[
+            Key::Char('x'),
+            Key::Backspace,
+            Key::Down,
+            Key::Char(' '),
+            Key::Char('\n'),
+        ]);
+        assert_eq!(checked, Some(vec![1]));
+    }
+
+    #[test]
+    fn check_all_and_invert_take_the_items_shown() {
+        // an arrow ends the typing, `a` and `i` are keys again
+        let checked = |key| multi(&[Key::Char('e'), Key::Down, key, Key::Char('\n')]).0;
+        assert_eq!(checked(Key::Char('a')), Some(vec![0, 2]));
+        assert_eq!(checked(Key::Char('i')), Some(vec![0, 2]));
+        // ESC shows all again, and leaves only once nothing is typed
+        let (checked, _) = multi(&[Key::Char('e'), Key::Esc, Key::Char('a'), Key::Char('\n')]);
+        assert_eq!(checked, Some(vec![0, 1, 2]));
+        let (checked, _) = multi(&[Key::Char('e'), Key::Esc, Key::Esc]);
+        assert_eq!(checked, None);
+    }
+
+    #[test]
+    fn bulk_gets_the_items_shown_and_the_list_reopens_narrowed() {
+        let keys = [
+            Key::Char('e'),
+            Key::Down,
+            Key::Char('*'),
+            Key::Char('a'),
+            Key::Char('\n'),
+        ];
+        let mut menus = scripted(&keys);
+        menus.set_bulk(true);
+        let checked = vec![false; FRUITS.len()];
+        let left = menus.select_menu_multi_at(FRUITS.iter(), checked, 0, None, "Fruits", ">", None);
+        assert!(
+            matches!(left.unwrap(), Checked::Bulk(2, _, ref shown) if shown == &[0, 2]),
+            "not left with the items shown"
+        );
+        let checked = vec![false; FRUITS.len()];
+        let left = menus.select_menu_multi_at(FRUITS.iter(), checked, 0, None, "Fruits", ">", None);
+        assert!(matches!(left.unwrap(), Checked::Done(picked) if picked == [0, 2]));
+        assert!(menus.keys.frames[3][0].starts_with("Fruits [e] 2/3"));
+        // without `*` it opens showing all
+        let mut menus = scripted(&[Key::Char('a'), Key::Char('\n')]);
+        menus.bulk_narrow = "e".to_string();
+        let checked = menus.select_menu_multi(FRUITS.iter(), "Fruits", ">", None);
+        assert_eq!(checked.unwrap(), Some(vec![0, 1, 2]));
+    }
 }
@@ -0,0 +1,109 @@
+//! When and why each app was detached, kept next to the config since detach.bin only
+//! holds the package names. One JSON object from package to
+//! `{"added_at": <unix time>, "note": "..."}`, where apps detached before it was kept
+//! are simply missing
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::json::{self, ToJson};
+use crate::{atomic, config, dry_run, journal};
+
+#[cfg(target_os = "android")]
+const META: &str = "/sdcard/zygisk-detach/meta.json";
+#[cfg(target_os = "linux")]
+const META: &str = "zygisk-detach-meta.json";
+
+#[derive(Clone, Default)]
+pub struct Meta {
+    /// Unix time the app was detached at
+    pub added_at: Option<u64>,
+    pub note: Option<String>,
+}
+
+/// Every app's metadata, none when the file is missing
+pub fn load() -> io::Result<BTreeMap<String, Meta>> {
+    let txt = match fs::read_to_string(META) {
+        Ok(txt) => txt,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    let Some(json::Value::Object(apps)) = json::parse(&txt) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{META} is not a JSON object"),
+        ));
+    };
+    Ok(apps
+        .into_iter()
+        .map(|(app, meta)| {
+            let meta = Meta {
+                added_at: meta.get("added_at").and_then(json::Value::as_u64),
+                note: meta
+                    .get("note")
+                    .and_then(json::Value::as_str)
+                    .map(str::to_string),
+            };
+            (app, meta)
+        })
+        .collect())
+}
+
+fn save(apps: &BTreeMap<String, Meta>) -> io::Result<()> {
+    let fields: String = apps
+        .iter()
+        .map(|(app, meta)| {
+            let meta = json::Object::default()
+                .field("added_at", meta.added_at)
+                .field("note", meta.note.as_deref());
+            format!("  {}: {}", app.to_json(), meta.to_json())
+        })
+        .intersperse(",\n".to_string())
+        .collect();
+    config::create_parent(META)?;
+    atomic::write(META, format!("{{\n{fields}\n}}\n").as_bytes())
+}
+
+/// Notes that the apps detach.bin gained going from `before` to `after` were detached
+/// now, and drops the ones it lost along with their notes
+pub fn update(before: &[u8], after: &[u8]) -> io::Result<()> {
+    if dry_run::enabled() {
+        return Ok(());
+    }
+    let (detached, reattached) = journal::diff(before, after);
+    if detached.is_empty() && reattached.is_empty() {
+        return Ok(());
+    }
+    let mut apps = load().unwrap_or_default();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    for app in detached {
+        apps.entry(app).or_default().added_at = Some(now);
+    }
+    for app in &reattached {
+        apps.remove(app);
+    }
+    save(&apps)
+}
+
+/// Sets the note of `app`, an empty one removes it
+pub fn set_note(app: &str, note: &str) -> io::Result<()> {
+    let mut apps = load()?;
+    let note = Some(note.trim()).filter(|n| !n.is_empty());
+    apps.entry(app.to_string()).or_default().note = note.map(str::to_string);
+    save(&apps)
+}
+
+/// When each app was detached, from the metadata or else as far as the journal goes back
+pub fn detached_times() -> HashMap<String, u64> {
+    let mut times = journal::detached_times();
+    for (app, meta) in load().unwrap_or_default() {
+        if let Some(time) = meta.added_at {
+            times.insert(app, time);
+        }
+    }
+    times
+}
@@ -0,0 +1,110 @@
+//! `pm list packages` takes seconds on older devices, so the menus keep the listings
+//! in memory for the run and on disk for a short while. Apps installed or removed
+//! meanwhile show up after a refresh from the main menu
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::atomic;
+use crate::users::{self, Scope};
+
+#[cfg(target_os = "android")]
+pub const PACKAGES_CACHE: &str = "/data/adb/zygisk-detach/packages.cache";
+#[cfg(target_os = "linux")]
+const PACKAGES_CACHE: &str = "packages.cache";
+
+/// Seconds a listing on disk is trusted
+const TTL: u64 = 120;
+
+type Cache = BTreeMap<String, (u64, Vec<u8>)>;
+
+/// key -> (unix time of the listing, raw `pm` output), `None` until the file is read
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Which listing `flags` under the current user scope is. Listings over all users
+/// also record where each package was found, so they are not cached
+fn key(flags: &[&str]) -> Option<String> {
+    let user = match users::scope() {
+        Scope::Current => "current".to_string(),
+        Scope::One(id) => id.to_string(),
+        Scope::All => return None,
+    };
+    Some(format!("{user} {}", flags.join(" ")))
+}
+
+/// `<key>\t<time>\t<package> <package>...` lines, the fields some flags add after a
+/// package are joined to it with `,`
+fn parse_cache(content: &str) -> Cache {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let key = fields.next()?;
+            let time = fields.next()?.parse().ok()?;
+            let mut out = Vec::new();
+            for pkg in fields.next()?.split(' ').filter(|p| !p.is_empty()) {
+                let line = pkg.replace(',', " ");
+                out.extend_from_slice(format!("package:{line}\n").as_bytes());
+            }
+            Some((key.to_string(), (time, out)))
+        })
+        .collect()
+}
+
+fn serialize_cache(cache: &Cache) -> String {
+    let mut out = String::new();
+    for (key, (time, raw)) in cache {
+        let pkgs: String = String::from_utf8_lossy(raw)
+            .lines()
+            .filter_map(|l| l.strip_prefix("package:"))
+            .map(|l| l.split_whitespace().intersperse(",").collect::<String>())
+            .filter(|l| !l.is_empty())
+            .intersperse(" ".to_string())
+            .collect();
+        out.push_str(&format!("{key}\t{time}\t{pkgs}\n"));
+    }
+    out
+}
+
+/// The listings of this run, plus those on disk that were still fresh when it started
+fn with_cache<T>(f: impl FnOnce(&mut Cache) -> T) -> T {
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(|| {
+        let mut cache = fs::read_to_string(PACKAGES_CACHE)
+            .map(|c| parse_cache(&c))
+            .unwrap_or_default();
+        let now = now();
+        cache.retain(|_, (time, _)| now.saturating_sub(*time) < TTL);
+        cache
+    });
+    f(cache)
+}
+
+pub fn get(flags: &[&str]) -> Option<Vec<u8>> {
+    let key = key(flags)?;
+    with_cache(|cache| cache.get(&key).map(|(_, raw)| raw.clone()))
+}
+
+pub fn put(flags: &[&str], raw: &[u8]) {
+    let Some(key) = key(flags) else {
+        return;
+    };
+    with_cache(|cache| {
+        cache.insert(key, (now(), raw.to_vec()));
+        let _ = atomic::write(PACKAGES_CACHE, serialize_cache(cache).as_bytes());
+    });
+}
+
+/// Forgets every listing, so the next one asks `pm` again
+pub fn invalidate() {
+    *CACHE.lock().unwrap() = Some(Cache::new());
+    let _ = fs::remove_file(PACKAGES_CACHE);
+}
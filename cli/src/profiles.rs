@@ -0,0 +1,107 @@
+//! Named detach lists to switch between, one text file each with a package per line.
+//! The active one is whatever detach.bin holds, its file is brought up to date when
+//! switching away from it
+
+use std::fs;
+use std::io;
+
+use crate::config::{self, Config};
+use crate::{dry_run, parse_txt};
+
+#[cfg(target_os = "android")]
+const PROFILES_DIR: &str = "/sdcard/zygisk-detach/profiles";
+#[cfg(target_os = "linux")]
+const PROFILES_DIR: &str = "profiles";
+
+/// The profile detach.bin belongs to before any other is created
+pub const DEFAULT: &str = "default";
+
+fn path(name: &str) -> String {
+    format!("{PROFILES_DIR}/{name}.txt")
+}
+
+/// Letters, digits, `-`, `_` and `.`, so the name is a file name on any filesystem
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+pub fn active() -> io::Result<String> {
+    Ok(Config::load()?.profile)
+}
+
+pub fn set_active(name: &str) -> io::Result<()> {
+    config::set("profile", name)
+}
+
+/// The saved profiles plus the active one, sorted
+pub fn list() -> io::Result<Vec<String>> {
+    let mut names = match fs::read_dir(PROFILES_DIR) {
+        Ok(dir) => dir
+            .filter_map(|e| e.ok())
+            .filter_map(|e| {
+                let name = e.file_name().into_string().ok()?;
+                name.strip_suffix(".txt").map(str::to_string)
+            })
+            .filter(|name| valid_name(name))
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let active = active()?;
+    if !names.contains(&active) {
+        names.push(active);
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Whether there is more to pick from than the default profile
+pub fn in_use() -> io::Result<bool> {
+    Ok(list()? != [DEFAULT])
+}
+
+pub fn exists(name: &str) -> io::Result<bool> {
+    Ok(list()?.iter().any(|n| n == name))
+}
+
+/// The apps of profile `name`, none for one that was never saved
+pub fn load(name: &str) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path(name)) {
+        Ok(txt) => Ok(parse_txt(&txt).map(str::to_string).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn save(name: &str, apps: &[&str]) -> io::Result<()> {
+    let mut txt = format!("# zygisk-detach profile {name}\n");
+    for app in apps {
+        txt.push_str(app);
+        txt.push('\n');
+    }
+    config::create_parent(&path(name))?;
+    dry_run::write_local(&path(name), txt.as_bytes())
+}
+
+pub fn delete(name: &str) -> io::Result<()> {
+    match dry_run::remove_local(&path(name)) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        r => r,
+    }
+}
+
+/// Moves profile `old` to `new`, following it with the active profile
+pub fn rename(old: &str, new: &str) -> io::Result<()> {
+    let apps = load(old)?;
+    save(new, &apps.iter().map(String::as_str).collect::<Vec<_>>())?;
+    delete(old)?;
+    if active()? == old {
+        set_active(new)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+//! How far a batch over many apps got, as `detach --all`, import or prune. The menus
+//! draw it as a bar on the bottom row, elsewhere a `123/800 com.foo.bar` line goes to
+//! stderr every few seconds. Ctrl+C stops the batch after the app it is on
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use detach_core::log;
+
+/// Batches done sooner show nothing
+const QUIET_FOR: Duration = Duration::from_secs(1);
+/// How often the bar is drawn again, and how often a line is printed without it
+const BAR_EVERY: Duration = Duration::from_millis(200);
+const LINE_EVERY: Duration = Duration::from_secs(3);
+/// Cells of the bar
+const BAR_WIDTH: usize = 20;
+
+/// Draws a line of progress, `None` clears it again. Set by the menus, see [`on_draw`]
+type Draw = Box<dyn Fn(Option<&str>) + Send>;
+
+static DRAW: Mutex<Option<(ThreadId, Draw)>> = Mutex::new(None);
+
+fn draw() -> std::sync::MutexGuard<'static, Option<(ThreadId, Draw)>> {
+    DRAW.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Has batches run on this thread drawn by `f` instead of printing lines
+pub fn on_draw(f: impl Fn(Option<&str>) + Send + 'static) {
+    *draw() = Some((thread::current().id(), Box::new(f)));
+}
+
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    STOPPED.store(true, Ordering::Relaxed);
+}
+
+/// Catches Ctrl+C while alive. Raw mode turns it into a key nobody reads during a
+/// batch, so it is made a signal again meanwhile
+struct Interrupts {
+    old: libc::sigaction,
+    termios: Option<libc::termios>,
+}
+
+impl Interrupts {
+    fn catch() -> Self {
+        STOPPED.store(false, Ordering::Relaxed);
+        let mut old: libc::sigaction = unsafe { std::mem::zeroed() };
+        unsafe {
+            let mut sa: libc::sigaction = std::mem::zeroed();
+            sa.sa_sigaction = on_interrupt as *const () as libc::sighandler_t;
+            libc::sigaction(libc::SIGINT, &sa, &mut old);
+        }
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        let raw = unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) } == 0
+            && termios.c_lflag & libc::ISIG == 0;
+        let termios = raw.then(|| {
+            let mut isig = termios;
+            isig.c_lflag |= libc::ISIG;
+            unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &isig) };
+            termios
+        });
+        Self { old, termios }
+    }
+}
+
+impl Drop for Interrupts {
+    fn drop(&mut self) {
+        if let Some(termios) = &self.termios {
+            unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, termios) };
+        }
+        unsafe { libc::sigaction(libc::SIGINT, &self.old, std::ptr::null_mut()) };
+    }
+}
+
+/// A batch Ctrl+C stopped, and how far it got
+pub struct Stopped {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl Display for Stopped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stopped after {} of {}", self.done, self.total)
+    }
+}
+
+pub struct Progress {
+    total: usize,
+    done: usize,
+    start: Instant,
+    shown: Option<Instant>,
+    /// Lines go to the log instead of the screen and Ctrl+C is left alone
+    logged: bool,
+    drawn: bool,
+    interrupts: Option<Interrupts>,
+}
+
+impl Progress {
+    /// A batch of `total` items shown on the screen, Ctrl+C is caught until it is dropped
+    pub fn new(total: usize) -> Self {
+        Self::with(total, false, Some(Interrupts::catch()))
+    }
+
+    /// A batch that only writes how far it got to the log, as the labels looked up in
+    /// the background
+    pub fn logged(total: usize) -> Self {
+        Self::with(total, true, None)
+    }
+
+    fn with(total: usize, logged: bool, interrupts: Option<Interrupts>) -> Self {
+        Self {
+            total,
+            done: 0,
+            start: Instant::now(),
+            shown: None,
+            logged,
+            drawn: false,
+            interrupts,
+        }
+    }
+
+    /// Counts `n` items done, `item` being the last of them
+    pub fn advance(&mut self, n: usize, item: &str) {
+        self.done = (self.done + n).min(self.total);
+        let now = Instant::now();
+        if now - self.start < QUIET_FOR {
+            return;
+        }
+        let draw = draw();
+        let bar = (draw.as_ref())
+            .filter(|(thread, _)| !self.logged && *thread == thread::current().id())
+            .map(|(_, f)| f);
+        let every = if bar.is_some() { BAR_EVERY } else { LINE_EVERY };
+        if self.shown.is_some_and(|shown| now - shown < every) && self.done < self.total {
+            return;
+        }
+        self.shown = Some(now);
+        let count = format!("{}/{} {item}", self.done, self.total);
+        match bar {
+            Some(f) => {
+                let filled = self.done * BAR_WIDTH / self.total.max(1);
+                f(Some(&format!(
+                    "[{}{}] {}% {item}{}",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    self.done * 100 / self.total.max(1),
+                    self.eta()
+                        .map_or(String::new(), |eta| format!(" · {}s left", eta.as_secs()))
+                )));
+                self.drawn = true;
+            }
+            None if self.logged => log::verbose(count),
+            None if log::level() > log::Level::Quiet => eprintln!("{count}"),
+            None => {}
+        }
+    }
+
+    /// One more item done, see [`Self::advance`]
+    pub fn step(&mut self, item: &str) {
+        self.advance(1, item);
+    }
+
+    fn eta(&self) -> Option<Duration> {
+        let elapsed = self.start.elapsed();
+        let left = self.total.checked_sub(self.done)?;
+        (self.done > 0).then(|| elapsed.mul_f64(left as f64 / self.done as f64))
+    }
+
+    /// Whether Ctrl+C was pressed, the item after the current one should not be started
+    pub fn stopped(&self) -> bool {
+        self.interrupts.is_some() && STOPPED.load(Ordering::Relaxed)
+    }
+
+    /// How far a batch that [`Self::stopped`] got
+    pub fn stop(&self) -> Option<Stopped> {
+        self.stopped().then_some(Stopped {
+            done: self.done,
+            total: self.total,
+        })
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.drawn {
+            if let Some((_, f)) = draw().as_ref() {
+                f(None);
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Reads the module's detach.bin back after each write. After an OTA the module
+//! directory can be left read-only or with an SELinux label that keeps writes from
+//! sticking, and the module then goes on with the old list without anyone noticing.
+//! What was written last is kept, so `doctor` can tell whether the module still has it
+
+use std::fs;
+use std::io;
+
+use detach_core::device::{self, DeviceFile, NotDetected};
+use detach_core::log;
+
+use crate::checks::Check;
+use crate::{atomic, config, custom_file, dry_run};
+
+#[cfg(target_os = "android")]
+const WRITTEN: &str = "/data/adb/zygisk-detach/written";
+#[cfg(target_os = "linux")]
+const WRITTEN: &str = "zygisk-detach-written";
+
+/// FNV-1a, the same from one version of the cli to the next
+fn hash(content: &[u8]) -> u64 {
+    (content.iter()).fold(0xcbf29ce484222325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Fails when `file` does not hold `content`
+fn verify(file: DeviceFile, content: &[u8]) -> io::Result<()> {
+    let read = device::get().read(file)?;
+    if hash(&read) != hash(content) {
+        return Err(io::Error::other(format!(
+            "read back {} bytes that are not the {} written",
+            read.len(),
+            content.len()
+        )));
+    }
+    Ok(())
+}
+
+fn write_verified(content: &[u8]) -> io::Result<()> {
+    device::get().write(DeviceFile::DetachBin, content)?;
+    verify(DeviceFile::DetachBin, content)
+}
+
+/// `err` naming the file, and why the module keeps the old list
+fn failed(path: &str, err: io::Error) -> io::Error {
+    let why = if err.raw_os_error() == Some(libc::EROFS) {
+        ", the module directory is mounted read-only"
+    } else {
+        ""
+    };
+    io::Error::new(
+        err.kind(),
+        format!("{path}: {err}{why}; the module still uses the list it had before"),
+    )
+}
+
+/// Writes `content` to the module's detach.bin and reads it back. A write that failed
+/// or did not stick is tried once more after giving the file its directory's label
+pub fn write(content: &[u8]) -> io::Result<()> {
+    let Err(err) = write_verified(content) else {
+        remember(content);
+        return Ok(());
+    };
+    // no module or a hung su, relabeling cannot help with either
+    if NotDetected::of(&err).is_some() || err.kind() == io::ErrorKind::TimedOut {
+        return Err(err);
+    }
+    let path = device::get().path(DeviceFile::DetachBin)?;
+    log::verbose(format_args!(
+        "{path}: {err}, relabeling it and trying again"
+    ));
+    let relabeled = device::get().relabel(DeviceFile::DetachBin);
+    match relabeled.and_then(|()| write_verified(content)) {
+        Ok(()) => {
+            remember(content);
+            Ok(())
+        }
+        Err(e) => {
+            log::verbose(format_args!("{path}: {e}"));
+            Err(failed(&path, err))
+        }
+    }
+}
+
+/// Keeps the hash and size of `content` as what the module was given last
+fn remember(content: &[u8]) {
+    if dry_run::enabled() || custom_file::foreign() {
+        return;
+    }
+    let written = format!("{:016x} {}\n", hash(content), content.len());
+    if let Err(e) =
+        config::create_parent(WRITTEN).and_then(|()| atomic::write(WRITTEN, written.as_bytes()))
+    {
+        log::verbose(format_args!("{WRITTEN}: {e}"));
+    }
+}
+
+/// Whether the module's detach.bin, read as `content`, is what the cli wrote last.
+/// `None` when nothing was written yet
+pub fn check(content: &[u8]) -> Option<Check> {
+    let written = fs::read_to_string(WRITTEN).ok()?;
+    let mut fields = written.split_whitespace();
+    let hash_written = u64::from_str_radix(fields.next()?, 16).ok()?;
+    let len: usize = fields.next()?.parse().ok()?;
+    Some(if hash(content) == hash_written {
+        Check::pass("detach.bin is what the cli wrote last")
+    } else {
+        Check::fail(format_args!(
+            "detach.bin ({} bytes) is not the {len} bytes the cli wrote last: the write did not stick or another program changed it since",
+            content.len()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_hash_stays_the_same() {
+        // FNV-1a of nothing and of "a"
+        assert_eq!(hash(b""), 0xcbf29ce484222325);
+        assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(hash(&[1, b'a', 0]), hash(&[1, 0, b'a']));
+    }
+
+    #[test]
+    fn a_failure_names_the_file_and_a_read_only_mount() {
+        let read_only = failed("/m/detach.bin", io::Error::from_raw_os_error(libc::EROFS));
+        let message = read_only.to_string();
+        assert!(message.starts_with("/m/detach.bin: "), "{message}");
+        assert!(message.contains("mounted read-only"), "{message}");
+        assert!(message.ends_with("the module still uses the list it had before"));
+        let denied = failed("/m/detach.bin", io::Error::from_raw_os_error(libc::EACCES));
+        assert_eq!(denied.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!denied.to_string().contains("read-only"));
+    }
+}
@@ -0,0 +1,189 @@
+//! The apps detached most often and most lately, so the detach menu can list them
+//! before anything is typed. One app per line:
+//! `<times detached>\t<unix time of the last>\t<package>`
+
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{atomic, config, dry_run, journal};
+
+#[cfg(target_os = "android")]
+const RECENT: &str = "/data/adb/zygisk-detach/recent.txt";
+#[cfg(target_os = "linux")]
+const RECENT: &str = "zygisk-detach-recent.txt";
+
+/// Apps remembered, the least used ones past it are forgotten
+const MAX: usize = 50;
+
+/// Seconds after which a detach counts half as much
+const HALF_LIFE: u64 = 30 * 24 * 60 * 60;
+
+struct Used {
+    app: String,
+    count: u32,
+    last: u64,
+}
+
+impl Used {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        let count = fields.next()?.parse().ok()?;
+        let last = fields.next()?.parse().ok()?;
+        let app = fields.next()?.trim();
+        (!app.is_empty()).then(|| Self {
+            app: app.to_string(),
+            count,
+            last,
+        })
+    }
+
+    /// How many times the app was detached, each counting less the longer ago
+    fn score(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last) as f64 / HALF_LIFE as f64;
+        self.count as f64 * 0.5f64.powf(age)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn load() -> Vec<Used> {
+    fs::read_to_string(RECENT)
+        .map(|txt| txt.lines().filter_map(Used::parse).collect())
+        .unwrap_or_default()
+}
+
+/// Most used first, the latest first among ones used as much
+fn rank(used: &mut [Used], now: u64) {
+    used.sort_by(|a, b| (b.score(now).total_cmp(&a.score(now))).then(b.last.cmp(&a.last)));
+}
+
+/// Counts `detached` as detached at `now`, forgetting the least used past [`MAX`]. An
+/// app seen for the first time goes ahead of the ones it ties with, so it is not the
+/// one forgotten right away
+fn record(used: &mut Vec<Used>, detached: Vec<String>, now: u64) {
+    for app in detached {
+        match used.iter_mut().find(|u| u.app == app) {
+            Some(u) => {
+                u.count += 1;
+                u.last = now;
+            }
+            None => used.insert(
+                0,
+                Used {
+                    app,
+                    count: 1,
+                    last: now,
+                },
+            ),
+        }
+    }
+    rank(used, now);
+    used.truncate(MAX);
+}
+
+/// Counts the apps detach.bin gained going from `before` to `after` as detached now
+pub fn update(before: &[u8], after: &[u8]) -> io::Result<()> {
+    if dry_run::enabled() {
+        return Ok(());
+    }
+    let (detached, _) = journal::diff(before, after);
+    if detached.is_empty() {
+        return Ok(());
+    }
+    let mut used = load();
+    record(&mut used, detached, now());
+    let txt: String = (used.iter())
+        .map(|u| format!("{}\t{}\t{}\n", u.count, u.last, u.app))
+        .collect();
+    config::create_parent(RECENT)?;
+    atomic::write(RECENT, txt.as_bytes())
+}
+
+/// The remembered apps, the most used first
+pub fn apps() -> Vec<String> {
+    let mut used = load();
+    rank(&mut used, now());
+    used.into_iter().map(|u| u.app).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn apps(used: &[Used]) -> Vec<&str> {
+        used.iter().map(|u| u.app.as_str()).collect()
+    }
+
+    fn detach(used: &mut Vec<Used>, apps: &[&str], now: u64) {
+        record(used, apps.iter().map(|a| a.to_string()).collect(), now);
+    }
+
+    #[test]
+    fn the_most_detached_come_first() {
+        let mut used = Vec::new();
+        detach(&mut used, &["com.a", "com.b"], 1000);
+        detach(&mut used, &["com.b"], 1000);
+        detach(&mut used, &["com.c"], 1000);
+        assert_eq!(apps(&used), ["com.b", "com.c", "com.a"]);
+        assert_eq!(used[0].count, 2);
+    }
+
+    #[test]
+    fn old_detaches_count_less() {
+        let mut used = Vec::new();
+        detach(&mut used, &["com.old"], 0);
+        detach(&mut used, &["com.old"], 0);
+        detach(&mut used, &["com.old"], 0);
+        // three detaches two half-lives ago weigh less than one now
+        detach(&mut used, &["com.new"], 60 * DAY);
+        assert_eq!(apps(&used), ["com.new", "com.old"]);
+        // and more than one nearly as old
+        detach(&mut used, &["com.older"], 1);
+        rank(&mut used, 60 * DAY);
+        assert_eq!(apps(&used), ["com.new", "com.old", "com.older"]);
+    }
+
+    #[test]
+    fn the_least_used_are_forgotten_past_the_cap() {
+        let mut used = Vec::new();
+        for i in 0..MAX as u64 {
+            detach(&mut used, &[&format!("com.app{i}")], i);
+        }
+        detach(&mut used, &["com.app0"], MAX as u64);
+        assert_eq!(used.len(), MAX);
+        // the one detached twice stays, the oldest detached once is dropped for the new
+        detach(&mut used, &["com.new"], MAX as u64);
+        assert_eq!(used.len(), MAX);
+        assert_eq!(apps(&used)[..2], ["com.app0", "com.new"]);
+        assert!(!apps(&used).contains(&"com.app1"));
+        assert!(apps(&used).contains(&"com.app2"));
+    }
+
+    #[test]
+    fn a_new_app_is_kept_over_ones_it_ties_with() {
+        let mut used = Vec::new();
+        for i in 0..MAX {
+            detach(&mut used, &[&format!("com.app{i}")], 1000);
+        }
+        detach(&mut used, &["com.new"], 1000);
+        assert_eq!(used.len(), MAX);
+        assert!(apps(&used).contains(&"com.new"));
+    }
+
+    #[test]
+    fn lines_that_do_not_parse_are_skipped() {
+        let used: Vec<Used> = "2\t1000\tcom.a\nx\t1000\tcom.b\n1\t1000\t\n1\t5\tcom.c d\n"
+            .lines()
+            .filter_map(Used::parse)
+            .collect();
+        assert_eq!(apps(&used), ["com.a", "com.c d"]);
+        assert_eq!((used[0].count, used[0].last), (2, 1000));
+    }
+}
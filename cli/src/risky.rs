@@ -0,0 +1,138 @@
+//! Packages the Play Store needs to work, which break it when detached. Writing a
+//! detach.bin that adds one takes `--force`, or typing yes in the menus. `risky` in the
+//! config adds packages to the list, `-name` takes one off it
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::{config, journal};
+
+/// Each with what detaching it breaks
+const BUILTIN: &[(&str, &str)] = &[
+    (
+        "com.android.vending",
+        "the Play Store stops updating itself and can fail to open or to install apps",
+    ),
+    (
+        "com.google.android.gms",
+        "Play Services stop being updated, apps signing in with Google or getting notifications through them break as they age",
+    ),
+    (
+        "com.google.android.gsf",
+        "the Services Framework the Play Store signs in with stops being updated",
+    ),
+];
+
+/// What detaching a package the config names breaks
+const FROM_CONFIG: &str = "the config marks it as one the phone needs updated";
+
+static FORCED: AtomicBool = AtomicBool::new(false);
+/// The packages typed yes for in the menus this run
+static ALLOWED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn allowed() -> std::sync::MutexGuard<'static, Vec<String>> {
+    ALLOWED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Lets every risky package through, as `--force`
+pub fn force() {
+    FORCED.store(true, Ordering::Relaxed);
+}
+
+/// Lets `apps` through for the rest of the run
+pub fn allow(apps: &[String]) {
+    allowed().extend(apps.iter().cloned());
+}
+
+/// The built-in packages with the config's `risky` applied, names separated by commas
+/// or spaces
+fn packages(extra: &str) -> Vec<String> {
+    let names: Vec<&str> = extra
+        .split([',', ' ', '\t'])
+        .filter(|n| !n.is_empty())
+        .collect();
+    let mut packages: Vec<String> = (BUILTIN.iter())
+        .map(|(pkg, _)| pkg.to_string())
+        .filter(|pkg| !names.iter().any(|n| n.strip_prefix('-') == Some(pkg)))
+        .collect();
+    for name in names.iter().filter(|n| !n.starts_with('-')) {
+        if !packages.iter().any(|p| p == name) {
+            packages.push(name.to_string());
+        }
+    }
+    packages
+}
+
+/// What detaching `pkg` breaks
+pub fn consequence(pkg: &str) -> &'static str {
+    (BUILTIN.iter())
+        .find(|(p, _)| *p == pkg)
+        .map_or(FROM_CONFIG, |(_, what)| what)
+}
+
+/// The risky packages `after` detaches that `before` did not, less the ones let through
+pub fn added(before: &[u8], after: &[u8]) -> Vec<String> {
+    if FORCED.load(Ordering::Relaxed) {
+        return Vec::new();
+    }
+    let (detached, _) = journal::diff(before, after);
+    if detached.is_empty() {
+        return Vec::new();
+    }
+    let extra = config::Config::load().map(|c| c.risky).unwrap_or_default();
+    let packages = packages(&extra);
+    let allowed = allowed();
+    (detached.into_iter())
+        .filter(|app| packages.contains(app) && !allowed.contains(app))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use detach_core::detach_bin;
+
+    fn bin(apps: &[&str]) -> Vec<u8> {
+        let mut bin = Vec::new();
+        for app in apps {
+            detach_bin::serialize(app, &mut bin).unwrap();
+        }
+        bin
+    }
+
+    #[test]
+    fn the_config_extends_and_takes_off_the_list() {
+        let builtin = [
+            "com.android.vending",
+            "com.google.android.gms",
+            "com.google.android.gsf",
+        ];
+        assert_eq!(packages(""), builtin);
+        assert_eq!(
+            packages("com.bank.app, com.android.vending\tcom.work"),
+            [&builtin[..], &["com.bank.app", "com.work"]].concat()
+        );
+        assert_eq!(
+            packages("-com.google.android.gms -com.google.android.gsf com.bank.app"),
+            ["com.android.vending", "com.bank.app"]
+        );
+        assert_eq!(consequence("com.bank.app"), FROM_CONFIG);
+        assert!(consequence("com.android.vending").contains("Play Store"));
+    }
+
+    #[test]
+    fn only_the_risky_packages_a_change_adds_are_held_up() {
+        let before = bin(&["com.app1"]);
+        let after = bin(&["com.app1", "com.android.vending", "org.xxx2"]);
+        assert_eq!(added(&before, &after), ["com.android.vending"]);
+        let both = bin(&["com.google.android.gms", "com.android.vending"]);
+        assert_eq!(
+            added(&before, &both),
+            ["com.google.android.gms", "com.android.vending"]
+        );
+        // already detached, taken off or not risky
+        assert!(added(&after, &after).is_empty());
+        assert!(added(&after, &before).is_empty());
+        assert!(added(&before, &bin(&["com.app1", "org.xxx2"])).is_empty());
+    }
+}
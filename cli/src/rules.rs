@@ -0,0 +1,164 @@
+//! Package name patterns for picking apps to detach automatically. The module only
+//! knows exact names, so the cli detaches whatever installed apps match
+
+use std::io;
+
+use crate::{dry_run, parse_txt};
+
+#[cfg(target_os = "android")]
+pub const RULES_TXT: &str = "/sdcard/zygisk-detach-rules.txt";
+#[cfg(target_os = "linux")]
+pub const RULES_TXT: &str = "zygisk-detach-rules.txt";
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of characters
+/// and `?` for exactly one
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    // where the last `*` was and how much of `name` it swallowed so far
+    let mut star = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Exact names and glob patterns, one per line with `#` comments
+#[derive(Default)]
+pub struct Rules(Vec<String>);
+
+impl Rules {
+    /// Reads the rules at `path`, a missing file has no rules
+    pub fn load(path: &str) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(txt) => Ok(Self(parse_txt(&txt).map(str::to_string).collect())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn matches(&self, pkg: &str) -> bool {
+        self.rule_for(pkg).is_some()
+    }
+
+    /// The first rule `pkg` matches
+    pub fn rule_for(&self, pkg: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .map(String::as_str)
+            .find(|rule| glob_match(rule, pkg))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+}
+
+/// Appends `rule` to the rules at `path`, false if it is already there
+pub fn add(path: &str, rule: &str) -> io::Result<bool> {
+    let mut txt = match std::fs::read_to_string(path) {
+        Ok(txt) => txt,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if parse_txt(&txt).any(|r| r == rule) {
+        return Ok(false);
+    }
+    if !txt.is_empty() && !txt.ends_with('\n') {
+        txt.push('\n');
+    }
+    txt.push_str(rule);
+    txt.push('\n');
+    dry_run::write_local(path, txt.as_bytes())?;
+    Ok(true)
+}
+
+/// Drops the lines holding `rule` from the rules at `path`, keeping the comments.
+/// False if it was not there
+pub fn remove(path: &str, rule: &str) -> io::Result<bool> {
+    let txt = match std::fs::read_to_string(path) {
+        Ok(txt) => txt,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let kept: Vec<&str> = txt
+        .lines()
+        .filter(|line| parse_txt(line).next() != Some(rule))
+        .collect();
+    if kept.len() == txt.lines().count() {
+        return Ok(false);
+    }
+    let mut out = kept.join("\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    dry_run::write_local(path, out.as_bytes())?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_run() {
+        assert!(glob_match("com.mycompany.*", "com.mycompany.app"));
+        assert!(glob_match("com.mycompany.*", "com.mycompany."));
+        assert!(glob_match("com.mycompany.*", "com.mycompany.a.b"));
+        assert!(!glob_match("com.mycompany.*", "com.mycompany"));
+        assert!(glob_match("*.maps", "com.google.android.maps"));
+        assert!(glob_match("com.*.maps", "com.google.maps"));
+        assert!(!glob_match("com.*.maps", "com.google.mapsx"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a**b", "ab"));
+        // the star gives back what the rest of the pattern needs
+        assert!(glob_match("*ab", "aab"));
+        assert!(glob_match("a*b*c", "a.b.b.c"));
+        assert!(!glob_match("a*b*c", "a.c.b"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_char() {
+        assert!(glob_match("app?", "app1"));
+        assert!(!glob_match("app?", "app"));
+        assert!(!glob_match("app?", "app12"));
+        assert!(glob_match("?pp", "äpp"));
+        assert!(glob_match("com.?.*", "com.x.y"));
+    }
+
+    #[test]
+    fn dots_are_literal() {
+        assert!(glob_match("com.app", "com.app"));
+        assert!(!glob_match("com.app", "comxapp"));
+        assert!(!glob_match("com.app", "com.app1"));
+        assert!(!glob_match("com.app", "com.ap"));
+        assert!(!glob_match("", "a"));
+    }
+
+    #[test]
+    fn first_matching_rule() {
+        let rules = Rules(vec!["com.exact".into(), "com.*".into(), "org.?".into()]);
+        assert_eq!(rules.rule_for("com.exact"), Some("com.exact"));
+        assert_eq!(rules.rule_for("com.other"), Some("com.*"));
+        assert_eq!(rules.rule_for("org.x"), Some("org.?"));
+        assert!(!rules.matches("org.xy"));
+        assert!(!Rules::default().matches("com.exact"));
+    }
+}
@@ -0,0 +1,203 @@
+//! The orders the app lists can be put in with 's'. Install and update times come from
+//! `dumpsys package`, read once per run and only when a list is first sorted by them
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::sync::Mutex;
+
+use detach_core::{parse_package_times, PackageTimes};
+
+use crate::device;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sort {
+    #[default]
+    Package,
+    Label,
+    /// The newest first
+    Installed,
+    /// The most recently updated first
+    Updated,
+}
+
+impl Sort {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Package => Self::Label,
+            Self::Label => Self::Installed,
+            Self::Installed => Self::Updated,
+            Self::Updated => Self::Package,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Package => "package",
+            Self::Label => "label",
+            Self::Installed => "install date",
+            Self::Updated => "last updated",
+        }
+    }
+
+    pub fn needs_times(self) -> bool {
+        matches!(self, Self::Installed | Self::Updated)
+    }
+}
+
+/// The sort each list was left in, so it is sorted the same when opened again
+static SORTS: Mutex<BTreeMap<&'static str, Sort>> = Mutex::new(BTreeMap::new());
+/// package -> times, `None` until they are first needed
+static TIMES: Mutex<Option<HashMap<String, PackageTimes>>> = Mutex::new(None);
+
+pub fn get(list: &'static str) -> Sort {
+    let sorts = SORTS.lock().unwrap_or_else(|e| e.into_inner());
+    sorts.get(list).copied().unwrap_or_default()
+}
+
+pub fn set(list: &'static str, sort: Sort) {
+    SORTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(list, sort);
+}
+
+/// The install and update times of every package, read from the device the first time
+pub fn times() -> io::Result<HashMap<String, PackageTimes>> {
+    let mut times = TIMES.lock().unwrap_or_else(|e| e.into_inner());
+    if times.is_none() {
+        *times = Some(parse_package_times(&device::get().dump_packages()?));
+    }
+    Ok(times.clone().unwrap_or_default())
+}
+
+/// Newest first, packages without a time last
+fn newest(a: Option<&String>, b: Option<&String>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => b.cmp(a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    }
+}
+
+/// The indices of `names` in the order of `sort`. Labels compare ignoring case, apps
+/// without one by their package name, and ties keep the order they came in
+pub fn order(
+    names: &[&str],
+    sort: Sort,
+    label: impl Fn(&str) -> Option<String>,
+    times: &HashMap<String, PackageTimes>,
+) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..names.len()).collect();
+    match sort {
+        Sort::Package => order.sort_by_key(|&i| names[i]),
+        Sort::Label => {
+            let keys: Vec<String> = names
+                .iter()
+                .map(|name| {
+                    label(name)
+                        .unwrap_or_else(|| name.to_string())
+                        .to_lowercase()
+                })
+                .collect();
+            order.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+        }
+        Sort::Installed | Sort::Updated => {
+            let time = |i: usize| {
+                let times = times.get(names[i])?;
+                match sort {
+                    Sort::Installed => times.installed.as_ref(),
+                    _ => times.updated.as_ref(),
+                }
+            };
+            order.sort_by(|&a, &b| newest(time(a), time(b)));
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(of: &[(&str, Option<&str>, Option<&str>)]) -> HashMap<String, PackageTimes> {
+        (of.iter())
+            .map(|&(pkg, installed, updated)| {
+                let times = PackageTimes {
+                    installed: installed.map(str::to_string),
+                    updated: updated.map(str::to_string),
+                };
+                (pkg.to_string(), times)
+            })
+            .collect()
+    }
+
+    fn no_label(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn by_package() {
+        let names = ["org.b", "com.c", "com.a"];
+        let order = order(&names, Sort::Package, no_label, &HashMap::new());
+        assert_eq!(order, [2, 1, 0]);
+    }
+
+    #[test]
+    fn by_label_ignoring_case() {
+        let names = ["com.x", "com.y", "com.z", "com.w"];
+        let label = |pkg: &str| match pkg {
+            "com.x" => Some("beta".to_string()),
+            "com.y" => Some("Alpha".to_string()),
+            "com.z" => Some("alpha".to_string()),
+            _ => None,
+        };
+        // the two alphas keep their order, an app without a label goes by its package
+        let order = order(&names, Sort::Label, label, &HashMap::new());
+        assert_eq!(order, [1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn by_time_newest_first() {
+        let names = ["a", "b", "c", "d"];
+        let times = times(&[
+            (
+                "a",
+                Some("2023-01-01 00:00:00"),
+                Some("2024-05-01 00:00:00"),
+            ),
+            ("b", Some("2024-01-01 00:00:00"), None),
+            ("c", None, Some("2024-06-01 00:00:00")),
+        ]);
+        // no time or not dumped at all go last, in their order
+        assert_eq!(
+            order(&names, Sort::Installed, no_label, &times),
+            [1, 0, 2, 3]
+        );
+        assert_eq!(order(&names, Sort::Updated, no_label, &times), [2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn ties_keep_their_order() {
+        let names = ["z", "y", "x", "w"];
+        let same = Some("2024-01-01 00:00:00");
+        let times = times(&[("z", same, same), ("y", same, same), ("w", same, same)]);
+        assert_eq!(
+            order(&names, Sort::Installed, no_label, &times),
+            [0, 1, 3, 2]
+        );
+        let label = |_: &str| Some("Same".to_string());
+        assert_eq!(order(&names, Sort::Label, label, &times), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn sorts_cycle_back_to_the_package() {
+        let mut sort = Sort::default();
+        let mut names = Vec::new();
+        for _ in 0..4 {
+            names.push(sort.name());
+            sort = sort.next();
+        }
+        assert!(sort == Sort::Package);
+        assert_eq!(names, ["package", "label", "install date", "last updated"]);
+    }
+}
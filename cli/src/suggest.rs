@@ -0,0 +1,99 @@
+//! Close matches for mistyped package names
+
+/// Names further than this many edits away are not suggested
+const MAX_DISTANCE: usize = 3;
+/// How many names are suggested at most
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The Levenshtein distance between `a` and `b`, or `None` once it is sure to exceed
+/// `cap`, so far off names cost about a row each
+pub fn distance(a: &str, b: &str, cap: usize) -> Option<usize> {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    if a.len().abs_diff(b.len()) > cap {
+        return None;
+    }
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut row = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != cb);
+            row[j + 1] = substitute.min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        // every later row is at least the smallest of this one
+        if row.iter().min().is_some_and(|&m| m > cap) {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    Some(prev[b.len()]).filter(|&d| d <= cap)
+}
+
+/// Up to three of `known` closest to `name`, the closest first and ties by name
+pub fn similar<'a>(name: &str, known: &[&'a str]) -> Vec<&'a str> {
+    let mut close: Vec<(usize, &str)> = known
+        .iter()
+        .filter(|k| **k != name)
+        .filter_map(|k| distance(name, k, MAX_DISTANCE).map(|d| (d, *k)))
+        .collect();
+    close.sort_unstable();
+    close.dedup();
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, k)| k)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        assert_eq!(distance("com.whatsap", "com.whatsapp", 3), Some(1));
+        assert_eq!(distance("kitten", "sitting", 3), Some(3));
+        assert_eq!(distance("", "abc", 3), Some(3));
+        assert_eq!(distance("same", "same", 0), Some(0));
+        assert_eq!(distance("ab", "ba", 3), Some(2));
+        assert_eq!(distance("日本", "日本語", 1), Some(1));
+    }
+
+    #[test]
+    fn distances_past_the_cap() {
+        assert_eq!(distance("kitten", "sitting", 2), None);
+        // too different in length to be looked at
+        assert_eq!(distance("a", "abcde", 3), None);
+        assert_eq!(distance("aaaaaaaa", "bbbbbbbb", 3), None);
+    }
+
+    #[test]
+    fn closest_first() {
+        let known = [
+            "com.whatsapp",
+            "com.whatsapp.w4b",
+            "com.chatapp",
+            "org.other",
+        ];
+        assert_eq!(
+            similar("com.whatsap", &known),
+            ["com.whatsapp", "com.chatapp"]
+        );
+        // the name itself is no suggestion
+        assert_eq!(similar("com.whatsapp", &known), ["com.chatapp"]);
+    }
+
+    #[test]
+    fn ties_by_name_and_at_most_three() {
+        let known = ["app.d", "app.c", "app.b", "app.a", "app.e"];
+        assert_eq!(similar("app.x", &known), ["app.a", "app.b", "app.c"]);
+        let known = ["app.a", "app.a"];
+        assert_eq!(similar("app.x", &known), ["app.a"]);
+    }
+
+    #[test]
+    fn nothing_to_suggest() {
+        assert!(similar("com.foo", &[]).is_empty());
+        assert!(similar("com.foo", &["org.completely.different"]).is_empty());
+    }
+}
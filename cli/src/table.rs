@@ -0,0 +1,150 @@
+//! Aligned columns for the app lists. Cells are measured by what they show, not by
+//! their escape codes, and the widest columns are cut first to fit the screen
+
+use std::fmt::Display;
+
+use crate::colorize::{display_width, truncate, ToColored};
+
+/// Spaces between two columns
+const GAP: usize = 2;
+/// Columns are not cut narrower than this, the line overflows instead
+const MIN_WIDTH: usize = 4;
+
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(header: &[&str]) -> Self {
+        Self {
+            header: header.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row, missing cells are left empty
+    pub fn row(&mut self, cells: impl IntoIterator<Item = impl Display>) {
+        let mut cells: Vec<String> = cells.into_iter().map(|c| c.to_string()).collect();
+        cells.resize(self.header.len(), String::new());
+        self.rows.push(cells);
+    }
+
+    /// How wide each column is drawn within `width` screen columns
+    fn widths(&self, width: usize) -> Vec<usize> {
+        let columns = self.header.len();
+        let mut widths: Vec<usize> = (0..columns)
+            .map(|c| {
+                std::iter::once(&self.header)
+                    .chain(&self.rows)
+                    .map(|row| display_width(&row[c]))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let fit = width.saturating_sub(GAP * columns.saturating_sub(1));
+        while widths.iter().sum::<usize>() > fit {
+            let Some(widest) = widths.iter_mut().max() else {
+                break;
+            };
+            if *widest <= MIN_WIDTH {
+                break;
+            }
+            *widest -= 1;
+        }
+        widths
+    }
+
+    fn line(cells: &[String], widths: &[usize]) -> String {
+        let mut line = String::new();
+        for (i, (cell, &width)) in cells.iter().zip(widths).enumerate() {
+            let cell = truncate(cell, width);
+            line.push_str(&cell);
+            // nothing trails the last column
+            if i + 1 < cells.len() {
+                let pad = width - display_width(&cell) + GAP;
+                line.push_str(&" ".repeat(pad));
+            }
+        }
+        line.trim_end().to_string()
+    }
+
+    /// The rows alone, aligned as `render` would, for lists that have a title instead
+    pub fn render_rows(&self, width: usize) -> Vec<String> {
+        let widths = self.widths(width);
+        self.rows.iter().map(|r| Self::line(r, &widths)).collect()
+    }
+
+    /// A bold header line, then a line per row with every other one dimmed
+    pub fn render(&self, width: usize) -> Vec<String> {
+        let widths = self.widths(width);
+        let mut lines = vec![Self::line(&self.header, &widths).bold().to_string()];
+        for (i, row) in self.rows.iter().enumerate() {
+            let line = Self::line(row, &widths);
+            lines.push(if i % 2 == 1 {
+                line.faint().to_string()
+            } else {
+                line
+            });
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colorize::strip_styles;
+
+    fn apps() -> Table {
+        let mut table = Table::new(&["package", "label", "installed", "installer", "added"]);
+        table.row([
+            "com.google.android.youtube",
+            "YouTube",
+            "yes",
+            "Play Store",
+            "2024-01-31",
+        ]);
+        table.row(["org.fdroid.fdroid", "F-Droid", "yes", "adb", "2024-02-01"]);
+        table.row(["com.whatsapp", "", "no"]);
+        table
+    }
+
+    #[test]
+    fn table_at_100_columns() {
+        assert_eq!(
+            apps().render(100),
+            [
+                "\x1b[1mpackage                     label    installed  installer   added\x1b[0m",
+                "com.google.android.youtube  YouTube  yes        Play Store  2024-01-31",
+                "\x1b[2morg.fdroid.fdroid           F-Droid  yes        adb         2024-02-01\x1b[0m",
+                "com.whatsapp                         no",
+            ]
+        );
+    }
+
+    #[test]
+    fn table_at_40_columns() {
+        let lines = apps().render(40);
+        assert_eq!(
+            lines.iter().map(|l| strip_styles(l)).collect::<Vec<_>>(),
+            [
+                "package  label    insta…  insta…  added",
+                "com.go…  YouTube  yes     Play …  2024-…",
+                "org.fd…  F-Droid  yes     adb     2024-…",
+                "com.wh…           no",
+            ]
+        );
+        assert!(lines.iter().all(|l| display_width(l) <= 40));
+    }
+
+    #[test]
+    fn escapes_take_no_room() {
+        let mut table = Table::new(&["a", "b"]);
+        table.row(["x".green().to_string(), "y".to_string()]);
+        table.row(["long".to_string(), "z".to_string()]);
+        let rows = table.render_rows(80);
+        assert_eq!(strip_styles(&rows[0]), "x     y");
+        assert_eq!(rows[1], "long  z");
+    }
+}
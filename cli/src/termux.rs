@@ -0,0 +1,138 @@
+//! Running from Termux, as root through `tsu` or not, where PATH and the home directory
+//! are Termux's. The cli's state is kept under /data/adb, the same for Termux, `su -c`
+//! and `adb shell`
+
+use std::fs;
+use std::path::Path;
+
+use detach_core::runner::SYSTEM_BIN;
+
+use crate::config;
+#[cfg(target_os = "android")]
+use crate::{labels, packages};
+
+/// Whether the cli was started from a Termux shell, which sets PREFIX to its own usr
+fn detected() -> bool {
+    is_termux(std::env::var("PREFIX").ok().as_deref())
+}
+
+fn is_termux(prefix: Option<&str>) -> bool {
+    prefix.is_some_and(|prefix| prefix.contains("/com.termux/"))
+}
+
+/// PATH with /system/bin first, so the tools Android has are found before Termux's
+/// and the children the cli starts find them too, or `None` when it already is first
+fn system_path(path: &str) -> Option<String> {
+    if path.split(':').next() == Some(SYSTEM_BIN) {
+        return None;
+    }
+    let rest = path
+        .split(':')
+        .filter(|dir| *dir != SYSTEM_BIN && !dir.is_empty());
+    Some(
+        std::iter::once(SYSTEM_BIN)
+            .chain(rest)
+            .collect::<Vec<_>>()
+            .join(":"),
+    )
+}
+
+/// Caches that were kept straight in /data/adb, and where they are now
+#[cfg(target_os = "android")]
+const MOVED: [(&str, &str); 2] = [
+    ("/data/adb/zygisk-detach-labels.cache", labels::LABELS_CACHE),
+    (
+        "/data/adb/zygisk-detach-packages.cache",
+        packages::PACKAGES_CACHE,
+    ),
+];
+/// The desktop build kept its state where it is from the start
+#[cfg(target_os = "linux")]
+const MOVED: [(&str, &str); 0] = [];
+
+/// Puts /system/bin first in PATH when running from Termux, before anything is run
+pub fn fix_path() {
+    if !detected() {
+        return;
+    }
+    let path = std::env::var("PATH").unwrap_or_default();
+    if let Some(path) = system_path(&path) {
+        std::env::set_var("PATH", path);
+    }
+}
+
+/// Makes the state directory and moves the state found where older versions kept it,
+/// unless it is there already. A move that fails leaves the old file for the next run
+pub fn migrate() {
+    move_state(&MOVED);
+}
+
+fn move_state(moved: &[(&str, &str)]) {
+    for &(old, new) in moved {
+        if config::create_parent(new).is_err() {
+            continue;
+        }
+        if Path::new(old).exists() && !Path::new(new).exists() {
+            let _ = fs::rename(old, new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn termux_is_told_by_its_prefix() {
+        assert!(is_termux(Some("/data/data/com.termux/files/usr")));
+        assert!(!is_termux(Some("/usr")));
+        assert!(!is_termux(Some("")));
+        assert!(!is_termux(None));
+    }
+
+    #[test]
+    fn system_bin_goes_first() {
+        let termux = "/data/data/com.termux/files/usr/bin";
+        assert_eq!(
+            system_path(&format!("{termux}:/system/bin:/system/xbin")).unwrap(),
+            format!("{SYSTEM_BIN}:{termux}:/system/xbin")
+        );
+        // tsu can leave it out altogether
+        assert_eq!(
+            system_path(termux).unwrap(),
+            format!("{SYSTEM_BIN}:{termux}")
+        );
+        assert_eq!(system_path("").unwrap(), SYSTEM_BIN);
+        assert_eq!(
+            system_path(&format!("{termux}::")).unwrap(),
+            format!("{SYSTEM_BIN}:{termux}")
+        );
+        assert_eq!(system_path(&format!("{SYSTEM_BIN}:{termux}")), None);
+    }
+
+    #[test]
+    fn state_is_moved_once() {
+        let dir = std::env::temp_dir().join(format!("detach-termux-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let (old, new) = (path("old.cache"), path("state/new.cache"));
+        let (kept, there) = (path("kept.cache"), path("state/there.cache"));
+        fs::write(&old, "old").unwrap();
+        fs::write(&kept, "older").unwrap();
+        fs::create_dir_all(dir.join("state")).unwrap();
+        fs::write(&there, "newer").unwrap();
+        move_state(&[
+            (&old, &new),
+            (&kept, &there),
+            (&path("none"), &path("state/none")),
+        ]);
+        assert_eq!(fs::read_to_string(&new).unwrap(), "old");
+        assert!(!Path::new(&old).exists());
+        // what the new place has already is not overwritten
+        assert_eq!(fs::read_to_string(&there).unwrap(), "newer");
+        assert!(Path::new(&kept).exists());
+        assert!(!Path::new(&path("state/none")).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+//! Android users and work profiles. detach.bin is shared by all of them, but each
+//! has its own set of installed packages
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io;
+use std::sync::{Mutex, OnceLock};
+
+use crate::{device, parse_installed_apps};
+
+pub struct User {
+    pub id: u32,
+    pub name: String,
+}
+
+impl Display for User {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (user {})", self.name, self.id)
+    }
+}
+
+/// `UserInfo{<id>:<name>:<flags>} running` lines from `pm list users`.
+/// The name may itself contain colons, the flags never do
+pub fn parse_users(out: &[u8]) -> Vec<User> {
+    String::from_utf8_lossy(out)
+        .lines()
+        .filter_map(|line| {
+            let info = line.trim().strip_prefix("UserInfo{")?;
+            let info = &info[..info.rfind('}')?];
+            let (id, rest) = info.split_once(':')?;
+            let name = rest.rsplit_once(':').map_or(rest, |(name, _)| name);
+            Some(User {
+                id: id.trim().parse().ok()?,
+                name: name.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Whose packages are listed
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Whatever `pm` defaults to, the current user
+    Current,
+    One(u32),
+    /// Every user, each package listed once
+    All,
+}
+
+impl Scope {
+    /// `all` or a user id
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "all" => Some(Self::All),
+            _ => s.parse().ok().map(Self::One),
+        }
+    }
+}
+
+static SCOPE: Mutex<Scope> = Mutex::new(Scope::Current);
+static USERS: OnceLock<Vec<User>> = OnceLock::new();
+/// package -> the users it was found under, from the last listing over all users
+static FOUND: Mutex<BTreeMap<String, Vec<u32>>> = Mutex::new(BTreeMap::new());
+
+pub fn scope() -> Scope {
+    *SCOPE.lock().unwrap()
+}
+
+pub fn set_scope(scope: Scope) {
+    *SCOPE.lock().unwrap() = scope;
+}
+
+/// The users on the device, asked for once
+pub fn users() -> io::Result<&'static [User]> {
+    if let Some(users) = USERS.get() {
+        return Ok(users);
+    }
+    let users = parse_users(&device::get().list_users()?);
+    Ok(USERS.get_or_init(|| users))
+}
+
+/// `pm list packages` output for the selected scope
+pub fn list_packages(flags: &[&str]) -> io::Result<Vec<u8>> {
+    match scope() {
+        Scope::Current => device::get().list_packages(flags),
+        Scope::One(id) => list_for(id, flags),
+        Scope::All => list_everywhere(flags),
+    }
+}
+
+fn list_for(id: u32, flags: &[&str]) -> io::Result<Vec<u8>> {
+    let id = id.to_string();
+    let mut flags = flags.to_vec();
+    flags.extend(["--user", &id]);
+    device::get().list_packages(&flags)
+}
+
+/// `pm list packages` output merged over every user, a package installed in several
+/// profiles listed once. Remembers where each was found for `found_under`
+pub fn list_everywhere(flags: &[&str]) -> io::Result<Vec<u8>> {
+    let users = users()?;
+    if users.is_empty() {
+        // an unparsable user list must not make every app look uninstalled
+        return device::get().list_packages(flags);
+    }
+    let mut found: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+    let mut out = Vec::new();
+    for user in users {
+        let listing = list_for(user.id, flags)?;
+        // whole lines are kept for the fields flags like `-i` add
+        for line in listing.split(|&b| b == b'\n') {
+            let Some(app) = parse_installed_apps(line).next() else {
+                continue;
+            };
+            let ids = found.entry(app.to_string()).or_default();
+            if ids.is_empty() {
+                out.extend_from_slice(line.trim_ascii());
+                out.push(b'\n');
+            }
+            ids.push(user.id);
+        }
+    }
+    *FOUND.lock().unwrap() = found;
+    Ok(out)
+}
+
+/// Which users `app` is installed for, when packages are listed over all of them
+pub fn found_under(app: &str) -> Option<String> {
+    if scope() != Scope::All {
+        return None;
+    }
+    let found = FOUND.lock().unwrap();
+    let ids = found.get(app)?;
+    let ids: String = ids
+        .iter()
+        .map(u32::to_string)
+        .intersperse(", ".to_string())
+        .collect();
+    Some(format!("[user {ids}]"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(out: &str) -> Vec<(u32, String)> {
+        (parse_users(out.as_bytes()).into_iter())
+            .map(|u| (u.id, u.name))
+            .collect()
+    }
+
+    #[test]
+    fn owner_and_work_profile() {
+        let out =
+            "Users:\n\tUserInfo{0:Owner:c13} running\n\tUserInfo{10:Work profile:30} running\n";
+        assert_eq!(
+            parsed(out),
+            [(0, "Owner".to_string()), (10, "Work profile".to_string())]
+        );
+    }
+
+    #[test]
+    fn name_with_colons() {
+        assert_eq!(
+            parsed("UserInfo{11:Work: personal:1030}"),
+            [(11, "Work: personal".to_string())]
+        );
+    }
+
+    #[test]
+    fn no_flags() {
+        assert_eq!(parsed("UserInfo{12:Guest}"), [(12, "Guest".to_string())]);
+    }
+
+    #[test]
+    fn unparsable_lines_skipped() {
+        let out = "Users:\nUserInfo{x:Nobody:0}\nUserInfo{13:Cut off\nUserInfo{}\n\nError: boom\n";
+        assert!(parsed(out).is_empty());
+    }
+
+    #[test]
+    fn user_displayed_with_id() {
+        let user = User {
+            id: 10,
+            name: "Work profile".to_string(),
+        };
+        assert_eq!(user.to_string(), "Work profile (user 10)");
+    }
+}
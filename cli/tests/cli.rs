@@ -0,0 +1,381 @@
+//! Runs the cli as a program. The desktop build keeps detach.bin, its backups and the
+//! rest of its files in the directory it runs in, and its device lists com.app1 and
+//! org.xxx2 as installed besides the Play Store, so each test gets a directory of its own
+
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+use detach_core::detach_bin;
+
+/// A directory for the cli to run in, removed again after the test
+struct Phone {
+    dir: PathBuf,
+}
+
+impl Phone {
+    fn new(test: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("detach-cli-{}-{test}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        // the first run would walk through the setup instead of the menus
+        fs::write(dir.join("zygisk-detach-wizard-done"), "").unwrap();
+        Self { dir }
+    }
+
+    /// The phone with `apps` already detached
+    fn detached(test: &str, apps: &[&str]) -> Self {
+        let phone = Self::new(test);
+        phone.write("detach.bin", &bin(apps));
+        phone
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(file)
+    }
+
+    fn write(&self, file: &str, content: &[u8]) {
+        let path = self.path(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, file: &str) -> Vec<u8> {
+        fs::read(self.path(file)).unwrap_or_default()
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_cli"));
+        cmd.args(args)
+            .current_dir(&self.dir)
+            .env("NO_COLOR", "1")
+            .env_remove("ZYGISK_DETACH_MODULE_DIR")
+            .env_remove("ZYGISK_DETACH_FILE");
+        cmd
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        self.run_with_input(args, "")
+    }
+
+    /// Runs the cli with the variables of `env` set
+    fn run_with_env(&self, args: &[&str], env: &[(&str, &str)]) -> Output {
+        self.command(args)
+            .envs(env.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    /// Runs the cli with `input` on stdin, as typed into the plain menus
+    fn run_with_input(&self, args: &[&str], input: &str) -> Output {
+        let mut child = (self.command(args))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// The files in `dir`, sorted
+    fn files(&self, dir: &str) -> Vec<String> {
+        let mut files: Vec<String> = fs::read_dir(self.path(dir))
+            .map(|d| {
+                d.map(|e| e.unwrap().file_name().into_string().unwrap())
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files
+    }
+}
+
+impl Drop for Phone {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn bin(apps: &[&str]) -> Vec<u8> {
+    let mut bin = Vec::new();
+    for app in apps {
+        detach_bin::serialize(app, &mut bin).unwrap();
+    }
+    bin
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// The name of a backup taken `n` minutes into 2024
+fn backup(n: u64) -> String {
+    format!(
+        "backups/{}",
+        detach_core::device::backup_file_name(1704067200 + n * 60)
+    )
+}
+
+#[test]
+fn restoring_a_backup_into_a_fresh_module_dir() {
+    let phone = Phone::new("restore");
+    phone.write(&backup(1), &bin(&["com.app1", "org.xxx2"]));
+    // the main menu's Restore backup, then the only backup
+    let output = phone.run_with_input(&["--plain", "--no-restart"], "11\n1\nq\n");
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains("restored: 2 apps"));
+    assert_eq!(phone.read("detach.bin"), bin(&["com.app1", "org.xxx2"]));
+}
+
+#[test]
+fn a_snapshot_drops_the_oldest_backups_past_ten() {
+    let phone = Phone::detached("rotate", &["com.app1"]);
+    for n in 0..10 {
+        phone.write(&backup(n), &bin(&["org.xxx2"]));
+    }
+    let output = phone.run(&["reset", "--yes", "--no-restart"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    let backups: Vec<String> = (phone.files("backups").iter())
+        .map(|b| format!("backups/{b}"))
+        .collect();
+    // the oldest is gone, the one just taken is the newest and holds the list reset
+    let kept: Vec<String> = (1..10).map(backup).collect();
+    assert_eq!(backups.len(), 10);
+    assert_eq!(backups[..9], kept);
+    assert_eq!(phone.read(&backups[9]), bin(&["com.app1"]));
+    assert!(!phone.path("detach.bin").exists());
+}
+
+#[test]
+fn detaching_the_names_piped_in() {
+    let phone = Phone::new("stdin-detach");
+    let output = phone.run_with_input(
+        &["detach", "-", "--no-restart"],
+        "com.app1\n\n  org.xxx2  \n",
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(phone.read("detach.bin"), bin(&["com.app1", "org.xxx2"]));
+}
+
+#[test]
+fn re_attaching_the_names_piped_in() {
+    let phone = Phone::detached("stdin-reattach", &["com.app1", "org.xxx2"]);
+    let output = phone.run_with_input(&["reattach", "-", "--no-restart"], "org.xxx2\n");
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(phone.read("detach.bin"), bin(&["com.app1"]));
+}
+
+/// detach.bin with a duplicate and an entry whose length byte outgrew the name
+fn damaged() -> Vec<u8> {
+    let mut damaged = bin(&["com.app1", "org.xxx2", "com.app1"]);
+    damaged.push(11);
+    damaged.extend(&bin(&["com.a"])[1..]);
+    damaged
+}
+
+#[test]
+fn inspecting_a_good_file() {
+    let phone = Phone::detached("inspect-good", &["com.app1", "org.xxx2"]);
+    let output = phone.run(&["inspect"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(
+        stdout(&output),
+        "./detach.bin: 32 bytes, 2 entries, all well formed\n\
+         OFFSET  LENGTH  NAME      PROBLEM\n\
+         0       15      com.app1\n\
+         16      15      org.xxx2\n"
+    );
+}
+
+#[test]
+fn inspecting_a_damaged_file() {
+    let phone = Phone::new("inspect-bad");
+    phone.write("detach.bin", &damaged());
+    let output = phone.run(&["inspect"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "./detach.bin: 58 bytes, 4 entries, 1 malformed, 1 duplicated\n\
+         OFFSET  LENGTH  NAME      PROBLEM\n\
+         0       15      com.app1\n\
+         16      15      org.xxx2\n\
+         32      15      com.app1  duplicate of the entry at offset 0\n\
+         48      11      com.a     cut off, 2 bytes short, the name takes 9 bytes\n"
+    );
+}
+
+#[test]
+fn inspecting_the_bytes() {
+    let phone = Phone::new("inspect-hex");
+    phone.write("detach.bin", &damaged());
+    let output = phone.run(&["inspect", "--hex"]);
+    assert_eq!(output.status.code(), Some(1));
+    let lines: Vec<String> = stdout(&output).lines().map(str::to_string).collect();
+    assert_eq!(lines.len(), 9);
+    assert_eq!(
+        lines[1..3],
+        [
+            "00000000  0f                                               length 15: com.app1",
+            "00000001  63 00 6f 00 6d 00 2e 00 61 00 70 00 70 00 31     c.o.m...a.p.p.1",
+        ]
+    );
+    assert_eq!(
+        lines[7..],
+        [
+            "00000030  0b                                               length 11: com.a cut off, 2 bytes short, the name takes 9 bytes",
+            "00000031  63 00 6f 00 6d 00 2e 00 61                       c.o.m...a",
+        ]
+    );
+    assert_eq!(phone.run(&["inspect", "--bad"]).status.code(), Some(2));
+}
+
+#[test]
+fn exit_codes_tell_what_happened() {
+    let phone = Phone::detached("exit-codes", &["com.app1"]);
+    let code = |args: &[&str]| phone.run(args).status.code();
+    assert_eq!(code(&["detach", "org.xxx2", "--no-restart"]), Some(0));
+    assert_eq!(code(&["detach", "org.xxx2", "--no-restart"]), Some(6));
+    assert_eq!(code(&["detach", "com.nope", "--no-restart"]), Some(5));
+    assert_eq!(code(&["detach", "-", "--no-restart"]), Some(2));
+    assert_eq!(code(&["--no-such-flag"]), Some(2));
+    assert_eq!(code(&["status", "com.app1"]), Some(0));
+    assert_eq!(code(&["reattach", "com.app1", "--no-restart"]), Some(0));
+    assert_eq!(code(&["status", "com.app1"]), Some(8));
+    assert_eq!(code(&["status", "com.nope"]), Some(5));
+    assert_eq!(phone.read("detach.bin"), bin(&["org.xxx2"]));
+}
+
+#[test]
+fn the_flag_wins_over_the_environment_over_the_config_over_detection() {
+    let phone = Phone::detached("paths", &["com.app1"]);
+    for (file, app) in [
+        ("conf/detach.bin", "com.conf"),
+        ("env/detach.bin", "com.env"),
+        ("flag/detach.bin", "com.flag"),
+        ("env-file.bin", "com.env.file"),
+        ("flag-file.bin", "com.flag.file"),
+    ] {
+        phone.write(file, &bin(&[app]));
+    }
+    let listed = |args: &[&str], env: &[(&str, &str)]| {
+        let output = phone.run_with_env(args, env);
+        assert!(output.status.success(), "{args:?} {env:?}");
+        stdout(&output)
+    };
+    let module_env = ("ZYGISK_DETACH_MODULE_DIR", "env");
+    let file_env = ("ZYGISK_DETACH_FILE", "env-file.bin");
+    assert_eq!(listed(&["list"], &[]), "com.app1\n");
+    phone.write("zygisk-detach.toml", b"module_dir = \"conf\"\n");
+    assert_eq!(listed(&["list"], &[]), "com.conf\n");
+    assert_eq!(listed(&["list"], &[module_env]), "com.env\n");
+    assert_eq!(
+        listed(&["--module-dir", "flag", "list"], &[module_env]),
+        "com.flag\n"
+    );
+    assert_eq!(
+        listed(&["--module-dir", "flag", "list"], &[module_env, file_env]),
+        "com.env.file\n"
+    );
+    assert_eq!(
+        listed(
+            &["--file", "flag-file.bin", "list"],
+            &[module_env, file_env]
+        ),
+        "com.flag.file\n"
+    );
+}
+
+#[test]
+fn a_file_given_leaves_the_phone_alone() {
+    let phone = Phone::detached("foreign", &["com.app1"]);
+    phone.write("copy.bin", &bin(&["org.xxx2"]));
+    phone.write("import.txt", b"com.app1\n");
+    let output = phone.run(&["--file", "copy.bin", "import", "import.txt"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(phone.read("copy.bin"), bin(&["com.app1", "org.xxx2"]));
+    assert_eq!(phone.read("detach.bin"), bin(&["com.app1"]));
+    assert!(phone.files("backups").is_empty());
+}
+
+#[test]
+fn a_write_that_fails_keeps_the_old_list_and_says_where() {
+    let phone = Phone::new("read-only");
+    phone.write("module/detach.bin", &bin(&["com.app1"]));
+    let module = phone.path("module");
+    fs::set_permissions(&module, fs::Permissions::from_mode(0o555)).unwrap();
+    let module_env = [("ZYGISK_DETACH_MODULE_DIR", "module")];
+    let detach = ["detach", "org.xxx2", "--no-restart"];
+    // root writes into read-only directories all the same
+    if fs::write(module.join("probe"), "").is_err() {
+        let output = phone.run_with_env(&detach, &module_env);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("module/detach.bin: "), "{stderr}");
+        assert!(stderr.contains("the module still uses the list it had before"));
+        assert_eq!(phone.read("module/detach.bin"), bin(&["com.app1"]));
+    }
+    // nor into a directory where the new file is written first
+    fs::set_permissions(&module, fs::Permissions::from_mode(0o755)).unwrap();
+    phone.write("module/detach.bin.tmp/keep", b"");
+    let output = phone.run_with_env(&detach, &module_env);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("module/detach.bin: "), "{stderr}");
+    assert!(stderr.contains("the module still uses the list it had before"));
+    assert_eq!(phone.read("module/detach.bin"), bin(&["com.app1"]));
+    assert!(phone.files("").iter().all(|f| f != "zygisk-detach-written"));
+}
+
+#[test]
+fn doctor_tells_whether_detach_bin_is_what_was_written() {
+    let phone = Phone::new("doctor-written");
+    let doctor = || stdout(&phone.run(&["doctor"]));
+    // nothing written yet, nothing to tell
+    assert!(!doctor().contains("wrote last"));
+    let output = phone.run(&["detach", "com.app1", "--no-restart"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(doctor().contains("detach.bin is what the cli wrote last"));
+    phone.write("detach.bin", &bin(&["com.app1", "org.xxx2"]));
+    let out = doctor();
+    assert!(
+        out.contains("detach.bin (32 bytes) is not the 16 bytes the cli wrote last"),
+        "{out}"
+    );
+}
+
+#[test]
+fn detaching_the_play_store_takes_force() {
+    let phone = Phone::detached("risky", &["com.app1"]);
+    let output = phone.run(&["detach", "com.android.vending", "--no-restart"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("detaching com.android.vending: the Play Store"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("add --force"), "{stderr}");
+    assert_eq!(phone.read("detach.bin"), bin(&["com.app1"]));
+    assert!(phone.files("backups").is_empty());
+    let output = phone.run(&["--force", "detach", "com.android.vending", "--no-restart"]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(
+        phone.read("detach.bin"),
+        bin(&["com.android.vending", "com.app1"])
+    );
+    // the config marks more
+    phone.write("zygisk-detach.toml", b"risky = \"org.xxx2\"\n");
+    let output = phone.run(&["detach", "org.xxx2", "--no-restart"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        phone.read("detach.bin"),
+        bin(&["com.android.vending", "com.app1"])
+    );
+}